@@ -53,9 +53,29 @@ async function build() {
     
     console.log('✓ Built theoldswitcheroo-bundled.cjs');
 
+    // Bundle maintenance agent (CommonJS, like the daemon it runs next to)
+    await esbuild.build({
+      entryPoints: ['maintenance.ts'],
+      bundle: true,
+      platform: 'node',
+      target: 'node18',
+      outfile: '../dist/maintenance-bundled.cjs',
+      external: [], // Bundle all dependencies
+      format: 'cjs'
+    });
+
+    // Add shebang to maintenance agent
+    const maintenancePath = path.join(distDir, 'maintenance-bundled.cjs');
+    let maintenanceContent = fs.readFileSync(maintenancePath, 'utf8');
+    maintenanceContent = maintenanceContent.replace(/^#!.*\n/, ''); // Remove existing shebang
+    fs.writeFileSync(maintenancePath, '#!/usr/bin/env node\n' + maintenanceContent);
+
+    console.log('✓ Built maintenance-bundled.cjs');
+
     // Make bundled files executable
     fs.chmodSync(daemonPath, 0o755);
     fs.chmodSync(cliPath, 0o755);
+    fs.chmodSync(maintenancePath, 0o755);
     console.log('✓ Made files executable');
 
   } catch (error) {
@@ -6,7 +6,13 @@ import * as path from 'path';
 describe('TaskSpace CLI Tool', () => {
   let daemonProcess: ChildProcess;
   const testSocketPath = '/tmp/test-cli-daemon.sock';
+  const testHistoryDir = '/tmp/test-cli-daemon-progress';
+  const testRegistryDir = '/tmp/test-cli-daemon-registry';
   const bundledDaemonPath = path.join(__dirname, '..', 'dist', 'daemon-bundled.cjs');
+  const daemonArgs = [
+    bundledDaemonPath, '--socket-path', testSocketPath,
+    '--history-dir', testHistoryDir, '--registry-file', path.join(testRegistryDir, 'registry.json')
+  ];
   const bundledCliPath = path.join(__dirname, '..', 'dist', 'theoldswitcheroo-bundled.cjs');
 
   beforeEach(async () => {
@@ -14,9 +20,11 @@ describe('TaskSpace CLI Tool', () => {
     if (fs.existsSync(testSocketPath)) {
       fs.unlinkSync(testSocketPath);
     }
+    fs.rmSync(testHistoryDir, { recursive: true, force: true });
+    fs.rmSync(testRegistryDir, { recursive: true, force: true });
 
     // Start daemon for testing
-    daemonProcess = spawn('node', [bundledDaemonPath, '--socket-path', testSocketPath], {
+    daemonProcess = spawn('node', daemonArgs, {
       stdio: ['pipe', 'pipe', 'pipe']
     });
 
@@ -47,6 +55,8 @@ describe('TaskSpace CLI Tool', () => {
     if (fs.existsSync(testSocketPath)) {
       fs.unlinkSync(testSocketPath);
     }
+    fs.rmSync(testHistoryDir, { recursive: true, force: true });
+    fs.rmSync(testRegistryDir, { recursive: true, force: true });
   });
 
   test('CLI tool sends new-taskspace message', async () => {
@@ -179,6 +189,98 @@ describe('TaskSpace CLI Tool', () => {
     expect(stdoutData).toContain('"category":"milestone"');
   });
 
+  test('CLI tool sends annotate message', async () => {
+    // Capture daemon stdout
+    let stdoutData = '';
+    daemonProcess.stdout?.on('data', (data) => {
+      stdoutData += data.toString();
+    });
+
+    // Run CLI tool
+    const cliProcess = spawn('node', [
+      bundledCliPath,
+      'annotate',
+      '--taskspace', '12345678-1234-1234-1234-123456789abc',
+      '--note', 'investigating issue #42',
+      '--label', 'issue=42',
+      '--label', 'owner=me'
+    ], {
+      stdio: ['pipe', 'pipe', 'pipe'],
+      env: { ...process.env, THEOLDSWITCHEROO_SOCKET: testSocketPath }
+    });
+
+    let cliStdout = '';
+    cliProcess.stdout?.on('data', (data) => {
+      cliStdout += data.toString();
+    });
+
+    // Wait for CLI to complete
+    const exitCode = await new Promise<number>((resolve, reject) => {
+      cliProcess.on('close', (code) => {
+        resolve(code || 0);
+      });
+
+      setTimeout(() => {
+        reject(new Error('CLI timeout'));
+      }, 10000);
+    });
+
+    expect(exitCode).toBe(0);
+    expect(cliStdout).toContain('Annotation sent for: 12345678-1234-1234-1234-123456789abc');
+
+    // Give daemon time to process message
+    await new Promise(resolve => setTimeout(resolve, 200));
+
+    // Check that message was forwarded to daemon stdout
+    expect(stdoutData).toContain('"type":"annotate_taskspace"');
+    expect(stdoutData).toContain('"note":"investigating issue #42"');
+    expect(stdoutData).toContain('"labels":{"issue":"42","owner":"me"}');
+  });
+
+  test('CLI tool sends delete-taskspace message', async () => {
+    // Capture daemon stdout
+    let stdoutData = '';
+    daemonProcess.stdout?.on('data', (data) => {
+      stdoutData += data.toString();
+    });
+
+    // Run CLI tool
+    const cliProcess = spawn('node', [
+      bundledCliPath,
+      'delete-taskspace',
+      '--taskspace', '12345678-1234-1234-1234-123456789abc'
+    ], {
+      stdio: ['pipe', 'pipe', 'pipe'],
+      env: { ...process.env, THEOLDSWITCHEROO_SOCKET: testSocketPath }
+    });
+
+    let cliStdout = '';
+    cliProcess.stdout?.on('data', (data) => {
+      cliStdout += data.toString();
+    });
+
+    // Wait for CLI to complete
+    const exitCode = await new Promise<number>((resolve, reject) => {
+      cliProcess.on('close', (code) => {
+        resolve(code || 0);
+      });
+
+      setTimeout(() => {
+        reject(new Error('CLI timeout'));
+      }, 10000);
+    });
+
+    expect(exitCode).toBe(0);
+    expect(cliStdout).toContain('TaskSpace delete request sent for: 12345678-1234-1234-1234-123456789abc');
+
+    // Give daemon time to process message
+    await new Promise(resolve => setTimeout(resolve, 200));
+
+    // Check that message was forwarded to daemon stdout
+    expect(stdoutData).toContain('"type":"delete_taskspace"');
+    expect(stdoutData).toContain('"uuid":"12345678-1234-1234-1234-123456789abc"');
+  });
+
   test('CLI tool sends signal-user message', async () => {
     // Capture daemon stdout
     let stdoutData = '';
@@ -222,6 +324,47 @@ describe('TaskSpace CLI Tool', () => {
     expect(stdoutData).toContain('"type":"user_signal"');
     expect(stdoutData).toContain('"message":"Need help with database configuration"');
   });
+
+  test('CLI tool copies standard input', async () => {
+    // Capture daemon stdout
+    let stdoutData = '';
+    daemonProcess.stdout?.on('data', (data) => {
+      stdoutData += data.toString();
+    });
+
+    // Run CLI tool the way the pbcopy shim does
+    const cliProcess = spawn('node', [bundledCliPath, 'copy'], {
+      stdio: ['pipe', 'pipe', 'pipe'],
+      env: { ...process.env, THEOLDSWITCHEROO_SOCKET: testSocketPath }
+    });
+    cliProcess.stdin?.end('line one\nline two\n');
+
+    let cliStdout = '';
+    cliProcess.stdout?.on('data', (data) => {
+      cliStdout += data.toString();
+    });
+
+    // Wait for CLI to complete
+    const exitCode = await new Promise<number>((resolve, reject) => {
+      cliProcess.on('close', (code) => {
+        resolve(code || 0);
+      });
+
+      setTimeout(() => {
+        reject(new Error('CLI timeout'));
+      }, 10000);
+    });
+
+    expect(exitCode).toBe(0);
+    expect(cliStdout).toBe('');
+
+    // Give daemon time to process message
+    await new Promise(resolve => setTimeout(resolve, 200));
+
+    // Check that message was forwarded to daemon stdout
+    expect(stdoutData).toContain('"type":"clipboard_copy"');
+    expect(stdoutData).toContain('"text":"line one\\nline two\\n"');
+  });
 });
 
 function waitForSocket(socketPath: string, timeout: number = 5000): Promise<void> {
@@ -5,6 +5,7 @@ import * as net from 'net';
 import * as fs from 'fs';
 import * as path from 'path';
 import * as os from 'os';
+import { PROTOCOL_VERSION, TaskSpaceMessage } from '../src/protocol.js';
 
 // Helper function to extract UUID from current working directory path
 function extractUuidFromPath(cwd: string): string | null {
@@ -14,10 +15,15 @@ function extractUuidFromPath(cwd: string): string | null {
   return match ? match[0] : null;
 }
 
-interface TaskSpaceMessage {
-  type: string;
-  timestamp: string;
-  [key: string]: any;
+// Copies larger than this are refused rather than sent through the daemon
+const MAX_COPY_BYTES = 1024 * 1024;
+
+async function readStdin(): Promise<Buffer> {
+  const chunks: Buffer[] = [];
+  for await (const chunk of process.stdin) {
+    chunks.push(chunk as Buffer);
+  }
+  return Buffer.concat(chunks);
 }
 
 class TaskSpaceCLI {
@@ -39,17 +45,30 @@ class TaskSpaceCLI {
 
       const client = net.createConnection(this.socketPath);
       let responseReceived = false;
+      let replies = '';
 
       client.on('connect', () => {
-        const messageStr = JSON.stringify(message);
+        const messageStr = JSON.stringify({ v: PROTOCOL_VERSION, ...message });
         client.write(messageStr);
         client.end();
       });
 
+      client.on('data', (data) => {
+        replies += data.toString();
+      });
+
       client.on('close', () => {
         if (!responseReceived) {
           responseReceived = true;
-          resolve();
+          // The daemon answers a message it rejects with a structured error
+          const rejection = replies.split('\n')
+            .map(line => { try { return JSON.parse(line); } catch { return null; } })
+            .find(reply => reply && reply.type === 'error');
+          if (rejection) {
+            reject(new Error(`Daemon rejected the message (${rejection.code}): ${rejection.message}`));
+          } else {
+            resolve();
+          }
         }
       });
 
@@ -112,6 +131,45 @@ class TaskSpaceCLI {
     }
   }
 
+  async annotateTaskSpace(uuid: string, options: { note?: string; labels: Record<string, string> }): Promise<void> {
+    if (!options.note && Object.keys(options.labels).length === 0) {
+      console.error('✗ Must specify --note or --label to annotate');
+      process.exit(1);
+    }
+
+    const message: TaskSpaceMessage = {
+      type: 'annotate_taskspace',
+      uuid,
+      timestamp: new Date().toISOString(),
+      ...(options.note && { note: options.note }),
+      labels: options.labels
+    };
+
+    try {
+      await this.sendMessage(message);
+      console.log(`✓ Annotation sent for: ${uuid}`);
+    } catch (error) {
+      console.error(`✗ Failed to annotate taskspace: ${error instanceof Error ? error.message : error}`);
+      process.exit(1);
+    }
+  }
+
+  async deleteTaskSpace(uuid: string): Promise<void> {
+    const message: TaskSpaceMessage = {
+      type: 'delete_taskspace',
+      uuid,
+      timestamp: new Date().toISOString()
+    };
+
+    try {
+      await this.sendMessage(message);
+      console.log(`✓ TaskSpace delete request sent for: ${uuid}`);
+    } catch (error) {
+      console.error(`✗ Failed to delete taskspace: ${error instanceof Error ? error.message : error}`);
+      process.exit(1);
+    }
+  }
+
   async status(): Promise<void> {
     const message: TaskSpaceMessage = {
       type: 'status_request',
@@ -160,6 +218,29 @@ class TaskSpaceCLI {
       process.exit(1);
     }
   }
+
+  async copy(text: string): Promise<void> {
+    if (Buffer.byteLength(text) > MAX_COPY_BYTES) {
+      console.error(`✗ Refusing to copy more than ${MAX_COPY_BYTES} bytes`);
+      process.exit(1);
+    }
+
+    const uuid = extractUuidFromPath(process.cwd());
+    const copyMessage: TaskSpaceMessage = {
+      type: 'clipboard_copy',
+      text,
+      timestamp: new Date().toISOString(),
+      ...(uuid && { taskspace_uuid: uuid })
+    };
+
+    try {
+      // Silent on success, like the pbcopy and xclip it stands in for
+      await this.sendMessage(copyMessage);
+    } catch (error) {
+      console.error(`✗ Failed to copy: ${error instanceof Error ? error.message : error}`);
+      process.exit(1);
+    }
+  }
 }
 
 async function main(): Promise<void> {
@@ -206,6 +287,42 @@ async function main(): Promise<void> {
       });
     });
 
+  program
+    .command('annotate')
+    .description('Attach a note or key=value labels to a taskspace (uses UUID from current directory)')
+    .option('-t, --taskspace <uuid>', 'TaskSpace UUID (default: from current directory)')
+    .option('--note <note>', 'Free-form note, e.g. why the taskspace exists')
+    .option('-l, --label <key=value>', 'Label to set; an empty value removes it (repeatable)',
+      (value: string, labels: string[]) => [...labels, value], [] as string[])
+    .action(async (options) => {
+      const uuid = options.taskspace || extractUuidFromPath(process.cwd());
+      if (!uuid) {
+        console.error('✗ Could not determine taskspace UUID from current directory');
+        console.error('  Run this command from within a taskspace directory or pass --taskspace');
+        process.exit(1);
+      }
+
+      const labels: Record<string, string> = {};
+      for (const label of options.label) {
+        const separator = label.indexOf('=');
+        if (separator <= 0) {
+          console.error(`✗ Invalid label '${label}'. Use key=value`);
+          process.exit(1);
+        }
+        labels[label.slice(0, separator)] = label.slice(separator + 1);
+      }
+
+      await cli.annotateTaskSpace(uuid, { note: options.note, labels });
+    });
+
+  program
+    .command('delete-taskspace')
+    .description('Stop a taskspace\'s server and remove it from the app (its files stay on the host)')
+    .requiredOption('-t, --taskspace <uuid>', 'TaskSpace UUID')
+    .action(async (options) => {
+      await cli.deleteTaskSpace(options.taskspace);
+    });
+
   program
     .command('status')
     .description('Get daemon and taskspace status')
@@ -236,6 +353,14 @@ async function main(): Promise<void> {
       await cli.signalUser(options.message);
     });
 
+  program
+    .command('copy')
+    .description('Copy standard input (or --text) to the clipboard of the machine running the app, if its clipboardBridge setting is on')
+    .option('--text <text>', 'Text to copy instead of standard input')
+    .action(async (options) => {
+      await cli.copy(options.text ?? (await readStdin()).toString('utf8'));
+    });
+
   // Show help if no command provided
   if (process.argv.length <= 2) {
     program.help();
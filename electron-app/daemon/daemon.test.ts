@@ -6,7 +6,13 @@ import * as path from 'path';
 describe('TaskSpace Daemon', () => {
   let daemonProcess: ChildProcess;
   const testSocketPath = '/tmp/test-daemon.sock';
+  const testHistoryDir = '/tmp/test-daemon-progress';
+  const testRegistryDir = '/tmp/test-daemon-registry';
   const bundledDaemonPath = path.join(__dirname, '..', 'dist', 'daemon-bundled.cjs');
+  const daemonArgs = [
+    bundledDaemonPath, '--socket-path', testSocketPath,
+    '--history-dir', testHistoryDir, '--registry-file', path.join(testRegistryDir, 'registry.json')
+  ];
 
   beforeEach(async () => {
     // Clean up any existing socket
@@ -14,6 +20,8 @@ describe('TaskSpace Daemon', () => {
       if (fs.existsSync(testSocketPath)) {
         fs.unlinkSync(testSocketPath);
       }
+      fs.rmSync(testHistoryDir, { recursive: true, force: true });
+      fs.rmSync(testRegistryDir, { recursive: true, force: true });
     } catch (error) {
       // Ignore cleanup errors
     }
@@ -39,6 +47,8 @@ describe('TaskSpace Daemon', () => {
       if (fs.existsSync(testSocketPath)) {
         fs.unlinkSync(testSocketPath);
       }
+      fs.rmSync(testHistoryDir, { recursive: true, force: true });
+      fs.rmSync(testRegistryDir, { recursive: true, force: true });
     } catch (error) {
       // Ignore cleanup errors
     }
@@ -46,7 +56,7 @@ describe('TaskSpace Daemon', () => {
 
   test('daemon starts and creates socket', async () => {
     // Start daemon
-    daemonProcess = spawn('node', [bundledDaemonPath, '--socket-path', testSocketPath], {
+    daemonProcess = spawn('node', daemonArgs, {
       stdio: ['pipe', 'pipe', 'pipe']
     });
 
@@ -58,7 +68,7 @@ describe('TaskSpace Daemon', () => {
 
   test('client can connect to daemon socket', async () => {
     // Start daemon
-    daemonProcess = spawn('node', [bundledDaemonPath, '--socket-path', testSocketPath], {
+    daemonProcess = spawn('node', daemonArgs, {
       stdio: ['pipe', 'pipe', 'pipe']
     });
 
@@ -82,7 +92,7 @@ describe('TaskSpace Daemon', () => {
 
   test('daemon forwards client messages to stdout', async () => {
     // Start daemon
-    daemonProcess = spawn('node', [bundledDaemonPath, '--socket-path', testSocketPath], {
+    daemonProcess = spawn('node', daemonArgs, {
       stdio: ['pipe', 'pipe', 'pipe']
     });
 
@@ -99,7 +109,7 @@ describe('TaskSpace Daemon', () => {
     
     await new Promise<void>((resolve) => {
       client.on('connect', () => {
-        const testMessage = '{"type":"test_message","data":"hello"}';
+        const testMessage = '{"type":"user_signal","message":"hello"}';
         client.write(testMessage);
         client.end();
         
@@ -108,7 +118,272 @@ describe('TaskSpace Daemon', () => {
       });
     });
 
-    expect(stdoutData).toContain('{"type":"test_message","data":"hello"}');
+    expect(stdoutData).toContain('{"type":"user_signal","message":"hello"}');
+  });
+
+  test('daemon rejects malformed messages with a structured error', async () => {
+    // Start daemon
+    daemonProcess = spawn('node', daemonArgs, {
+      stdio: ['pipe', 'pipe', 'pipe']
+    });
+
+    await waitForSocket(testSocketPath, 5000);
+
+    // Capture daemon stdout
+    let stdoutData = '';
+    daemonProcess.stdout?.on('data', (data) => {
+      stdoutData += data.toString();
+    });
+
+    // Send a message missing a required field and collect the replies
+    const client = net.createConnection(testSocketPath);
+    let replies = '';
+    client.on('data', (data) => {
+      replies += data.toString();
+    });
+
+    await new Promise<void>((resolve) => {
+      client.on('connect', () => {
+        client.write('{"type":"progress_log","category":"info"}\n');
+        setTimeout(resolve, 100);
+      });
+    });
+    client.end();
+
+    const messages = replies.trim().split('\n').map(line => JSON.parse(line));
+    expect(messages[0]).toMatchObject({ type: 'hello', versions: [1] });
+    expect(messages[1]).toMatchObject({ type: 'error', code: 'invalid_message' });
+    expect(stdoutData).not.toContain('"type":"progress_log"');
+  });
+
+  test('daemon exits and removes its socket when Electron disconnects', async () => {
+    // Start daemon
+    daemonProcess = spawn('node', daemonArgs, {
+      stdio: ['pipe', 'pipe', 'pipe']
+    });
+
+    await waitForSocket(testSocketPath, 5000);
+
+    const exited = new Promise<void>((resolve) => daemonProcess.on('exit', () => resolve()));
+    daemonProcess.stdin?.end();
+    await exited;
+
+    expect(fs.existsSync(testSocketPath)).toBe(false);
+  });
+
+  test('daemon replays progress to new clients after a restart', async () => {
+    const startDaemon = async () => {
+      daemonProcess = spawn('node', daemonArgs, {
+        stdio: ['pipe', 'pipe', 'pipe']
+      });
+      await waitForSocket(testSocketPath, 5000);
+    };
+
+    const connectAndCollect = async (line?: string) => {
+      const client = net.createConnection(testSocketPath);
+      let replies = '';
+      client.on('data', (data) => {
+        replies += data.toString();
+      });
+      await new Promise<void>((resolve) => {
+        client.on('connect', () => {
+          if (line) {
+            client.write(line);
+          }
+          setTimeout(resolve, 100);
+        });
+      });
+      client.end();
+      return replies.trim().split('\n').map(reply => JSON.parse(reply));
+    };
+
+    await startDaemon();
+    await connectAndCollect('{"type":"progress_log","message":"tests pass","category":"milestone","taskspace_uuid":"abc"}\n');
+
+    // Restart the daemon; shutting down writes the history to disk
+    const exited = new Promise<void>((resolve) => daemonProcess.on('exit', () => resolve()));
+    daemonProcess.kill('SIGTERM');
+    await exited;
+    await startDaemon();
+
+    const messages = await connectAndCollect();
+    expect(messages[0]).toMatchObject({ type: 'hello' });
+    expect(messages[1]).toMatchObject({ type: 'progress_log', message: 'tests pass', taskspace_uuid: 'abc' });
+  });
+
+  test('daemon only sends subscribed topics', async () => {
+    // Start daemon
+    daemonProcess = spawn('node', daemonArgs, {
+      stdio: ['pipe', 'pipe', 'pipe']
+    });
+
+    await waitForSocket(testSocketPath, 5000);
+
+    const client = net.createConnection(testSocketPath);
+    let replies = '';
+    client.on('data', (data) => {
+      replies += data.toString();
+    });
+
+    await new Promise<void>((resolve) => {
+      client.on('connect', () => {
+        client.write('{"type":"subscribe","topics":["taskspace:abc"]}\n');
+        setTimeout(resolve, 100);
+      });
+    });
+
+    // Messages from Electron about two taskspaces and one global one
+    daemonProcess.stdin?.write('{"type":"taskspace_status","uuid":"abc","status":"ready"}\n');
+    daemonProcess.stdin?.write('{"type":"taskspace_status","uuid":"xyz","status":"ready"}\n');
+    daemonProcess.stdin?.write('{"type":"status_response","taskspaces":[]}\n');
+    await new Promise(resolve => setTimeout(resolve, 100));
+    client.end();
+
+    const messages = replies.trim().split('\n').map(line => JSON.parse(line));
+    expect(messages.map(message => message.type)).toEqual(['hello', 'subscribed', 'taskspace_status']);
+    expect(messages[1].topics).toEqual(['taskspace:abc']);
+    expect(messages[2].uuid).toBe('abc');
+  });
+
+  test('daemon registry rejects stale writes and survives a restart', async () => {
+    const startDaemon = async () => {
+      daemonProcess = spawn('node', daemonArgs, {
+        stdio: ['pipe', 'pipe', 'pipe']
+      });
+      await waitForSocket(testSocketPath, 5000);
+    };
+
+    const request = async (lines: string[]) => {
+      const client = net.createConnection(testSocketPath);
+      let replies = '';
+      client.on('data', (data) => {
+        replies += data.toString();
+      });
+      await new Promise<void>((resolve) => {
+        client.on('connect', () => {
+          client.write(lines.join('\n') + '\n');
+          setTimeout(resolve, 200);
+        });
+      });
+      client.end();
+      return replies.trim().split('\n').map(reply => JSON.parse(reply)).filter(reply => reply.request_id);
+    };
+
+    await startDaemon();
+    const writes = await request([
+      '{"type":"registry_put","uuid":"abc","fields":{"name":"api"},"expected_revision":0,"request_id":"first"}',
+      '{"type":"registry_put","uuid":"abc","fields":{"name":"web"},"expected_revision":0,"request_id":"second"}'
+    ]);
+    expect(writes[0]).toMatchObject({ type: 'registry_result', uuid: 'abc', revision: 1, request_id: 'first' });
+    expect(writes[1]).toMatchObject({ type: 'error', code: 'conflict', current_revision: 1, request_id: 'second' });
+
+    // Restart the daemon; the registry is read back from disk
+    const exited = new Promise<void>((resolve) => daemonProcess.on('exit', () => resolve()));
+    daemonProcess.kill('SIGTERM');
+    await exited;
+    await startDaemon();
+
+    const [state] = await request(['{"type":"registry_get","request_id":"state"}']);
+    expect(state.entries).toEqual([{ uuid: 'abc', revision: 1, fields: { name: 'api' } }]);
+  });
+
+  test('daemon passes on maintenance results once each', async () => {
+    const resultsFile = path.join(testRegistryDir, 'results.jsonl');
+    const seenFile = path.join(path.dirname(testSocketPath), 'maintenance-seen');
+    fs.rmSync(seenFile, { force: true });
+    fs.mkdirSync(testRegistryDir, { recursive: true });
+    fs.writeFileSync(resultsFile, '{"type":"maintenance_result","seq":1,"job":"logs","ok":true,"output":"while away"}\n');
+
+    const startDaemon = async () => {
+      let stdout = '';
+      daemonProcess = spawn('node', [...daemonArgs, '--maintenance-results', resultsFile], {
+        stdio: ['pipe', 'pipe', 'pipe']
+      });
+      daemonProcess.stdout?.on('data', (data) => {
+        stdout += data.toString();
+      });
+      await waitForSocket(testSocketPath, 5000);
+      return () => stdout;
+    };
+
+    // A result from before the daemon started is passed on when it starts
+    let output = await startDaemon();
+    expect(output()).toContain('"output":"while away"');
+
+    // A new one is passed on as it arrives
+    fs.appendFileSync(resultsFile, '{"type":"maintenance_result","seq":2,"job":"logs","ok":true,"output":"just now"}\n');
+    await new Promise(resolve => setTimeout(resolve, 2500));
+    expect(output()).toContain('"output":"just now"');
+
+    // A restarted daemon does not pass them on again
+    const exited = new Promise<void>((resolve) => daemonProcess.on('exit', () => resolve()));
+    daemonProcess.kill('SIGTERM');
+    await exited;
+    output = await startDaemon();
+    await new Promise(resolve => setTimeout(resolve, 200));
+    expect(output()).not.toContain('maintenance_result');
+
+    fs.rmSync(seenFile, { force: true });
+  });
+
+  test('daemon negotiates a protocol version', async () => {
+    // Start daemon
+    daemonProcess = spawn('node', daemonArgs, {
+      stdio: ['pipe', 'pipe', 'pipe']
+    });
+
+    await waitForSocket(testSocketPath, 5000);
+
+    const client = net.createConnection(testSocketPath);
+    let replies = '';
+    client.on('data', (data) => {
+      replies += data.toString();
+    });
+
+    await new Promise<void>((resolve) => {
+      client.on('connect', () => {
+        client.write('{"type":"hello","versions":[1,2]}\n{"type":"hello","versions":[99]}\n');
+        setTimeout(resolve, 100);
+      });
+    });
+    client.end();
+
+    const messages = replies.trim().split('\n').map(line => JSON.parse(line));
+    expect(messages[1]).toMatchObject({ type: 'hello', version: 1 });
+    expect(messages[2]).toMatchObject({ type: 'error', code: 'unsupported_version' });
+  });
+
+  test('daemon rejects type names inherited from Object as unknown', async () => {
+    // Start daemon
+    daemonProcess = spawn('node', daemonArgs, {
+      stdio: ['pipe', 'pipe', 'pipe']
+    });
+
+    await waitForSocket(testSocketPath, 5000);
+
+    const client = net.createConnection(testSocketPath);
+    let replies = '';
+    client.on('data', (data) => {
+      replies += data.toString();
+    });
+
+    await new Promise<void>((resolve) => {
+      client.on('connect', () => {
+        client.write('{"type":"toString"}\n{"type":"constructor"}\n{"type":"__proto__"}\n{"type":"hello","versions":[1]}\n');
+        setTimeout(resolve, 100);
+      });
+    });
+    client.end();
+
+    const messages = replies.trim().split('\n').map(line => JSON.parse(line));
+    expect(messages.slice(1, 4)).toEqual([
+      expect.objectContaining({ type: 'error', code: 'unknown_type' }),
+      expect.objectContaining({ type: 'error', code: 'unknown_type' }),
+      expect.objectContaining({ type: 'error', code: 'unknown_type' })
+    ]);
+    // Still serving
+    expect(messages[4]).toMatchObject({ type: 'hello', version: 1 });
+    expect(daemonProcess.exitCode).toBeNull();
   });
 });
 
@@ -2,22 +2,131 @@
 
 import * as net from 'net';
 import * as fs from 'fs';
+import * as path from 'path';
 import * as readline from 'readline';
+import { ProgressHistory } from './progress-history.js';
+import { RegistryConflictError, TaskSpaceRegistry } from './registry.js';
+import { MaintenanceResults } from './maintenance-results.js';
+import {
+  ALL_TOPICS, PROTOCOL_VERSION, PROTOCOL_VERSIONS, TaskSpaceMessage, errorMessage, messageTopic, negotiateVersion, parseClientMessage
+} from '../src/protocol.js';
 
-interface TaskSpaceMessage {
-  type: string;
-  [key: string]: any;
+/// Messages queued for one reader before the oldest droppable ones are discarded
+const MAX_QUEUED_MESSAGES = 1000;
+
+/// Message types that only report progress. When a reader falls behind, the
+/// oldest of these are dropped first so they cannot crowd out requests and replies.
+const DROPPABLE_TYPES = new Set(['progress_log', 'taskspace_status']);
+
+function isDroppable(line: string): boolean {
+  try {
+    return DROPPABLE_TYPES.has(JSON.parse(line).type);
+  } catch {
+    return false;
+  }
+}
+
+/// Bounded write queue in front of a stream, so a slow reader can neither
+/// block the router nor make the daemon buffer without limit.
+class Outbox {
+  private queue: { line: string; droppable: boolean }[] = [];
+  private waitingForDrain = false;
+  private dropped = 0;
+
+  constructor(
+    private stream: NodeJS.WritableStream,
+    private name: string,
+    // Called with a message that does not fit and cannot be dropped in favour of an older one
+    private onOverflow: (line: string) => void
+  ) {
+    stream.on('drain', () => {
+      this.waitingForDrain = false;
+      if (this.dropped > 0) {
+        console.error(`${this.name} fell behind; dropped ${this.dropped} progress messages`);
+        this.dropped = 0;
+      }
+      this.flush();
+    });
+  }
+
+  push(line: string): void {
+    const droppable = isDroppable(line);
+    if (this.queue.length >= MAX_QUEUED_MESSAGES) {
+      const oldest = this.queue.findIndex(entry => entry.droppable);
+      if (oldest !== -1) {
+        this.queue.splice(oldest, 1);
+        this.dropped++;
+      } else if (droppable) {
+        this.dropped++;
+        return;
+      } else {
+        this.onOverflow(line);
+        return;
+      }
+    }
+    this.queue.push({ line, droppable });
+    this.flush();
+  }
+
+  private flush(): void {
+    while (!this.waitingForDrain && this.queue.length > 0) {
+      const { line } = this.queue.shift()!;
+      if (!this.stream.write(line + '\n')) {
+        this.waitingForDrain = true;
+      }
+    }
+  }
+}
+
+/// How long a new client has to subscribe before it is sent the whole progress history
+const REPLAY_GRACE_MS = 50;
+
+function topicOf(line: string): string {
+  try {
+    return messageTopic(JSON.parse(line));
+  } catch {
+    return 'global';
+  }
+}
+
+interface Client {
+  outbox: Outbox;
+  topics: Set<string>;
+  // Whether the client has subscribed, replacing the default of everything
+  subscribed: boolean;
+  // Whether the progress history has been sent yet
+  replayed: boolean;
+  replayTimer: NodeJS.Timeout;
 }
 
 class TaskSpaceDaemon {
   private server: net.Server;
-  private clients = new Set<net.Socket>();
+  private clients = new Map<net.Socket, Client>();
+  // Which client sent each pending request, so the reply reaches it whatever it subscribed to
+  private requesters = new Map<string, net.Socket>();
+  private electron: Outbox;
   private socketPath: string;
+  private history: ProgressHistory;
+  private registry: TaskSpaceRegistry;
+  private maintenanceResults: MaintenanceResults | null = null;
   private stdinReader: readline.Interface;
 
-  constructor(socketPath: string) {
+  constructor(socketPath: string, historyDir: string, registryFile: string, maintenanceResultsFile: string | null) {
     this.socketPath = socketPath;
+    this.history = new ProgressHistory(historyDir);
+    this.registry = new TaskSpaceRegistry(registryFile);
     this.server = net.createServer(this.handleClient.bind(this));
+    this.electron = new Outbox(process.stdout, 'Electron', (line) => {
+      console.error('Electron is not reading; dropped message:', line);
+    });
+    if (maintenanceResultsFile) {
+      // Results of the host's scheduled jobs go to Electron and to clients subscribed to `global`
+      const seenFile = path.join(path.dirname(socketPath), 'maintenance-seen');
+      this.maintenanceResults = new MaintenanceResults(maintenanceResultsFile, seenFile, (line) => {
+        this.electron.push(line);
+        this.broadcast(line);
+      });
+    }
     this.stdinReader = readline.createInterface({
       input: process.stdin,
       output: process.stdout,
@@ -30,32 +139,242 @@ class TaskSpaceDaemon {
 
   private handleClient(socket: net.Socket): void {
     console.log('Client connected to daemon');
-    this.clients.add(socket);
+    this.clients.set(socket, {
+      outbox: new Outbox(socket, 'Client', () => {
+        // A client that lets requests and replies pile up is stuck; it can reconnect
+        console.error('Client is not reading its messages; disconnecting it');
+        socket.destroy();
+      }),
+      topics: new Set([ALL_TOPICS]),
+      subscribed: false,
+      replayed: false,
+      // Catch the client up on recent progress, including from before a daemon restart.
+      // A client that subscribes first only gets the history of its topics.
+      replayTimer: setTimeout(() => this.replayHistory(socket), REPLAY_GRACE_MS)
+    });
+
+    // Tell the client which protocol versions we speak; it may answer with a hello of its own
+    this.send(socket, { type: 'hello', v: PROTOCOL_VERSION, versions: PROTOCOL_VERSIONS });
 
+    // Messages are newline-separated; a client may also send one message and close without a newline
+    let buffer = '';
     socket.on('data', (data: Buffer) => {
-      try {
-        const message = data.toString().trim();
-        if (message) {
-          // Forward message to Electron via stdout
-          process.stdout.write(message + '\n');
-          console.log('Forwarded to Electron:', message);
-        }
-      } catch (error) {
-        console.error('Error processing client message:', error);
+      buffer += data.toString();
+      const lines = buffer.split('\n');
+      buffer = lines.pop()!;
+      for (const line of lines) {
+        this.dispatchClientLine(socket, line);
       }
     });
 
+    socket.on('end', () => {
+      this.dispatchClientLine(socket, buffer);
+      buffer = '';
+    });
+
     socket.on('close', () => {
       console.log('Client disconnected');
-      this.clients.delete(socket);
+      this.removeClient(socket);
     });
 
     socket.on('error', (error) => {
       console.error('Client socket error:', error);
-      this.clients.delete(socket);
+      this.removeClient(socket);
     });
   }
 
+  private removeClient(socket: net.Socket): void {
+    clearTimeout(this.clients.get(socket)?.replayTimer);
+    this.clients.delete(socket);
+    for (const [requestId, requester] of this.requesters) {
+      if (requester === socket) {
+        this.requesters.delete(requestId);
+      }
+    }
+  }
+
+  private replayHistory(socket: net.Socket): void {
+    const client = this.clients.get(socket);
+    if (!client || client.replayed) {
+      return;
+    }
+    client.replayed = true;
+    clearTimeout(client.replayTimer);
+    for (const line of this.history.all()) {
+      if (this.isSubscribed(client, topicOf(line))) {
+        client.outbox.push(line);
+      }
+    }
+  }
+
+  private isSubscribed(client: Client, topic: string): boolean {
+    return client.topics.has(ALL_TOPICS) || client.topics.has(topic);
+  }
+
+  /// Send `line` to every client subscribed to its topic, and to the client
+  /// that sent the request it answers
+  private broadcast(line: string, except?: net.Socket): void {
+    let message: TaskSpaceMessage | null = null;
+    try {
+      message = JSON.parse(line);
+    } catch {
+      // Not a protocol message; only clients receiving everything get it
+    }
+    const topic = message ? messageTopic(message) : ALL_TOPICS;
+    const requester = message?.request_id ? this.requesters.get(message.request_id) : undefined;
+    if (requester) {
+      this.requesters.delete(message!.request_id);
+    }
+
+    for (const [socket, client] of this.clients) {
+      if (socket !== except && !socket.destroyed && (socket === requester || this.isSubscribed(client, topic))) {
+        client.outbox.push(line);
+      }
+    }
+  }
+
+  /// Validate one line from a client and forward it to Electron, or reply with a structured error
+  // A message that trips up its handler is answered with an error rather than taking the daemon down
+  private dispatchClientLine(socket: net.Socket, line: string): void {
+    try {
+      this.handleClientLine(socket, line);
+    } catch (error) {
+      console.error('Failed to handle client message:', error);
+      this.send(socket, errorMessage({ code: 'internal_error', message: `could not handle message: ${(error as Error).message}` }));
+    }
+  }
+
+  private handleClientLine(socket: net.Socket, line: string): void {
+    const trimmed = line.trim();
+    if (!trimmed) {
+      return;
+    }
+
+    const parsed = parseClientMessage(trimmed);
+    if ('error' in parsed) {
+      console.error(`Rejected client message (${parsed.error.code}): ${parsed.error.message}`);
+      this.send(socket, errorMessage(parsed.error));
+      return;
+    }
+
+    if (parsed.message.type === 'hello') {
+      const version = negotiateVersion(parsed.message.versions);
+      if (version === null) {
+        this.send(socket, errorMessage({
+          code: 'unsupported_version',
+          message: `no common protocol version (daemon: ${PROTOCOL_VERSIONS.join(', ')}, client: ${parsed.message.versions.join(', ')})`
+        }));
+      } else {
+        this.send(socket, { type: 'hello', v: version, version });
+      }
+      return;
+    }
+
+    const client = this.clients.get(socket);
+    if (!client) {
+      return;
+    }
+
+    if (parsed.message.type === 'subscribe' || parsed.message.type === 'unsubscribe') {
+      const topics: string[] = parsed.message.topics.map(String);
+      if (parsed.message.type === 'subscribe') {
+        // The first subscription replaces the default of everything
+        if (!client.subscribed) {
+          client.topics.clear();
+          client.subscribed = true;
+        }
+        topics.forEach(topic => client.topics.add(topic));
+      } else {
+        topics.forEach(topic => client.topics.delete(topic));
+      }
+      this.send(socket, { type: 'subscribed', v: PROTOCOL_VERSION, topics: Array.from(client.topics) });
+      this.replayHistory(socket);
+      return;
+    }
+
+    // Anything else ends the grace period: the client did not subscribe first
+    this.replayHistory(socket);
+
+    if (this.handleRegistryMessage(parsed.message, 'client', reply => this.send(socket, reply))) {
+      return;
+    }
+
+    if (typeof parsed.message.request_id === 'string') {
+      this.requesters.set(parsed.message.request_id, socket);
+    }
+
+    if (parsed.message.type === 'progress_log') {
+      this.history.record(trimmed, parsed.message.taskspace_uuid);
+      // Progress is also a stream other clients can watch
+      this.broadcast(trimmed, socket);
+    }
+
+    // Forward message to Electron via stdout
+    this.electron.push(trimmed);
+    console.log('Forwarded to Electron:', trimmed);
+  }
+
+  /// Serve `registry_get`, `registry_put` and `registry_delete`, from a client
+  /// or from Electron. Returns false for any other message.
+  ///
+  /// Changes are announced to subscribed clients as `registry_changed`, and to
+  /// Electron as well when a client made them.
+  private handleRegistryMessage(message: TaskSpaceMessage, writer: 'client' | 'electron', reply: (message: TaskSpaceMessage) => void): boolean {
+    const requestId = message.request_id;
+    const respond = (response: TaskSpaceMessage) => reply({
+      v: PROTOCOL_VERSION,
+      ...response,
+      ...(requestId && { request_id: requestId }),
+      timestamp: new Date().toISOString()
+    });
+
+    try {
+      switch (message.type) {
+        case 'registry_get': {
+          const entries = message.uuid ? [this.registry.get(message.uuid)].filter(entry => entry) : this.registry.list();
+          respond({ type: 'registry_state', entries });
+          return true;
+        }
+        case 'registry_put': {
+          const entry = this.registry.put(message.uuid, message.fields, message.expected_revision);
+          respond({ type: 'registry_result', uuid: entry.uuid, revision: entry.revision });
+          this.announceRegistryChange({ uuid: entry.uuid, revision: entry.revision, fields: entry.fields, writer });
+          return true;
+        }
+        case 'registry_delete': {
+          const existed = this.registry.delete(message.uuid, message.expected_revision);
+          respond({ type: 'registry_result', uuid: message.uuid, revision: 0 });
+          if (existed) {
+            this.announceRegistryChange({ uuid: message.uuid, revision: 0, writer });
+          }
+          return true;
+        }
+        default:
+          return false;
+      }
+    } catch (error) {
+      if (!(error instanceof RegistryConflictError)) {
+        throw error;
+      }
+      reply({ ...errorMessage({ code: 'conflict', message: error.message }, requestId), current_revision: error.currentRevision });
+      return true;
+    }
+  }
+
+  private announceRegistryChange(change: { uuid: string; revision: number; fields?: Record<string, any>; writer: string }): void {
+    const line = JSON.stringify({ type: 'registry_changed', v: PROTOCOL_VERSION, ...change, timestamp: new Date().toISOString() });
+    this.broadcast(line);
+    if (change.writer !== 'electron') {
+      this.electron.push(line);
+    }
+  }
+
+  private send(socket: net.Socket, message: TaskSpaceMessage): void {
+    if (!socket.destroyed) {
+      this.clients.get(socket)?.outbox.push(JSON.stringify(message));
+    }
+  }
+
   private setupStdinHandling(): void {
     // Receive messages from Electron via stdin
     this.stdinReader.on('line', (line: string) => {
@@ -63,11 +382,20 @@ class TaskSpaceDaemon {
         const message = line.trim();
         if (message) {
           console.log('Received from Electron:', message);
-          
-          // Broadcast to all connected clients
-          for (const client of this.clients) {
-            client.write(message + '\n');
+
+          // Electron keeps the registry up to date through the same requests clients use
+          const parsed = parseClientMessage(message);
+          if ('message' in parsed && this.handleRegistryMessage(parsed.message, 'electron', reply => {
+            // Electron's writes are unconditional, so only errors are worth sending back
+            if (reply.type === 'error') {
+              this.electron.push(JSON.stringify(reply));
+            }
+          })) {
+            return;
           }
+
+          // Broadcast to the clients subscribed to it
+          this.broadcast(message);
         }
       } catch (error) {
         console.error('Error processing stdin message:', error);
@@ -78,9 +406,12 @@ class TaskSpaceDaemon {
   private setupShutdownHandlers(): void {
     const cleanup = () => {
       console.log('Shutting down daemon...');
+      this.history.save();
+      this.registry.close();
+      this.maintenanceResults?.stop();
       
       // Close all client connections
-      for (const client of this.clients) {
+      for (const client of this.clients.keys()) {
         client.end();
       }
       
@@ -97,7 +428,15 @@ class TaskSpaceDaemon {
 
     process.on('SIGINT', cleanup);
     process.on('SIGTERM', cleanup);
+    process.on('SIGHUP', cleanup);
     process.on('exit', cleanup);
+
+    // Electron's SSH session is gone. Exit rather than linger, so that when it
+    // reconnects it starts a fresh daemon instead of finding this one in the way.
+    this.stdinReader.on('close', () => {
+      console.log('Electron disconnected');
+      cleanup();
+    });
   }
 
   async start(): Promise<void> {
@@ -125,25 +464,34 @@ class TaskSpaceDaemon {
 }
 
 // Parse command line arguments
-function parseArgs(): { socketPath: string } {
+function parseArgs(): { socketPath: string; historyDir: string; registryFile: string; maintenanceResultsFile: string | null } {
   const args = process.argv.slice(2);
   const socketPathIndex = args.indexOf('--socket-path');
+  const historyDirIndex = args.indexOf('--history-dir');
+  const registryFileIndex = args.indexOf('--registry-file');
+  const maintenanceResultsIndex = args.indexOf('--maintenance-results');
   
-  if (socketPathIndex === -1 || socketPathIndex === args.length - 1) {
-    console.error('Usage: daemon --socket-path <path>');
+  if (socketPathIndex === -1 || [socketPathIndex, historyDirIndex, registryFileIndex, maintenanceResultsIndex].includes(args.length - 1)) {
+    console.error('Usage: daemon --socket-path <path> [--history-dir <dir>] [--registry-file <path>] [--maintenance-results <path>]');
     process.exit(1);
   }
   
+  // Progress history and the registry live next to the socket unless told otherwise
+  const socketPath = args[socketPathIndex + 1];
   return {
-    socketPath: args[socketPathIndex + 1]
+    socketPath,
+    historyDir: historyDirIndex === -1 ? path.join(path.dirname(socketPath), 'progress') : args[historyDirIndex + 1],
+    registryFile: registryFileIndex === -1 ? path.join(path.dirname(socketPath), 'registry.json') : args[registryFileIndex + 1],
+    // The maintenance agent is shared by every session on the host, so its results are only followed when asked
+    maintenanceResultsFile: maintenanceResultsIndex === -1 ? null : args[maintenanceResultsIndex + 1]
   };
 }
 
 // Main execution
 async function main(): Promise<void> {
   try {
-    const { socketPath } = parseArgs();
-    const daemon = new TaskSpaceDaemon(socketPath);
+    const { socketPath, historyDir, registryFile, maintenanceResultsFile } = parseArgs();
+    const daemon = new TaskSpaceDaemon(socketPath, historyDir, registryFile, maintenanceResultsFile);
     await daemon.start();
     
     console.log('TaskSpace daemon started successfully');
@@ -5,7 +5,13 @@ import * as os from 'os';
 
 describe('Integration Tests', () => {
   const testSocketPath = '/tmp/integration-test-daemon.sock';
+  const testHistoryDir = '/tmp/integration-test-daemon-progress';
+  const testRegistryDir = '/tmp/integration-test-daemon-registry';
   const bundledDaemonPath = path.join(__dirname, '..', 'dist', 'daemon-bundled.cjs');
+  const daemonArgs = [
+    bundledDaemonPath, '--socket-path', testSocketPath,
+    '--history-dir', testHistoryDir, '--registry-file', path.join(testRegistryDir, 'registry.json')
+  ];
   const bundledCliPath = path.join(__dirname, '..', 'dist', 'theoldswitcheroo-bundled.cjs');
 
   beforeEach(() => {
@@ -13,12 +19,16 @@ describe('Integration Tests', () => {
     if (fs.existsSync(testSocketPath)) {
       fs.unlinkSync(testSocketPath);
     }
+    fs.rmSync(testHistoryDir, { recursive: true, force: true });
+    fs.rmSync(testRegistryDir, { recursive: true, force: true });
   });
 
   afterEach(() => {
     if (fs.existsSync(testSocketPath)) {
       fs.unlinkSync(testSocketPath);
     }
+    fs.rmSync(testHistoryDir, { recursive: true, force: true });
+    fs.rmSync(testRegistryDir, { recursive: true, force: true });
   });
 
   test('complete workflow: daemon startup -> CLI commands -> message flow', async () => {
@@ -26,7 +36,7 @@ describe('Integration Tests', () => {
     
     try {
       // 1. Start daemon
-      daemonProcess = spawn('node', [bundledDaemonPath, '--socket-path', testSocketPath], {
+      daemonProcess = spawn('node', daemonArgs, {
         stdio: ['pipe', 'pipe', 'pipe']
       });
 
@@ -102,7 +112,7 @@ describe('Integration Tests', () => {
     
     try {
       // 1. Start daemon
-      daemonProcess = spawn('node', [bundledDaemonPath, '--socket-path', testSocketPath], {
+      daemonProcess = spawn('node', daemonArgs, {
         stdio: ['pipe', 'pipe', 'pipe']
       });
 
@@ -193,7 +203,7 @@ describe('Integration Tests', () => {
     
     try {
       // Start daemon
-      daemonProcess = spawn('node', [bundledDaemonPath, '--socket-path', testSocketPath], {
+      daemonProcess = spawn('node', daemonArgs, {
         stdio: ['pipe', 'pipe', 'pipe']
       });
 
@@ -0,0 +1,68 @@
+import * as fs from 'fs';
+
+// How often to look for new results; they arrive at most once a minute per job
+const POLL_INTERVAL_MS = 1000;
+
+/// Follows the results file the maintenance agent appends to, and hands each
+/// result it has not handed out before to `onResult`, as its JSON line.
+///
+/// The sequence number of the last one is kept in `seenFile`, so results from
+/// while no daemon was running are passed on when the next one starts, and
+/// none are passed on twice.
+export class MaintenanceResults {
+  private lastSeen = 0;
+
+  constructor(private file: string, private seenFile: string, private onResult: (line: string) => void) {
+    try {
+      this.lastSeen = Number(fs.readFileSync(seenFile, 'utf8')) || 0;
+    } catch {
+      // First start
+    }
+    this.check();
+    // Polling also notices the file being replaced when the agent trims it
+    fs.watchFile(file, { interval: POLL_INTERVAL_MS }, () => this.check());
+  }
+
+  stop(): void {
+    fs.unwatchFile(this.file);
+  }
+
+  private check(): void {
+    let lines: string[];
+    try {
+      lines = fs.readFileSync(this.file, 'utf8').split('\n').filter(line => line);
+    } catch {
+      return;
+    }
+
+    const results = lines.flatMap(line => {
+      try {
+        const result = JSON.parse(line);
+        return typeof result.seq === 'number' ? [{ seq: result.seq as number, line }] : [];
+      } catch {
+        // Being written right now; it is read again on the next change
+        return [];
+      }
+    });
+    if (results.length === 0) {
+      return;
+    }
+
+    // The agent numbers from 1 again after its results were deleted
+    if (results[results.length - 1].seq < this.lastSeen) {
+      this.lastSeen = 0;
+    }
+
+    const fresh = results.filter(result => result.seq > this.lastSeen);
+    if (fresh.length === 0) {
+      return;
+    }
+    fresh.forEach(result => this.onResult(result.line));
+    this.lastSeen = fresh[fresh.length - 1].seq;
+    try {
+      fs.writeFileSync(this.seenFile, String(this.lastSeen));
+    } catch (error) {
+      console.error('Could not record the maintenance results passed on:', error);
+    }
+  }
+}
@@ -0,0 +1,66 @@
+import { spawn } from 'child_process';
+import * as fs from 'fs';
+import * as path from 'path';
+
+describe('Maintenance Agent', () => {
+  const testBaseDir = '/tmp/test-maintenance-base';
+  const maintenanceDir = path.join(testBaseDir, 'maintenance');
+  const bundledAgentPath = path.join(__dirname, '..', 'dist', 'maintenance-bundled.cjs');
+
+  beforeEach(() => {
+    fs.rmSync(testBaseDir, { recursive: true, force: true });
+    fs.mkdirSync(maintenanceDir, { recursive: true });
+  });
+
+  afterEach(() => {
+    fs.rmSync(testBaseDir, { recursive: true, force: true });
+  });
+
+  test('agent runs jobs and records their results', async () => {
+    // An old server log directory, and a current one that must survive
+    const logsDir = path.join(testBaseDir, 'taskspaces', 'taskspace-abc', 'server-data', 'data', 'logs');
+    fs.mkdirSync(path.join(logsDir, 'old'), { recursive: true });
+    fs.mkdirSync(path.join(logsDir, 'new'), { recursive: true });
+    const monthAgo = new Date(Date.now() - 30 * 24 * 60 * 60 * 1000);
+    fs.utimesSync(path.join(logsDir, 'old'), monthAgo, monthAgo);
+
+    fs.writeFileSync(path.join(maintenanceDir, 'jobs.json'), JSON.stringify({
+      jobs: [
+        { name: 'logs', schedule: '@daily', task: 'rotate-logs', keepDays: 7 },
+        { name: 'hello', schedule: '*/5 * * * *', task: 'command', command: 'echo hello from $PWD' },
+        { name: 'broken', schedule: 'every day', task: 'command', command: 'true' }
+      ]
+    }));
+
+    const exitCode = await runAgent(['--dir', maintenanceDir, '--once']);
+    expect(exitCode).toBe(0);
+
+    const results = fs.readFileSync(path.join(maintenanceDir, 'results.jsonl'), 'utf8')
+      .trim().split('\n').map(line => JSON.parse(line));
+    const byJob = Object.fromEntries(results.map(result => [result.job, result]));
+
+    expect(byJob.logs).toMatchObject({ type: 'maintenance_result', task: 'rotate-logs', ok: true, removed: 1 });
+    expect(fs.existsSync(path.join(logsDir, 'old'))).toBe(false);
+    expect(fs.existsSync(path.join(logsDir, 'new'))).toBe(true);
+
+    expect(byJob.hello).toMatchObject({ ok: true, output: `hello from ${testBaseDir}` });
+    expect(byJob.broken.ok).toBe(false);
+    expect(byJob.broken.output).toContain('5 fields');
+
+    // Every result is numbered, so the daemon can tell which it has passed on
+    expect(results.map(result => result.seq).sort()).toEqual([1, 2, 3]);
+  });
+
+  test('agent exits when no jobs are configured', async () => {
+    const exitCode = await runAgent(['--dir', maintenanceDir]);
+    expect(exitCode).toBe(0);
+    expect(fs.existsSync(path.join(maintenanceDir, 'agent.pid'))).toBe(false);
+  });
+
+  function runAgent(args: string[]): Promise<number | null> {
+    return new Promise((resolve) => {
+      const agent = spawn('node', [bundledAgentPath, ...args], { stdio: ['ignore', 'pipe', 'pipe'] });
+      agent.on('exit', (code) => resolve(code));
+    });
+  }
+});
@@ -0,0 +1,373 @@
+#!/usr/bin/env node
+
+// Maintenance agent: runs the scheduled jobs in `<dir>/jobs.json` on the host
+// and appends each result to `<dir>/results.jsonl`, where the daemon picks it
+// up. It is started detached from any SSH session, so jobs keep running while
+// the app is disconnected.
+
+import * as fs from 'fs';
+import * as path from 'path';
+import { exec, execFile } from 'child_process';
+import { Schedule } from './schedule.js';
+
+/// One entry of jobs.json
+interface MaintenanceJob {
+  name: string;
+  schedule: string;
+  task: 'rotate-logs' | 'prune-cache' | 'idle-check' | 'snapshot' | 'command';
+  // rotate-logs: remove server log directories older than this (default 7)
+  keepDays?: number;
+  // prune-cache, snapshot: how many of the newest files to keep (default 3 and 5)
+  keep?: number;
+  // idle-check: report taskspaces without activity for this long (default 120)
+  idleMinutes?: number;
+  // command: shell command to run in the base directory
+  command?: string;
+  // command: seconds before the command is killed (default 600)
+  timeoutSecs?: number;
+}
+
+interface TaskResult {
+  ok: boolean;
+  output: string;
+  [key: string]: any;
+}
+
+// Results kept in results.jsonl; it is trimmed back to this when it reaches twice as many
+const RESULTS_KEPT = 200;
+
+// Longest output recorded per result; the end is kept, since that is where errors are
+const MAX_OUTPUT_CHARS = 4000;
+
+class MaintenanceAgent {
+  private jobsFile: string;
+  private resultsFile: string;
+  private pidFile: string;
+  private jobs: { job: MaintenanceJob; schedule: Schedule }[] = [];
+  private jobsLoadedAt = 0;
+  private running = new Set<string>();
+  private seq = 0;
+
+  constructor(private dir: string, private baseDir: string) {
+    this.jobsFile = path.join(dir, 'jobs.json');
+    this.resultsFile = path.join(dir, 'results.jsonl');
+    this.pidFile = path.join(dir, 'agent.pid');
+    this.seq = this.lastSeq();
+  }
+
+  /// Run every job once, now, regardless of its schedule
+  async runOnce(): Promise<void> {
+    this.loadJobs();
+    await Promise.all(this.jobs.map(({ job }) => this.run(job)));
+  }
+
+  /// Run jobs on their schedules until jobs.json is removed
+  start(): void {
+    if (!this.claimPidFile()) {
+      console.log('Maintenance agent already running');
+      process.exit(0);
+    }
+    const release = () => {
+      fs.rmSync(this.pidFile, { force: true });
+      process.exit(0);
+    };
+    process.on('SIGTERM', release);
+    process.on('SIGINT', release);
+    process.on('SIGHUP', () => {
+      // Outlive the SSH session that started us
+    });
+
+    console.log(`Maintenance agent started (PID ${process.pid})`);
+    this.tick();
+  }
+
+  private tick(): void {
+    if (!fs.existsSync(this.jobsFile)) {
+      console.log('No jobs configured; exiting');
+      fs.rmSync(this.pidFile, { force: true });
+      process.exit(0);
+    }
+    this.loadJobs();
+
+    const now = new Date();
+    for (const { job, schedule } of this.jobs) {
+      if (schedule.matches(now) && !this.running.has(job.name)) {
+        this.run(job).catch((error) => console.error(`Job ${job.name} failed:`, error));
+      }
+    }
+
+    // Wake at the start of the next minute
+    setTimeout(() => this.tick(), 60000 - now.getSeconds() * 1000 - now.getMilliseconds());
+  }
+
+  // Reread jobs.json when it changed, so the app can update jobs without restarting the agent
+  private loadJobs(): void {
+    let mtime: number;
+    try {
+      mtime = fs.statSync(this.jobsFile).mtimeMs;
+    } catch {
+      this.jobs = [];
+      return;
+    }
+    if (mtime === this.jobsLoadedAt) {
+      return;
+    }
+    this.jobsLoadedAt = mtime;
+
+    let jobs: MaintenanceJob[];
+    try {
+      jobs = JSON.parse(fs.readFileSync(this.jobsFile, 'utf8')).jobs || [];
+    } catch (error) {
+      this.record({ name: 'jobs.json', task: 'command' } as MaintenanceJob, new Date(), { ok: false, output: `cannot read jobs: ${(error as Error).message}` });
+      this.jobs = [];
+      return;
+    }
+
+    this.jobs = [];
+    for (const job of jobs) {
+      try {
+        if (!TASKS[job.task]) {
+          throw new Error(`unknown task '${job.task}'`);
+        }
+        this.jobs.push({ job, schedule: new Schedule(job.schedule) });
+      } catch (error) {
+        // Report a bad job once, when it is loaded, rather than on every tick
+        this.record(job, new Date(), { ok: false, output: (error as Error).message });
+      }
+    }
+  }
+
+  private async run(job: MaintenanceJob): Promise<void> {
+    this.running.add(job.name);
+    const started = new Date();
+    let result: TaskResult;
+    try {
+      result = await TASKS[job.task](job, this.baseDir);
+    } catch (error) {
+      result = { ok: false, output: (error as Error).message };
+    } finally {
+      this.running.delete(job.name);
+    }
+    this.record(job, started, result);
+  }
+
+  private record(job: MaintenanceJob, started: Date, result: TaskResult): void {
+    const { ok, output, ...details } = result;
+    const line = JSON.stringify({
+      type: 'maintenance_result',
+      seq: ++this.seq,
+      job: job.name,
+      task: job.task,
+      ok,
+      output: output.length > MAX_OUTPUT_CHARS ? output.slice(-MAX_OUTPUT_CHARS) : output,
+      ...details,
+      started_at: started.toISOString(),
+      duration_ms: Date.now() - started.getTime()
+    });
+    console.log(line);
+
+    fs.mkdirSync(this.dir, { recursive: true });
+    fs.appendFileSync(this.resultsFile, line + '\n');
+    const lines = fs.readFileSync(this.resultsFile, 'utf8').split('\n').filter(l => l);
+    if (lines.length >= RESULTS_KEPT * 2) {
+      const tmp = `${this.resultsFile}.tmp`;
+      fs.writeFileSync(tmp, lines.slice(-RESULTS_KEPT).join('\n') + '\n');
+      fs.renameSync(tmp, this.resultsFile);
+    }
+  }
+
+  // Continue numbering after the results already on disk, so the daemon can tell new ones apart
+  private lastSeq(): number {
+    if (!fs.existsSync(this.resultsFile)) {
+      return 0;
+    }
+    const lines = fs.readFileSync(this.resultsFile, 'utf8').split('\n').filter(l => l);
+    try {
+      return JSON.parse(lines[lines.length - 1]).seq || 0;
+    } catch {
+      return lines.length;
+    }
+  }
+
+  private claimPidFile(): boolean {
+    fs.mkdirSync(this.dir, { recursive: true });
+    if (fs.existsSync(this.pidFile)) {
+      const pid = Number(fs.readFileSync(this.pidFile, 'utf8'));
+      if (pid && pid !== process.pid && isAlive(pid)) {
+        return false;
+      }
+    }
+    fs.writeFileSync(this.pidFile, String(process.pid));
+    return true;
+  }
+}
+
+const TASKS: Record<MaintenanceJob['task'], (job: MaintenanceJob, baseDir: string) => Promise<TaskResult>> = {
+  // The server writes a log directory per start under <server-data>/data/logs
+  'rotate-logs': async (job, baseDir) => {
+    const cutoff = Date.now() - (job.keepDays ?? 7) * 24 * 60 * 60 * 1000;
+    let removed = 0;
+    for (const taskspaceDir of taskspaceDirs(baseDir, 'taskspace-')) {
+      const logsDir = path.join(taskspaceDir, 'server-data', 'data', 'logs');
+      for (const entry of listDir(logsDir)) {
+        const entryPath = path.join(logsDir, entry);
+        if (fs.statSync(entryPath).mtimeMs < cutoff) {
+          fs.rmSync(entryPath, { recursive: true, force: true });
+          removed++;
+        }
+      }
+    }
+    return { ok: true, output: `Removed ${removed} server log directories older than ${job.keepDays ?? 7} days`, removed };
+  },
+
+  'prune-cache': async (job, baseDir) => {
+    const removed = keepNewest(path.join(baseDir, 'cache'), job.keep ?? 3);
+    return { ok: true, output: `Removed ${removed.length} cached server downloads${removed.length ? `: ${removed.join(', ')}` : ''}`, removed: removed.length };
+  },
+
+  // Activity is the clone's git index changing or the server writing logs
+  'idle-check': async (job, baseDir) => {
+    const idleMinutes = job.idleMinutes ?? 120;
+    const cutoff = Date.now() - idleMinutes * 60 * 1000;
+    const idle: string[] = [];
+    for (const dir of taskspaceDirs(baseDir, '')) {
+      const uuid = path.basename(dir);
+      if (uuid.startsWith('taskspace-')) {
+        continue;
+      }
+      const lastActivity = Math.max(
+        newestMtime(path.join(dir, 'clone', '.git', 'index')),
+        newestMtime(path.join(baseDir, 'taskspaces', `taskspace-${uuid}`, 'server-data', 'data', 'logs'))
+      );
+      if (lastActivity < cutoff) {
+        idle.push(uuid);
+      }
+    }
+    return {
+      ok: true,
+      output: idle.length ? `Idle for over ${idleMinutes} minutes: ${idle.join(', ')}` : 'No idle taskspaces',
+      idle
+    };
+  },
+
+  'snapshot': async (job, baseDir) => {
+    const clones = taskspaceDirs(baseDir, '')
+      .map(dir => path.join(dir, 'clone'))
+      .filter(clone => fs.existsSync(clone))
+      .map(clone => path.relative(baseDir, clone));
+    if (clones.length === 0) {
+      return { ok: true, output: 'No taskspace clones to snapshot' };
+    }
+    const snapshotsDir = path.join(baseDir, 'snapshots');
+    fs.mkdirSync(snapshotsDir, { recursive: true });
+    const file = path.join(snapshotsDir, `snapshot-${new Date().toISOString().replace(/[:.]/g, '-')}.tar.gz`);
+    const output = await run((done) => execFile('tar', ['-czf', file, ...clones], { cwd: baseDir }, done));
+    const removed = keepNewest(snapshotsDir, job.keep ?? 5);
+    return { ok: true, output: `${output}Wrote ${file} (${clones.length} clones); removed ${removed.length} older snapshots`.trim(), file };
+  },
+
+  'command': async (job, baseDir) => {
+    if (!job.command) {
+      throw new Error("task 'command' needs a 'command'");
+    }
+    const output = await run((done) => exec(job.command!, { cwd: baseDir, timeout: (job.timeoutSecs ?? 600) * 1000 }, done));
+    return { ok: true, output: output.trim() };
+  },
+};
+
+// Run a child process and resolve with its combined output, or reject with it
+function run(start: (done: (error: Error | null, stdout: string, stderr: string) => void) => void): Promise<string> {
+  return new Promise((resolve, reject) => {
+    start((error, stdout, stderr) => {
+      const output = `${stdout}${stderr}`;
+      if (error) {
+        reject(new Error(`${output}${(error as Error).message}`.trim()));
+      } else {
+        resolve(output);
+      }
+    });
+  });
+}
+
+function listDir(dir: string): string[] {
+  try {
+    return fs.readdirSync(dir);
+  } catch {
+    return [];
+  }
+}
+
+// Directories under <baseDir>/taskspaces whose names start with `prefix`
+function taskspaceDirs(baseDir: string, prefix: string): string[] {
+  const root = path.join(baseDir, 'taskspaces');
+  return listDir(root)
+    .filter(name => name.startsWith(prefix))
+    .map(name => path.join(root, name))
+    .filter(dir => fs.statSync(dir).isDirectory());
+}
+
+// Newest modification time of a file, or of anything in a directory tree; 0 if there is nothing
+function newestMtime(file: string): number {
+  let stat: fs.Stats;
+  try {
+    stat = fs.statSync(file);
+  } catch {
+    return 0;
+  }
+  if (!stat.isDirectory()) {
+    return stat.mtimeMs;
+  }
+  return Math.max(stat.mtimeMs, ...listDir(file).map(entry => newestMtime(path.join(file, entry))));
+}
+
+// Delete all but the `keep` most recently modified files in `dir`; returns the names deleted
+function keepNewest(dir: string, keep: number): string[] {
+  const files = listDir(dir)
+    .map(name => ({ name, mtime: fs.statSync(path.join(dir, name)).mtimeMs }))
+    .sort((a, b) => b.mtime - a.mtime);
+  const removed = files.slice(keep).map(file => file.name);
+  for (const name of removed) {
+    fs.rmSync(path.join(dir, name), { recursive: true, force: true });
+  }
+  return removed;
+}
+
+function isAlive(pid: number): boolean {
+  try {
+    process.kill(pid, 0);
+    return true;
+  } catch (error) {
+    return (error as NodeJS.ErrnoException).code === 'EPERM';
+  }
+}
+
+// Parse command line arguments
+function parseArgs(): { dir: string; baseDir: string; once: boolean } {
+  const args = process.argv.slice(2);
+  const dirIndex = args.indexOf('--dir');
+  const baseDirIndex = args.indexOf('--base-dir');
+
+  if (dirIndex === -1 || [dirIndex, baseDirIndex].includes(args.length - 1)) {
+    console.error('Usage: maintenance --dir <dir> [--base-dir <dir>] [--once]');
+    process.exit(1);
+  }
+
+  // The maintenance directory lives in the base directory unless told otherwise
+  const dir = args[dirIndex + 1];
+  return {
+    dir,
+    baseDir: baseDirIndex === -1 ? path.dirname(dir) : args[baseDirIndex + 1],
+    once: args.includes('--once')
+  };
+}
+
+const { dir, baseDir, once } = parseArgs();
+const agent = new MaintenanceAgent(dir, baseDir);
+if (once) {
+  agent.runOnce().catch((error) => {
+    console.error('Maintenance failed:', error);
+    process.exit(1);
+  });
+} else {
+  agent.start();
+}
@@ -0,0 +1,88 @@
+import * as fs from 'fs';
+import * as path from 'path';
+
+/// Progress messages kept per taskspace; older ones are discarded
+export const HISTORY_PER_TASKSPACE = 200;
+
+// Bucket for progress messages that do not name a taskspace
+const UNASSIGNED = 'unassigned';
+
+// Delay before writing changes, so a burst of progress costs one write per taskspace
+const SAVE_DELAY_MS = 500;
+
+/// A bounded ring buffer of recent progress messages per taskspace, kept on
+/// disk as one JSONL file per taskspace so it outlives the daemon.
+export class ProgressHistory {
+  private entries = new Map<string, string[]>();
+  private dirty = new Set<string>();
+  private saveTimer: NodeJS.Timeout | null = null;
+
+  constructor(private dir: string) {
+    this.load();
+  }
+
+  /// Record a validated `progress_log` message, given as its JSON line
+  record(line: string, taskspaceUuid?: string): void {
+    // Keys come from clients and name files; keep them to safe characters
+    const key = (taskspaceUuid || UNASSIGNED).replace(/[^A-Za-z0-9_-]/g, '_');
+    const lines = this.entries.get(key) || [];
+    lines.push(line);
+    if (lines.length > HISTORY_PER_TASKSPACE) {
+      lines.splice(0, lines.length - HISTORY_PER_TASKSPACE);
+    }
+    this.entries.set(key, lines);
+
+    this.dirty.add(key);
+    if (!this.saveTimer) {
+      this.saveTimer = setTimeout(() => this.save(), SAVE_DELAY_MS);
+    }
+  }
+
+  /// All recorded messages, oldest first within each taskspace
+  all(): string[] {
+    return Array.from(this.entries.values()).flat();
+  }
+
+  /// Write pending changes now; called on shutdown
+  save(): void {
+    if (this.saveTimer) {
+      clearTimeout(this.saveTimer);
+      this.saveTimer = null;
+    }
+    if (this.dirty.size === 0) {
+      return;
+    }
+
+    try {
+      fs.mkdirSync(this.dir, { recursive: true, mode: 0o700 });
+      for (const key of this.dirty) {
+        const file = this.fileFor(key);
+        fs.writeFileSync(`${file}.tmp`, this.entries.get(key)!.join('\n') + '\n', { mode: 0o600 });
+        fs.renameSync(`${file}.tmp`, file);
+      }
+      this.dirty.clear();
+    } catch (error) {
+      console.error('Failed to save progress history:', error);
+    }
+  }
+
+  private load(): void {
+    if (!fs.existsSync(this.dir)) {
+      return;
+    }
+    for (const name of fs.readdirSync(this.dir)) {
+      if (!name.endsWith('.jsonl')) {
+        continue;
+      }
+      const lines = fs.readFileSync(path.join(this.dir, name), 'utf8')
+        .split('\n')
+        .filter(line => line.trim())
+        .slice(-HISTORY_PER_TASKSPACE);
+      this.entries.set(name.slice(0, -'.jsonl'.length), lines);
+    }
+  }
+
+  private fileFor(key: string): string {
+    return path.join(this.dir, `${key}.jsonl`);
+  }
+}
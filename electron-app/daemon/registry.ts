@@ -0,0 +1,197 @@
+import * as fs from 'fs';
+import * as path from 'path';
+
+/// One taskspace as recorded in the registry. `revision` goes up by one on
+/// every change and is what compare-and-swap updates are checked against.
+export interface RegistryEntry {
+  uuid: string;
+  revision: number;
+  fields: Record<string, any>;
+}
+
+interface Snapshot {
+  // Revision of the last journal record folded into this snapshot
+  journalRevision: number;
+  entries: Record<string, RegistryEntry>;
+}
+
+type JournalRecord =
+  | { seq: number; op: 'put'; entry: RegistryEntry }
+  | { seq: number; op: 'delete'; uuid: string };
+
+/// Raised when `expectedRevision` does not match the entry's current revision
+export class RegistryConflictError extends Error {
+  currentRevision: number;
+
+  constructor(uuid: string, expected: number, current: number) {
+    super(`taskspace ${uuid} is at revision ${current}, not ${expected}`);
+    this.name = 'RegistryConflictError';
+    this.currentRevision = current;
+  }
+}
+
+// Journal records written before the snapshot is rewritten and the journal truncated
+const COMPACT_EVERY = 100;
+
+/// The taskspace registry on the host. The daemon is its only writer, so
+/// changes are serialized by construction; a lock file keeps a second daemon
+/// from opening the same registry.
+///
+/// Every change is appended to a journal and fsynced before it is applied, so
+/// a crash loses nothing that was acknowledged. The snapshot is rewritten
+/// atomically (write and rename) every `COMPACT_EVERY` changes.
+export class TaskSpaceRegistry {
+  private entries = new Map<string, RegistryEntry>();
+  private seq = 0;
+  private journalRevision = 0;
+  private journalFd: number;
+  private snapshotPath: string;
+  private journalPath: string;
+  private lockPath: string;
+  private closed = false;
+
+  constructor(file: string) {
+    this.snapshotPath = file;
+    this.journalPath = `${file}.journal`;
+    this.lockPath = `${file}.lock`;
+
+    fs.mkdirSync(path.dirname(file), { recursive: true, mode: 0o700 });
+    this.acquireLock();
+    this.load();
+    this.journalFd = fs.openSync(this.journalPath, 'a', 0o600);
+  }
+
+  list(): RegistryEntry[] {
+    return Array.from(this.entries.values());
+  }
+
+  get(uuid: string): RegistryEntry | undefined {
+    return this.entries.get(uuid);
+  }
+
+  /// Create or update `uuid`, merging `fields` into the existing ones. With
+  /// `expectedRevision`, fail unless the entry is at that revision (0 meaning
+  /// it must not exist yet).
+  put(uuid: string, fields: Record<string, any>, expectedRevision?: number): RegistryEntry {
+    const current = this.entries.get(uuid);
+    this.checkRevision(uuid, current, expectedRevision);
+
+    const entry: RegistryEntry = {
+      uuid,
+      revision: (current?.revision || 0) + 1,
+      fields: { ...current?.fields, ...fields }
+    };
+    this.append({ seq: this.seq + 1, op: 'put', entry });
+    this.entries.set(uuid, entry);
+    return entry;
+  }
+
+  /// Remove `uuid`; with `expectedRevision`, only if it is at that revision.
+  /// Returns false if there was nothing to remove.
+  delete(uuid: string, expectedRevision?: number): boolean {
+    const current = this.entries.get(uuid);
+    this.checkRevision(uuid, current, expectedRevision);
+    if (!current) {
+      return false;
+    }
+
+    this.append({ seq: this.seq + 1, op: 'delete', uuid });
+    this.entries.delete(uuid);
+    return true;
+  }
+
+  /// Write the snapshot, truncate the journal and release the lock
+  close(): void {
+    if (this.closed) {
+      return;
+    }
+    this.closed = true;
+    this.compact();
+    fs.closeSync(this.journalFd);
+    fs.rmSync(this.lockPath, { force: true });
+  }
+
+  private checkRevision(uuid: string, current: RegistryEntry | undefined, expected: number | undefined): void {
+    const actual = current?.revision || 0;
+    if (expected !== undefined && expected !== actual) {
+      throw new RegistryConflictError(uuid, expected, actual);
+    }
+  }
+
+  private append(record: JournalRecord): void {
+    fs.writeSync(this.journalFd, JSON.stringify(record) + '\n');
+    fs.fsyncSync(this.journalFd);
+    this.seq = record.seq;
+
+    if (this.seq - this.journalRevision >= COMPACT_EVERY) {
+      this.compact();
+    }
+  }
+
+  private compact(): void {
+    const snapshot: Snapshot = { journalRevision: this.seq, entries: Object.fromEntries(this.entries) };
+    const tmp = `${this.snapshotPath}.tmp`;
+    fs.writeFileSync(tmp, JSON.stringify(snapshot, null, 2), { mode: 0o600 });
+    fs.renameSync(tmp, this.snapshotPath);
+    // Records up to `seq` are in the snapshot; a crash before this truncate only means replaying them again
+    fs.ftruncateSync(this.journalFd, 0);
+    this.journalRevision = this.seq;
+  }
+
+  private load(): void {
+    if (fs.existsSync(this.snapshotPath)) {
+      const snapshot: Snapshot = JSON.parse(fs.readFileSync(this.snapshotPath, 'utf8'));
+      this.entries = new Map(Object.entries(snapshot.entries));
+      this.seq = this.journalRevision = snapshot.journalRevision;
+    }
+
+    if (!fs.existsSync(this.journalPath)) {
+      return;
+    }
+    for (const line of fs.readFileSync(this.journalPath, 'utf8').split('\n')) {
+      let record: JournalRecord;
+      try {
+        record = JSON.parse(line);
+      } catch {
+        // Empty, or a record torn by a crash mid-write; it was never acknowledged
+        continue;
+      }
+      if (record.seq <= this.seq) {
+        continue;
+      }
+      if (record.op === 'put') {
+        this.entries.set(record.entry.uuid, record.entry);
+      } else {
+        this.entries.delete(record.uuid);
+      }
+      this.seq = record.seq;
+    }
+  }
+
+  private acquireLock(): void {
+    try {
+      fs.writeFileSync(this.lockPath, String(process.pid), { flag: 'wx', mode: 0o600 });
+      return;
+    } catch (error) {
+      if ((error as NodeJS.ErrnoException).code !== 'EEXIST') {
+        throw error;
+      }
+    }
+
+    // Take over the lock only if the daemon holding it is gone
+    const holder = Number(fs.readFileSync(this.lockPath, 'utf8'));
+    if (holder && holder !== process.pid && isAlive(holder)) {
+      throw new Error(`Taskspace registry ${this.snapshotPath} is locked by process ${holder}`);
+    }
+    fs.writeFileSync(this.lockPath, String(process.pid), { mode: 0o600 });
+  }
+}
+
+function isAlive(pid: number): boolean {
+  try {
+    process.kill(pid, 0);
+    return true;
+  } catch (error) {
+    return (error as NodeJS.ErrnoException).code === 'EPERM';
+  }
+}
@@ -0,0 +1,63 @@
+/// A cron schedule: five fields (minute, hour, day of month, month, day of
+/// week), each `*`, a number, a range `a-b`, a step `*/n` or `a-b/n`, or a
+/// comma-separated list of those. `@hourly`, `@daily` and `@weekly` are
+/// shorthands. Times are the host's local time.
+export class Schedule {
+  private fields: Set<number>[];
+  // Like cron: when both day fields are restricted, either one matching is enough
+  private anyDay: boolean;
+
+  constructor(readonly expression: string) {
+    const expanded = SHORTHANDS[expression.trim()] || expression.trim();
+    const parts = expanded.split(/\s+/);
+    if (parts.length !== 5) {
+      throw new Error(`schedule '${expression}' must have 5 fields (minute hour day-of-month month day-of-week)`);
+    }
+    this.fields = parts.map((part, index) => parseField(part, FIELD_RANGES[index], expression));
+    // Sunday may be written as 7
+    if (this.fields[4].has(7)) {
+      this.fields[4].add(0);
+    }
+    this.anyDay = parts[2] !== '*' && parts[4] !== '*';
+  }
+
+  /// Whether the schedule fires in the minute containing `date`
+  matches(date: Date): boolean {
+    const [minutes, hours, days, months, weekdays] = this.fields;
+    if (!minutes.has(date.getMinutes()) || !hours.has(date.getHours()) || !months.has(date.getMonth() + 1)) {
+      return false;
+    }
+    const dayMatch = days.has(date.getDate());
+    const weekdayMatch = weekdays.has(date.getDay());
+    return this.anyDay ? dayMatch || weekdayMatch : dayMatch && weekdayMatch;
+  }
+}
+
+const SHORTHANDS: Record<string, string> = {
+  '@hourly': '0 * * * *',
+  '@daily': '0 0 * * *',
+  '@weekly': '0 0 * * 0',
+};
+
+const FIELD_RANGES: [number, number][] = [[0, 59], [0, 23], [1, 31], [1, 12], [0, 7]];
+
+function parseField(field: string, [min, max]: [number, number], expression: string): Set<number> {
+  const values = new Set<number>();
+  for (const item of field.split(',')) {
+    const match = /^(\*|(\d+)(?:-(\d+))?)(?:\/(\d+))?$/.exec(item);
+    if (!match) {
+      throw new Error(`schedule '${expression}': cannot parse '${item}'`);
+    }
+    const start = match[1] === '*' ? min : Number(match[2]);
+    // A step after a single number runs to the end of the range, as in cron
+    const end = match[1] === '*' ? max : match[3] !== undefined ? Number(match[3]) : match[4] ? max : start;
+    const step = match[4] ? Number(match[4]) : 1;
+    if (start < min || end > max || start > end || step < 1) {
+      throw new Error(`schedule '${expression}': '${item}' is outside ${min}-${max}`);
+    }
+    for (let value = start; value <= end; value += step) {
+      values.add(value);
+    }
+  }
+  return values;
+}
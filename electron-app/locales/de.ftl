@@ -0,0 +1,119 @@
+# German translations of the CLI messages in en.ftl
+
+## Startup
+
+no-hostname = Kein Hostname angegeben
+no-hostname-configured = In den Einstellungen ist kein Hostname konfiguriert.
+no-hostname-configured-hint = Lege { $file } mit { "{" }"hostname": "dein-host"{ "}" } an
+no-last-good = Für { $host } ist keine zuletzt funktionierende Konfiguration gespeichert
+invalid-notifications = Ungültige Einstellung notifications: { $reason }
+needs-electron = Dieses Skript muss mit Electron oder mit einem der Befehle { $commands } gestartet werden
+
+## Command errors; the reason follows on the same line
+
+failed-clean = ✗ Aufräumen auf { $host } fehlgeschlagen:
+failed-prereqs = ✗ Installation der Voraussetzungen auf { $host } fehlgeschlagen:
+failed-prime-cache = ✗ Befüllen des Server-Caches auf { $host } fehlgeschlagen:
+failed-inspect = ✗ Untersuchen von { $host } fehlgeschlagen:
+failed-audit = ✗ Audit fehlgeschlagen:
+failed-status = ✗ Statusabfrage fehlgeschlagen:
+failed-stop = ✗ Beenden der Sitzung fehlgeschlagen:
+failed-logs = ✗ Lesen der Logs fehlgeschlagen:
+failed-prewarm = ✗ Vorwärmen fehlgeschlagen:
+failed-daemon-proxy = ✗ Weiterleiten des Daemon-Sockets von { $host } fehlgeschlagen:
+failed-sessions = ✗ Auflisten der Sitzungen fehlgeschlagen:
+failed-handoff-export = ✗ Exportieren der Sitzung fehlgeschlagen:
+failed-handoff-accept = ✗ Übernehmen der Sitzung fehlgeschlagen:
+failed-import = ✗ Import aus VS Code Remote-SSH fehlgeschlagen:
+failed-shell = ✗ Öffnen einer Shell fehlgeschlagen:
+failed-ephemeral = ✗ Starten einer kurzlebigen Sitzung fehlgeschlagen:
+failed-wait = ✗ Warten auf die Sitzung fehlgeschlagen:
+failed-mount = ✗ Einhängen des Taskspace fehlgeschlagen:
+mount-usage = ✗ Verwende --mount <Verzeichnis> [Taskspace]
+failed-open-app = ✗ Öffnen des Taskspace in einem Browserfenster fehlgeschlagen:
+failed-run = ✗ Ausführen des Alias fehlgeschlagen:
+run-usage = ✗ Verwende --run <Alias> [Taskspace]
+failed-ps = ✗ Auflisten der Serverprozesse fehlgeschlagen:
+failed-kill = ✗ Beenden des Prozesses fehlgeschlagen:
+kill-usage = ✗ Verwende --kill <PID> [--signal <Name>]
+wait-usage = ✗ Verwende --wait [Taskspace] [--timeout <Sekunden>]
+wait-timed-out = ✗ Die Sitzung war nach { $secs } Sekunden nicht bereit
+failed-git-status = ✗ Abfragen des Git-Status fehlgeschlagen:
+failed-taskspace = ✗ Verwalten der Taskspaces fehlgeschlagen:
+failed-reconcile = ✗ Abgleichen fehlgeschlagen:
+no-hosts-to-audit = ✗ Keine Hosts für das Audit; nenne sie (--audit build1,build2) oder setze hostname oder hostGroups
+app-not-running = ✗ Die App läuft nicht; starte sie (z. B. mit --detach) und versuche es erneut
+taskspace-usage = ✗ Verwende --taskspace list, --taskspace create [Name] oder --taskspace delete <Name> [--purge]
+
+## Prompts
+
+answer-yes = j, ja, y, yes
+offer-session = Jetzt einen verwalteten Server für { $profile } in einer Hintergrundsitzung einrichten? [j/N]
+offer-session-start = Starten mit: { $command }
+offer-session-later = Später: { $command }
+
+## Host capabilities (--inspect)
+
+caps-title = Fähigkeiten von { $host }:
+caps-arch = Architektur
+caps-os = Betriebssystem
+caps-kernel = Kernel
+caps-libc = libc
+caps-container-runtimes = Container-Laufzeiten
+caps-systemd-user = systemd --user
+caps-tmux = tmux
+caps-gpus = GPUs
+caps-github = Erreicht GitHub
+caps-yes = ja
+caps-no = nein
+caps-unknown = unbekannt
+caps-none = keine
+caps-not-installed = nicht installiert
+
+## Progress
+
+priming-cache = Fülle den Server-Cache auf { $host } für { $versions } auf { $arches }...
+primed-cache = ✓ Server-Cache auf { $host } gefüllt
+opening-shell = Öffne eine Shell in { $taskspace } auf { $host } (exit kehrt zurück)...
+
+## Session status (--status)
+
+status-title = Status der Sitzung auf { $host }:
+status-title-named = Status der Sitzung { $session } auf { $host }:
+status-port-forwards = Portweiterleitungen:
+status-app = App
+status-app-running = läuft (PID { $pid })
+status-app-running-detached = läuft im Hintergrund (PID { $pid })
+status-app-detached = abgekoppelt um { $time }, Server laufen weiter; starte die App, um wieder anzukoppeln
+status-app-not-running = läuft nicht
+status-session = Sitzung
+status-session-missing = keine gespeicherte Sitzung in { $file }
+status-session-unreadable = { $file } ist nicht lesbar: { $reason }
+status-session-taskspaces = { $count } Taskspaces auf { $host }
+status-session-no-host = { $file } nennt keinen Host
+status-ssh = SSH
+status-ssh-connected = mit { $host } verbunden
+status-servers = Server
+status-drift-check = Abweichung
+status-server = Server von { $taskspace }
+status-server-running = PID { $pid } läuft
+status-server-stopped = PID { $pid } läuft nicht
+status-server-no-pid = keine PID gespeichert (Server nie gestartet oder von einer älteren Version gestartet)
+status-forward = Weiterleitung von { $taskspace }
+status-forward-accepting = localhost:{ $port } nimmt Verbindungen an
+status-forward-refused = auf localhost:{ $port } nimmt nichts Verbindungen an
+status-drift = Abweichung von { $taskspace }
+status-drift-no-fingerprint = kein Fingerabdruck gespeichert (von einer älteren Version eingerichtet)
+status-drift-changed = { $changes } seit { $time }; --reconcile stellt die deklarierte Konfiguration wieder her
+status-drift-none = entspricht der Einrichtung vom { $time }
+status-skipped = übersprungen (keine SSH-Verbindung)
+status-unknown = unbekannt
+
+## Sessions (--sessions)
+
+sessions-none = Keine Sitzungen
+sessions-row = { $session }: { $host }, { $count } Taskspaces, { $state }
+sessions-no-host = kein Host
+sessions-running = läuft (PID { $pid })
+sessions-running-detached = läuft im Hintergrund (PID { $pid })
+sessions-stopped = gestoppt
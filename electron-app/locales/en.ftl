@@ -0,0 +1,122 @@
+# User-facing CLI messages, in Fluent syntax (https://projectfluent.org).
+# Every message lives here; other locales translate any subset of them and
+# fall back to these. See src/i18n.ts for the part of Fluent understood.
+
+## Startup
+
+no-hostname = No hostname provided
+no-hostname-configured = No hostname configured in settings.
+no-hostname-configured-hint = Create { $file } with { "{" }"hostname": "your-host"{ "}" }
+no-last-good = No last known good configuration saved for { $host }
+invalid-notifications = Invalid notifications setting: { $reason }
+needs-electron = This script must be run with Electron, or with one of the commands { $commands }
+
+## Command errors; the reason follows on the same line
+
+failed-clean = ✗ Failed to clean from { $host }:
+failed-prereqs = ✗ Failed to install prerequisites on { $host }:
+failed-prime-cache = ✗ Failed to prime server cache on { $host }:
+failed-inspect = ✗ Failed to inspect { $host }:
+failed-audit = ✗ Failed to audit:
+failed-status = ✗ Failed to check status:
+failed-stop = ✗ Failed to stop the session:
+failed-logs = ✗ Failed to read the logs:
+failed-prewarm = ✗ Failed to pre-warm:
+failed-daemon-proxy = ✗ Failed to forward the daemon socket of { $host }:
+failed-sessions = ✗ Failed to list sessions:
+failed-handoff-export = ✗ Failed to export the session:
+failed-handoff-accept = ✗ Failed to accept the session:
+failed-import = ✗ Failed to import from VS Code Remote-SSH:
+failed-shell = ✗ Failed to open a shell:
+failed-ephemeral = ✗ Failed to start an ephemeral session:
+failed-wait = ✗ Failed to wait for the session:
+failed-mount = ✗ Failed to mount the taskspace:
+mount-usage = ✗ Use --mount <directory> [taskspace]
+failed-open-app = ✗ Failed to open the taskspace in a browser window:
+failed-run = ✗ Failed to run the alias:
+run-usage = ✗ Use --run <alias> [taskspace]
+failed-ps = ✗ Failed to list the server processes:
+failed-kill = ✗ Failed to kill the process:
+kill-usage = ✗ Use --kill <pid> [--signal <name>]
+wait-usage = ✗ Use --wait [taskspace] [--timeout <seconds>]
+wait-timed-out = ✗ The session was not ready within { $secs } seconds
+failed-git-status = ✗ Failed to collect git status:
+failed-taskspace = ✗ Failed to manage taskspaces:
+failed-reconcile = ✗ Failed to reconcile:
+no-hosts-to-audit = ✗ No hosts to audit; name them (--audit build1,build2) or set hostname or hostGroups
+app-not-running = ✗ The app is not running; start it (e.g. with --detach) and try again
+taskspace-usage = ✗ Use --taskspace list, --taskspace create [name] or --taskspace delete <name> [--purge]
+
+## Prompts
+
+# Answers that mean yes, comma-separated
+answer-yes = y, yes
+offer-session = Set up a managed server for { $profile } now, in a background session? [y/N]
+offer-session-start = Start it with: { $command }
+offer-session-later = Later: { $command }
+
+## Host capabilities (--inspect)
+
+caps-title = Capabilities of { $host }:
+caps-arch = Architecture
+caps-os = OS
+caps-kernel = Kernel
+caps-libc = libc
+caps-container-runtimes = Container runtimes
+caps-systemd-user = systemd --user
+caps-tmux = tmux
+caps-gpus = GPUs
+caps-github = Reaches GitHub
+caps-yes = yes
+caps-no = no
+caps-unknown = unknown
+caps-none = none
+caps-not-installed = not installed
+
+## Progress
+
+priming-cache = Priming server cache on { $host } for { $versions } on { $arches }...
+primed-cache = ✓ Primed server cache on { $host }
+opening-shell = Opening a shell in { $taskspace } on { $host } (exit to return)...
+
+## Session status (--status)
+
+status-title = Status of the session on { $host }:
+status-title-named = Status of the session { $session } on { $host }:
+status-port-forwards = Port forwards:
+status-app = App
+status-app-running = running (PID { $pid })
+status-app-running-detached = running in the background (PID { $pid })
+status-app-detached = detached at { $time }, servers left running; start the app to reattach
+status-app-not-running = not running
+status-session = Session
+status-session-missing = no saved session at { $file }
+status-session-unreadable = cannot read { $file }: { $reason }
+status-session-taskspaces = { $count } taskspaces on { $host }
+status-session-no-host = { $file } names no host
+status-ssh = SSH
+status-ssh-connected = connected to { $host }
+status-servers = Servers
+status-drift-check = Drift
+status-server = { $taskspace } server
+status-server-running = PID { $pid } running
+status-server-stopped = PID { $pid } is not running
+status-server-no-pid = no PID recorded (server never started, or started by an older version)
+status-forward = { $taskspace } forward
+status-forward-accepting = localhost:{ $port } accepts connections
+status-forward-refused = nothing accepts connections on localhost:{ $port }
+status-drift = { $taskspace } drift
+status-drift-no-fingerprint = no fingerprint recorded (set up by an older version)
+status-drift-changed = { $changes } since { $time }; --reconcile restores the declared configuration
+status-drift-none = matches the setup of { $time }
+status-skipped = skipped (no SSH connection)
+status-unknown = unknown
+
+## Sessions (--sessions)
+
+sessions-none = No sessions
+sessions-row = { $session }: { $host }, { $count } taskspaces, { $state }
+sessions-no-host = no host
+sessions-running = running (PID { $pid })
+sessions-running-detached = running in the background (PID { $pid })
+sessions-stopped = stopped
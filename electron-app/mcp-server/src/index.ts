@@ -4,13 +4,26 @@ import { Server } from '@modelcontextprotocol/sdk/server/index.js';
 import { StdioServerTransport } from '@modelcontextprotocol/sdk/server/stdio.js';
 import {
   CallToolRequestSchema,
+  ListResourcesRequestSchema,
+  ListResourceTemplatesRequestSchema,
   ListToolsRequestSchema,
+  ReadResourceRequestSchema,
 } from '@modelcontextprotocol/sdk/types.js';
+import { randomUUID } from 'crypto';
+import { spawn } from 'child_process';
 import * as net from 'net';
 import * as fs from 'fs';
 import * as path from 'path';
 import * as os from 'os';
 
+// Limits for execute_in_taskspace: commands are bounded in time and in captured output
+const DEFAULT_EXEC_TIMEOUT_SECS = 60;
+const MAX_EXEC_TIMEOUT_SECS = 600;
+const MAX_CAPTURED_BYTES = 64 * 1024;
+// How long output is still collected after the command exits, in case a
+// child it left in the background keeps its stdout or stderr open
+const EXEC_DRAIN_MS = 200;
+
 interface TaskSpaceMessage {
   type: string;
   timestamp: string;
@@ -21,6 +34,7 @@ class TaskSpaceMCPServer {
   private server: Server;
   private socketPath: string;
   private taskspaceUuid: string | null;
+  private baseDir: string;
 
   constructor() {
     this.server = new Server(
@@ -31,6 +45,7 @@ class TaskSpaceMCPServer {
       {
         capabilities: {
           tools: {},
+          resources: {},
         },
       }
     );
@@ -38,11 +53,13 @@ class TaskSpaceMCPServer {
     // Default socket path - matches daemon configuration
     const baseDir = process.env.BASE_DIR || path.join(os.homedir(), '.socratic-shell', 'theoldswitcheroo');
     this.socketPath = process.env.THEOLDSWITCHEROO_SOCKET || path.join(baseDir, 'daemon.sock');
+    this.baseDir = baseDir;
 
     // Extract UUID from current working directory
     this.taskspaceUuid = this.extractUuidFromPath(process.cwd());
 
     this.setupToolHandlers();
+    this.setupResourceHandlers();
   }
 
   private extractUuidFromPath(cwd: string): string | null {
@@ -105,6 +122,28 @@ class TaskSpaceMCPServer {
               required: ['message', 'category'],
             },
           },
+          {
+            name: 'execute_in_taskspace',
+            description: 'Run a shell command in a taskspace\'s project checkout (e.g. tests or a build) and return its stdout, stderr and exit code. The command is killed if it exceeds the timeout, and output is truncated to 64 KiB per stream.',
+            inputSchema: {
+              type: 'object',
+              properties: {
+                taskspace: {
+                  type: 'string',
+                  description: 'Name or uuid of the taskspace (default: the current one)',
+                },
+                command: {
+                  type: 'string',
+                  description: 'Shell command to run, e.g. "cargo test"',
+                },
+                timeout_secs: {
+                  type: 'number',
+                  description: `Seconds before the command is killed (default ${DEFAULT_EXEC_TIMEOUT_SECS}, max ${MAX_EXEC_TIMEOUT_SECS})`,
+                },
+              },
+              required: ['command'],
+            },
+          },
           {
             name: 'signal_user',
             description: 'Signal the user for help or attention',
@@ -160,6 +199,13 @@ class TaskSpaceMCPServer {
               message: string;
             });
 
+          case 'execute_in_taskspace':
+            return await this.handleExecuteInTaskSpace(args as {
+              taskspace?: string;
+              command: string;
+              timeout_secs?: number;
+            });
+
           default:
             throw new Error(`Unknown tool: ${name}`);
         }
@@ -177,6 +223,82 @@ class TaskSpaceMCPServer {
     });
   }
 
+  /// Read-only views of the current environment, so an agent can look before it spawns or changes taskspaces.
+  private setupResourceHandlers(): void {
+    this.server.setRequestHandler(ListResourcesRequestSchema, async () => {
+      const status = await this.requestStatus();
+      return {
+        resources: [
+          {
+            uri: 'taskspace://sessions',
+            name: 'Taskspaces',
+            description: 'All taskspaces with their status, port, notes and labels',
+            mimeType: 'application/json',
+          },
+          ...status.taskspaces.flatMap((taskspace: any) => [
+            {
+              uri: `taskspace://sessions/${taskspace.uuid}/status`,
+              name: `${taskspace.name} status`,
+              mimeType: 'application/json',
+            },
+            {
+              uri: `taskspace://sessions/${taskspace.uuid}/logs`,
+              name: `${taskspace.name} server log`,
+              mimeType: 'text/plain',
+            },
+          ]),
+        ],
+      };
+    });
+
+    this.server.setRequestHandler(ListResourceTemplatesRequestSchema, async () => ({
+      resourceTemplates: [
+        {
+          uriTemplate: 'taskspace://sessions/{uuid}/status',
+          name: 'Taskspace status',
+          mimeType: 'application/json',
+        },
+        {
+          uriTemplate: 'taskspace://sessions/{uuid}/logs',
+          name: 'Recent VSCode server log lines of a taskspace',
+          mimeType: 'text/plain',
+        },
+      ],
+    }));
+
+    this.server.setRequestHandler(ReadResourceRequestSchema, async (request) => {
+      const uri = request.params.uri;
+
+      if (uri === 'taskspace://sessions') {
+        const status = await this.requestStatus();
+        return { contents: [{ uri, mimeType: 'application/json', text: JSON.stringify(status, null, 2) }] };
+      }
+
+      const match = /^taskspace:\/\/sessions\/([0-9a-f-]+)\/(status|logs)$/i.exec(uri);
+      if (!match) {
+        throw new Error(`Unknown resource: ${uri}`);
+      }
+      const [, uuid, kind] = match;
+
+      if (kind === 'status') {
+        const status = await this.requestStatus();
+        const taskspace = status.taskspaces.find((t: any) => t.uuid === uuid);
+        if (!taskspace) {
+          throw new Error(`No taskspace ${uuid}`);
+        }
+        return { contents: [{ uri, mimeType: 'application/json', text: JSON.stringify(taskspace, null, 2) }] };
+      }
+
+      const logs = await this.request({ type: 'logs_request', uuid, lines: 200, timestamp: new Date().toISOString() }, 'logs_response');
+      return { contents: [{ uri, mimeType: 'text/plain', text: (logs.lines || []).join('\n') }] };
+    });
+  }
+
+  private async requestStatus(): Promise<{ taskspaces: any[]; activeTaskSpace?: string }> {
+    const status = await this.request({ type: 'status_request', timestamp: new Date().toISOString() }, 'status_response');
+    return { taskspaces: status.taskspaces || [], activeTaskSpace: status.activeTaskSpace };
+  }
+
   private async handleNewTaskSpace(args: {
     name: string;
     short_description: string;
@@ -237,6 +359,39 @@ class TaskSpaceMCPServer {
     };
   }
 
+  private async handleExecuteInTaskSpace(args: { taskspace?: string; command: string; timeout_secs?: number }) {
+    let uuid = this.taskspaceUuid;
+    if (args.taskspace && args.taskspace !== uuid) {
+      const status = await this.requestStatus();
+      const taskspace = status.taskspaces.find((t: any) => t.uuid === args.taskspace || t.name === args.taskspace);
+      if (!taskspace) {
+        throw new Error(`No taskspace named ${args.taskspace}`);
+      }
+      uuid = taskspace.uuid;
+    }
+    if (!uuid) {
+      throw new Error('No current taskspace: this MCP server runs outside one, so pass `taskspace` to name the taskspace to run in');
+    }
+
+    const cwd = path.join(this.baseDir, 'taskspaces', uuid, 'clone');
+    if (!fs.existsSync(cwd)) {
+      throw new Error(`Taskspace ${uuid} has no checkout at ${cwd} on this host`);
+    }
+
+    const timeoutSecs = Math.min(Math.max(args.timeout_secs || DEFAULT_EXEC_TIMEOUT_SECS, 1), MAX_EXEC_TIMEOUT_SECS);
+    const result = await runBounded(args.command, cwd, timeoutSecs);
+
+    return {
+      content: [
+        {
+          type: 'text',
+          text: JSON.stringify({ taskspace: uuid, command: args.command, ...result }, null, 2),
+        },
+      ],
+      isError: result.exit_code !== 0,
+    };
+  }
+
   private async handleSignalUser(args: { message: string }) {
     const message: TaskSpaceMessage = {
       type: 'user_signal',
@@ -299,6 +454,58 @@ class TaskSpaceMCPServer {
     });
   }
 
+  /// Send `message` and wait for the app's reply of type `responseType`.
+  ///
+  /// The request carries a `request_id` that the app echoes back. The daemon
+  /// routes the reply to us by it, so we subscribe to no topics and skip the
+  /// rest of the traffic.
+  private async request(message: TaskSpaceMessage, responseType: string): Promise<TaskSpaceMessage> {
+    return new Promise((resolve, reject) => {
+      if (!fs.existsSync(this.socketPath)) {
+        reject(new Error('No active theoldswitcheroo instance found. Is the daemon running?'));
+        return;
+      }
+
+      const requestId = randomUUID();
+      const client = net.createConnection(this.socketPath);
+      let buffer = '';
+
+      const timeout = setTimeout(() => {
+        client.destroy();
+        reject(new Error('Timeout waiting for daemon response'));
+      }, 5000);
+
+      client.on('connect', () => {
+        client.write(JSON.stringify({ type: 'subscribe', topics: [] }) + '\n');
+        client.write(JSON.stringify({ ...message, request_id: requestId }) + '\n');
+      });
+
+      client.on('data', (data) => {
+        buffer += data.toString();
+        const lines = buffer.split('\n');
+        buffer = lines.pop()!;
+        for (const line of lines) {
+          try {
+            const reply = JSON.parse(line) as TaskSpaceMessage;
+            if (reply.type === responseType && reply.request_id === requestId) {
+              clearTimeout(timeout);
+              client.end();
+              resolve(reply);
+              return;
+            }
+          } catch (error) {
+            // Not a protocol message; ignore
+          }
+        }
+      });
+
+      client.on('error', (error) => {
+        clearTimeout(timeout);
+        reject(new Error(`Failed to connect to daemon: ${error.message}`));
+      });
+    });
+  }
+
   async run(): Promise<void> {
     const transport = new StdioServerTransport();
     await this.server.connect(transport);
@@ -306,6 +513,79 @@ class TaskSpaceMCPServer {
   }
 }
 
+interface BoundedResult {
+  exit_code: number | null;
+  signal: string | null;
+  timed_out: boolean;
+  stdout: string;
+  stderr: string;
+  stdout_truncated: boolean;
+  stderr_truncated: boolean;
+}
+
+/// Run `command` in `cwd`, killing its whole process group after `timeoutSecs`
+/// and keeping at most MAX_CAPTURED_BYTES of each output stream. The result
+/// is in once the command exits and its output has drained, or EXEC_DRAIN_MS
+/// later if something it started in the background holds the output open.
+function runBounded(command: string, cwd: string, timeoutSecs: number): Promise<BoundedResult> {
+  return new Promise((resolve, reject) => {
+    const child = spawn('sh', ['-c', command], { cwd, detached: true, stdio: ['ignore', 'pipe', 'pipe'] });
+    const captured = { stdout: '', stderr: '', stdout_truncated: false, stderr_truncated: false };
+    let timedOut = false;
+
+    const capture = (stream: 'stdout' | 'stderr') => (data: Buffer) => {
+      const room = MAX_CAPTURED_BYTES - Buffer.byteLength(captured[stream]);
+      if (data.length > room) {
+        captured[`${stream}_truncated`] = true;
+      }
+      if (room > 0) {
+        captured[stream] += data.subarray(0, room).toString();
+      }
+    };
+    child.stdout!.on('data', capture('stdout'));
+    child.stderr!.on('data', capture('stderr'));
+
+    const timer = setTimeout(() => {
+      timedOut = true;
+      try {
+        process.kill(-child.pid!, 'SIGKILL');
+      } catch (error) {
+        // Already gone
+      }
+    }, timeoutSecs * 1000);
+
+    let exited: { code: number | null; signal: string | null } | null = null;
+    let drainTimer: NodeJS.Timeout | undefined;
+    let finished = false;
+    const finish = () => {
+      if (finished) {
+        return;
+      }
+      finished = true;
+      clearTimeout(drainTimer);
+      child.stdout!.destroy();
+      child.stderr!.destroy();
+      resolve({ exit_code: exited!.code, signal: exited!.signal, timed_out: timedOut, ...captured });
+    };
+
+    child.on('error', (error) => {
+      clearTimeout(timer);
+      reject(error);
+    });
+    child.on('exit', (code, signal) => {
+      clearTimeout(timer);
+      exited = { code, signal };
+      drainTimer = setTimeout(finish, EXEC_DRAIN_MS);
+    });
+    // Both streams are drained: all output is in
+    child.on('close', () => {
+      if (exited) {
+        finish();
+      }
+    });
+  });
+}
+
 // Main execution
 async function main() {
   const server = new TaskSpaceMCPServer();
@@ -0,0 +1,73 @@
+import { spawn } from 'child_process';
+import * as fs from 'fs';
+import * as path from 'path';
+import { SESSION_DIR, SESSION_NAME, loadSettings } from './settings.js';
+
+/// Opening a taskspace in a browser window of its own (`--open-app`): a
+/// Chromium-family browser in app mode, without tabs or address bar, so each
+/// taskspace is a window of its own in the task switcher. The browser runs
+/// with a profile kept in the session directory, which keeps the session's
+/// windows, their sizes and trusted certificates apart from everyday browsing.
+
+// Tried in order when `appBrowser` is not set
+const BROWSER_CANDIDATES: Record<string, string[]> = {
+  linux: ['google-chrome', 'google-chrome-stable', 'chromium', 'chromium-browser', 'microsoft-edge', 'brave-browser'],
+  darwin: [
+    '/Applications/Google Chrome.app/Contents/MacOS/Google Chrome',
+    '/Applications/Chromium.app/Contents/MacOS/Chromium',
+    '/Applications/Microsoft Edge.app/Contents/MacOS/Microsoft Edge',
+    '/Applications/Brave Browser.app/Contents/MacOS/Brave Browser',
+  ],
+};
+
+const BROWSER_PROFILE_DIR = path.join(SESSION_DIR, 'browser-profile');
+
+function onPath(command: string): string | null {
+  for (const dir of (process.env.PATH || '').split(path.delimiter)) {
+    const candidate = path.join(dir, command);
+    try {
+      fs.accessSync(candidate, fs.constants.X_OK);
+      return candidate;
+    } catch {
+      // not in this directory
+    }
+  }
+  return null;
+}
+
+/// The browser to open app windows with: `appBrowser`, else the first
+/// Chromium-family browser installed
+export function findAppBrowser(): string {
+  const configured = loadSettings().appBrowser;
+  if (configured) {
+    return configured;
+  }
+  for (const candidate of BROWSER_CANDIDATES[process.platform] || []) {
+    const found = path.isAbsolute(candidate) ? (fs.existsSync(candidate) ? candidate : null) : onPath(candidate);
+    if (found) {
+      return found;
+    }
+  }
+  throw new Error('No Chromium-family browser found; install Chrome or Chromium, or set appBrowser to one');
+}
+
+/// Open `url` in an app-mode window of `browser`, in the session's browser
+/// profile. Resolves once the browser started; it keeps running on its own.
+export function openAppWindow(browser: string, url: string): Promise<void> {
+  fs.mkdirSync(BROWSER_PROFILE_DIR, { recursive: true });
+  const child = spawn(browser, [
+    `--user-data-dir=${BROWSER_PROFILE_DIR}`,
+    `--app=${url}`,
+    // The window class on Linux, so window managers can tell the sessions' windows apart
+    `--class=theoldswitcheroo-${SESSION_NAME}`,
+    '--no-first-run',
+    '--no-default-browser-check'
+  ], { detached: true, stdio: 'ignore' });
+  return new Promise((resolve, reject) => {
+    child.on('error', (error) => reject(new Error(`Could not start ${browser}: ${error.message}`)));
+    child.on('spawn', () => {
+      child.unref();
+      resolve();
+    });
+  });
+}
@@ -0,0 +1,163 @@
+import { shellQuote } from './ssh-manager.js';
+
+/// How the remote host fetches artifacts (server tarballs, the agent's
+/// Node.js) from one kind of URL. Every method returns shell commands, so the
+/// download runs on the host with the host's own tools and credentials.
+export interface ArtifactFetcher {
+  // The tool doing the work, for messages, e.g. "curl" or "aws"
+  tool: string;
+  /// Commands that download `url` to the remote file `dest`. They leave the
+  /// exit code in `$rc`, 0 on success, and the HTTP status, if there is one, in
+  /// `$status`. Where the source allows it, a partial `dest` left by an
+  /// interrupted download is resumed rather than started over.
+  download(url: string, dest: string): string;
+  /// A command that prints `url` to stdout, failing if it cannot
+  print(url: string): string;
+  /// A command that prints the size of `url` in bytes, or nothing if the source does not say
+  size(url: string): string;
+  /// Why a download failed, from `$status` and `$rc`
+  describeFailure(httpStatus: number | null, exitCode: number): string;
+  /// Whether the failure means the source is refusing or out of reach, rather than missing the artifact
+  blocked(httpStatus: number | null, exitCode: number): boolean;
+  /// What the user can do about a failure
+  guidance(httpStatus: number | null, exitCode: number): string;
+}
+
+/// curl's exit code for an HTTP error; a download with any status but 200 (or 206, resumed) is given it
+export const CURL_HTTP_ERROR = 22;
+
+// curl's exit code when the server does not support resuming
+const CURL_CANNOT_RESUME = 33;
+
+// The shell's exit code for a command that is not installed
+const COMMAND_NOT_FOUND = 127;
+
+function httpFetcher(proxy?: string): ArtifactFetcher {
+  const proxyFlag = proxy ? `--proxy ${shellQuote(proxy)} ` : '';
+  return {
+    tool: 'curl',
+    // A server that cannot resume (or a partial file it does not recognise) gets a fresh download
+    download: (url, dest) => `
+      rc=0
+      resume=
+      if [ -s ${dest} ]; then resume='-C -'; fi
+      status=$(curl -sSL ${proxyFlag}$resume -w '%{http_code}' ${shellQuote(url)} -o ${dest}) || rc=$?
+      if [ -n "$resume" ] && { [ "$rc" = ${CURL_CANNOT_RESUME} ] || [ "$status" = 416 ]; }; then
+        rm -f ${dest}
+        rc=0
+        status=$(curl -sSL ${proxyFlag}-w '%{http_code}' ${shellQuote(url)} -o ${dest}) || rc=$?
+      fi
+      if [ "$rc" = 0 ] && [ "$status" != 200 ] && [ "$status" != 206 ]; then rc=${CURL_HTTP_ERROR}; fi
+    `,
+    print: (url) => `curl -sSfL ${proxyFlag}${shellQuote(url)}`,
+    // The Content-Length of the last response, after redirects
+    size: (url) => `curl -sSIL ${proxyFlag}${shellQuote(url)} | ` +
+      `awk '/^HTTP\\// { length_ = "" } tolower($1) == "content-length:" { length_ = $2 + 0 } END { if (length_) print length_ }'`,
+    describeFailure: (httpStatus, exitCode) => {
+      switch (exitCode) {
+        case CURL_HTTP_ERROR:
+          return `HTTP ${httpStatus}`;
+        case 5:
+          return 'could not resolve the proxy';
+        case 6:
+          return 'could not resolve the host';
+        case 7:
+          return 'connection refused';
+        case 28:
+          return 'connection timed out';
+        case 35:
+        case 60:
+          return 'TLS handshake failed (an intercepting proxy?)';
+        default:
+          return `curl exited with code ${exitCode}`;
+      }
+    },
+    blocked: (httpStatus, exitCode) =>
+      httpStatus === 403 || httpStatus === 429 || (exitCode !== 0 && exitCode !== CURL_HTTP_ERROR),
+    guidance: (httpStatus) => {
+      const mirror = 'Set `serverDownloadUrl` to a mirror of the GitHub releases';
+      const offline = 'or prime the cache from a connected machine with `--prime-cache` (see the docs) and copy it over';
+      if (httpStatus === 429 || httpStatus === 403) {
+        return `GitHub is rate-limiting or blocking downloads from this host. Wait and retry, ${mirror.toLowerCase()}, ${offline}.`;
+      }
+      if (httpStatus === 404) {
+        return 'This release does not exist for this architecture. Check the `serverVersion` setting.';
+      }
+      return `The host cannot reach the download server. If it needs a proxy, set \`serverDownloadProxy\`. ` +
+        `${mirror}, ${offline}.`;
+    }
+  };
+}
+
+// Object stores are read with their own CLI and whatever credentials the host
+// has for it (an instance role, AWS_PROFILE, `gcloud auth`, ...); the proxy
+// reaches them through the environment.
+function objectStoreFetcher(
+  tool: string,
+  copy: (url: string, dest: string) => string,
+  cat: (url: string) => string,
+  credentials: string,
+  proxy?: string
+): ArtifactFetcher {
+  const env = proxy ? `HTTPS_PROXY=${shellQuote(proxy)} ` : '';
+  return {
+    tool,
+    download: (url, dest) => `
+      rc=0
+      status=
+      ${env}${copy(shellQuote(url), dest)} || rc=$?
+    `,
+    print: (url) => `${env}${cat(shellQuote(url))}`,
+    size: () => 'true',
+    describeFailure: (_httpStatus, exitCode) => exitCode === COMMAND_NOT_FOUND
+      ? `the ${tool} CLI is not installed on the host`
+      : `${tool} exited with code ${exitCode} (see its error above)`,
+    blocked: () => false,
+    guidance: (_httpStatus, exitCode) => exitCode === COMMAND_NOT_FOUND
+      ? `Install the ${tool} CLI on the host, or set \`serverDownloadUrl\` to an HTTP mirror.`
+      : `Check that the object exists and that the host's credentials can read it: ${credentials}.`
+  };
+}
+
+function s3Fetcher(proxy?: string): ArtifactFetcher {
+  return objectStoreFetcher('aws',
+    (url, dest) => `aws s3 cp --only-show-errors ${url} ${dest}`,
+    (url) => `aws s3 cp --only-show-errors ${url} -`,
+    'run `aws sts get-caller-identity` there, and set AWS_PROFILE or AWS_REGION in its shell profile if needed',
+    proxy);
+}
+
+// gcloud where it is installed, gsutil on older SDKs
+function gcsFetcher(proxy?: string): ArtifactFetcher {
+  const either = (gcloud: string, gsutil: string) =>
+    `{ if command -v gcloud >/dev/null 2>&1; then ${gcloud}; else ${gsutil}; fi; }`;
+  return objectStoreFetcher('gcloud',
+    (url, dest) => either(`gcloud storage cp --quiet ${url} ${dest}`, `gsutil -q cp ${url} ${dest}`),
+    (url) => either(`gcloud storage cat ${url}`, `gsutil cat ${url}`),
+    'run `gcloud auth list` there, or attach a service account that can read the bucket',
+    proxy);
+}
+
+// Fetchers by URL scheme; add one here to support another kind of source
+const FETCHERS: Record<string, (proxy?: string) => ArtifactFetcher> = {
+  http: httpFetcher,
+  https: httpFetcher,
+  s3: s3Fetcher,
+  gs: gcsFetcher
+};
+
+/// The scheme of `url`, e.g. "https" or "s3"
+export function urlScheme(url: string): string {
+  return (/^([a-z][a-z0-9+.-]*):\/\//i.exec(url)?.[1] || '').toLowerCase();
+}
+
+/// The fetcher for `url`, going through `proxy` if given. Throws for schemes
+/// no fetcher handles, so a typo in a download URL fails before anything runs.
+export function fetcherFor(url: string, proxy?: string): ArtifactFetcher {
+  const scheme = urlScheme(url);
+  const fetcher = FETCHERS[scheme];
+  if (!fetcher) {
+    throw new Error(`Cannot download from ${url}: supported URL schemes are ${Object.keys(FETCHERS).map(name => `${name}://`).join(', ')}`);
+  }
+  return fetcher(proxy);
+}
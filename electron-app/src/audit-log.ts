@@ -0,0 +1,26 @@
+import * as fs from 'fs';
+import * as path from 'path';
+import { SESSION_DIR, loadSettings } from './settings.js';
+
+/// The session's record of security-relevant actions (`auditLog`): which
+/// servers were started how, and who reached into a taskspace from the
+/// command line. One JSON line per action in `audit.log` in the session
+/// directory, appended to and never rotated by the app.
+
+export const AUDIT_LOG_FILE = path.join(SESSION_DIR, 'audit.log');
+
+export type AuditEvent = 'server-start' | 'shell' | 'run' | 'kill' | 'mount' | 'clipboard-copy';
+
+/// Record `event` with `details` if `auditLog` is on. Failing to write is
+/// only warned about: the action itself has happened either way.
+export function audit(event: AuditEvent, details: Record<string, any>): void {
+  if (!loadSettings().auditLog) {
+    return;
+  }
+  try {
+    fs.mkdirSync(SESSION_DIR, { recursive: true });
+    fs.appendFileSync(AUDIT_LOG_FILE, JSON.stringify({ time: new Date().toISOString(), event, ...details }) + '\n', { mode: 0o600 });
+  } catch (error) {
+    console.warn(`Warning: Could not write to ${AUDIT_LOG_FILE}: ${error.message}`);
+  }
+}
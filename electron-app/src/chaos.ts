@@ -0,0 +1,97 @@
+/// Deliberate failures for exercising the retry, reconnect and checksum code
+/// paths: `--chaos` (or `THEOLDSWITCHEROO_CHAOS`) turns them on for a run, and
+/// tests can call `configureChaos` directly. Off unless asked for; every hook
+/// is then a single map lookup.
+
+export type ChaosFault =
+  // A remote command fails as if the connection had been reset
+  | 'command'
+  // A port forward is torn down, as if the network had dropped it
+  | 'tunnel'
+  // The server download stalls before it starts
+  | 'slow-download'
+  // The downloaded server tarball is truncated, so its checksum does not match
+  | 'corrupt-download';
+
+const FAULTS: ChaosFault[] = ['command', 'tunnel', 'slow-download', 'corrupt-download'];
+
+// Chance of each fault when `--chaos` names none: enough to hit every retry
+// path in a few minutes, not so much that nothing ever works
+const DEFAULT_PROBABILITY = 0.1;
+
+// How long a slow download stalls
+export const CHAOS_DOWNLOAD_DELAY_SECS = 20;
+
+/// The chance of each fault per opportunity, and the seed of the random numbers
+export interface ChaosConfig {
+  probabilities: Partial<Record<ChaosFault, number>>;
+  seed: number;
+}
+
+let config: ChaosConfig | null = null;
+let random: () => number = Math.random;
+
+/// Parse a `--chaos` spec: empty or `all` for every fault at the default chance, or a
+/// comma-separated list of faults, each optionally with its chance, e.g.
+/// `command=0.3,tunnel`. `seed=<n>` makes a run reproducible.
+export function parseChaosSpec(spec: string): ChaosConfig {
+  const parsed: ChaosConfig = { probabilities: {}, seed: Date.now() % 2147483647 };
+  for (const part of spec.split(',').map(part => part.trim()).filter(part => part && part !== 'all')) {
+    const [name, value] = part.split('=');
+    if (name === 'seed') {
+      parsed.seed = Number(value);
+      continue;
+    }
+    if (!FAULTS.includes(name as ChaosFault)) {
+      throw new Error(`Unknown chaos fault '${name}': use ${FAULTS.join(', ')} or seed`);
+    }
+    const probability = value === undefined ? DEFAULT_PROBABILITY : Number(value);
+    if (!(probability >= 0 && probability <= 1)) {
+      throw new Error(`Chance of chaos fault '${name}' must be between 0 and 1, not '${value}'`);
+    }
+    parsed.probabilities[name as ChaosFault] = probability;
+  }
+  if (Object.keys(parsed.probabilities).length === 0) {
+    FAULTS.forEach(fault => parsed.probabilities[fault] = DEFAULT_PROBABILITY);
+  }
+  return parsed;
+}
+
+/// Turn chaos on with `chaos`, or off with null
+export function configureChaos(chaos: ChaosConfig | null): void {
+  config = chaos;
+  random = chaos ? seededRandom(chaos.seed) : Math.random;
+  if (chaos) {
+    const faults = Object.entries(chaos.probabilities).map(([fault, probability]) => `${fault}=${probability}`);
+    console.warn(`⚠ Chaos mode: injecting failures (${faults.join(', ')}; seed=${chaos.seed})`);
+  }
+}
+
+/// Whether `fault` strikes now. `what` is logged when it does, so an injected
+/// failure can be told from a real one in the logs.
+export function chaos(fault: ChaosFault, what: string): boolean {
+  const probability = config?.probabilities[fault];
+  if (!probability || random() >= probability) {
+    return false;
+  }
+  console.warn(`⚠ Chaos: injecting ${fault} into ${what}`);
+  return true;
+}
+
+/// The error a chaos-failed remote command rejects with. It reads like a
+/// dropped connection, so the retry logic treats it as one.
+export function chaosError(what: string): Error {
+  return new Error(`${what} failed: Connection reset by peer (injected by chaos mode)`);
+}
+
+// mulberry32: small, fast and good enough to make a seed reproduce a run
+function seededRandom(seed: number): () => number {
+  let state = seed >>> 0;
+  return () => {
+    state = (state + 0x6D2B79F5) >>> 0;
+    let t = state;
+    t = Math.imul(t ^ (t >>> 15), t | 1);
+    t ^= t + Math.imul(t ^ (t >>> 7), t | 61);
+    return ((t ^ (t >>> 14)) >>> 0) / 4294967296;
+  };
+}
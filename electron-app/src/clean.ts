@@ -3,18 +3,24 @@
 import { spawn } from 'child_process';
 import { unlink } from 'fs/promises';
 import { loadSettings, TASKSPACES_FILE } from './settings.js';
+import { asRemoteUser, sshManager } from './ssh-manager.js';
+import { guardUnsavedWork } from './work-backup.js';
+import { enablePlainOutput } from './plain-output.js';
 
-const hostname = process.argv[2];
+const hostname = process.argv.slice(2).find(arg => !arg.startsWith('--'));
+const settings = loadSettings();
+if (settings.plain) {
+  enablePlainOutput();
+}
 
 // If no hostname provided, try to load from settings
 let targetHostname = hostname;
 if (!targetHostname) {
-  const settings = loadSettings();
   if (settings.hostname) {
     targetHostname = settings.hostname;
   } else {
     console.error('No hostname provided and no hostname configured in settings.');
-    console.error('Usage: node clean.js [hostname]');
+    console.error('Usage: node clean.js [hostname] [--force] [--plain]');
     console.error('Or configure hostname in ~/.socratic-shell/theoldswitcheroo/settings.json');
     process.exit(1);
   }
@@ -22,25 +28,45 @@ if (!targetHostname) {
 
 console.log(`Cleaning ~/.socratic-shell/theoldswitcheroo on ${targetHostname}...`);
 
-const ssh = spawn('ssh', [targetHostname, 'rm -rf ~/.socratic-shell/theoldswitcheroo'], {
-  stdio: 'inherit'
-});
-
-ssh.on('close', async (code) => {
-  if (code === 0) {
-    console.log(`Successfully cleaned ${targetHostname}`);
-    
-    // Also delete local taskspaces.json
-    try {
-      await unlink(TASKSPACES_FILE);
-      console.log('Deleted local taskspaces.json');
-    } catch (error: any) {
-      if (error.code !== 'ENOENT') {
-        console.log(`Warning: Could not delete local taskspaces.json: ${error.message}`);
+// Uncommitted and unpushed work in the taskspaces is saved locally first
+sshManager.setJumpHost(settings.jumpHost);
+sshManager.setBatchMode(!!settings.nonInteractive);
+if (settings.remoteUser) {
+  sshManager.setRemoteUser(targetHostname, settings.remoteUser);
+}
+guardUnsavedWork(targetHostname, process.argv.includes('--force'))
+  .then(() => {
+    sshManager.cleanup();
+    clean();
+  }, (error) => {
+    console.error(error.message);
+    sshManager.cleanup();
+    process.exit(1);
+  });
+
+function clean() {
+  const jumpOptions = settings.jumpHost ? ['-J', settings.jumpHost] : [];
+  const batchOptions = settings.nonInteractive ? ['-o', 'BatchMode=yes'] : [];
+  const ssh = spawn('ssh', [...jumpOptions, ...batchOptions, targetHostname, asRemoteUser(settings.remoteUser, 'rm -rf ~/.socratic-shell/theoldswitcheroo')], {
+    stdio: 'inherit'
+  });
+
+  ssh.on('close', async (code) => {
+    if (code === 0) {
+      console.log(`Successfully cleaned ${targetHostname}`);
+
+      // Also delete local taskspaces.json
+      try {
+        await unlink(TASKSPACES_FILE);
+        console.log('Deleted local taskspaces.json');
+      } catch (error: any) {
+        if (error.code !== 'ENOENT') {
+          console.log(`Warning: Could not delete local taskspaces.json: ${error.message}`);
+        }
       }
+    } else {
+      console.error(`Failed to clean ${targetHostname} (exit code: ${code})`);
     }
-  } else {
-    console.error(`Failed to clean ${targetHostname} (exit code: ${code})`);
-  }
-  process.exit(code || 0);
-});
+    process.exit(code || 0);
+  });
+}
@@ -0,0 +1,68 @@
+/// The configuration a taskspace's server was set up with, recorded in
+/// taskspaces.json so a later connect can tell what has changed since.
+export interface AppliedConfig {
+  // Marketplace ids and .vsix file names
+  extensions: string[];
+  // Server-side ("Machine") VSCode settings
+  machineSettings: Record<string, any>;
+  // The shell lines that set up the server's environment (locale, CUDA, umask, limits, ...)
+  environment: string;
+}
+
+/// What differs between the recorded and the desired configuration
+export interface ConfigDelta {
+  addedExtensions: string[];
+  removedExtensions: string[];
+  // Keys of Machine settings that were added, removed or changed
+  changedSettings: string[];
+  // Only a restart gives a running server a new environment
+  environmentChanged: boolean;
+}
+
+/// Compare what a taskspace's server was set up with to what the settings
+/// ask for now. Without a record (taskspaces saved by older versions) every
+/// extension and setting counts as missing, since installing and writing them
+/// again is harmless, but the environment is assumed to be unchanged.
+export function diffConfig(recorded: AppliedConfig | undefined, desired: AppliedConfig): ConfigDelta {
+  const recordedExtensions = new Set(recorded?.extensions || []);
+  const desiredExtensions = new Set(desired.extensions);
+  const recordedSettings = recorded?.machineSettings || {};
+
+  const settingKeys = new Set([...Object.keys(recordedSettings), ...Object.keys(desired.machineSettings)]);
+  const changedSettings = [...settingKeys].filter(key =>
+    !recorded || JSON.stringify(recordedSettings[key]) !== JSON.stringify(desired.machineSettings[key]));
+
+  return {
+    addedExtensions: desired.extensions.filter(extension => !recordedExtensions.has(extension)),
+    removedExtensions: [...recordedExtensions].filter(extension => !desiredExtensions.has(extension)),
+    changedSettings: changedSettings.sort(),
+    environmentChanged: !!recorded && recorded.environment !== desired.environment
+  };
+}
+
+export function isEmptyDelta(delta: ConfigDelta): boolean {
+  return delta.addedExtensions.length === 0 && delta.removedExtensions.length === 0 &&
+    delta.changedSettings.length === 0 && !delta.environmentChanged;
+}
+
+/// One line per kind of change, saying what was done about it
+export function formatDelta(delta: ConfigDelta, failedExtensions: string[] = []): string[] {
+  const lines: string[] = [];
+  const installed = delta.addedExtensions.filter(extension => !failedExtensions.includes(extension));
+  if (installed.length > 0) {
+    lines.push(`installed ${installed.length} extensions: ${installed.join(', ')}`);
+  }
+  if (failedExtensions.length > 0) {
+    lines.push(`failed to install ${failedExtensions.join(', ')} (retried on the next connect)`);
+  }
+  if (delta.removedExtensions.length > 0) {
+    lines.push(`left ${delta.removedExtensions.join(', ')} installed; uninstall them from the Extensions view if they are unwanted`);
+  }
+  if (delta.changedSettings.length > 0) {
+    lines.push(`updated Machine settings: ${delta.changedSettings.join(', ')}`);
+  }
+  if (delta.environmentChanged) {
+    lines.push('the server environment changed (locale, CUDA, working directory, umask or limits); restart the taskspace to apply it');
+  }
+  return lines;
+}
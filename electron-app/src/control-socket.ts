@@ -0,0 +1,142 @@
+import * as fs from 'fs';
+import * as net from 'net';
+import * as path from 'path';
+import { SESSION_DIR } from './settings.js';
+
+/// Local socket a running app answers `--status`, `--stop`, `--logs`, `--prewarm`, `--taskspace` and `--reconcile` on, one per session
+export function controlSocketPath(sessionDir: string): string {
+  return path.join(sessionDir, 'control.sock');
+}
+
+export const CONTROL_SOCKET = controlSocketPath(SESSION_DIR);
+
+/// Where a `--detach`ed app writes its console output
+export const DETACHED_LOG = path.join(SESSION_DIR, 'detached.log');
+
+export type ControlCommand = 'status' | 'stop' | 'logs' | 'prewarm' | 'list-taskspaces' | 'create-taskspace' | 'delete-taskspace' | 'reconcile';
+
+export interface ControlHandlers {
+  status(): any;
+  stop(): void;
+  logs(lines: number): string[];
+  // Replies once the warm-up is done, which can take minutes
+  prewarm(taskspace: string | undefined, globs: string[] | undefined): Promise<any>;
+  listTaskSpaces(): any[];
+  // Replies once the taskspace's clone is ready
+  createTaskSpace(name: string | undefined): Promise<any>;
+  deleteTaskSpace(taskspace: string, purge: boolean): Promise<any>;
+  // What was found and done for each taskspace, or just `taskspace`
+  reconcile(taskspace: string | undefined): Promise<any>;
+}
+
+/// Answer control commands from later invocations of the app, one JSON
+/// request and one JSON reply per connection.
+export function serveControlSocket(handlers: ControlHandlers): net.Server {
+  fs.mkdirSync(SESSION_DIR, { recursive: true });
+  // A socket left behind by a crashed app would make listen fail
+  fs.rmSync(CONTROL_SOCKET, { force: true });
+
+  const server = net.createServer((socket) => {
+    let buffer = '';
+    socket.on('data', async (data) => {
+      buffer += data.toString();
+      const newline = buffer.indexOf('\n');
+      if (newline === -1) {
+        return;
+      }
+      const line = buffer.slice(0, newline);
+      buffer = '';
+
+      let reply: any;
+      try {
+        const request = JSON.parse(line);
+        switch (request.command as ControlCommand) {
+          case 'status':
+            reply = { pid: process.pid, ...handlers.status() };
+            break;
+          case 'logs':
+            reply = { lines: handlers.logs(Number(request.lines) || 50) };
+            break;
+          case 'stop':
+            reply = { pid: process.pid, stopping: true };
+            break;
+          case 'prewarm':
+            reply = await handlers.prewarm(request.taskspace, request.globs);
+            break;
+          case 'list-taskspaces':
+            reply = { taskspaces: handlers.listTaskSpaces() };
+            break;
+          case 'create-taskspace':
+            reply = await handlers.createTaskSpace(request.name);
+            break;
+          case 'delete-taskspace':
+            reply = await handlers.deleteTaskSpace(request.taskspace, !!request.purge);
+            break;
+          case 'reconcile':
+            reply = await handlers.reconcile(request.taskspace);
+            break;
+          default:
+            reply = { error: `unknown command '${request.command}'` };
+        }
+      } catch (error) {
+        reply = { error: error.message };
+      }
+
+      socket.end(JSON.stringify(reply) + '\n', () => {
+        if (reply.stopping) {
+          handlers.stop();
+        }
+      });
+    });
+    socket.on('error', () => socket.destroy());
+  });
+
+  server.listen(CONTROL_SOCKET, () => fs.chmodSync(CONTROL_SOCKET, 0o600));
+  server.on('error', (error) => console.error('Control socket error:', error));
+  return server;
+}
+
+/// Send `command` to the running app and resolve with its reply, or with null
+/// if no app is running. Talks to this run's session unless given another
+/// session's `socketPath`.
+export function sendControlCommand(command: ControlCommand, extra: Record<string, any> = {}, socketPath = CONTROL_SOCKET): Promise<any | null> {
+  return new Promise((resolve, reject) => {
+    if (!fs.existsSync(socketPath)) {
+      resolve(null);
+      return;
+    }
+
+    const socket = net.createConnection(socketPath, () => {
+      socket.write(JSON.stringify({ command, ...extra }) + '\n');
+    });
+    let buffer = '';
+    socket.on('data', (data) => buffer += data.toString());
+    socket.on('end', () => {
+      try {
+        resolve(JSON.parse(buffer));
+      } catch (error) {
+        reject(new Error(`Unexpected reply from the running app: ${buffer}`));
+      }
+    });
+    socket.on('error', (error: NodeJS.ErrnoException) => {
+      // A stale socket from an app that crashed
+      if (error.code === 'ECONNREFUSED' || error.code === 'ENOENT') {
+        resolve(null);
+      } else {
+        reject(error);
+      }
+    });
+  });
+}
+
+/// The last `count` lines of the detached log
+export function tailDetachedLog(count: number): string[] {
+  if (!fs.existsSync(DETACHED_LOG)) {
+    return [];
+  }
+  const lines = fs.readFileSync(DETACHED_LOG, 'utf8').split('\n');
+  if (lines[lines.length - 1] === '') {
+    lines.pop();
+  }
+  return lines.slice(-count);
+}
@@ -0,0 +1,66 @@
+import * as fs from 'fs';
+import * as net from 'net';
+import * as path from 'path';
+import { sshManager } from './ssh-manager.js';
+import { BASE_DIR, DEFAULT_SESSION, LOCAL_DATA_DIR, REMOTE_DAEMON_SOCKET, SESSION_NAME } from './settings.js';
+
+// Relays stdin to the daemon socket and the socket to stdout, using the node the daemon runs on
+const BRIDGE_SCRIPT = `const s = require('net').connect(process.argv[1]);` +
+  ` process.stdin.pipe(s); s.pipe(process.stdout);` +
+  ` s.on('error', e => { console.error(e.message); process.exit(1); });`;
+
+/// Default local socket for `--daemon-proxy <hostname>`
+export function localDaemonSocket(hostname: string): string {
+  const suffix = SESSION_NAME === DEFAULT_SESSION ? '' : `-${SESSION_NAME}`;
+  return path.join(LOCAL_DATA_DIR, `daemon-${hostname}${suffix}.sock`);
+}
+
+/// Listen on the local Unix socket `localPath` and connect everything that
+/// arrives there to this session's daemon on `hostname`.
+///
+/// Each local connection gets its own SSH session over the master connection,
+/// running a small bridge next to the daemon. Unlike `ssh -L`, this works when
+/// the daemon belongs to `remoteUser` rather than the SSH login user.
+export async function proxyDaemonSocket(hostname: string, localPath: string): Promise<net.Server> {
+  // Fail early, and with a clear message, if there is no daemon to talk to
+  const check = await sshManager.executeCommand(hostname, `test -S ${REMOTE_DAEMON_SOCKET} && echo running || echo missing`);
+  if (check.trim() !== 'running') {
+    throw new Error(`No daemon is running on ${hostname}; start the app for it first`);
+  }
+
+  fs.mkdirSync(path.dirname(localPath), { recursive: true });
+  if (fs.existsSync(localPath)) {
+    fs.unlinkSync(localPath);
+  }
+
+  const bridgeCommand = `cd ${BASE_DIR} && exec ./nodejs/bin/node -e "${BRIDGE_SCRIPT}" ${REMOTE_DAEMON_SOCKET}`;
+  const server = net.createServer(async (local) => {
+    let bridge;
+    try {
+      bridge = await sshManager.executeStreamingCommand(hostname, bridgeCommand);
+    } catch (error) {
+      console.error(`[daemon-proxy ${hostname}] Could not reach the daemon: ${error.message}`);
+      local.destroy();
+      return;
+    }
+    local.pipe(bridge.stdin!);
+    bridge.stdout!.pipe(local);
+    bridge.stderr!.on('data', (data) => console.error(`[daemon-proxy ${hostname}] ${data.toString().trim()}`));
+
+    // The piped stdout ends the local socket once the daemon's side closes
+    bridge.on('error', () => local.destroy());
+    local.on('close', () => bridge.kill());
+    local.on('error', () => bridge.kill());
+  });
+
+  await new Promise<void>((resolve, reject) => {
+    server.once('error', reject);
+    server.listen(localPath, () => {
+      server.off('error', reject);
+      // Same as the remote socket: only the owner may connect
+      fs.chmodSync(localPath, 0o600);
+      resolve();
+    });
+  });
+  return server;
+}
@@ -0,0 +1,101 @@
+import * as path from 'path';
+import { sshManager, shellQuote } from './ssh-manager.js';
+import { REMOTE_DAEMON_SOCKET, SESSION_NAME } from './settings.js';
+
+// The session's own directory on the host, next to its daemon socket, with
+// `~` spelled so it also expands inside an `at` job
+const REMOTE_DIR = path.posix.dirname(REMOTE_DAEMON_SOCKET).replace(/^~/, '$HOME');
+const SCRIPT = `${REMOTE_DIR}/dead-man-${SESSION_NAME}.sh`;
+const LOG = `${REMOTE_DIR}/dead-man-${SESSION_NAME}.log`;
+const AT_JOB_FILE = `${REMOTE_DIR}/dead-man-${SESSION_NAME}.at`;
+const UNIT = `theoldswitcheroo-dead-man-${SESSION_NAME}`;
+
+// How often the app checks in at most; a short deadline gets checked in on four times over
+const MAX_HEARTBEAT_MS = 15 * 60 * 1000;
+
+/// Shell command that cancels the session's dead-man timer, whichever kind it is,
+/// printing a line if there was one
+export function cancelDeadManCommand(): string {
+  return `
+    cancelled=
+    if command -v systemctl >/dev/null 2>&1 && systemctl --user is-active --quiet ${UNIT}.timer 2>/dev/null; then
+      systemctl --user stop ${UNIT}.timer 2>/dev/null && cancelled=1
+    fi
+    if [ -f "${AT_JOB_FILE}" ]; then
+      atrm "$(cat "${AT_JOB_FILE}")" 2>/dev/null && cancelled=1
+      rm -f "${AT_JOB_FILE}"
+    fi
+    [ -n "$cancelled" ] && echo "Cancelled the dead-man timer"
+    true`;
+}
+
+/// A timer on the host that stops the session's servers and daemon if the
+/// app has not checked in for `hours`. Every check-in cancels the timer and
+/// sets it again, with the current taskspaces, so it only ever fires once
+/// the app has been gone for the whole time.
+///
+/// Uses a transient systemd user timer when the user's systemd instance
+/// outlives their logins (lingering is on), and otherwise an `at` job.
+export class DeadManTimer {
+  private interval: NodeJS.Timeout | null = null;
+  private mechanism: string | null = null;
+
+  constructor(
+    private hostname: string,
+    private hours: number,
+    // Shell script that stops the session as it is at the time of the check-in
+    private stopScript: () => string
+  ) {}
+
+  /// Set the timer now and check in periodically until `stop`
+  async start(): Promise<void> {
+    await this.checkIn();
+    const every = Math.min(MAX_HEARTBEAT_MS, this.hours * 60 * 60 * 1000 / 4);
+    this.interval = setInterval(() => {
+      this.checkIn().catch((error) => console.warn(`Could not check in with the dead-man timer on ${this.hostname}: ${error.message}`));
+    }, every);
+  }
+
+  /// Stop checking in. The timer stays set on the host, so the servers the
+  /// app leaves running are still stopped if it does not come back.
+  stop(): void {
+    if (this.interval) {
+      clearInterval(this.interval);
+      this.interval = null;
+    }
+  }
+
+  /// Push the deadline back by `hours` from now
+  async checkIn(): Promise<void> {
+    const seconds = Math.round(this.hours * 60 * 60);
+    const script = `{
+      echo "=== $(date): the app has not checked in for ${this.hours} hours; stopping the session ${SESSION_NAME}"
+      ${this.stopScript()}
+      rm -f "${AT_JOB_FILE}"
+    } >> "${LOG}" 2>&1
+`;
+
+    const mechanism = (await sshManager.executeCommand(this.hostname, `
+      mkdir -p "${REMOTE_DIR}"
+      printf '%s\\n' ${shellQuote(script)} > "${SCRIPT}"
+      (${cancelDeadManCommand()}) >/dev/null
+      if command -v systemd-run >/dev/null 2>&1 && systemctl --user show-environment >/dev/null 2>&1 \\
+          && [ "$(loginctl show-user "$(id -un)" -p Linger --value 2>/dev/null)" = yes ]; then
+        systemctl --user reset-failed ${UNIT}.timer ${UNIT}.service >/dev/null 2>&1
+        systemd-run --user --quiet --unit=${UNIT} --on-active=${seconds}s /bin/sh "${SCRIPT}" && echo systemd
+      elif command -v at >/dev/null 2>&1; then
+        echo "/bin/sh \\"${SCRIPT}\\"" | at now + ${Math.ceil(seconds / 60)} minutes 2>&1 \\
+          | sed -n 's/^job \\([0-9]*\\) .*/\\1/p' > "${AT_JOB_FILE}"
+        [ -s "${AT_JOB_FILE}" ] && echo at
+      fi
+    `)).trim();
+
+    if (!mechanism) {
+      throw new Error(`neither a lingering systemd user instance nor \`at\` is available on ${this.hostname}`);
+    }
+    if (mechanism !== this.mechanism) {
+      console.log(`✓ Dead-man timer on ${this.hostname} (${mechanism}): the session is stopped after ${this.hours} hours without the app`);
+      this.mechanism = mechanism;
+    }
+  }
+}
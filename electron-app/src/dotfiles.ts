@@ -0,0 +1,93 @@
+/// Codespaces-style dotfiles (`--dotfiles-repo`): the repository is cloned on
+/// the host and its install script run before any server starts, so the
+/// integrated terminals get the user's shell setup, git config and aliases.
+
+import { sshManager, shellQuote } from './ssh-manager.js';
+import { BASE_DIR, Settings } from './settings.js';
+
+// The repository and commit last installed, so an unchanged repository is not installed again
+const MARKER = `${BASE_DIR}/dotfiles-installed`;
+const LOG = `${BASE_DIR}/dotfiles-install.log`;
+
+// Scripts looked for in the repository, in this order, as Codespaces does
+const INSTALL_SCRIPTS = ['install.sh', 'install', 'bootstrap.sh', 'bootstrap', 'script/bootstrap', 'setup.sh', 'setup', 'script/setup'];
+
+/// The repository to clone: a URL or path as given, or `owner/repo` on GitHub
+export function dotfilesRepoUrl(repo: string): string {
+  return /^[\w.-]+\/[\w.-]+$/.test(repo) ? `https://github.com/${repo}.git` : repo;
+}
+
+/// Shell command that clones or updates the dotfiles repository and, if it
+/// changed since the last install, runs its install script: `dotfilesInstallCommand`,
+/// else the first of INSTALL_SCRIPTS there is, else it links the repository's
+/// dotfiles into the home directory. Prints `unchanged <commit>`,
+/// `installed <commit>` or `failed <commit> <where to look>` as its last line.
+export function dotfilesCommand(settings: Settings): string {
+  const repo = dotfilesRepoUrl(settings.dotfilesRepo);
+  const target = (settings.dotfilesTargetPath || '~/dotfiles').replace(/^~(?=$|\/)/, '$HOME');
+  const install = settings.dotfilesInstallCommand
+    ? `sh -c ${shellQuote(settings.dotfilesInstallCommand)}`
+    : `
+      how=
+      for script in ${INSTALL_SCRIPTS.join(' ')}; do
+        if [ -f "$script" ]; then how=$script; break; fi
+      done
+      if [ -n "$how" ]; then
+        chmod +x "$how" && "./$how"
+      else
+        for file in .[!.]*; do
+          [ "$file" = .git ] || [ ! -e "$file" ] && continue
+          if [ -e "$HOME/$file" ] && [ ! -L "$HOME/$file" ]; then
+            echo "skipped $file: $HOME/$file exists"
+          else
+            ln -sfn "$TARGET/$file" "$HOME/$file" && echo "linked $file"
+          fi
+        done
+      fi`;
+  return `
+    set -e
+    REPO=${shellQuote(repo)}
+    TARGET="${target}"
+    mkdir -p ${BASE_DIR}
+    if [ -d "$TARGET/.git" ]; then
+      git -C "$TARGET" pull --ff-only --quiet >&2 || echo "Could not update $TARGET; installing what is there" >&2
+    else
+      git clone --quiet "$REPO" "$TARGET" >&2
+    fi
+    COMMIT=$(git -C "$TARGET" rev-parse --short HEAD)
+    if [ "$(cat ${MARKER} 2>/dev/null)" = "$REPO $COMMIT" ]; then
+      echo "unchanged $COMMIT"
+      exit 0
+    fi
+    cd "$TARGET"
+    set +e
+    ( ${install}
+    ) > ${LOG} 2>&1
+    status=$?
+    if [ $status -ne 0 ]; then
+      echo "failed $COMMIT see ${LOG}"
+      exit 0
+    fi
+    echo "$REPO $COMMIT" > ${MARKER}
+    echo "installed $COMMIT"
+  `;
+}
+
+/// Install the `dotfilesRepo` dotfiles on `hostname`, reporting through
+/// `log`. A failed install is reported, not fatal: the servers start anyway.
+export async function installDotfiles(hostname: string, settings: Settings, log: (message: string) => void): Promise<void> {
+  if (!settings.dotfilesRepo) {
+    return;
+  }
+  try {
+    const output = await sshManager.executeCommand(hostname, dotfilesCommand(settings));
+    const [result, commit, ...rest] = output.trim().split('\n').pop().split(' ');
+    if (result === 'installed') {
+      log(`✓ Installed dotfiles from ${settings.dotfilesRepo} (${commit}) on ${hostname}`);
+    } else if (result === 'failed') {
+      log(`⚠️ The dotfiles install script from ${settings.dotfilesRepo} (${commit}) failed on ${hostname}; ${rest.join(' ')}`);
+    }
+  } catch (error) {
+    log(`⚠️ Could not set up dotfiles from ${settings.dotfilesRepo} on ${hostname}: ${error.message}`);
+  }
+}
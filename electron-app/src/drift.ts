@@ -0,0 +1,136 @@
+import * as path from 'path';
+import { sshManager } from './ssh-manager.js';
+import { BASE_DIR } from './settings.js';
+import { serverFlavor } from './server-flavor.js';
+
+/// What a taskspace's server looked like on the host right after the app set
+/// it up, recorded in taskspaces.json so `--status` can tell when something
+/// changed it behind the app's back: a manual server upgrade, extensions
+/// installed or removed from inside the editor or the shell, edited Machine
+/// settings, or a different agent (daemon).
+export interface EnvironmentFingerprint {
+  // The installed release (the `.release` marker) and the version in its product.json
+  serverRelease: string;
+  serverVersion: string;
+  // Checksum of the daemon bundle on the host
+  agent: string;
+  // Directory names in the extensions directory, e.g. "ms-python.python-2024.2.1"
+  extensions: string[];
+  // Checksum of the Machine settings file, or "" without one
+  machineSettings: string;
+  recordedAt: string;
+}
+
+/// Where a taskspace's state lives on the host, as saved in taskspaces.json
+export interface FingerprintTarget {
+  uuid: string;
+  // `<base>/taskspaces/taskspace-<uuid>/server-data`; the extensions are next to it
+  serverDataDir: string;
+}
+
+/// What changed between a recorded and a current fingerprint
+export interface Drift {
+  server?: { from: string; to: string };
+  addedExtensions: string[];
+  removedExtensions: string[];
+  machineSettingsChanged: boolean;
+  agentChanged: boolean;
+}
+
+// The extensions directory that belongs to a server data directory
+function extensionsDir(serverDataDir: string): string {
+  return `${path.posix.dirname(serverDataDir)}/extensions`;
+}
+
+/// Shell command printing the host's and each target's facts, one
+/// `<uuid or ->\t<fact>\t<value>` line each
+export function fingerprintCommand(targets: FingerprintTarget[]): string {
+  const flavor = serverFlavor();
+  const install = `${BASE_DIR}/${flavor.installDir}`;
+  return `
+    checksum() { if [ -f "$1" ]; then cksum < "$1" | cut -d' ' -f1; fi; }
+    printf -- '-\\tserver-release\\t%s\\n' "$(cat ${install}/.release 2>/dev/null)"
+    printf -- '-\\tserver-version\\t%s\\n' "$(sed -n 's/^ *"version": *"\\([^"]*\\)".*/\\1/p' ${install}/${flavor.productJson} 2>/dev/null | head -1)"
+    printf -- '-\\tagent\\t%s\\n' "$(checksum ${BASE_DIR}/daemon-bundled.cjs)"
+    ${targets.map(target => `
+    printf '%s\\textensions\\t%s\\n' ${target.uuid} "$(ls ${extensionsDir(target.serverDataDir)} 2>/dev/null | grep -v '^extensions.json$' | grep -v '^\\.' | sort | tr '\\n' ' ')"
+    printf '%s\\tsettings\\t%s\\n' ${target.uuid} "$(checksum ${target.serverDataDir}/data/Machine/settings.json)"`).join('')}
+  `;
+}
+
+/// The fingerprints in the output of fingerprintCommand, by taskspace uuid
+export function parseFingerprints(output: string, now: Date = new Date()): Map<string, EnvironmentFingerprint> {
+  const host: Record<string, string> = {};
+  const facts = new Map<string, Record<string, string>>();
+  for (const line of output.split('\n')) {
+    const [uuid, fact, value = ''] = line.split('\t');
+    if (!fact) {
+      continue;
+    }
+    if (uuid === '-') {
+      host[fact] = value.trim();
+    } else {
+      facts.set(uuid, { ...facts.get(uuid), [fact]: value.trim() });
+    }
+  }
+
+  const fingerprints = new Map<string, EnvironmentFingerprint>();
+  for (const [uuid, fact] of facts) {
+    fingerprints.set(uuid, {
+      serverRelease: host['server-release'] || '',
+      serverVersion: host['server-version'] || '',
+      agent: host['agent'] || '',
+      extensions: (fact.extensions || '').split(' ').filter(name => name),
+      machineSettings: fact.settings || '',
+      recordedAt: now.toISOString()
+    });
+  }
+  return fingerprints;
+}
+
+/// The current fingerprints of `targets` on `hostname`, in one round trip
+export async function collectFingerprints(hostname: string, targets: FingerprintTarget[]): Promise<Map<string, EnvironmentFingerprint>> {
+  if (targets.length === 0) {
+    return new Map();
+  }
+  return parseFingerprints(await sshManager.executeCommand(hostname, fingerprintCommand(targets)));
+}
+
+/// Compare a recorded fingerprint to the current one
+export function diffFingerprint(recorded: EnvironmentFingerprint, current: EnvironmentFingerprint): Drift {
+  const describe = (fingerprint: EnvironmentFingerprint) => `${fingerprint.serverRelease || 'no release'} (${fingerprint.serverVersion || 'no version'})`;
+  const serverChanged = recorded.serverRelease !== current.serverRelease || recorded.serverVersion !== current.serverVersion;
+  return {
+    server: serverChanged ? { from: describe(recorded), to: describe(current) } : undefined,
+    addedExtensions: current.extensions.filter(name => !recorded.extensions.includes(name)),
+    removedExtensions: recorded.extensions.filter(name => !current.extensions.includes(name)),
+    machineSettingsChanged: recorded.machineSettings !== current.machineSettings,
+    agentChanged: recorded.agent !== current.agent
+  };
+}
+
+export function hasDrift(drift: Drift): boolean {
+  return !!drift.server || drift.addedExtensions.length > 0 || drift.removedExtensions.length > 0 ||
+    drift.machineSettingsChanged || drift.agentChanged;
+}
+
+/// One short phrase per change, e.g. "extensions removed: ms-python.python-2024.2.1"
+export function formatDrift(drift: Drift): string[] {
+  const lines: string[] = [];
+  if (drift.server) {
+    lines.push(`server changed from ${drift.server.from} to ${drift.server.to}`);
+  }
+  if (drift.addedExtensions.length > 0) {
+    lines.push(`extensions added: ${drift.addedExtensions.join(', ')}`);
+  }
+  if (drift.removedExtensions.length > 0) {
+    lines.push(`extensions removed: ${drift.removedExtensions.join(', ')}`);
+  }
+  if (drift.machineSettingsChanged) {
+    lines.push('Machine settings edited');
+  }
+  if (drift.agentChanged) {
+    lines.push('agent (daemon) replaced');
+  }
+  return lines;
+}
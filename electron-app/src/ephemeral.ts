@@ -0,0 +1,36 @@
+import { randomBytes } from 'crypto';
+
+/// Ephemeral sessions, for preview environments that CI starts per pull
+/// request (`--ephemeral`): a session of their own, run in the background,
+/// that deletes its taskspaces with all their files on the host when its time
+/// to live is up or when it is stopped. The deadline reaches the background
+/// app through the environment, like THEOLDSWITCHEROO_DETACHED.
+
+export const EPHEMERAL_UNTIL_ENV = 'THEOLDSWITCHEROO_EPHEMERAL_UNTIL';
+
+const TTL_UNITS: Record<string, number> = { s: 1, m: 60, h: 60 * 60, d: 24 * 60 * 60 };
+
+// Timers in node cannot wait longer than about 24 days; a preview needs far less
+const MAX_TTL_SECS = 7 * 24 * 60 * 60;
+
+/// Seconds in a time to live such as `90s`, `30m`, `1h` or `2d`
+export function parseTtl(text: string): number {
+  const match = /^(\d+(?:\.\d+)?)([smhd])$/.exec(text.trim());
+  const secs = match ? Number(match[1]) * TTL_UNITS[match[2]] : NaN;
+  if (!(secs > 0) || secs > MAX_TTL_SECS) {
+    throw new Error(`Invalid time to live '${text}': expected a number with s, m, h or d, e.g. 1h, of at most 7d`);
+  }
+  return secs;
+}
+
+/// A fresh session name for an ephemeral session started without `--name`
+export function ephemeralSessionName(): string {
+  return `ephemeral-${randomBytes(4).toString('hex')}`;
+}
+
+/// When this app's ephemeral session ends, or null if it is not one
+export function ephemeralDeadline(): Date | null {
+  const until = process.env[EPHEMERAL_UNTIL_ENV];
+  const deadline = until ? new Date(until) : null;
+  return deadline && !isNaN(deadline.getTime()) ? deadline : null;
+}
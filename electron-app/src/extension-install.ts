@@ -0,0 +1,129 @@
+import { sshManager, shellQuote } from './ssh-manager.js';
+import { BASE_DIR, loadSettings } from './settings.js';
+import { serverFlavor } from './server-flavor.js';
+import { SERVER_CACHE_DIR } from './server-cache.js';
+
+/// Remote cache of extension packages (.vsix), shared by every taskspace on the host
+export const EXTENSION_CACHE_DIR = `${SERVER_CACHE_DIR}/extensions`;
+
+// Where marketplace extensions are fetched from as packages, for the cache
+const OPEN_VSX_API_URL = 'https://open-vsx.org/api';
+
+// A marketplace id, optionally pinned to a version: `publisher.name[@version]`
+const EXTENSION_SPEC = /^([\w-]+)\.([\w.-]+?)(?:@(\d[\w.-]*))?$/;
+
+// Marketplace errors worth retrying: network hiccups, rate limiting and gateway errors
+const TRANSIENT_FAILURE = /ETIMEDOUT|ECONNRESET|ECONNREFUSED|EAI_AGAIN|socket hang up|timed? ?out|\b(429|502|503|504)\b/i;
+
+const MAX_ATTEMPTS = 3;
+
+export interface ExtensionFailure {
+  extension: string;
+  reason: string;
+}
+
+export interface ExtensionInstallSummary {
+  installed: string[];
+  failed: ExtensionFailure[];
+}
+
+/// Install `extensions` (marketplace ids or remote .vsix paths) into `extensionsDir`
+/// on `hostname`, running at most `concurrency` installs at once.
+///
+/// Transient marketplace failures are retried with backoff. A failing extension
+/// never stops the others; failures are collected into the returned summary.
+export async function installExtensions(
+  hostname: string,
+  extensionsDir: string,
+  extensions: string[],
+  concurrency: number = 3
+): Promise<ExtensionInstallSummary> {
+  const summary: ExtensionInstallSummary = { installed: [], failed: [] };
+  const queue = [...extensions];
+
+  const worker = async () => {
+    for (let extension = queue.shift(); extension !== undefined; extension = queue.shift()) {
+      try {
+        await installWithRetry(hostname, extensionsDir, extension);
+        summary.installed.push(extension);
+      } catch (error) {
+        summary.failed.push({ extension, reason: lastLine(error.message) });
+      }
+    }
+  };
+
+  const workers = Math.max(1, Math.min(concurrency, queue.length));
+  await Promise.all(Array.from({ length: workers }, worker));
+  return summary;
+}
+
+async function installWithRetry(hostname: string, extensionsDir: string, extension: string): Promise<void> {
+  const flavor = serverFlavor();
+  const install = `./${flavor.installDir}/${flavor.binary} --extensions-dir ${extensionsDir} --install-extension`;
+  const spec = EXTENSION_SPEC.exec(extension);
+  // A package path, or an id for a configured gallery, goes to the server as it is
+  const command = `cd ${BASE_DIR} && mkdir -p ${extensionsDir} && ` +
+    (spec && !loadSettings().extensionGallery ? cachedInstallCommand(install, extension, spec) : `${install} ${shellQuote(extension)}`);
+
+  for (let attempt = 1; ; attempt++) {
+    try {
+      await sshManager.executeCommand(hostname, command);
+      console.log(`✓ Installed extension ${extension}`);
+      return;
+    } catch (error) {
+      if (attempt >= MAX_ATTEMPTS || !TRANSIENT_FAILURE.test(error.message)) {
+        throw error;
+      }
+      const delay = 1000 * Math.pow(2, attempt - 1);
+      console.log(`Installing ${extension} failed transiently, retrying in ${delay}ms (${attempt}/${MAX_ATTEMPTS})...`);
+      await new Promise(resolve => setTimeout(resolve, delay));
+    }
+  }
+}
+
+// Install a marketplace extension from its package in EXTENSION_CACHE_DIR,
+// fetching the package from Open VSX first if no taskspace on the host has:
+// the build for the host's platform where the extension has one, else the
+// universal one. Without a pinned version, whichever version is cached is
+// used. An extension Open VSX does not have is installed by id from the
+// server's own gallery, uncached.
+function cachedInstallCommand(install: string, extension: string, spec: RegExpExecArray): string {
+  const [, publisher, name, version] = spec;
+  const id = `${publisher}.${name}`;
+  const cached = `${EXTENSION_CACHE_DIR}/${id}-${version || '[0-9]*'}`;
+  const release = version || 'latest';
+  return `{
+    case "$(uname -sm)" in
+      "Linux x86_64") target=linux-x64 ;;
+      "Linux aarch64"|"Linux arm64") target=linux-arm64 ;;
+      "Linux armv7l") target=linux-armhf ;;
+      "Darwin x86_64") target=darwin-x64 ;;
+      "Darwin arm64") target=darwin-arm64 ;;
+      *) target=universal ;;
+    esac
+    vsix=$(ls -t ${cached}@$target.vsix ${cached}.vsix 2>/dev/null | head -n 1)
+    if [ -z "$vsix" ]; then
+      for api in ${OPEN_VSX_API_URL}/${publisher}/${name}/$target/${release} ${OPEN_VSX_API_URL}/${publisher}/${name}/${release}; do
+        url=$(curl -fsSL "$api" 2>/dev/null | sed -n 's/.*"download":"\\([^"]*\\)".*/\\1/p')
+        [ -n "$url" ] && break
+      done
+      if [ -n "$url" ]; then
+        mkdir -p ${EXTENSION_CACHE_DIR}
+        vsix=${EXTENSION_CACHE_DIR}/$(basename "$url")
+        echo "Downloading $url into the extension cache"
+        curl -fsSL -o "$vsix.partial" "$url" && mv "$vsix.partial" "$vsix" || { rm -f "$vsix.partial"; exit 1; }
+      fi
+    fi
+    if [ -n "$vsix" ]; then
+      ${install} "$vsix"
+    else
+      ${install} ${shellQuote(extension)}
+    fi
+  }`;
+}
+
+// The useful part of an SSH failure is the last line of stderr
+function lastLine(message: string): string {
+  const lines = message.split('\n').map(line => line.trim()).filter(line => line);
+  return lines[lines.length - 1] || message;
+}
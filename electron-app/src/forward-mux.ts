@@ -0,0 +1,121 @@
+import * as net from 'net';
+import { Duplex } from 'stream';
+
+/// Traffic through one forwarded local port since it was opened
+export interface ForwardStats {
+  localPort: number;
+  remotePort: number;
+  openedAt: string;
+  // Connections being forwarded now, and all accepted so far
+  activeConnections: number;
+  totalConnections: number;
+  // Connections whose stream to the host could not be opened
+  failedConnections: number;
+  // Bytes from the host to this machine, and from this machine to the host
+  bytesIn: number;
+  bytesOut: number;
+  lastActivity?: string;
+}
+
+/// Opens one stream to `remotePort` on the host, e.g. an SSH direct-tcpip
+/// channel on a connection that already exists
+export type StreamOpener = (remotePort: number, origin: net.Socket) => Promise<Duplex>;
+
+interface Forward {
+  server: net.Server;
+  stats: ForwardStats;
+}
+
+/// The local ends of every port forward to one host. Each forward is a
+/// listener on localhost; each connection to it gets its own stream from
+/// `open`, so any number of forwards share the host's one SSH connection
+/// instead of taking a process or connection each. Counts traffic per forward.
+export class ForwardMultiplexer {
+  private forwards = new Map<number, Forward>();
+
+  constructor(private hostname: string, private openStream: StreamOpener) {}
+
+  /// Forward `localhost:<localPort>` to `localhost:<remotePort>` on the host,
+  /// replacing any forward of `localPort`. Closing the returned listener ends the forward.
+  open(localPort: number, remotePort: number): net.Server {
+    this.forwards.get(localPort)?.server.close();
+    const stats: ForwardStats = {
+      localPort, remotePort, openedAt: new Date().toISOString(),
+      activeConnections: 0, totalConnections: 0, failedConnections: 0, bytesIn: 0, bytesOut: 0
+    };
+    const server = net.createServer((socket) => this.accept(socket, stats));
+    const forward = { server, stats };
+    server.on('error', (error) => console.error(`Forwarding localhost:${localPort} to ${this.hostname}:${remotePort} failed: ${error.message}`));
+    server.on('close', () => {
+      if (this.forwards.get(localPort) === forward) {
+        this.forwards.delete(localPort);
+      }
+    });
+    server.listen(localPort, '127.0.0.1');
+    this.forwards.set(localPort, forward);
+    return server;
+  }
+
+  /// Traffic of every open forward, by local port
+  stats(): ForwardStats[] {
+    return [...this.forwards.values()].map(forward => ({ ...forward.stats })).sort((a, b) => a.localPort - b.localPort);
+  }
+
+  /// Stop every forward
+  closeAll(): void {
+    for (const forward of this.forwards.values()) {
+      forward.server.close();
+    }
+    this.forwards.clear();
+  }
+
+  private accept(socket: net.Socket, stats: ForwardStats): void {
+    stats.totalConnections++;
+    stats.activeConnections++;
+    let counted = true;
+    const done = () => {
+      if (counted) {
+        counted = false;
+        stats.activeConnections--;
+      }
+    };
+    socket.on('close', done);
+    socket.on('error', () => socket.destroy());
+
+    this.openStream(stats.remotePort, socket).then((stream) => {
+      if (socket.destroyed) {
+        stream.destroy();
+        return;
+      }
+      const touch = () => stats.lastActivity = new Date().toISOString();
+      socket.on('data', (chunk: Buffer) => { stats.bytesOut += chunk.length; touch(); });
+      stream.on('data', (chunk: Buffer) => { stats.bytesIn += chunk.length; touch(); });
+      socket.pipe(stream).pipe(socket);
+      socket.on('close', () => stream.destroy());
+      stream.on('error', () => socket.destroy());
+      stream.on('close', () => socket.destroy());
+    }, () => {
+      stats.failedConnections++;
+      socket.destroy();
+    });
+  }
+}
+
+function formatBytes(bytes: number): string {
+  const units = ['B', 'KB', 'MB', 'GB'];
+  let unit = 0;
+  while (bytes >= 1024 && unit < units.length - 1) {
+    bytes /= 1024;
+    unit++;
+  }
+  return `${unit === 0 ? bytes : bytes.toFixed(1)} ${units[unit]}`;
+}
+
+/// One line per forward for `--status`, e.g.
+/// `localhost:8080 -> 43211: 2 open, 17 total, 1.2 MB in, 80.5 KB out`
+export function formatForwardStats(stats: ForwardStats[]): string[] {
+  return stats.map(forward => `localhost:${forward.localPort} -> ${forward.remotePort}: ` +
+    `${forward.activeConnections} open, ${forward.totalConnections} total` +
+    (forward.failedConnections > 0 ? `, ${forward.failedConnections} failed` : '') +
+    `, ${formatBytes(forward.bytesIn)} in, ${formatBytes(forward.bytesOut)} out`);
+}
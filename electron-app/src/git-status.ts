@@ -0,0 +1,166 @@
+import * as fs from 'fs';
+import * as path from 'path';
+import { sshManager } from './ssh-manager.js';
+import { BASE_DIR, loadSettings, sessionDir } from './settings.js';
+import { listSessions } from './sessions.js';
+
+/// The state of one taskspace's clone, as `--git-status` reports it
+export interface TaskSpaceGitStatus {
+  session: string;
+  hostname: string;
+  taskspace: string;
+  uuid: string;
+  // Null on a detached HEAD
+  branch: string | null;
+  // Null when the branch tracks nothing, so ahead/behind are unknown
+  upstream: string | null;
+  ahead: number;
+  behind: number;
+  staged: number;
+  modified: number;
+  untracked: number;
+  conflicts: number;
+  stashes: number;
+  // Why the clone could not be read, e.g. the host was unreachable
+  error?: string;
+}
+
+/// Whether the taskspace has work that exists only in it: changes, stashes,
+/// commits not pushed, or a branch that was never pushed
+export function hasUnsavedWork(status: TaskSpaceGitStatus): boolean {
+  return status.staged + status.modified + status.untracked + status.conflicts + status.stashes + status.ahead > 0 ||
+    (!status.upstream && !!status.branch);
+}
+
+// One line per fact, each prefixed with the taskspace's uuid and a tab: git's
+// porcelain v2 status (branch headers and one line per changed file), then the stash count
+function gitStatusScript(uuids: string[]): string {
+  return `
+    for uuid in ${uuids.join(' ')}; do
+      dir=${BASE_DIR}/taskspaces/$uuid/clone
+      if ! git -C "$dir" rev-parse --git-dir >/dev/null 2>&1; then
+        printf '%s\\tmissing\\n' "$uuid"
+        continue
+      fi
+      git -C "$dir" status --porcelain=v2 --branch 2>&1 | sed "s/^/$uuid\\t/"
+      printf '%s\\tstashes %s\\n' "$uuid" "$(git -C "$dir" stash list 2>/dev/null | wc -l | tr -d ' ')"
+    done
+    true
+  `;
+}
+
+/// The git state of every taskspace of `sessions` (default: every session),
+/// one SSH round trip per host, all hosts at once. The app does not need to
+/// be running; the taskspaces come from each session's taskspaces.json.
+export async function collectGitStatus(sessions?: string[]): Promise<TaskSpaceGitStatus[]> {
+  const names = sessions || (await listSessions()).map(session => session.name);
+  const byHost = new Map<string, { session: string; name: string; uuid: string }[]>();
+  for (const session of names) {
+    let data: any = {};
+    try {
+      data = JSON.parse(fs.readFileSync(path.join(sessionDir(session), 'taskspaces.json'), 'utf8'));
+    } catch {
+      continue;
+    }
+    if (!data.hostname) {
+      continue;
+    }
+    const entries = byHost.get(data.hostname) || [];
+    entries.push(...(data.taskspaces || []).map((taskspace: any) => ({ session, name: taskspace.name, uuid: taskspace.uuid })));
+    byHost.set(data.hostname, entries);
+  }
+
+  const remoteUser = loadSettings().remoteUser;
+  const results = await Promise.all([...byHost.entries()].map(async ([hostname, taskspaces]) => {
+    if (remoteUser) {
+      sshManager.setRemoteUser(hostname, remoteUser);
+    }
+    // Only uuids go into the script, so a name can't break it
+    const uuids = taskspaces.map(taskspace => taskspace.uuid).filter(uuid => /^[\w-]+$/.test(uuid));
+    let output = '';
+    let error: string | undefined;
+    try {
+      output = await sshManager.executeCommand(hostname, gitStatusScript(uuids));
+    } catch (failure) {
+      error = failure.message.split('\n').filter((line: string) => line.trim()).pop() || failure.message;
+    }
+    return taskspaces.map(taskspace => parseGitStatus(taskspace.session, hostname, taskspace.name, taskspace.uuid, output, error));
+  }));
+  return results.flat();
+}
+
+function parseGitStatus(session: string, hostname: string, taskspace: string, uuid: string, output: string, error?: string): TaskSpaceGitStatus {
+  const status: TaskSpaceGitStatus = {
+    session, hostname, taskspace, uuid,
+    branch: null, upstream: null, ahead: 0, behind: 0,
+    staged: 0, modified: 0, untracked: 0, conflicts: 0, stashes: 0,
+    error
+  };
+  if (error) {
+    return status;
+  }
+
+  const lines = output.split('\n').filter(line => line.startsWith(`${uuid}\t`)).map(line => line.slice(uuid.length + 1));
+  if (lines.length === 0 || lines[0] === 'missing') {
+    status.error = 'no git clone';
+    return status;
+  }
+  for (const line of lines) {
+    const [kind, ...rest] = line.split(' ');
+    if (line.startsWith('# branch.head ')) {
+      status.branch = rest[1] === '(detached)' ? null : rest[1];
+    } else if (line.startsWith('# branch.upstream ')) {
+      status.upstream = rest[1];
+    } else if (line.startsWith('# branch.ab ')) {
+      status.ahead = Math.abs(Number(rest[1]));
+      status.behind = Math.abs(Number(rest[2]));
+    } else if (kind === '1' || kind === '2') {
+      // XY: the index and the worktree state, '.' for unchanged
+      const [index, worktree] = rest[0];
+      status.staged += index !== '.' ? 1 : 0;
+      status.modified += worktree !== '.' ? 1 : 0;
+    } else if (kind === 'u') {
+      status.conflicts++;
+    } else if (kind === '?') {
+      status.untracked++;
+    } else if (kind === 'stashes') {
+      status.stashes = Number(rest[0]) || 0;
+    } else if (kind === 'fatal:') {
+      status.error = line;
+    }
+  }
+  return status;
+}
+
+/// Human-readable form: one line per taskspace, the ones with unsaved work first
+export function formatGitStatus(statuses: TaskSpaceGitStatus[]): string {
+  if (statuses.length === 0) {
+    return 'No taskspaces';
+  }
+  const unsaved = statuses.filter(status => !status.error && hasUnsavedWork(status));
+  const sorted = [...statuses].sort((a, b) =>
+    Number(hasUnsavedWork(b) && !b.error) - Number(hasUnsavedWork(a) && !a.error) ||
+    a.session.localeCompare(b.session) || a.taskspace.localeCompare(b.taskspace));
+
+  const lines = sorted.map((status) => {
+    const where = `${status.session}/${status.taskspace}`.padEnd(32);
+    if (status.error) {
+      return `  ? ${where} ${status.hostname}: ${status.error}`;
+    }
+    const facts: string[] = [];
+    if (!status.upstream) {
+      facts.push('not pushed');
+    } else if (status.ahead || status.behind) {
+      facts.push(`${status.ahead} ahead, ${status.behind} behind ${status.upstream}`);
+    }
+    for (const [count, what] of [[status.staged, 'staged'], [status.modified, 'modified'], [status.untracked, 'untracked'],
+      [status.conflicts, 'conflicted'], [status.stashes, 'stashed']] as [number, string][]) {
+      if (count > 0) {
+        facts.push(`${count} ${what}`);
+      }
+    }
+    const mark = hasUnsavedWork(status) ? '✗' : '✓';
+    return `  ${mark} ${where} ${status.branch || '(detached)'}${facts.length > 0 ? `: ${facts.join(', ')}` : ', clean'}`;
+  });
+  return [`${unsaved.length} of ${statuses.length} taskspaces have work that exists only there:`, ...lines].join('\n');
+}
@@ -0,0 +1,122 @@
+import * as fs from 'fs';
+import * as path from 'path';
+import { createCipheriv, createDecipheriv, randomBytes } from 'crypto';
+import { DEFAULT_SESSION, SESSION_NAME, TASKSPACES_FILE, isValidSessionName, loadSettings, sessionDir } from './settings.js';
+
+// Marks a handoff bundle, so a wrong file is reported as such rather than as a bad key
+const BUNDLE_PREFIX = 'theoldswitcheroo-handoff:v1:';
+
+// A bundle older than this is refused, so a copy found later can't be replayed
+const BUNDLE_TTL_MS = 60 * 60 * 1000;
+
+/// What one client tells another about a session: where it runs and its
+/// taskspaces. Connection tokens never travel; the accepting client reads
+/// them from the host over its own SSH login, like after any restart.
+interface HandoffBundle {
+  session: string;
+  hostname: string;
+  remoteUser?: string;
+  exportedAt: string;
+  taskspaces: any;
+}
+
+/// Seal the current session into an encrypted bundle. Returns the bundle
+/// (one line of text) and the key that opens it, to be passed on separately.
+export function exportHandoff(): { bundle: string; key: string } {
+  let data: any;
+  try {
+    data = JSON.parse(fs.readFileSync(TASKSPACES_FILE, 'utf8'));
+  } catch {
+    throw new Error(`The session${SESSION_NAME === DEFAULT_SESSION ? '' : ` ${SESSION_NAME}`} has no saved taskspaces to hand off (${TASKSPACES_FILE})`);
+  }
+  if (!data.hostname) {
+    throw new Error(`${TASKSPACES_FILE} does not say which host the session runs on`);
+  }
+
+  // Local ports belong to this machine; the other one picks its own
+  const taskspaces = {
+    ...data,
+    taskspaces: (data.taskspaces || []).map(({ localPort, proxyPort, ...taskspace }: any) => taskspace)
+  };
+  const handoff: HandoffBundle = {
+    session: SESSION_NAME,
+    hostname: data.hostname,
+    remoteUser: loadSettings().remoteUser,
+    exportedAt: new Date().toISOString(),
+    taskspaces
+  };
+
+  const key = randomBytes(32);
+  const iv = randomBytes(12);
+  const cipher = createCipheriv('aes-256-gcm', key, iv);
+  const sealed = Buffer.concat([cipher.update(JSON.stringify(handoff), 'utf8'), cipher.final()]);
+  return {
+    bundle: BUNDLE_PREFIX + Buffer.concat([iv, cipher.getAuthTag(), sealed]).toString('base64url'),
+    key: key.toString('base64url')
+  };
+}
+
+/// Open a bundle with its key and make its session this machine's: the
+/// taskspaces go to the session's taskspaces.json, the host and remote user
+/// to the session's settings.json. Refuses to overwrite a session that
+/// already has taskspaces unless `force`. Returns the session's name.
+export function acceptHandoff(bundle: string, key: string, force: boolean = false): { session: string; hostname: string; taskspaces: number } {
+  const handoff = openBundle(bundle.trim(), key.trim());
+
+  if (Date.now() - Date.parse(handoff.exportedAt) > BUNDLE_TTL_MS) {
+    throw new Error(`The handoff bundle was exported at ${handoff.exportedAt} and has expired; export a new one`);
+  }
+  if (!isValidSessionName(handoff.session)) {
+    throw new Error(`The handoff bundle names an invalid session '${handoff.session}'`);
+  }
+
+  const dir = sessionDir(handoff.session);
+  const taskspacesFile = path.join(dir, 'taskspaces.json');
+  if (!force && fs.existsSync(taskspacesFile)) {
+    const existing = JSON.parse(fs.readFileSync(taskspacesFile, 'utf8'));
+    if ((existing.taskspaces || []).length > 0) {
+      throw new Error(`This machine already has ${existing.taskspaces.length} taskspaces in that session (${taskspacesFile}); pass --force to replace them`);
+    }
+  }
+
+  fs.mkdirSync(dir, { recursive: true });
+  fs.writeFileSync(taskspacesFile, JSON.stringify(handoff.taskspaces, null, 2));
+
+  // The default session's settings.json is the main settings file, which is left alone
+  if (handoff.session !== DEFAULT_SESSION) {
+    const settingsFile = path.join(dir, 'settings.json');
+    let settings: any = {};
+    try {
+      settings = JSON.parse(fs.readFileSync(settingsFile, 'utf8'));
+    } catch {
+      // No session settings yet
+    }
+    settings.hostname = handoff.hostname;
+    if (handoff.remoteUser) {
+      settings.remoteUser = handoff.remoteUser;
+    }
+    fs.writeFileSync(settingsFile, JSON.stringify(settings, null, 2));
+  }
+
+  return { session: handoff.session, hostname: handoff.hostname, taskspaces: (handoff.taskspaces.taskspaces || []).length };
+}
+
+function openBundle(bundle: string, key: string): HandoffBundle {
+  if (!bundle.startsWith(BUNDLE_PREFIX)) {
+    throw new Error('Not a handoff bundle');
+  }
+  const raw = Buffer.from(bundle.slice(BUNDLE_PREFIX.length), 'base64url');
+  const keyBytes = Buffer.from(key, 'base64url');
+  if (keyBytes.length !== 32 || raw.length < 28) {
+    throw new Error('The handoff key or bundle is malformed');
+  }
+
+  try {
+    const decipher = createDecipheriv('aes-256-gcm', keyBytes, raw.subarray(0, 12));
+    decipher.setAuthTag(raw.subarray(12, 28));
+    const json = Buffer.concat([decipher.update(raw.subarray(28)), decipher.final()]).toString('utf8');
+    return JSON.parse(json);
+  } catch {
+    throw new Error('The handoff key does not open this bundle, or the bundle was altered');
+  }
+}
@@ -0,0 +1,112 @@
+import * as http from 'http';
+import * as https from 'https';
+import { randomBytes, randomUUID } from 'crypto';
+
+/// A single readiness probe against a server's base URL.
+/// `run` resolves when the check passes and rejects with the reason otherwise.
+export interface HealthCheck {
+  name: string;
+  run(baseUrl: string, timeoutMs: number): Promise<void>;
+}
+
+// A request over HTTP or HTTPS, by the URL's scheme. `tls` servers have
+// self-signed certificates and the checks reach them through the SSH
+// tunnel, so the certificate is not verified here.
+function request(url: URL, options: https.RequestOptions, callback?: (res: http.IncomingMessage) => void): http.ClientRequest {
+  return url.protocol === 'https:'
+    ? https.request(url, { ...options, rejectUnauthorized: false }, callback)
+    : http.request(url, options, callback);
+}
+
+// Redirects the workbench check follows, e.g. code-server's from `/` to the folder it opens
+const MAX_REDIRECTS = 3;
+
+/// The workbench page must load with HTTP 200, after any redirects.
+export const workbenchCheck: HealthCheck = {
+  name: 'workbench',
+  run(baseUrl: string, timeoutMs: number): Promise<void> {
+    return loadPage(baseUrl, timeoutMs, MAX_REDIRECTS);
+  }
+};
+
+function loadPage(url: string, timeoutMs: number, redirects: number): Promise<void> {
+  return new Promise((resolve, reject) => {
+    const req = request(new URL(url), {}, (res) => {
+      res.resume();
+      if (res.statusCode === 200) {
+        resolve();
+      } else if (res.statusCode >= 300 && res.statusCode < 400 && res.headers.location && redirects > 0) {
+        loadPage(new URL(res.headers.location, url).toString(), timeoutMs, redirects - 1).then(resolve, reject);
+      } else {
+        reject(new Error(`HTTP ${res.statusCode}`));
+      }
+    });
+
+    req.on('error', reject);
+    req.setTimeout(timeoutMs, () => {
+      req.destroy();
+      reject(new Error(`no response within ${timeoutMs}ms`));
+    });
+    req.end();
+  });
+}
+
+/// The server must accept a websocket upgrade the way the workbench client
+/// requests one. A server that serves HTML but can't upgrade leaves the
+/// editor stuck on "reconnecting".
+export const websocketCheck: HealthCheck = {
+  name: 'websocket',
+  run(baseUrl: string, timeoutMs: number): Promise<void> {
+    return new Promise((resolve, reject) => {
+      const url = new URL(baseUrl);
+      url.searchParams.set('reconnectionToken', randomUUID());
+      url.searchParams.set('reconnection', 'false');
+      url.searchParams.set('skipWebSocketFrames', 'false');
+
+      const req = request(url, {
+        headers: {
+          'Connection': 'Upgrade',
+          'Upgrade': 'websocket',
+          'Sec-WebSocket-Version': '13',
+          'Sec-WebSocket-Key': randomBytes(16).toString('base64')
+        }
+      });
+
+      req.on('upgrade', (_res, socket) => {
+        socket.destroy();
+        resolve();
+      });
+
+      req.on('response', (res) => {
+        res.resume();
+        reject(new Error(`upgrade refused with HTTP ${res.statusCode}`));
+      });
+
+      req.on('error', reject);
+      req.setTimeout(timeoutMs, () => {
+        req.destroy();
+        reject(new Error(`no upgrade within ${timeoutMs}ms`));
+      });
+
+      req.end();
+    });
+  }
+};
+
+/// Readiness checks each server backend must pass, in order, by flavor name.
+export const BACKEND_HEALTH_CHECKS: Record<string, HealthCheck[]> = {
+  'openvscode': [workbenchCheck, websocketCheck],
+  'code-server': [workbenchCheck, websocketCheck]
+};
+
+/// Run `checks` in order, stopping at the first failure.
+/// The rejection names the failing check so it can be shown to the user.
+export async function runHealthChecks(checks: HealthCheck[], baseUrl: string, timeoutMs: number): Promise<void> {
+  for (const check of checks) {
+    try {
+      await check.run(baseUrl, timeoutMs);
+    } catch (error) {
+      throw new Error(`${check.name} check failed: ${error.message}`);
+    }
+  }
+}
@@ -0,0 +1,84 @@
+import { sshManager } from './ssh-manager.js';
+import { BASE_DIR } from './settings.js';
+import { t } from './i18n.js';
+
+/// What a remote host offers, as far as running taskspaces on it is concerned
+export interface HostCapabilities {
+  arch: string;
+  kernel: string;
+  libc: string;
+  os: string;
+  containerRuntimes: string[];
+  systemdUser: boolean;
+  tmux: string | null;
+  baseDirFilesystem: string;
+  diskFree: string;
+  gpus: string[];
+  githubReachable: boolean;
+}
+
+// One `key: value` line per fact; list-valued facts repeat their key
+const INSPECT_SCRIPT = `
+  echo "arch: $(uname -m)"
+  echo "kernel: $(uname -r)"
+  echo "libc: $(ldd --version 2>&1 | head -n 1)"
+  echo "os: $(. /etc/os-release 2>/dev/null && echo "$PRETTY_NAME")"
+  for runtime in docker podman nerdctl; do
+    command -v $runtime >/dev/null 2>&1 && echo "container: $runtime"
+  done
+  systemctl --user show-environment >/dev/null 2>&1 && echo "systemd-user: yes"
+  command -v tmux >/dev/null 2>&1 && echo "tmux: $(tmux -V)"
+  mkdir -p ${BASE_DIR}
+  echo "fstype: $(stat -f -c %T ${BASE_DIR})"
+  echo "disk: $(df -h ${BASE_DIR} | awk 'NR == 2 { print $4 " free of " $2 }')"
+  command -v nvidia-smi >/dev/null 2>&1 && nvidia-smi --query-gpu=name,driver_version --format=csv,noheader | sed 's/^/gpu: /'
+  curl -sS -o /dev/null --max-time 5 https://github.com 2>/dev/null && echo "github: yes"
+  true
+`;
+
+/// Collect the capability report for `hostname` in a single SSH round trip.
+export async function inspectHost(hostname: string): Promise<HostCapabilities> {
+  const report = await sshManager.executeCommand(hostname, INSPECT_SCRIPT);
+
+  const values = (key: string) => report.split('\n')
+    .filter(line => line.startsWith(`${key}: `))
+    .map(line => line.slice(key.length + 2).trim());
+  const value = (key: string) => values(key)[0] || '';
+
+  return {
+    arch: value('arch'),
+    kernel: value('kernel'),
+    libc: value('libc'),
+    os: value('os'),
+    containerRuntimes: values('container'),
+    systemdUser: value('systemd-user') === 'yes',
+    tmux: value('tmux') || null,
+    baseDirFilesystem: value('fstype'),
+    diskFree: value('disk'),
+    gpus: values('gpu'),
+    githubReachable: value('github') === 'yes',
+  };
+}
+
+/// Human-readable form of the report, one fact per line
+export function formatCapabilities(hostname: string, caps: HostCapabilities): string {
+  const yesNo = (flag: boolean) => flag ? t('caps-yes') : t('caps-no');
+  const rows: [string, string][] = [
+    [t('caps-arch'), caps.arch],
+    [t('caps-os'), caps.os || t('caps-unknown')],
+    [t('caps-kernel'), caps.kernel],
+    [t('caps-libc'), caps.libc || t('caps-unknown')],
+    [t('caps-container-runtimes'), caps.containerRuntimes.join(', ') || t('caps-none')],
+    [t('caps-systemd-user'), yesNo(caps.systemdUser)],
+    [t('caps-tmux'), caps.tmux || t('caps-not-installed')],
+    [BASE_DIR, `${caps.baseDirFilesystem}, ${caps.diskFree}`],
+    [t('caps-gpus'), caps.gpus.join('; ') || t('caps-none')],
+    [t('caps-github'), yesNo(caps.githubReachable)],
+  ];
+  // Labels differ in length between languages, so align the values to the longest
+  const width = Math.max(...rows.filter(([label]) => label !== BASE_DIR).map(([label]) => label.length)) + 1;
+  return [
+    t('caps-title', { host: hostname }),
+    ...rows.map(([label, value]) => `  ${`${label}:`.padEnd(label === BASE_DIR ? 0 : width)} ${value}`)
+  ].join('\n');
+}
@@ -0,0 +1,121 @@
+import * as fs from 'fs';
+import * as path from 'path';
+import { fileURLToPath } from 'url';
+import { LOCAL_DATA_DIR, loadSettings } from './settings.js';
+
+/// Translations of the user-facing CLI messages, in Fluent (.ftl) files: one
+/// per locale in `locales/` next to the app, where `en.ftl` holds every
+/// message, and optionally in LOCAL_DATA_DIR/locales, which take precedence.
+/// Only the part of Fluent the messages need is understood: messages,
+/// indented continuation lines, comments, `{ $variable }` and string literal
+/// placeables like `{ "{" }`. Selectors and terms are not.
+
+const __dirname = path.dirname(fileURLToPath(import.meta.url));
+const BUNDLED_LOCALES_DIR = path.join(__dirname, '..', 'locales');
+const USER_LOCALES_DIR = path.join(LOCAL_DATA_DIR, 'locales');
+
+const FALLBACK_LOCALE = 'en';
+
+/// Parse Fluent source into its messages, by id
+export function parseFtl(source: string): Map<string, string> {
+  const messages = new Map<string, string>();
+  let current: { id: string; lines: string[] } | null = null;
+  const finish = () => {
+    if (current) {
+      messages.set(current.id, current.lines.join('\n').trim());
+      current = null;
+    }
+  };
+
+  for (const line of source.split(/\r?\n/)) {
+    if (line.startsWith('#')) {
+      finish();
+      continue;
+    }
+    const message = /^([a-zA-Z][\w-]*)\s*=\s?(.*)$/.exec(line);
+    if (message) {
+      finish();
+      current = { id: message[1], lines: [message[2]] };
+    } else if (current && /^\s+\S/.test(line)) {
+      current.lines.push(line.trim());
+    } else if (line.trim()) {
+      finish();
+    }
+  }
+  finish();
+  return messages;
+}
+
+/// The locale to show messages in: the `locale` setting, else the one from
+/// LC_ALL, LC_MESSAGES or LANG, e.g. `de_DE.UTF-8` -> `de-DE`
+export function messageLocale(): string {
+  const raw = loadSettings().locale || process.env.LC_ALL || process.env.LC_MESSAGES || process.env.LANG || '';
+  const locale = raw.split('.')[0].split('@')[0].replace('_', '-');
+  return locale && locale !== 'C' && locale !== 'POSIX' ? locale : FALLBACK_LOCALE;
+}
+
+// Locales to look a message up in, most specific first: `de-DE`, `de`, `en`
+function localeChain(locale: string): string[] {
+  const chain = [locale];
+  const language = locale.split('-')[0];
+  if (language !== locale) {
+    chain.push(language);
+  }
+  if (!chain.includes(FALLBACK_LOCALE)) {
+    chain.push(FALLBACK_LOCALE);
+  }
+  return chain;
+}
+
+function loadBundle(locale: string): Map<string, string> {
+  const messages = new Map<string, string>();
+  for (const dir of [BUNDLED_LOCALES_DIR, USER_LOCALES_DIR]) {
+    const file = path.join(dir, `${locale}.ftl`);
+    if (!fs.existsSync(file)) {
+      continue;
+    }
+    try {
+      parseFtl(fs.readFileSync(file, 'utf8')).forEach((value, id) => messages.set(id, value));
+    } catch (error) {
+      console.log(`Warning: Could not load translations from ${file}: ${error.message}`);
+    }
+  }
+  return messages;
+}
+
+let bundles: Map<string, string>[] | null = null;
+// The id of each message text t() has produced lately, for messageIdOf. Only
+// the most recent ones are kept: a text is looked up right after it is printed.
+const producedIds = new Map<string, string>();
+const MAX_PRODUCED_IDS = 256;
+
+/// The message `id` in the user's locale, with `args` filled in. A message
+/// missing from a translation falls back to English, and a message missing
+/// altogether shows as its id.
+export function t(id: string, args: Record<string, string | number> = {}): string {
+  if (!bundles) {
+    bundles = localeChain(messageLocale()).map(loadBundle);
+  }
+  const pattern = bundles.map(bundle => bundle.get(id)).find(value => value !== undefined);
+  if (pattern === undefined) {
+    return id;
+  }
+  const text = pattern.replace(/\{\s*(?:\$([\w-]+)|"([^"]*)")\s*\}/g, (placeable, variable, literal) => {
+    if (literal !== undefined) {
+      return literal;
+    }
+    return variable in args ? String(args[variable]) : placeable;
+  });
+  // Most recent last, so the oldest is the first to go
+  producedIds.delete(text);
+  producedIds.set(text, id);
+  if (producedIds.size > MAX_PRODUCED_IDS) {
+    producedIds.delete(producedIds.keys().next().value);
+  }
+  return text;
+}
+
+/// The id of the message t() returned as `text`, if it did
+export function messageIdOf(text: string): string | undefined {
+  return producedIds.get(text);
+}
@@ -0,0 +1,75 @@
+import { Settings } from './settings.js';
+import { shellQuote } from './ssh-manager.js';
+
+// Runs on the host with the server's bundled node. Appends every line of stdin to the
+// full log, and prints the lines that pass the level, pattern and sampling.
+// Lines up to the "Web UI available" banner always pass when asked to wait for
+// it, since the app reads the server's port from them.
+const FILTER_SCRIPT = `
+const fs = require('fs');
+const [fullLog, level, pattern, sample, defaultLevel, waitForBanner] = process.argv.slice(1);
+const LEVELS = { trace: 0, debug: 1, info: 2, warning: 3, warn: 3, error: 4, critical: 4 };
+const minLevel = LEVELS[level] ?? 0;
+const regex = pattern ? new RegExp(pattern) : null;
+const every = Math.max(1, Number(sample) || 1);
+const full = fs.openSync(fullLog, 'a');
+let started = waitForBanner !== '1';
+let sampled = 0;
+let dropped = 0;
+setInterval(() => {
+  if (dropped > 0) {
+    console.log('[log-filter] ' + dropped + ' lines not streamed; the full log is ' + fullLog);
+    dropped = 0;
+  }
+}, 60000).unref();
+require('readline').createInterface({ input: process.stdin }).on('line', (line) => {
+  fs.writeSync(full, line + '\\n');
+  if (!started) {
+    started = /Web UI available at/.test(line);
+    console.log(line);
+    return;
+  }
+  const match = /\\[(trace|debug|info|warning|warn|error|critical)\\]/i.exec(line);
+  const lineLevel = LEVELS[match ? match[1].toLowerCase() : defaultLevel];
+  // Warnings and errors are never sampled away
+  if (lineLevel >= minLevel && (!regex || regex.test(line)) && (lineLevel >= 3 || sampled++ % every === 0)) {
+    console.log(line);
+  } else {
+    dropped++;
+  }
+});
+`;
+
+// The full log is moved aside to `.1` at server start once it grows past this
+const MAX_FULL_LOG_BYTES = 10 * 1024 * 1024;
+
+/// Whether any of `logLevel`, `logFilter` and `logSample` asks for less than every line
+export function logFilterEnabled(settings: Settings): boolean {
+  return !!(settings.logLevel || settings.logFilter || (settings.logSample && settings.logSample > 1));
+}
+
+/// Shell commands that start the filters for a server whose full output goes
+/// to `fullLog`, and the redirection to put on the server's `exec` line.
+/// Paths must be absolute (or start with ~), since the server may run in
+/// another directory.
+///
+/// The server writes to two named pipes, one per stream, so its stderr stays
+/// stderr, and `exec` still keeps the PID that `--status` and `--stop` use.
+/// The filters end when the server closes the pipes by exiting.
+export function logFilterCommands(settings: Settings, node: string, fullLog: string): { setup: string; redirect: string } {
+  if (settings.logFilter) {
+    // Fail here rather than in a filter on the host, which would lose the server's output
+    new RegExp(settings.logFilter);
+  }
+  // fullLog stays unquoted so a leading ~ expands
+  const filterArgs = `${fullLog} ${[settings.logLevel || 'trace', settings.logFilter || '', String(settings.logSample || 1)].map(shellQuote).join(' ')}`;
+  const outPipe = `${fullLog}.out.fifo`;
+  const errPipe = `${fullLog}.err.fifo`;
+
+  const setup = `
+    if [ -f ${fullLog} ] && [ "$(wc -c < ${fullLog})" -gt ${MAX_FULL_LOG_BYTES} ]; then mv ${fullLog} ${fullLog}.1; fi
+    rm -f ${outPipe} ${errPipe} && mkfifo ${outPipe} ${errPipe}
+    ${node} -e ${shellQuote(FILTER_SCRIPT)} ${filterArgs} info 1 < ${outPipe} &
+    ${node} -e ${shellQuote(FILTER_SCRIPT)} ${filterArgs} error 0 < ${errPipe} >&2 &`;
+  return { setup, redirect: `> ${outPipe} 2> ${errPipe}` };
+}
@@ -0,0 +1,63 @@
+import { loadSettings, Settings } from './settings.js';
+
+export type LogTimestampMode = NonNullable<Settings['logTimestamps']>;
+
+const DEFAULT_TIMESTAMP_FORMAT = 'YYYY-MM-DD HH:mm:ss';
+
+// Relative timestamps count from when the app started
+const startTime = Date.now();
+
+let cachedConfig: { mode: LogTimestampMode; format: string } | null = null;
+
+function timestampConfig(): { mode: LogTimestampMode; format: string } {
+  if (!cachedConfig) {
+    const settings = loadSettings();
+    cachedConfig = {
+      mode: settings.logTimestamps || 'none',
+      format: settings.logTimestampFormat || DEFAULT_TIMESTAMP_FORMAT
+    };
+  }
+  return cachedConfig;
+}
+
+/// Render `date` using a format string with the tokens
+/// `YYYY`, `MM`, `DD`, `HH`, `mm`, `ss` and `SSS`.
+export function formatTimestamp(date: Date, format: string, utc: boolean): string {
+  const pad = (value: number, width: number = 2) => String(value).padStart(width, '0');
+  const parts: Record<string, string> = utc ? {
+    YYYY: String(date.getUTCFullYear()),
+    MM: pad(date.getUTCMonth() + 1),
+    DD: pad(date.getUTCDate()),
+    HH: pad(date.getUTCHours()),
+    mm: pad(date.getUTCMinutes()),
+    ss: pad(date.getUTCSeconds()),
+    SSS: pad(date.getUTCMilliseconds(), 3)
+  } : {
+    YYYY: String(date.getFullYear()),
+    MM: pad(date.getMonth() + 1),
+    DD: pad(date.getDate()),
+    HH: pad(date.getHours()),
+    mm: pad(date.getMinutes()),
+    ss: pad(date.getSeconds()),
+    SSS: pad(date.getMilliseconds(), 3)
+  };
+  return format.replace(/YYYY|MM|DD|HH|mm|SSS|ss/g, token => parts[token]);
+}
+
+/// Prefix for one line of a streamed remote log (install scripts, VSCode servers),
+/// according to the `logTimestamps` and `logTimestampFormat` settings.
+///
+/// Returns e.g. `[2025-08-26 01:48:15] `, `[+3.2s] `, or an empty string.
+export function logTimestampPrefix(now: Date = new Date()): string {
+  const { mode, format } = timestampConfig();
+  switch (mode) {
+    case 'utc':
+      return `[${formatTimestamp(now, format, true)}] `;
+    case 'local':
+      return `[${formatTimestamp(now, format, false)}] `;
+    case 'relative':
+      return `[+${((now.getTime() - startTime) / 1000).toFixed(1)}s] `;
+    default:
+      return '';
+  }
+}
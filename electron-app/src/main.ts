@@ -1,14 +1,67 @@
-import { app, BaseWindow, WebContentsView, session, ipcMain, Session } from 'electron';
+import { app, BaseWindow, WebContentsView, session, ipcMain, powerMonitor, Session, clipboard } from 'electron';
 import * as path from 'path';
 import * as fs from 'fs';
 import * as os from 'os';
 import * as http from 'http';
-import { spawn, ChildProcess } from 'child_process';
+import * as net from 'net';
+import { ChildProcess, spawn } from 'child_process';
 import { randomUUID } from 'crypto';
 import { fileURLToPath } from 'url';
-import { LOCAL_DATA_DIR, TASKSPACES_FILE, SETTINGS_FILE, BASE_DIR, loadSettings, saveSettings, Settings } from './settings.js';
-import { sshManager } from './ssh-manager.js';
+import {
+  LOCAL_DATA_DIR, TASKSPACES_FILE, SETTINGS_FILE, BASE_DIR, loadSettings, saveSettings, Settings,
+  pinSettings, saveLastGood, loadLastGood, DEFAULT_SESSION, SESSION_NAME, SESSION_DIR, sessionDir, REMOTE_DAEMON_SOCKET, CONFIG_FILE
+} from './settings.js';
+import { sshManager, forEachLine, OutputSource, RemoteProcess, shellQuote, shellQuotePath } from './ssh-manager.js';
 import { TaskSpaceCommunicationManager } from './taskspace-communication-manager.js';
+import { logTimestampPrefix } from './log-format.js';
+import { SessionLogFile } from './session-log.js';
+import { installExtensions } from './extension-install.js';
+import { LIBC_PROBE, checkPlatform, installPrerequisites, muslGuidance } from './prereqs.js';
+import { findFreeLocalPort, isLocalPortInUse, localEndpointServes } from './port-forward.js';
+import { TokenProxy } from './token-proxy.js';
+import { inspectHost, formatCapabilities } from './host-capabilities.js';
+import { checkSessionStatus, formatStatus, serverPidFile } from './session-status.js';
+import { localDaemonSocket, proxyDaemonSocket } from './daemon-proxy.js';
+import { stopSession, stopSessionScript, teardownSessionScript } from './session-stop.js';
+import { ControlCommand, DETACHED_LOG, controlSocketPath, sendControlCommand, serveControlSocket, tailDetachedLog } from './control-socket.js';
+import { mountTaskSpace, unmountSession } from './mounts.js';
+import { findAppBrowser, openAppWindow } from './app-window.js';
+import { formatProcessTrees, killServerProcess, serverProcessTrees } from './process-viewer.js';
+import { audit } from './audit-log.js';
+import { EPHEMERAL_UNTIL_ENV, ephemeralDeadline, ephemeralSessionName, parseTtl } from './ephemeral.js';
+import { formatSessions, listSessions } from './sessions.js';
+import { acceptHandoff, exportHandoff } from './handoff.js';
+import { logFilterCommands, logFilterEnabled } from './log-filter.js';
+import { Notifier } from './notifications.js';
+import { DeadManTimer } from './dead-man.js';
+import { withRetry } from './retry.js';
+import { AppliedConfig, diffConfig, formatDelta, isEmptyDelta } from './config-diff.js';
+import { auditFleet, fleetHosts, formatAuditReport } from './security-audit.js';
+import { TunnelSupervisor } from './tunnel-supervisor.js';
+import { formatForwardStats } from './forward-mux.js';
+import { appendProfiles, existingProfiles, findRemoteTargets, profilesToml, suggestProfiles } from './vscode-remote-import.js';
+import { configureChaos, parseChaosSpec } from './chaos.js';
+import { TapeRecorder, TapeReplay } from './tape.js';
+import { t } from './i18n.js';
+import { enablePlainOutput } from './plain-output.js';
+import { enableMachineReadableErrors, nonInteractive } from './non-interactive.js';
+import { collectGitStatus, formatGitStatus } from './git-status.js';
+import { serverFlavor, connectionTokensEnabled } from './server-flavor.js';
+import { guardUnsavedWork } from './work-backup.js';
+import { syncUserSettings } from './settings-sync.js';
+import { installDotfiles } from './dotfiles.js';
+import { ensureReachable } from './reachability.js';
+import { prepareManagedKey } from './managed-keys.js';
+import { EnvironmentFingerprint, collectFingerprints, diffFingerprint, formatDrift, hasDrift } from './drift.js';
+import {
+  installServerd, pinServerPort, pinnedPortCommand, serverdEnabled, serverdShutdownCommand, serverdStopCommand, supervisedServerCommand
+} from './serverd.js';
+import {
+  DOWNLOAD_BLOCKED_EXIT_CODE, SERVER_ARCHITECTURES, SERVER_CACHE_DIR, PrimeCacheError, ServerChecksumError, ServerDownloadError,
+  asServerDownloadError, cachedServerTarball, fetchServerTarballCommand, primeServerCache, resolveServerVersion, serverReleaseName,
+  serverTransfer, uploadServerTarball
+} from './server-cache.js';
+import { BACKEND_HEALTH_CHECKS, HealthCheck, runHealthChecks } from './health-checks.js';
 
 // ES6 module equivalent of __dirname
 const __filename = fileURLToPath(import.meta.url);
@@ -22,7 +75,38 @@ interface Extensions {
 
 interface ServerInfo {
   port: number;
-  serverProcess?: ChildProcess;
+  serverProcess?: RemoteProcess;
+  webUiUrl?: string;
+  listenAddress?: string;
+  connectionToken?: string;
+  tlsHost?: string;
+  tlsFingerprint?: string;
+  failedExtensions?: string[];
+}
+
+/// A VSCode server that exited or timed out before announcing it was ready
+class ServerStartupError extends Error {
+  exitCode: number | null;
+  stderr: string;
+
+  constructor(message: string, exitCode: number | null, stderr: string) {
+    super(message);
+    this.name = 'ServerStartupError';
+    this.exitCode = exitCode;
+    this.stderr = stderr;
+  }
+}
+
+/// Facts extracted from the server's startup output
+interface ServerStartupFacts {
+  listenAddress?: string;
+  listenPort?: number;
+  webUiUrl?: string;
+  webUiPort?: number;
+  connectionToken?: string;
+  // With `tls`: the host's name and the SHA-256 fingerprint of its certificate
+  tlsHost?: string;
+  tlsFingerprint?: string;
 }
 
 interface ILoadingView {
@@ -34,15 +118,26 @@ interface SavedTaskSpaceDatum {
   uuid: string;
   name: string;
   port: number;
+  proxyPort?: number;
+  localPort?: number;
+  notes?: string[];
+  labels?: Record<string, string>;
+  webUiUrl?: string;
+  listenAddress?: string;
+  tlsFingerprint?: string;
   serverDataDir: string;
   lastSeen: string;
   extensions: Extensions;
+  applied?: AppliedConfig;
+  fingerprint?: EnvironmentFingerprint;
 }
 
 interface SavedTaskSpaceData {
   hostname: string | null;
   activeTaskSpaceUuid?: string | null;
   taskspaces: SavedTaskSpaceDatum[];
+  // When the app last detached from the session (see watchDetachKey), leaving its servers running
+  detachedAt?: string;
 }
 
 // Generate UUID v4
@@ -50,6 +145,14 @@ function generateUUID(): string {
   return randomUUID();
 }
 
+// ssh's exit code when the connection failed or dropped, as opposed to the remote command's own
+const SSH_CONNECTION_LOST_EXIT = 255;
+
+// Pre-warming opens at most this many files, however many the globs match
+const PREWARM_MAX_FILES = 500;
+const PREWARM_POLL_INTERVAL_MS = 2000;
+const PREWARM_TIMEOUT_MS = 10 * 60 * 1000;
+
 class TaskSpacePaths {
   dir: string;
   cloneDir: string;
@@ -93,43 +196,1187 @@ function readProjectExtensions(): Extensions {
       console.log(`Warning: Could not parse vscode-extensions.json: ${error.message}`);
     }
   }
-
-  console.log(`DEBUG: readProjectExtensions - final extensions:`, extensions);
-  return extensions;
-}
-
-// Configure user agent to prevent Electron blocking
-const STANDARD_USER_AGENT = 'Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36';
-
-async function main() {
-  const hostname = getHostname();
-
-  if (!hostname) {
-    console.error('No hostname provided');
-    app.quit();
-    return;
+
+  // Extra .vsix files from settings, for hosts that can't reach a marketplace
+  extensions.local = [...extensions.local, ...configuredExtensionFiles()];
+  // Marketplace extensions from settings and `--extension`
+  extensions.marketplace = [...new Set([...extensions.marketplace, ...(loadSettings().extensions || [])])];
+
+  console.log(`DEBUG: readProjectExtensions - final extensions:`, extensions);
+  return extensions;
+}
+
+// Expand the `extensionFiles` setting into absolute .vsix paths.
+// Directories contribute every .vsix file directly inside them.
+function configuredExtensionFiles(): string[] {
+  const files: string[] = [];
+
+  for (const entry of loadSettings().extensionFiles || []) {
+    const resolved = path.resolve(entry.replace(/^~(?=$|\/)/, os.homedir()));
+    try {
+      if (fs.statSync(resolved).isDirectory()) {
+        const vsixFiles = fs.readdirSync(resolved).filter(name => name.endsWith('.vsix')).sort();
+        if (vsixFiles.length === 0) {
+          console.log(`Warning: No .vsix files in extension directory ${resolved}`);
+        }
+        files.push(...vsixFiles.map(name => path.join(resolved, name)));
+      } else {
+        files.push(resolved);
+      }
+    } catch (error) {
+      console.log(`Warning: Skipping extension file ${entry}: ${error.message}`);
+    }
+  }
+
+  return files;
+}
+
+// Configure user agent to prevent Electron blocking
+const STANDARD_USER_AGENT = 'Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36';
+
+// The running app, so that signal and quit handlers can shut it down in order
+let activeApp: SwitcherooApp | null = null;
+let shuttingDown = false;
+let controlServer: net.Server | null = null;
+
+// Set in the background process started by --detach
+const isDetached = !!process.env.THEOLDSWITCHEROO_DETACHED;
+
+// How long graceful shutdown may take after a signal before we force quit
+const GRACEFUL_SHUTDOWN_TIMEOUT_SECS = 10;
+
+async function main() {
+  const configuredHostname = getHostname();
+
+  if (!configuredHostname) {
+    console.error(t('no-hostname'));
+    app.quit();
+    return;
+  }
+
+  // Replay the configuration this host last started successfully with
+  if (args.includes('--last-good')) {
+    const snapshot = loadLastGood(configuredHostname);
+    if (!snapshot) {
+      console.error(t('no-last-good', { host: configuredHostname }));
+      app.quit();
+      return;
+    }
+    console.log(`Using the configuration that last worked for ${configuredHostname} (saved ${snapshot.savedAt})`);
+    pinSettings(snapshot.settings);
+  }
+
+  let hostname: string;
+  try {
+    hostname = await selectHost(configuredHostname);
+  } catch (error) {
+    console.error(error.message);
+    app.quit();
+    return;
+  }
+
+  // A mistyped sink should stop startup, not go unnoticed until a crash
+  let notifier: Notifier;
+  try {
+    notifier = new Notifier(loadSettings().notifications);
+  } catch (error) {
+    console.error(t('invalid-notifications', { reason: error.message }));
+    app.quit();
+    return;
+  }
+
+  activeApp = new SwitcherooApp(hostname, configuredHostname, notifier);
+  await startControlSocket();
+  const deadline = ephemeralDeadline();
+  if (deadline) {
+    setTimeout(() => {
+      console.log(`The session's time to live is up (${deadline.toISOString()})`);
+      shutdownActiveApp('time to live is up').finally(() => app.exit(0));
+    }, Math.max(deadline.getTime() - Date.now(), 0));
+  }
+  await activeApp.bootUp();
+  // Only now, so that nothing startup asks in the terminal competes for its keys
+  if (!isDetached && !nonInteractive() && process.stdin.isTTY) {
+    watchDetachKey();
+  }
+}
+
+// The detach key is this prefix (Ctrl+], as in telnet) followed by `d`, like tmux's prefix-d
+const DETACH_PREFIX = 0x1d;
+
+/// In a foreground app, read the terminal a key at a time for the detach key.
+/// The terminal no longer turns Ctrl+C and Ctrl+Z into signals then, so they
+/// are passed on as the signals they stand for.
+function watchDetachKey() {
+  let prefixed = false;
+  process.stdin.setRawMode(true);
+  process.stdin.on('data', (keys: Buffer) => {
+    for (const key of keys) {
+      if (key === 0x03) {
+        process.kill(process.pid, 'SIGINT');
+      } else if (key === 0x1a) {
+        process.stdin.setRawMode(false);
+        process.kill(process.pid, 'SIGTSTP');
+      } else if (prefixed && key === 0x64) {
+        detachActiveApp();
+      }
+      prefixed = key === DETACH_PREFIX && !prefixed;
+    }
+  });
+  process.on('SIGCONT', () => process.stdin.setRawMode(true));
+  console.log('Press Ctrl+] then d to detach, leaving the servers running');
+}
+
+/// Leave the session without ending it: shut down like --stop, which leaves
+/// the servers running, and record in taskspaces.json that the app detached,
+/// which --status reports and the next start reattaches from.
+function detachActiveApp() {
+  if (shuttingDown || !activeApp) {
+    return;
+  }
+  activeApp.detachedAt = new Date().toISOString();
+  console.log('Detaching; the servers keep running. Start the app again to reattach');
+  if (serverFlavor().name === 'openvscode' && !loadSettings().persistServers) {
+    console.log('  openvscode-server shuts itself down once nothing has been connected for a while; set persistServers to keep it up');
+  }
+  process.stdin.setRawMode(false);
+  process.stdin.pause();
+  shutdownActiveApp('detach').finally(() => app.exit(0));
+}
+
+/// Let later `--status`, `--stop`, `--logs` and `--prewarm` invocations talk to this app
+async function startControlSocket(): Promise<void> {
+  const running = await sendControlCommand('status').catch(() => null);
+  if (running) {
+    console.warn(`Another instance (PID ${running.pid}) answers --status, --stop, --logs and --prewarm; this one will not`);
+    return;
+  }
+
+  controlServer = serveControlSocket({
+    status: () => ({
+      hostname: activeApp?.hostname,
+      detached: isDetached,
+      ready: !!activeApp?.ready,
+      startupError: activeApp?.startupError,
+      ephemeralUntil: ephemeralDeadline()?.toISOString(),
+      taskspaces: activeApp ? activeApp.taskspaces.length : 0,
+      forwards: activeApp ? activeApp.tunnels.stats() : []
+    }),
+    stop: () => {
+      console.log('Received stop command');
+      shutdownActiveApp('stop command').finally(() => app.exit(0));
+    },
+    // A foreground app logs to its terminal, not to the detached log
+    logs: (lines) => isDetached ? tailDetachedLog(lines) : [],
+    prewarm: async (name, globs) => {
+      if (!activeApp) {
+        throw new Error('The app is still starting');
+      }
+      const taskspace = name
+        ? activeApp.taskspaces.find(t => t.name === name || t.uuid === name)
+        : activeApp.taskspaces[activeApp.taskspaces.length - 1];
+      if (!taskspace) {
+        throw new Error(name ? `No taskspace named ${name}` : 'There are no taskspaces yet');
+      }
+      const prewarmGlobs = globs && globs.length > 0 ? globs : loadSettings().prewarmGlobs;
+      if (!prewarmGlobs || prewarmGlobs.length === 0) {
+        throw new Error('No globs to pre-warm; pass --globs or set prewarmGlobs');
+      }
+      return activeApp.prewarmTaskSpace(taskspace, prewarmGlobs);
+    },
+    listTaskSpaces: () => (activeApp ? activeApp.taskspaces : []).map(taskspace => ({
+      uuid: taskspace.uuid,
+      name: taskspace.name,
+      active: taskspace.uuid === activeApp.activeTaskSpaceUuid,
+      port: taskspace.port,
+      localPort: taskspace.localPort,
+      url: taskspace.port ? taskspace.vscodeUrl : undefined,
+      dir: `${BASE_DIR}/${new TaskSpacePaths(taskspace.uuid).dir}`
+    })),
+    createTaskSpace: async (name) => {
+      if (!activeApp) {
+        throw new Error('The app is still starting');
+      }
+      if (name && activeApp.taskspaces.some(t => t.name === name)) {
+        throw new Error(`There already is a taskspace named ${name}`);
+      }
+      const taskspace = await activeApp.createNewTaskSpace();
+      if (name) {
+        taskspace.name = name;
+        activeApp.notifyTaskSpacesChanged();
+        activeApp.emitLifecycleEvent('renamed', taskspace);
+      }
+      return { uuid: taskspace.uuid, name: taskspace.name, dir: `${BASE_DIR}/${new TaskSpacePaths(taskspace.uuid).dir}` };
+    },
+    deleteTaskSpace: async (name, purge) => {
+      if (!activeApp) {
+        throw new Error('The app is still starting');
+      }
+      const taskspace = activeApp.taskspaces.find(t => t.name === name || t.uuid === name);
+      if (!taskspace) {
+        throw new Error(`No taskspace named ${name}`);
+      }
+      await activeApp.deleteTaskSpace(taskspace.uuid, purge);
+      return { uuid: taskspace.uuid, name: taskspace.name, purged: purge };
+    },
+    reconcile: async (name) => {
+      if (!activeApp) {
+        throw new Error('The app is still starting');
+      }
+      const taskspaces = name ? activeApp.taskspaces.filter(t => t.name === name || t.uuid === name) : activeApp.taskspaces;
+      if (name && taskspaces.length === 0) {
+        throw new Error(`No taskspace named ${name}`);
+      }
+      const results = [];
+      for (const taskspace of taskspaces) {
+        results.push({ name: taskspace.name, done: await activeApp.reconcileTaskSpace(taskspace) });
+      }
+      return { taskspaces: results };
+    }
+  });
+}
+
+/// Offer to start a background session for each imported profile, one
+/// question each when there is a terminal to ask in, and otherwise say how.
+async function offerSessions(profiles: string[]): Promise<void> {
+  const command = (profile: string) => ['--profile', profile, '--name', profile, '--detach'];
+  if (!process.stdin.isTTY || nonInteractive()) {
+    profiles.forEach(profile => console.log(`  ${t('offer-session-start', { command: `electron . ${command(profile).join(' ')}` })}`));
+    return;
+  }
+
+  const readline = await import('readline');
+  const prompt = readline.createInterface({ input: process.stdin, output: process.stdout });
+  const ask = (question: string) => new Promise<string>(resolve => prompt.question(question, resolve));
+  // The app itself and any other flags it was given, without this command's own
+  const appArgs = process.argv.slice(1).filter(arg => !['--import-vscode-remote', '--write', '--no-probe', '--json'].includes(arg));
+  const yes = t('answer-yes').split(',').map(word => word.trim().toLowerCase());
+  for (const profile of profiles) {
+    const answer = await ask(`${t('offer-session', { profile })} `);
+    if (yes.includes(answer.trim().toLowerCase())) {
+      const child = spawn(process.execPath, [...appArgs, ...command(profile)], { stdio: 'inherit' });
+      await new Promise(resolve => child.on('close', resolve));
+    } else {
+      console.log(`  ${t('offer-session-later', { command: `electron . ${command(profile).join(' ')}` })}`);
+    }
+  }
+  prompt.close();
+}
+
+/// Hosts piped in one per line, e.g. from an inventory tool; blank lines and `#` comments are skipped
+async function readHostsFromStdin(): Promise<string[]> {
+  let input = '';
+  process.stdin.setEncoding('utf8');
+  for await (const chunk of process.stdin) {
+    input += chunk;
+  }
+  return input.split('\n').map(line => line.replace(/#.*/, '').trim()).filter(host => host);
+}
+
+// --wait's exit code when the time is up, as timeout(1)'s, apart from 1 for a session that failed
+const WAIT_TIMEOUT_EXIT = 124;
+
+/// Wait until the session's app has started and taskspace `selector` (or the
+/// active one) passes the server health checks, for up to `timeoutSecs`.
+/// Resolves with the taskspace, or null when the time is up; rejects if the
+/// app reports that its startup failed.
+async function waitUntilServing(selector: string | undefined, timeoutSecs: number): Promise<{ name: string; url: string } | null> {
+  const deadline = Date.now() + timeoutSecs * 1000;
+  const healthChecks = selectedHealthChecks();
+  const checkTimeoutMs = loadSettings().healthCheckTimeoutMs || 2000;
+  let waitingFor = 'the app to answer';
+  let reported = '';
+  while (Date.now() < deadline) {
+    // Not answering yet is fine: a --detach'ed app may still be starting
+    const status = await sendControlCommand('status').catch(() => null);
+    if (status?.startupError) {
+      throw new Error(`The session failed to start: ${status.startupError}`);
+    }
+    if (status?.ready) {
+      const { taskspaces } = await sendControlCommand('list-taskspaces');
+      const taskspace = taskspaces.find((candidate: any) => selector ? candidate.name === selector || candidate.uuid === selector : candidate.active);
+      if (!taskspace) {
+        throw new Error(`The session has no taskspace '${selector}'`);
+      }
+      try {
+        if (!taskspace.url) {
+          throw new Error('its server is not started');
+        }
+        await runHealthChecks(healthChecks, taskspace.url, checkTimeoutMs);
+        return taskspace;
+      } catch (error) {
+        waitingFor = `${taskspace.name}: ${error.message}`;
+      }
+    } else if (status) {
+      waitingFor = 'the app to finish starting';
+    }
+    if (waitingFor !== reported) {
+      console.log(`  Waiting for ${waitingFor}...`);
+      reported = waitingFor;
+    }
+    await new Promise(resolve => setTimeout(resolve, 2000));
+  }
+  return null;
+}
+
+// How long --ephemeral waits for the session's first server before giving up on it
+const EPHEMERAL_START_TIMEOUT_SECS = 10 * 60;
+
+/// Wait for the app started as `child` to answer on `socketPath` that it is
+/// ready, and resolve with its active taskspace, `url` included. Rejects if
+/// the app fails to start or exits, and stops it if it takes over `timeoutSecs`.
+async function waitForSessionReady(socketPath: string, child: ChildProcess, timeoutSecs: number): Promise<any> {
+  let exitCode: number | null = null;
+  child.on('exit', (code) => exitCode = code ?? 1);
+  const deadline = Date.now() + timeoutSecs * 1000;
+  while (Date.now() < deadline) {
+    if (exitCode !== null) {
+      throw new Error(`The app exited (code ${exitCode}) before the session was ready`);
+    }
+    const status = await sendControlCommand('status', {}, socketPath).catch(() => null);
+    if (status?.startupError) {
+      throw new Error(`The session failed to start: ${status.startupError}`);
+    }
+    if (status?.ready) {
+      const { taskspaces } = await sendControlCommand('list-taskspaces', {}, socketPath);
+      return { hostname: status.hostname, ...taskspaces.find((taskspace: any) => taskspace.active) };
+    }
+    await new Promise(resolve => setTimeout(resolve, 1000));
+  }
+  await sendControlCommand('stop', {}, socketPath).catch(() => null);
+  throw new Error(`The session was not ready after ${timeoutSecs / 60} minutes; stopped it`);
+}
+
+/// When the app last left the session with the detach key, if the session has not been started since
+function sessionDetachedAt(): string | null {
+  try {
+    return JSON.parse(fs.readFileSync(TASKSPACES_FILE, 'utf8')).detachedAt || null;
+  } catch {
+    return null;
+  }
+}
+
+// The session's host and taskspaces as last saved, e.g. for commands that work without the app
+function savedSession(): { hostname: string; taskspaces: any[]; activeTaskSpaceUuid?: string } {
+  const data = fs.existsSync(TASKSPACES_FILE) ? JSON.parse(fs.readFileSync(TASKSPACES_FILE, 'utf8')) : {};
+  const session = SESSION_NAME === DEFAULT_SESSION ? 'The session' : `Session ${SESSION_NAME}`;
+  if (!data.hostname || !data.taskspaces || data.taskspaces.length === 0) {
+    throw new Error(`${session} has no taskspaces (${TASKSPACES_FILE}); start it first, or pick another with --name`);
+  }
+  return data;
+}
+
+// The session's host and its taskspace `selector` (a name or uuid; without
+// it, the active one), from the session's saved taskspaces
+function savedTaskSpace(selector?: string): { hostname: string; taskspace: any } {
+  const data = savedSession();
+  const taskspaces: any[] = data.taskspaces;
+  const session = SESSION_NAME === DEFAULT_SESSION ? 'The session' : `Session ${SESSION_NAME}`;
+  const taskspace = selector
+    ? taskspaces.find(candidate => candidate.name === selector || candidate.uuid === selector)
+    : taskspaces.find(candidate => candidate.uuid === data.activeTaskSpaceUuid) || taskspaces[taskspaces.length - 1];
+  if (!taskspace) {
+    throw new Error(`${session} has no taskspace '${selector}'; it has ${taskspaces.map(candidate => candidate.name).join(', ')}`);
+  }
+  return { hostname: data.hostname, taskspace };
+}
+
+// Shell commands that give what follows the environment the taskspace's
+// server was started with, in its clone
+function taskspaceEnvironmentScript(taskspace: any): string {
+  const dirs = new TaskSpacePaths(taskspace.uuid);
+  // As recorded when the server started, or as the settings say now for servers started by older versions
+  const environment = taskspace.applied?.environment ?? [localeExports(), cudaExports(), sessionExports(), clipboardExports(), serverProcessSetup()].join('\n');
+  return `
+    cd ${BASE_DIR}
+    ${environment}
+    cd ${BASE_DIR}/${dirs.cloneDir} 2>/dev/null || cd ${BASE_DIR}/${dirs.dir} || exit 1
+    export THEOLDSWITCHEROO_TASKSPACE=${shellQuote(taskspace.name)}
+  `;
+}
+
+/// Open an interactive shell on the session's host in a taskspace's clone,
+/// with the environment its server was started with (locale, CUDA, session
+/// socket, umask and limits). `selector` is a taskspace name or uuid; without
+/// it, the session's active taskspace. Resolves with the shell's exit code.
+async function openTaskSpaceShell(selector?: string): Promise<number> {
+  const { hostname, taskspace } = savedTaskSpace(selector);
+  if (nonInteractive()) {
+    throw new Error('--shell needs a terminal, which --non-interactive rules out; run a command with ssh instead');
+  }
+
+  configureRemoteUser(hostname);
+  const script = `
+    ${taskspaceEnvironmentScript(taskspace)}
+    exec "\${SHELL:-/bin/bash}" -l
+  `;
+  console.log(t('opening-shell', { taskspace: taskspace.name, host: hostname }));
+  audit('shell', { host: hostname, taskspace: taskspace.name });
+  return sshManager.interactive(hostname, script);
+}
+
+/// Run the command of `alias` (from the `aliases` setting) in a taskspace's
+/// clone like openTaskSpaceShell's shell would, streaming its output.
+/// Resolves with the command's exit code.
+async function runTaskSpaceAlias(alias: string, selector?: string): Promise<number> {
+  const aliases = loadSettings().aliases || {};
+  const command = Object.prototype.hasOwnProperty.call(aliases, alias) ? aliases[alias] : undefined;
+  if (typeof command !== 'string') {
+    const known = Object.keys(aliases);
+    throw new Error(`No alias '${alias}'; ${known.length > 0 ? `the aliases are ${known.join(', ')}` : 'set some in the aliases setting'}`);
+  }
+  const { hostname, taskspace } = savedTaskSpace(selector);
+
+  configureRemoteUser(hostname);
+  const script = `
+    ${taskspaceEnvironmentScript(taskspace)}
+    exec sh -c ${shellQuote(command)}
+  `;
+  console.error(`Running ${alias} (${command}) in ${taskspace.name} on ${hostname}...`);
+  audit('run', { host: hostname, taskspace: taskspace.name, alias, command });
+  // A terminal only if there is one, so the output can be piped or run in CI
+  return sshManager.interactive(hostname, script, Boolean(process.stdin.isTTY && process.stdout.isTTY) && !nonInteractive());
+}
+
+/// Skip any remaining remote cleanup: tear down local SSH processes and exit now.
+function forceQuit(): never {
+  sshManager.cleanup();
+  app.exit(1);
+  process.exit(1);
+}
+
+/// Run the orderly shutdown once, whatever triggered it (signal or app quit).
+async function shutdownActiveApp(reason: string): Promise<void> {
+  if (shuttingDown) {
+    return;
+  }
+  shuttingDown = true;
+
+  try {
+    if (activeApp) {
+      await activeApp.shutdown(reason);
+    }
+  } catch (error) {
+    console.error('Error during shutdown:', error);
+  }
+  controlServer?.close();
+}
+
+// Commands that run without Electron and exit, in the order they are dispatched below
+const CLI_COMMANDS = [
+  '--clean', '--install-prereqs', '--prime-cache', '--inspect', '--audit', '--status', '--stop', '--logs', '--prewarm', '--daemon-proxy',
+  '--sessions', '--handoff-export', '--handoff-accept', '--import-vscode-remote', '--shell', '--git-status', '--taskspace', '--reconcile',
+  '--mount', '--unmount', '--run', '--ps', '--kill', '--open-app', '--wait'
+];
+
+// Parse CLI arguments for the CLI_COMMANDS, and --detach and --ephemeral for normal startup.
+// --name <session> (read in settings.ts) picks the session that --status, --stop, --logs, --prewarm, --daemon-proxy, --shell, --git-status, --taskspace, --reconcile, --wait, --mount, --unmount,
+// --open-app, --run, --ps, --kill, --detach and normal startup work on.
+const args = process.argv.slice(2);
+const cleanIndex = args.indexOf('--clean');
+const prereqsIndex = args.indexOf('--install-prereqs');
+const primeCacheIndex = args.indexOf('--prime-cache');
+const inspectIndex = args.indexOf('--inspect');
+const auditIndex = args.indexOf('--audit');
+const statusIndex = args.indexOf('--status');
+const stopIndex = args.indexOf('--stop');
+const logsIndex = args.indexOf('--logs');
+const prewarmIndex = args.indexOf('--prewarm');
+const daemonProxyIndex = args.indexOf('--daemon-proxy');
+const sessionsIndex = args.indexOf('--sessions');
+const handoffExportIndex = args.indexOf('--handoff-export');
+const handoffAcceptIndex = args.indexOf('--handoff-accept');
+const importVSCodeRemoteIndex = args.indexOf('--import-vscode-remote');
+const shellIndex = args.indexOf('--shell');
+const gitStatusIndex = args.indexOf('--git-status');
+const taskspaceIndex = args.indexOf('--taskspace');
+const reconcileIndex = args.indexOf('--reconcile');
+const waitIndex = args.indexOf('--wait');
+const mountIndex = args.indexOf('--mount');
+const unmountIndex = args.indexOf('--unmount');
+const openAppIndex = args.indexOf('--open-app');
+const runIndex = args.indexOf('--run');
+const psIndex = args.indexOf('--ps');
+const killIndex = args.indexOf('--kill');
+
+// `--plain` (or the `plain` setting): sequential, timestamped lines only, for screen readers
+if (loadSettings().plain) {
+  enablePlainOutput();
+}
+
+// `--non-interactive` (or the `nonInteractive` setting): nothing waits for a person, and errors are JSON lines
+if (nonInteractive()) {
+  enableMachineReadableErrors();
+}
+
+// Hidden debugging mode, see md/development.md: `--record <tape>` writes every remote step of the
+// run to a tape, and `--replay <tape>` plays one back instead of connecting, with the settings it was recorded with
+const recordIndex = args.indexOf('--record');
+const replayIndex = args.indexOf('--replay');
+if (replayIndex !== -1) {
+  const tape = args[replayIndex + 1] || '';
+  try {
+    const replay = TapeReplay.load(tape);
+    pinSettings(replay.header.settings);
+    sshManager.setReplay(replay);
+    console.log(`Replaying ${tape}, recorded ${replay.header.recordedAt}; no host is contacted`);
+  } catch (error) {
+    console.error(`Could not replay ${tape || 'a tape'}: ${error.message}`);
+    process.exit(1);
+  }
+} else if (recordIndex !== -1) {
+  const tape = args[recordIndex + 1];
+  if (!tape || tape.startsWith('--')) {
+    console.error('--record needs the file to write the tape to, e.g. --record run.tape');
+    process.exit(1);
+  }
+  sshManager.setRecorder(new TapeRecorder(path.resolve(tape), loadSettings()));
+  console.log(`Recording every remote step to ${tape}`);
+}
+
+// Every remote step (except the long-running server and daemon) is bounded by this timeout
+sshManager.setCommandTimeout(loadSettings().remoteCommandTimeoutSecs || 300);
+// The native transport needs the optional ssh2 package, which is not installed with the app
+sshManager.setTransport(loadSettings().sshTransport || 'subprocess');
+try {
+  sshManager.setJumpHost(loadSettings().jumpHost);
+  sshManager.setBatchMode(nonInteractive());
+} catch (error) {
+  console.error(error.message);
+  process.exit(1);
+}
+
+// Hidden testing mode, see md/development.md: `--chaos [spec]` or THEOLDSWITCHEROO_CHAOS=<spec> injects failures
+const chaosIndex = args.indexOf('--chaos');
+const chaosSpec = chaosIndex === -1 ? process.env.THEOLDSWITCHEROO_CHAOS
+  : /^[a-z-]+(=[\d.]+)?(,[a-z-]+(=[\d.]+)?)*$/.test(args[chaosIndex + 1] || '') ? args[chaosIndex + 1] : '';
+if (chaosSpec !== undefined) {
+  try {
+    configureChaos(parseChaosSpec(chaosSpec));
+  } catch (error) {
+    console.error(error.message);
+    process.exit(1);
+  }
+}
+
+// Comma-separated values of `--flag a,b`, or `fallback` if the flag is absent
+function listArgument(flag: string, fallback: string[]): string[] {
+  const index = args.indexOf(flag);
+  if (index === -1 || index + 1 >= args.length) {
+    return fallback;
+  }
+  return args[index + 1].split(',').map(value => value.trim()).filter(value => value);
+}
+
+if (cleanIndex !== -1 && cleanIndex + 1 < args.length) {
+  const hostname = args[cleanIndex + 1];
+  console.log(`Cleaning ~/.socratic-shell/theoldswitcheroo from ${hostname}...`);
+
+  configureRemoteUser(hostname);
+
+  // Uncommitted and unpushed work in the taskspaces is saved locally first
+  guardUnsavedWork(hostname, args.includes('--force'))
+    // A server supervisor left running would restart the servers out of the removed directory
+    .then(() => execSSHCommand(hostname, `${serverdShutdownCommand(false)}\n rm -rf ~/.socratic-shell/theoldswitcheroo`))
+    .then(() => {
+      console.log(`✓ Cleaned ~/.socratic-shell/theoldswitcheroo from ${hostname}`);
+      process.exit(0);
+    })
+    .catch((error) => {
+      console.error(t('failed-clean', { host: hostname }), error.message);
+      process.exit(1);
+    });
+} else if (prereqsIndex !== -1 && prereqsIndex + 1 < args.length) {
+  // Installs system packages as the SSH login user, so remoteUser is deliberately not applied
+  const hostname = args[prereqsIndex + 1];
+  console.log(`Installing server prerequisites on ${hostname}...`);
+
+  installPrerequisites(hostname)
+    .then(() => {
+      console.log(`✓ Installed server prerequisites on ${hostname}`);
+      process.exit(0);
+    })
+    .catch((error) => {
+      console.error(t('failed-prereqs', { host: hostname }), error.message);
+      process.exit(1);
+    });
+} else if (primeCacheIndex !== -1 && primeCacheIndex + 1 < args.length) {
+  const hostname = args[primeCacheIndex + 1];
+  const arches = listArgument('--arch', SERVER_ARCHITECTURES);
+  const requested = listArgument('--server-version', [loadSettings().serverVersion || serverFlavor().defaultVersion]);
+
+  configureRemoteUser(hostname);
+
+  Promise.all(requested.map(version => resolveServerVersion(version)))
+    .then(async (versions) => {
+      console.log(t('priming-cache', { host: hostname, versions: versions.join(', '), arches: arches.join(', ') }));
+      await prepareNfsHome(hostname);
+      await primeServerCache(hostname, arches, versions);
+    })
+    .then(() => {
+      console.log(t('primed-cache', { host: hostname }));
+      process.exit(0);
+    })
+    .catch((error) => {
+      console.error(t('failed-prime-cache', { host: hostname }), error.message);
+      const blocked = error instanceof PrimeCacheError ? error.blocked : undefined;
+      if (blocked) {
+        console.error(blocked.guidance());
+        process.exit(DOWNLOAD_BLOCKED_EXIT_CODE);
+      }
+      process.exit(1);
+    });
+} else if (inspectIndex !== -1 && inspectIndex + 1 < args.length) {
+  const hostname = args[inspectIndex + 1];
+
+  configureRemoteUser(hostname);
+
+  inspectHost(hostname)
+    .then((caps) => {
+      console.log(args.includes('--json') ? JSON.stringify(caps, null, 2) : formatCapabilities(hostname, caps));
+      process.exit(0);
+    })
+    .catch((error) => {
+      console.error(t('failed-inspect', { host: hostname }), error.message);
+      process.exit(1);
+    });
+} else if (auditIndex !== -1) {
+  // Without a host list, every host the settings and saved sessions know about; with `-`, the hosts on stdin
+  const next = args[auditIndex + 1];
+  const explicit = next && next !== '-' && !next.startsWith('--') ? next.split(',').map(host => host.trim()).filter(host => host) : null;
+
+  (next === '-' ? readHostsFromStdin() : explicit ? Promise.resolve(explicit) : fleetHosts())
+    .then(async (hosts) => {
+      if (hosts.length === 0) {
+        console.error(t('no-hosts-to-audit'));
+        process.exit(1);
+      }
+      const report = await auditFleet(hosts);
+      console.log(args.includes('--json') ? JSON.stringify(report, null, 2) : formatAuditReport(report));
+      sshManager.cleanup();
+      const serious = report.findings.some(finding => finding.severity === 'critical' || finding.severity === 'high');
+      process.exit(serious || report.unreachable.length > 0 ? 1 : 0);
+    })
+    .catch((error) => {
+      console.error(t('failed-audit'), error.message);
+      process.exit(1);
+    });
+} else if (statusIndex !== -1) {
+  Promise.all([sendControlCommand('status'), checkSessionStatus()])
+    .then(([running, { hostname, checks }]) => {
+      const detachedAt = running ? null : sessionDetachedAt();
+      checks.unshift(running
+        ? { name: t('status-app'), ok: true, detail: t(running.detached ? 'status-app-running-detached' : 'status-app-running', { pid: running.pid }) }
+        : { name: t('status-app'), ok: false, detail: detachedAt ? t('status-app-detached', { time: detachedAt }) : t('status-app-not-running') });
+      if (hostname) {
+        console.log(SESSION_NAME === DEFAULT_SESSION
+          ? t('status-title', { host: hostname })
+          : t('status-title-named', { session: SESSION_NAME, host: hostname }));
+      }
+      console.log(formatStatus(checks));
+      if (running?.forwards?.length > 0) {
+        console.log(t('status-port-forwards'));
+        formatForwardStats(running.forwards).forEach(line => console.log(`  ${line}`));
+      }
+      process.exit(checks.every(check => check.ok) ? 0 : 1);
+    })
+    .catch((error) => {
+      console.error(t('failed-status'), error.message);
+      process.exit(1);
+    });
+} else if (stopIndex !== -1) {
+  // A running app shuts down in order; after a crash we clean up the host ourselves
+  sendControlCommand('stop')
+    .then(async (running) => {
+      if (running) {
+        console.log(`✓ Asked the running app (PID ${running.pid}) to shut down`);
+      } else {
+        // What the app would have unmounted, then the host
+        const report = [...await unmountSession(), ...await stopSession()];
+        report.forEach(line => console.log(`✓ ${line}`));
+      }
+      process.exit(0);
+    })
+    .catch((error) => {
+      console.error(t('failed-stop'), error.message);
+      process.exit(1);
+    });
+} else if (logsIndex !== -1) {
+  const count = Number(args[logsIndex + 1]) || 50;
+  sendControlCommand('logs', { lines: count })
+    .then(async (running) => {
+      if (running) {
+        const status = await sendControlCommand('status');
+        if (!status?.detached) {
+          console.log('The app is running in the foreground; its output is in its terminal');
+        }
+        running.lines.forEach((line: string) => console.log(line));
+      } else {
+        console.log(`The app is not running; the end of its last detached log (${DETACHED_LOG}):`);
+        tailDetachedLog(count).forEach(line => console.log(line));
+      }
+      process.exit(0);
+    })
+    .catch((error) => {
+      console.error(t('failed-logs'), error.message);
+      process.exit(1);
+    });
+} else if (prewarmIndex !== -1) {
+  // The taskspace name is optional; without it the newest taskspace is warmed
+  const next = args[prewarmIndex + 1];
+  const taskspace = next && !next.startsWith('--') ? next : undefined;
+  const globs = listArgument('--globs', []);
+  console.log(`Pre-warming ${taskspace || 'the newest taskspace'}; this can take a few minutes...`);
+  sendControlCommand('prewarm', { taskspace, globs })
+    .then((result) => {
+      if (!result) {
+        console.error(t('app-not-running'));
+        process.exit(1);
+      }
+      if (result.error) {
+        console.error(t('failed-prewarm'), result.error);
+        process.exit(1);
+      }
+      console.log(`✓ Pre-warmed ${result.taskspace}: opened ${result.opened} files (${result.languages.join(', ') || 'no languages'}) in ${Math.round(result.durationMs / 1000)}s`);
+      process.exit(0);
+    })
+    .catch((error) => {
+      console.error(t('failed-prewarm'), error.message);
+      process.exit(1);
+    });
+} else if (daemonProxyIndex !== -1 && daemonProxyIndex + 1 < args.length) {
+  const hostname = args[daemonProxyIndex + 1];
+  const socketIndex = args.indexOf('--socket');
+  const localPath = socketIndex !== -1 && socketIndex + 1 < args.length ? args[socketIndex + 1] : localDaemonSocket(hostname);
+
+  configureRemoteUser(hostname);
+
+  proxyDaemonSocket(hostname, localPath)
+    .then((server) => {
+      console.log(`✓ Forwarding ${localPath} to the daemon on ${hostname} (Ctrl+C to stop)`);
+      const stop = () => {
+        server.close();
+        fs.rmSync(localPath, { force: true });
+        sshManager.cleanup();
+        process.exit(0);
+      };
+      process.on('SIGINT', stop);
+      process.on('SIGTERM', stop);
+    })
+    .catch((error) => {
+      console.error(t('failed-daemon-proxy', { host: hostname }), error.message);
+      process.exit(1);
+    });
+} else if (sessionsIndex !== -1) {
+  listSessions()
+    .then((sessions) => {
+      console.log(formatSessions(sessions));
+      process.exit(0);
+    })
+    .catch((error) => {
+      console.error(t('failed-sessions'), error.message);
+      process.exit(1);
+    });
+} else if (handoffExportIndex !== -1) {
+  // The bundle goes to stdout or the named file, the key to stderr, so they are easy to pass on separately
+  const file = args[handoffExportIndex + 1]?.startsWith('--') ? undefined : args[handoffExportIndex + 1];
+  try {
+    const { bundle, key } = exportHandoff();
+    if (file) {
+      fs.writeFileSync(file, bundle + '\n', { mode: 0o600 });
+      console.error(`✓ Wrote the handoff bundle to ${file}`);
+    } else {
+      console.log(bundle);
+    }
+    console.error(`Handoff key (send it separately; the bundle expires in an hour): ${key}`);
+    console.error(`On the other machine: electron . --handoff-accept <bundle file> --handoff-key <key>`);
+    process.exit(0);
+  } catch (error) {
+    console.error(t('failed-handoff-export'), error.message);
+    process.exit(1);
+  }
+} else if (handoffAcceptIndex !== -1 && handoffAcceptIndex + 1 < args.length) {
+  const file = args[handoffAcceptIndex + 1];
+  const keyIndex = args.indexOf('--handoff-key');
+  const key = keyIndex !== -1 && keyIndex + 1 < args.length ? args[keyIndex + 1] : process.env.THEOLDSWITCHEROO_HANDOFF_KEY;
+  try {
+    if (!key) {
+      throw new Error('No key given; pass --handoff-key <key> or set THEOLDSWITCHEROO_HANDOFF_KEY');
+    }
+    const bundle = fs.readFileSync(file === '-' ? 0 : file, 'utf8');
+    const accepted = acceptHandoff(bundle, key, args.includes('--force'));
+    const nameFlag = accepted.session === DEFAULT_SESSION ? '' : ` --name ${accepted.session}`;
+    console.log(`✓ Accepted the session${nameFlag ? ` ${accepted.session}` : ''} on ${accepted.hostname} with ${accepted.taskspaces} taskspaces`);
+    if (accepted.session === DEFAULT_SESSION && loadSettings().hostname !== accepted.hostname) {
+      console.log(`  Set "hostname": "${accepted.hostname}" in ${SETTINGS_FILE} to connect to it`);
+    }
+    console.log(`  Start it with: electron .${nameFlag}`);
+    console.log('  The app reconnects to the running servers through its own tunnels, and offers to take over the host\'s daemon from the other machine.');
+    process.exit(0);
+  } catch (error) {
+    console.error(t('failed-handoff-accept'), error.message);
+    process.exit(1);
+  }
+} else if (importVSCodeRemoteIndex !== -1) {
+  const write = args.includes('--write');
+  const targets = findRemoteTargets();
+  suggestProfiles(targets, !args.includes('--no-probe'))
+    .then(async (suggestions) => {
+      sshManager.cleanup();
+      const known = existingProfiles();
+      const fresh = suggestions.filter(suggestion => !known.has(suggestion.name));
+      if (args.includes('--json')) {
+        console.log(JSON.stringify({ targets, suggestions: fresh }, null, 2));
+        process.exit(0);
+      }
+      if (targets.length === 0) {
+        console.log('Found no Remote-SSH hosts: no VS Code settings, recent remote folders or ~/.ssh/config hosts');
+        process.exit(0);
+      }
+
+      console.log(`Found ${targets.length} Remote-SSH hosts:`);
+      for (const suggestion of suggestions) {
+        const where = suggestion.folder ? `${suggestion.host}:${suggestion.folder}` : suggestion.host;
+        const state = known.has(suggestion.name) ? 'already in config.toml'
+          : suggestion.note || (suggestion.cloneRepo ? `clones ${suggestion.cloneRepo}` : 'new');
+        console.log(`  ${suggestion.name.padEnd(24)} ${where} (${state})`);
+      }
+      const toml = profilesToml(fresh);
+      if (!toml) {
+        console.log('Nothing to import');
+        process.exit(0);
+      }
+      if (!write) {
+        console.log(`\nProfiles to add to ${CONFIG_FILE} (run again with --write to add them):\n\n${toml}`);
+        process.exit(0);
+      }
+
+      appendProfiles(toml);
+      const added = fresh.filter(suggestion => toml.includes(`[profiles.${suggestion.name}]`));
+      console.log(`✓ Added ${added.length} profiles to ${CONFIG_FILE}`);
+      await offerSessions(added.map(suggestion => suggestion.name));
+      process.exit(0);
+    })
+    .catch((error) => {
+      console.error(t('failed-import'), error.message);
+      process.exit(1);
+    });
+} else if (shellIndex !== -1) {
+  // The taskspace name is optional; without it the active taskspace is opened
+  const next = args[shellIndex + 1];
+  openTaskSpaceShell(next && !next.startsWith('--') ? next : undefined)
+    .then((code) => {
+      sshManager.cleanup();
+      process.exit(code);
+    })
+    .catch((error) => {
+      console.error(t('failed-shell'), error.message);
+      sshManager.cleanup();
+      process.exit(1);
+    });
+} else if (gitStatusIndex !== -1) {
+  // Every session unless one is named
+  collectGitStatus(args.includes('--name') ? [SESSION_NAME] : undefined)
+    .then((statuses) => {
+      sshManager.cleanup();
+      console.log(args.includes('--json') ? JSON.stringify(statuses, null, 2) : formatGitStatus(statuses));
+      process.exit(0);
+    })
+    .catch((error) => {
+      console.error(t('failed-git-status'), error.message);
+      sshManager.cleanup();
+      process.exit(1);
+    });
+} else if (taskspaceIndex !== -1) {
+  // `--taskspace list`, `--taskspace create [name]` or `--taskspace delete <name> [--purge]`, done by the running app
+  const action = args[taskspaceIndex + 1];
+  const next = args[taskspaceIndex + 2];
+  const name = next && !next.startsWith('--') ? next : undefined;
+  const requests: Record<string, [ControlCommand, any]> = {
+    list: ['list-taskspaces', {}],
+    create: ['create-taskspace', { name }],
+    delete: ['delete-taskspace', { taskspace: name, purge: args.includes('--purge') }]
+  };
+  if (!requests[action] || (action === 'delete' && !name)) {
+    console.error(t('taskspace-usage'));
+    process.exit(1);
+  }
+  if (action === 'create') {
+    console.log(`Creating ${name ? `taskspace ${name}` : 'a taskspace'}; cloning the project can take a while...`);
+  }
+  sendControlCommand(...requests[action])
+    .then((result) => {
+      if (!result) {
+        console.error(t('app-not-running'));
+        process.exit(1);
+      }
+      if (result.error) {
+        console.error(t('failed-taskspace'), result.error);
+        process.exit(1);
+      }
+      if (action === 'list' && args.includes('--json')) {
+        console.log(JSON.stringify(result.taskspaces, null, 2));
+      } else if (action === 'list') {
+        result.taskspaces.forEach((taskspace: any) => console.log(`${taskspace.active ? '*' : ' '} ${taskspace.name}\t${taskspace.uuid}\t${taskspace.port ? `port ${taskspace.port}` : 'not started'}\t${taskspace.dir}`));
+      } else if (action === 'create') {
+        console.log(`✓ Created taskspace ${result.name} (${result.uuid}) in ${result.dir}`);
+      } else {
+        console.log(`✓ Deleted taskspace ${result.name}${result.purged ? ' and its files' : ''}`);
+      }
+      process.exit(0);
+    })
+    .catch((error) => {
+      console.error(t('failed-taskspace'), error.message);
+      process.exit(1);
+    });
+} else if (reconcileIndex !== -1) {
+  // The taskspace name is optional; without it every taskspace is reconciled
+  const next = args[reconcileIndex + 1];
+  sendControlCommand('reconcile', { taskspace: next && !next.startsWith('--') ? next : undefined })
+    .then((result) => {
+      if (!result) {
+        console.error(t('app-not-running'));
+        process.exit(1);
+      }
+      if (result.error) {
+        console.error(t('failed-reconcile'), result.error);
+        process.exit(1);
+      }
+      for (const taskspace of result.taskspaces) {
+        if (taskspace.done.length === 0) {
+          console.log(`✓ ${taskspace.name}: no drift`);
+        } else {
+          console.log(`✓ ${taskspace.name}:`);
+          taskspace.done.forEach((line: string) => console.log(`  - ${line}`));
+        }
+      }
+      process.exit(0);
+    })
+    .catch((error) => {
+      console.error(t('failed-reconcile'), error.message);
+      process.exit(1);
+    });
+} else if (mountIndex !== -1) {
+  // `--mount <dir> [taskspace]`: the taskspace's clone (default: the active one) at a local directory
+  const dir = args[mountIndex + 1];
+  const next = args[mountIndex + 2];
+  if (!dir || dir.startsWith('--')) {
+    console.error(t('mount-usage'));
+    process.exit(1);
+  }
+  sendControlCommand('list-taskspaces')
+    .then(async (result) => {
+      if (!result) {
+        console.error(t('app-not-running'));
+        process.exit(1);
+      }
+      const selector = next && !next.startsWith('--') ? next : undefined;
+      const taskspace = result.taskspaces.find((candidate: any) => selector ? candidate.name === selector || candidate.uuid === selector : candidate.active);
+      if (!taskspace) {
+        throw new Error(`The session has no taskspace '${selector}'`);
+      }
+      const { hostname } = await sendControlCommand('status');
+      configureRemoteUser(hostname);
+      const remoteDir = `${BASE_DIR}/${new TaskSpacePaths(taskspace.uuid).cloneDir}`;
+      await mountTaskSpace(hostname, taskspace.name, remoteDir, dir);
+      audit('mount', { host: hostname, taskspace: taskspace.name, dir: path.resolve(dir) });
+      console.log(`✓ Mounted ${taskspace.name} (${hostname}:${remoteDir}) at ${path.resolve(dir)}; it is unmounted when the session stops`);
+      process.exit(0);
+    })
+    .catch((error) => {
+      console.error(t('failed-mount'), error.message);
+      process.exit(1);
+    });
+} else if (unmountIndex !== -1) {
+  // Without a directory, every mount of the session
+  const next = args[unmountIndex + 1];
+  unmountSession(next && !next.startsWith('--') ? next : undefined)
+    .then((report) => {
+      report.forEach(line => console.log(line.startsWith('Could not') ? `✗ ${line}` : `✓ ${line}`));
+      process.exit(report.some(line => line.startsWith('Could not')) ? 1 : 0);
+    })
+    .catch((error) => {
+      console.error(t('failed-mount'), error.message);
+      process.exit(1);
+    });
+} else if (runIndex !== -1) {
+  // `--run <alias> [taskspace]`; without a taskspace, in the active one
+  const alias = args[runIndex + 1];
+  const next = args[runIndex + 2];
+  if (!alias || alias.startsWith('--')) {
+    console.error(t('run-usage'));
+    process.exit(1);
+  }
+  runTaskSpaceAlias(alias, next && !next.startsWith('--') ? next : undefined)
+    .then((code) => {
+      sshManager.cleanup();
+      process.exit(code);
+    })
+    .catch((error) => {
+      console.error(t('failed-run'), error.message);
+      sshManager.cleanup();
+      process.exit(1);
+    });
+} else if (psIndex !== -1) {
+  // `--ps [taskspace]`; without a taskspace, every server of the session
+  const next = args[psIndex + 1];
+  const selector = next && !next.startsWith('--') ? next : undefined;
+  Promise.resolve()
+    .then(async () => {
+      const { hostname, taskspaces: all } = savedSession();
+      const taskspaces = selector ? [savedTaskSpace(selector).taskspace] : all;
+      configureRemoteUser(hostname);
+      const trees = await serverProcessTrees(hostname, taskspaces);
+      if (args.includes('--json')) {
+        console.log(JSON.stringify(trees, null, 2));
+      } else {
+        formatProcessTrees(trees, process.stdout.columns || 160).forEach(line => console.log(line));
+      }
+      sshManager.cleanup();
+      process.exit(0);
+    })
+    .catch((error) => {
+      console.error(t('failed-ps'), error.message);
+      sshManager.cleanup();
+      process.exit(1);
+    });
+} else if (killIndex !== -1) {
+  // `--kill <pid> [--signal <name>]`, TERM by default
+  const pid = Number(args[killIndex + 1]);
+  const signalIndex = args.indexOf('--signal');
+  const signal = (signalIndex !== -1 ? args[signalIndex + 1] || '' : 'TERM').toUpperCase().replace(/^SIG/, '');
+  if (!Number.isInteger(pid) || pid <= 0) {
+    console.error(t('kill-usage'));
+    process.exit(1);
+  }
+  Promise.resolve()
+    .then(async () => {
+      const { hostname, taskspaces } = savedSession();
+      configureRemoteUser(hostname);
+      const killed = await killServerProcess(hostname, taskspaces, pid, signal);
+      audit('kill', { host: hostname, pid, signal, process: killed });
+      console.log(`✓ Sent SIG${signal} to ${pid} (${killed})`);
+      sshManager.cleanup();
+      process.exit(0);
+    })
+    .catch((error) => {
+      console.error(t('failed-kill'), error.message);
+      sshManager.cleanup();
+      process.exit(1);
+    });
+} else if (openAppIndex !== -1) {
+  // `--open-app [taskspace]`: the taskspace (default: the active one) in a browser app window
+  const next = args[openAppIndex + 1];
+  const selector = next && !next.startsWith('--') ? next : undefined;
+  sendControlCommand('list-taskspaces')
+    .then(async (result) => {
+      if (!result) {
+        console.error(t('app-not-running'));
+        process.exit(1);
+      }
+      const taskspace = result.taskspaces.find((candidate: any) => selector ? candidate.name === selector || candidate.uuid === selector : candidate.active);
+      if (!taskspace) {
+        throw new Error(`The session has no taskspace '${selector}'`);
+      }
+      if (!taskspace.url) {
+        throw new Error(`${taskspace.name}'s server is not started; open it in the app first`);
+      }
+      const browser = findAppBrowser();
+      await openAppWindow(browser, taskspace.url);
+      console.log(`✓ Opened ${taskspace.name} (${taskspace.url}) in ${path.basename(browser)}`);
+      process.exit(0);
+    })
+    .catch((error) => {
+      console.error(t('failed-open-app'), error.message);
+      process.exit(1);
+    });
+} else if (waitIndex !== -1) {
+  // The taskspace name is optional; without it the active taskspace is waited for
+  const next = args[waitIndex + 1];
+  const timeoutIndex = args.indexOf('--timeout');
+  const timeoutSecs = timeoutIndex !== -1 ? Number(args[timeoutIndex + 1]) : 120;
+  if (!(timeoutSecs > 0)) {
+    console.error(t('wait-usage'));
+    process.exit(1);
+  }
+  waitUntilServing(next && !next.startsWith('--') ? next : undefined, timeoutSecs)
+    .then((taskspace) => {
+      if (!taskspace) {
+        console.error(t('wait-timed-out', { secs: timeoutSecs }));
+        process.exit(WAIT_TIMEOUT_EXIT);
+      }
+      console.log(`✓ ${taskspace.name} is ready at ${taskspace.url}`);
+      process.exit(0);
+    })
+    .catch((error) => {
+      console.error(t('failed-wait'), error.message);
+      process.exit(1);
+    });
+} else if (args.includes('--ephemeral') && !isDetached) {
+  // A background session of its own, which deletes itself when its time to live is up or it is stopped
+  const ttlIndex = args.indexOf('--ttl');
+  let ttlSecs: number;
+  try {
+    ttlSecs = parseTtl(ttlIndex !== -1 ? args[ttlIndex + 1] || '' : '1h');
+  } catch (error) {
+    console.error(t('failed-ephemeral'), error.message);
+    process.exit(1);
+  }
+  const session = SESSION_NAME === DEFAULT_SESSION ? ephemeralSessionName() : SESSION_NAME;
+  const expiresAt = new Date(Date.now() + ttlSecs * 1000);
+  // The session reaches the background app through the environment, so --name goes too
+  const skipped = new Set([ttlIndex + 1, args.indexOf('--name') + 1].filter(index => index > 0));
+  const appArgs = process.argv.slice(1).filter((arg, index) =>
+    !['--ephemeral', '--ttl', '--name', '--json'].includes(arg) && !skipped.has(index - 1));
+  const dir = sessionDir(session);
+  fs.mkdirSync(dir, { recursive: true });
+  const logFile = path.join(dir, 'detached.log');
+  const log = fs.openSync(logFile, 'a');
+  const child = spawn(process.execPath, appArgs, {
+    detached: true,
+    stdio: ['ignore', log, log],
+    env: { ...process.env, THEOLDSWITCHEROO_DETACHED: '1', THEOLDSWITCHEROO_SESSION: session, [EPHEMERAL_UNTIL_ENV]: expiresAt.toISOString() }
+  });
+  child.unref();
+  if (!args.includes('--json')) {
+    console.log(`Starting the ephemeral session ${session} (PID ${child.pid}) until ${expiresAt.toISOString()}; logs go to ${logFile}`);
   }
 
-  await new SwitcherooApp(hostname).bootUp();
-}
-
-// Parse CLI arguments for --clean command
-const args = process.argv.slice(2);
-const cleanIndex = args.indexOf('--clean');
-if (cleanIndex !== -1 && cleanIndex + 1 < args.length) {
-  const hostname = args[cleanIndex + 1];
-  console.log(`Cleaning ~/.socratic-shell/theoldswitcheroo from ${hostname}...`);
-
-  execSSHCommand(hostname, `rm -rf ~/.socratic-shell/theoldswitcheroo`)
-    .then(() => {
-      console.log(`✓ Cleaned ~/.socratic-shell/theoldswitcheroo from ${hostname}`);
+  waitForSessionReady(controlSocketPath(dir), child, EPHEMERAL_START_TIMEOUT_SECS)
+    .then((taskspace) => {
+      const result = { session, hostname: taskspace.hostname, taskspace: taskspace.name, url: taskspace.url, expiresAt: expiresAt.toISOString(), pid: child.pid, log: logFile };
+      if (args.includes('--json')) {
+        console.log(JSON.stringify(result, null, 2));
+      } else {
+        console.log(`✓ ${taskspace.name} is ready at ${taskspace.url}`);
+        console.log(`  Tear it down early with --name ${session} --stop`);
+      }
       process.exit(0);
     })
     .catch((error) => {
-      console.error(`✗ Failed to clean from ${hostname}:`, error.message);
+      console.error(t('failed-ephemeral'), error.message);
       process.exit(1);
     });
+} else if (args.includes('--detach') && !isDetached) {
+  // Start this same command again in the background, with its output in the detached log
+  fs.mkdirSync(path.dirname(DETACHED_LOG), { recursive: true });
+  const log = fs.openSync(DETACHED_LOG, 'a');
+  const child = spawn(process.execPath, process.argv.slice(1).filter(arg => arg !== '--detach'), {
+    detached: true,
+    stdio: ['ignore', log, log],
+    env: { ...process.env, THEOLDSWITCHEROO_DETACHED: '1' }
+  });
+  child.unref();
+  console.log(`✓ Started in the background (PID ${child.pid}); logs go to ${DETACHED_LOG}`);
+  console.log('  Use --status, --logs and --stop to check on it');
+  process.exit(0);
 } else if (app) {
+  // Sessions on the same host share a browser partition name, so each named
+  // session keeps its Electron profile apart to run alongside the others
+  if (SESSION_NAME !== DEFAULT_SESSION) {
+    app.setPath('userData', path.join(SESSION_DIR, 'electron'));
+  }
+
   // Normal app startup (only if running in Electron)
   app.whenReady().then(() => {
     main().catch(console.error);
@@ -142,22 +1389,45 @@ if (cleanIndex !== -1 && cleanIndex + 1 < args.length) {
   });
 
   // Add cleanup for daemon processes
-  app.on('before-quit', async () => {
+  app.on('before-quit', () => {
     console.log('App quitting, cleaning up daemon processes...');
-    // Stop all active daemons
-    const activeHosts = ['hostname']; // We'd need to track this properly
-    for (const hostname of activeHosts) {
-      try {
-        // Note: We can't easily access SwitcherooApp instance here
-        // The SSH connections will close automatically
-        console.log(`Daemon cleanup for ${hostname} will happen via SSH connection close`);
-      } catch (error) {
-        console.error(`Error cleaning up daemon for ${hostname}:`, error);
-      }
-    }
+    shutdownActiveApp('app quit');
   });
+
+  // Closing the terminal (SIGHUP) or a service manager stop (SIGTERM) should
+  // clean up exactly like Ctrl+C does, then exit.
+  //
+  // Graceful shutdown talks to the remote host and can hang on a dead network,
+  // so it runs against a visible countdown, and a second signal skips straight
+  // to local cleanup.
+  for (const signal of ['SIGINT', 'SIGTERM', 'SIGHUP'] as NodeJS.Signals[]) {
+    process.on(signal, () => {
+      if (shuttingDown) {
+        console.log(`Received ${signal} again, skipping graceful shutdown`);
+        forceQuit();
+        return;
+      }
+
+      console.log(`Received ${signal}, shutting down gracefully (press Ctrl+C again to force quit)...`);
+      let remaining = GRACEFUL_SHUTDOWN_TIMEOUT_SECS;
+      const countdown = setInterval(() => {
+        remaining--;
+        if (remaining <= 0) {
+          console.log('Graceful shutdown timed out, forcing quit');
+          forceQuit();
+        } else {
+          console.log(`  Waiting for shutdown... ${remaining}s left (Ctrl+C to force quit)`);
+        }
+      }, 1000);
+
+      shutdownActiveApp(signal).finally(() => {
+        clearInterval(countdown);
+        app.exit(0);
+      });
+    });
+  }
 } else {
-  console.error('This script must be run with Electron or with --clean flag');
+  console.error(t('needs-electron', { commands: CLI_COMMANDS.join(', ') }));
   process.exit(1);
 }
 
@@ -173,12 +1443,23 @@ class SwitcherooApp {
   mainView: WebContentsView | null = null;
   sidebarWidth: number = 250; // Track sidebar width
   taskspaceManager: TaskSpaceCommunicationManager; // Add taskspace communication manager
-
-  constructor(hostname: string) {
+  tokenProxies = new Map<string, TokenProxy>(); // Local token-injecting proxies, by taskspace uuid
+  configuredHostname: string; // Hostname or host group from settings, which `hostname` was selected from
+  notifier: Notifier; // Sends readiness, crash, signal and idle shutdown notifications to the configured sinks
+  deadMan: DeadManTimer | null = null; // Checks in with the host's dead-man timer, if `deadManHours` is set
+  tunnels: TunnelSupervisor; // The taskspaces' port forwards, reopened after network drops and sleep
+  detachedAt: string | null = null; // Set when leaving with the detach key, for saveTaskSpaceData
+  ready = false; // Set once startup is done and the active taskspace's server is up
+  startupError: string | null = null; // Why startup failed, if it did
+
+  constructor(hostname: string, configuredHostname: string = hostname, notifier: Notifier = new Notifier()) {
     // Global session management
     this.taskspaces = [];
     this.activeTaskSpaceUuid = null;
     this.hostname = hostname;
+    this.configuredHostname = configuredHostname;
+    this.notifier = notifier;
+    this.tunnels = new TunnelSupervisor(hostname, (localPorts) => this.handleTunnelsHealed(localPorts));
     this.loadingView = new LoadingView();
     this.errorView = new ErrorView();
 
@@ -188,8 +1469,16 @@ class SwitcherooApp {
     // Set up taskspace request handlers
     this.taskspaceManager.setTaskSpaceRequestHandler(this.handleTaskSpaceRequest.bind(this));
     this.taskspaceManager.setStatusRequestHandler(this.handleStatusRequest.bind(this));
+    this.taskspaceManager.setLogsRequestHandler((uuid, lines) =>
+      this.taskspaceWithUuid(uuid) ? new SessionLogFile(uuid).tail(lines) : []);
     this.taskspaceManager.setProgressLogHandler(this.handleProgressLog.bind(this));
     this.taskspaceManager.setUserSignalHandler(this.handleUserSignal.bind(this));
+    this.taskspaceManager.setClipboardCopyHandler(this.handleClipboardCopy.bind(this));
+    this.taskspaceManager.setRegistryChangeHandler(this.handleRegistryChange.bind(this));
+    this.taskspaceManager.setMaintenanceResultHandler((result) => {
+      const when = result.startedAt ? ` (${result.startedAt})` : '';
+      this.log(`${result.ok ? '🛠️' : '❌'} Maintenance job ${result.job}${when}: ${result.output}`);
+    });
 
     // Create a persistent session for this hostname (shared across all sessions)
     // and initialize it for vscode compatibility.
@@ -204,6 +1493,14 @@ class SwitcherooApp {
       });
     });
 
+    // A `tls` server's certificate is self-signed: trust it on the tunnel's
+    // localhost end when it is the one the server announced at startup
+    this.vscodeSession.setCertificateVerifyProc((request, callback) => {
+      const announced = request.hostname === 'localhost' && this.taskspaces.some(taskspace =>
+        taskspace.tlsFingerprint && electronFingerprint(taskspace.tlsFingerprint) === request.certificate.fingerprint);
+      callback(announced ? 0 : -3);
+    });
+
     // Create the main window
     this.mainWindow = new BaseWindow({
       width: 1200,
@@ -271,11 +1568,67 @@ class SwitcherooApp {
         this.sidebarView.webContents.once('did-finish-load', () => resolve());
       });
 
+      // Every later command and upload goes over this one SSH connection
+      this.loadingView.updateMessage(`Connecting to ${this.hostname}...`);
+      await withRetry(`Connecting to ${this.hostname}`, () => sshManager.ensureMaster(this.hostname),
+        (message) => this.loadingView.updateMessage(message));
+
+      // Reopen the port forwards whenever the network drops or the machine wakes up
+      this.tunnels.start();
+      powerMonitor.on('resume', () => this.tunnels.check('waking from sleep'));
+
+      // Make sure we can act as the configured remote user before installing anything
+      this.loadingView.updateMessage('Checking remote user...');
+      await sshManager.verifyRemoteUser(this.hostname);
+
+      // Keep the server install off NFS homes, where chmod and locking misbehave
+      await prepareNfsHome(this.hostname);
+
+      // Report GPUs so it is obvious when an ML host came up without its driver
+      const gpuReport = await detectGpus(this.hostname);
+      if (gpuReport) {
+        this.log(`GPUs on ${this.hostname}:\n${gpuReport}`);
+      }
+
+      // The user's dotfiles, so the servers' terminals start with their shell setup
+      if (loadSettings().dotfilesRepo) {
+        this.loadingView.updateMessage('Installing dotfiles...');
+        await installDotfiles(this.hostname, loadSettings(), message => this.log(message));
+      }
+
       // Start daemon for this hostname
       this.loadingView.updateMessage('Starting communication daemon...');
       await this.taskspaceManager.deployDaemonFiles(this.hostname);
       await this.taskspaceManager.startDaemon(this.hostname);
       this.log('✓ Daemon started successfully');
+
+      // Scheduled maintenance runs on the host on its own; its results arrive through the daemon
+      const maintenanceJobs = loadSettings().maintenanceJobs || [];
+      try {
+        await this.taskspaceManager.configureMaintenance(this.hostname, maintenanceJobs);
+        if (maintenanceJobs.length > 0) {
+          this.log(`✓ Maintenance agent running ${maintenanceJobs.length} scheduled jobs`);
+        }
+      } catch (error) {
+        this.log(`⚠️ Could not set up maintenance jobs: ${error.message}`);
+      }
+
+      // Have the host stop the session if this app goes away for good. An
+      // ephemeral session is torn down instead, at the latest its remaining
+      // time to live after the app last checked in.
+      const deadline = ephemeralDeadline();
+      const deadManHours = deadline
+        ? Math.min(loadSettings().deadManHours || Infinity, Math.max(deadline.getTime() - Date.now(), 60 * 1000) / (60 * 60 * 1000))
+        : loadSettings().deadManHours;
+      if (deadManHours) {
+        this.deadMan = new DeadManTimer(this.hostname, deadManHours, () => deadline ? this.teardownScript() : stopSessionScript(this.stoppableTaskSpaces()));
+        try {
+          await this.deadMan.start();
+        } catch (error) {
+          this.log(`⚠️ Could not set up the dead-man timer: ${error.message}`);
+          this.deadMan = null;
+        }
+      }
       this.log('✓ CLI tool available in terminals as: theoldswitcheroo');
 
       // Load existing taskspaces
@@ -288,6 +1641,10 @@ class SwitcherooApp {
         this.loadingView.updateMessage('Restoring saved taskspaces...');
         await this.restoreSavedTaskSpaces(savedTaskSpaceData);
         this.notifyTaskSpacesChanged();
+        // The host's registry may predate this session or be missing entries; bring it up to date
+        for (const taskspace of this.taskspaces) {
+          this.updateRegistry(taskspace);
+        }
       }
 
       // Make sure there is at least one taskspace
@@ -307,8 +1664,18 @@ class SwitcherooApp {
       this.loadingView.updateMessage('Loading VSCode...');
       await this.switchTaskSpace(this.taskspaceWithUuid(this.activeTaskSpaceUuid));
 
+      await this.saveLastGoodConfiguration();
+      this.ready = true;
+
     } catch (error) {
       this.log(`Error during startup: ${error instanceof Error ? error.message : error}`);
+      this.startupError = error instanceof Error ? error.message : String(error);
+
+      // An ephemeral session has nobody to look at the error; clean up what it made
+      if (ephemeralDeadline()) {
+        shutdownActiveApp('startup failed').finally(() => app.exit(1));
+        return;
+      }
 
       // Show error view for any startup failure
       this.errorView.showError(
@@ -320,6 +1687,86 @@ class SwitcherooApp {
     }
   }
 
+  /// Record the configuration that just started successfully, so `--last-good`
+  /// can replay it if a later settings or default change breaks this host.
+  async saveLastGoodConfiguration() {
+    const arch = await remoteArch(this.hostname);
+    saveLastGood({
+      configuredHostname: this.configuredHostname,
+      hostname: this.hostname,
+      savedAt: new Date().toISOString(),
+      arch,
+      settings: { ...loadSettings(), serverVersion: await resolveServerVersion() },
+      taskspacePorts: Object.fromEntries(this.taskspaces.map(taskspace => [taskspace.uuid, taskspace.port])),
+    });
+    this.log(`✓ Saved last known good configuration for ${this.configuredHostname}`);
+  }
+
+  /// Orderly shutdown: persist taskspace state, stop daemons, and close SSH
+  /// connections. An ephemeral session is torn down instead of saved.
+  async shutdown(reason: string) {
+    this.log(`Shutting down (${reason})...`);
+    if (ephemeralDeadline()) {
+      await this.tearDown();
+    } else {
+      this.saveTaskSpaceData();
+    }
+    // The host's timer stays set for the servers left running
+    this.deadMan?.stop();
+    this.tunnels.stop();
+
+    for (const hostname of this.taskspaceManager.getActiveHosts()) {
+      try {
+        await this.taskspaceManager.stopDaemon(hostname);
+      } catch (error) {
+        console.error(`Error cleaning up daemon for ${hostname}:`, error);
+      }
+    }
+
+    for (const proxy of this.tokenProxies.values()) {
+      proxy.close();
+    }
+
+    // Before the SSH connections they may share go
+    for (const line of await unmountSession().catch((error) => [`Could not unmount: ${error.message}`])) {
+      this.log(line);
+    }
+
+    sshManager.cleanup();
+    this.log('✓ Shutdown complete');
+  }
+
+  // The taskspaces as stopSessionScript takes them
+  stoppableTaskSpaces() {
+    return this.taskspaces.map(taskspace => ({
+      uuid: taskspace.uuid,
+      name: taskspace.name,
+      serverDataDir: `${BASE_DIR}/${new TaskSpacePaths(taskspace.uuid).serverDataDir}`
+    }));
+  }
+
+  // Script that stops the session and deletes everything it has on the host
+  teardownScript(): string {
+    const dirs = this.taskspaces.flatMap(taskspace => {
+      const paths = new TaskSpacePaths(taskspace.uuid);
+      return [`${BASE_DIR}/${paths.dir}`, `${BASE_DIR}/${path.posix.dirname(paths.serverDataDir)}`];
+    });
+    return teardownSessionScript(this.stoppableTaskSpaces(), dirs);
+  }
+
+  /// End an ephemeral session for good: stop its servers and daemon, delete
+  /// its taskspaces and their files on the host, and forget it locally
+  async tearDown() {
+    try {
+      const output = await execSSHCommand(this.hostname, this.teardownScript());
+      output.split('\n').filter(line => line.trim()).forEach(line => this.log(`  ${line.trim()}`));
+      fs.rmSync(TASKSPACES_FILE, { force: true });
+      this.log(`✓ Tore down the ephemeral session ${SESSION_NAME}`);
+    } catch (error) {
+      console.error(`Could not tear down the session on ${this.hostname}: ${error.message}; its dead-man timer will`);
+    }
+  }
+
   /// Replace the "main view" in our app with `view`
   /// and resize it to balance it with the sidebar (which is never removed).
   /// This will remove the existing main view, if any.
@@ -340,39 +1787,171 @@ class SwitcherooApp {
   }
 
   /// Create a vscode server for taskspace with uuid and (optionally) a previous port.
-  async ensureVSCodeServer(taskspace) {
+  async ensureVSCodeServer(taskspace, loadingView: ILoadingView | null = null) {
     // If taskspace already has a running server, check if it's still alive
     if (taskspace.port) {
-      if (await checkTaskSpaceHealth(this.hostname, taskspace.port)) {
+      const token = connectionTokensEnabled() ? await readConnectionToken(this.hostname, taskspace.uuid) : undefined;
+      if (await checkTaskSpaceHealth(this.hostname, taskspace.port, token)) {
         this.log(`✓ TaskSpace ${taskspace.name}: Server still running on port ${taskspace.port}`);
         // Ensure port forwarding is active
-        this.forwardPort(taskspace.port);
+        await this.forwardPort(taskspace, token);
+        if (token) {
+          await this.attachTokenProxy(taskspace, token);
+        }
+        // Catch the server up with settings changed since it started; a failure here leaves it usable
+        try {
+          await this.applyConfigChanges(taskspace, loadingView);
+        } catch (error) {
+          console.warn(`Could not apply configuration changes to ${taskspace.name}: ${error.message}`);
+        }
         return; // Server is good
       } else {
-        this.log(`TaskSpace ${taskspace.name}: Server died, restarting...`);
+        const cause = await diagnoseServerExit(this.hostname);
+        this.log(`TaskSpace ${taskspace.name}: Server died${cause ? ` (${cause})` : ''}, restarting...`);
       }
     }
 
     // Start fresh server
     this.log(`Starting VSCode server for taskspace ${taskspace.name}...`);
+    if (loadSettings().insecure) {
+      this.log(`⚠ TaskSpace ${taskspace.name}: starting without a connection token (insecure); anyone who can reach its port on ${this.hostname} gets the editor`);
+    }
 
-    // Detect architecture
-    const archOutput = await execSSHCommand(this.hostname, 'uname -m');
-    const arch = mapArchitecture(String(archOutput).toLowerCase());
+    // Detect architecture, unless it is configured
+    const report = (message: string) => loadingView?.updateMessage(message);
+    await withRetry('Checking the platform', () => checkPlatform(this.hostname), report);
+    const arch = await withRetry('Detecting the architecture', () => remoteArch(this.hostname), report);
 
     // Install VSCode server
-    await installVSCodeServer(this.hostname, arch);
+    await withRetry('Installing the VSCode server', () => installVSCodeServer(this.hostname, arch, loadingView), report);
+    if (serverdEnabled()) {
+      await withRetry('Installing the server supervisor', () => installServerd(this.hostname, arch), report);
+    }
+
+    // Bring this machine's VS Code settings along; the server starts without them if that fails
+    if (loadSettings().syncSettings) {
+      const userSettingsDir = serverFlavor().userSettingsDir({
+        userDataDir: `${BASE_DIR}/vscode-user-data`,
+        serverDataDir: `${BASE_DIR}/${new TaskSpacePaths(taskspace.uuid).serverDataDir}`
+      });
+      try {
+        const synced = await syncUserSettings(this.hostname, userSettingsDir);
+        this.log(`✓ TaskSpace ${taskspace.name}: synced local ${synced.length > 0 ? synced.join(', ') : 'nothing (no settings, keybindings or snippets)'}`);
+      } catch (error) {
+        this.log(`⚠️ TaskSpace ${taskspace.name}: could not sync local VS Code settings: ${error.message}`);
+      }
+    }
+
+    // Start server, turning a failed startup into a specific diagnosis where we can
+    let serverInfo: ServerInfo;
+    try {
+      serverInfo = await this.startVSCodeServer(this.hostname, taskspace.uuid, taskspace.name, taskspace.extensions);
+    } catch (error) {
+      if (error instanceof ServerStartupError) {
+        const diagnosis = await diagnoseServerStartupFailure(this.hostname, arch, error);
+        this.log(`✗ TaskSpace ${taskspace.name}: ${diagnosis}`);
+        throw new Error(`${error.message}\n\nDiagnosis: ${diagnosis}`);
+      }
+      throw error;
+    }
 
-    // Start server
-    const serverInfo = await this.startVSCodeServer(this.hostname, taskspace.uuid, taskspace.name, taskspace.extensions);
+    // A fresh server has the current configuration, except for extensions that failed to install
+    const applied = desiredConfig(taskspace.extensions || { marketplace: [], local: [] });
+    taskspace.applied = { ...applied, extensions: applied.extensions.filter(id => !serverInfo.failedExtensions?.includes(id)) };
+    await this.recordFingerprint(taskspace);
 
-    // Update port on the taskspace
+    // Update port and startup facts on the taskspace; the local port follows the new server port again
     taskspace.port = serverInfo.port;
+    if (serverdEnabled()) {
+      // Should the daemon restart it, it binds this port again
+      await pinServerPort(this.hostname, `${BASE_DIR}/${new TaskSpacePaths(taskspace.uuid).serverDataDir}`, serverInfo.port);
+    }
+    taskspace.localPort = undefined;
+    taskspace.webUiUrl = serverInfo.webUiUrl;
+    taskspace.listenAddress = serverInfo.listenAddress;
+    taskspace.tlsFingerprint = serverInfo.tlsFingerprint;
 
     // Start port forwarding
-    this.forwardPort(serverInfo.port);
+    const token = connectionTokensEnabled()
+      ? serverInfo.connectionToken || await readConnectionToken(this.hostname, taskspace.uuid)
+      : undefined;
+    await this.forwardPort(taskspace, token);
+    if (connectionTokensEnabled()) {
+      await this.attachTokenProxy(taskspace, token);
+    }
 
-    this.log(`✓ TaskSpace ${taskspace.name}: Server ready on port ${taskspace.port}`);
+    this.log(`✓ TaskSpace ${taskspace.name}: Server ready on port ${taskspace.port}, at ${taskspace.vscodeUrl}`);
+    if (token) {
+      // The tokenized URL works without the proxy, e.g. in a browser
+      this.log(`  Direct URL: http://localhost:${taskspace.localPort || taskspace.port}/?tkn=${token}`);
+    }
+    if (serverInfo.tlsFingerprint) {
+      this.log(`  HTTPS URL: https://${serverInfo.tlsHost || this.hostname}:${taskspace.port}/`);
+      this.log(`  Certificate SHA-256 fingerprint: ${serverInfo.tlsFingerprint}`);
+      this.log(`  ⚠ Anyone who can reach port ${taskspace.port} on ${this.hostname} gets the editor; check the fingerprint before trusting the certificate`);
+    }
+    this.notifyTaskSpaceReady(taskspace);
+  }
+
+  /// A running server went away: find out whether the host killed it
+  /// (OOM killer, full disk) and report the cause with the crash event.
+  /// A clean exit with no such cause is the server's own idle shutdown
+  /// (`--enable-remote-auto-shutdown`), which is not a crash.
+  async reportServerExit(uuid: string, name: string, exitCode: number | null, logFile: SessionLogFile) {
+    const cause = await diagnoseServerExit(this.hostname);
+    if (exitCode === 0 && !cause) {
+      this.log(`TaskSpace ${name}: VSCode server shut down after going idle`);
+      logFile.write('=== Server shut down after going idle');
+      this.notifier.notify({
+        event: 'idle-shutdown',
+        title: `${name} shut down`,
+        message: 'The VSCode server stopped after nothing was connected to it',
+        taskspace: { uuid, name }
+      });
+      return;
+    }
+
+    const description = cause || `server exited with code ${exitCode}`;
+    this.log(`✗ TaskSpace ${name}: VSCode server stopped: ${description}`);
+    logFile.write(`=== Server stopped: ${description}`);
+    this.notifier.notify({
+      event: 'crash',
+      title: `${name} crashed`,
+      message: description,
+      taskspace: { uuid, name }
+    });
+
+    this.taskspaceManager.sendMessage(this.hostname, {
+      type: 'taskspace_status',
+      uuid,
+      status: 'crashed',
+      exitCode,
+      cause: description,
+      timestamp: new Date().toISOString()
+    }).catch((error) => console.warn(`Could not send crash event: ${error.message}`));
+  }
+
+  /// Tell tools listening on the daemon that a taskspace's server is ready
+  notifyTaskSpaceReady(taskspace: TaskSpace) {
+    this.saveTaskSpaceData();
+    // So the timer covers the new server right away
+    this.deadMan?.checkIn().catch((error) => console.warn(`Could not check in with the dead-man timer: ${error.message}`));
+    this.notifier.notify({
+      event: 'ready',
+      title: `${taskspace.name} is ready`,
+      message: `VSCode server ready at ${taskspace.vscodeUrl}`,
+      taskspace: { uuid: taskspace.uuid, name: taskspace.name },
+      url: taskspace.vscodeUrl
+    });
+    this.taskspaceManager.sendMessage(this.hostname, {
+      type: 'taskspace_status',
+      uuid: taskspace.uuid,
+      status: 'ready',
+      port: taskspace.port,
+      webUiUrl: taskspace.webUiUrl,
+      listenAddress: taskspace.listenAddress,
+      timestamp: new Date().toISOString()
+    }).catch((error) => console.warn(`Could not send readiness event: ${error.message}`));
   }
 
   /// Log messages to console
@@ -483,11 +2062,13 @@ class SwitcherooApp {
 
   /// Handle taskspace requests from CLI tools via daemon
   private handleTaskSpaceRequest(request: {
-    type: 'new_taskspace' | 'update_taskspace';
+    type: 'new_taskspace' | 'update_taskspace' | 'annotate_taskspace' | 'delete_taskspace';
     name?: string;
     description?: string;
     cwd?: string;
     uuid?: string;
+    note?: string;
+    labels?: Record<string, string>;
     hostname: string;
   }): void {
     if (request.type === 'new_taskspace' && request.name) {
@@ -496,6 +2077,10 @@ class SwitcherooApp {
     } else if (request.type === 'update_taskspace' && request.uuid) {
       // Update existing taskspace
       this.updateTaskSpaceFromCLI(request.uuid, request.name, request.description);
+    } else if (request.type === 'annotate_taskspace' && request.uuid) {
+      this.annotateTaskSpaceFromCLI(request.uuid, request.note, request.labels || {});
+    } else if (request.type === 'delete_taskspace' && request.uuid) {
+      this.deleteTaskSpaceFromCLI(request.uuid);
     }
   }
 
@@ -505,7 +2090,10 @@ class SwitcherooApp {
       taskspaces: this.taskspaces.map(taskspace => ({
         name: taskspace.name,
         status: taskspace.uuid === this.activeTaskSpaceUuid ? 'active' : 'inactive',
-        uuid: taskspace.uuid
+        uuid: taskspace.uuid,
+        port: taskspace.port,
+        notes: taskspace.notes,
+        labels: taskspace.labels
       })),
       activeTaskSpace: this.activeTaskSpaceUuid || undefined
     };
@@ -524,6 +2112,7 @@ class SwitcherooApp {
       // Note: description and cwd would need to be added to TaskSpace class
 
       this.notifyTaskSpacesChanged();
+      this.emitLifecycleEvent('renamed', taskspace);
 
       console.log(`✓ Created taskspace ${name} from CLI request`);
     } catch (error) {
@@ -539,12 +2128,150 @@ class SwitcherooApp {
       // Note: description would need to be added to TaskSpace class
 
       this.notifyTaskSpacesChanged();
+      if (name) {
+        this.emitLifecycleEvent('renamed', taskspace);
+      }
       console.log(`✓ Updated taskspace ${uuid} from CLI request`);
     } else {
       console.warn(`TaskSpace ${uuid} not found for update`);
     }
   }
 
+  /// Add a note and/or set labels on a taskspace from a CLI request.
+  /// A label with an empty value is removed.
+  private annotateTaskSpaceFromCLI(uuid: string, note: string | undefined, labels: Record<string, string>): void {
+    const taskspace = this.taskspaceWithUuid(uuid);
+    if (!taskspace) {
+      console.warn(`TaskSpace ${uuid} not found for annotation`);
+      return;
+    }
+
+    if (note) {
+      taskspace.notes.push(note);
+    }
+    for (const [key, value] of Object.entries(labels)) {
+      if (value) {
+        taskspace.labels[key] = value;
+      } else {
+        delete taskspace.labels[key];
+      }
+    }
+
+    this.notifyTaskSpacesChanged();
+    this.emitLifecycleEvent('annotated', taskspace);
+    console.log(`✓ Annotated taskspace ${uuid} from CLI request`);
+  }
+
+  /// Stop a taskspace's server and forget it. Its clone and server data stay
+  /// on the host. The active taskspace cannot be deleted this way, since it is
+  /// the one the user is looking at (and likely where the request came from).
+  private async deleteTaskSpaceFromCLI(uuid: string): Promise<void> {
+    if (!this.taskspaceWithUuid(uuid)) {
+      console.warn(`TaskSpace ${uuid} not found for deletion`);
+      return;
+    }
+    try {
+      await this.deleteTaskSpace(uuid);
+    } catch (error) {
+      this.log(`✗ ${error.message}`);
+    }
+  }
+
+  /// Stop a taskspace's server and forget it, and with `purge` remove its
+  /// clone, server data and extensions from the host; otherwise they stay.
+  /// The active taskspace can't be deleted.
+  async deleteTaskSpace(uuid: string, purge = false): Promise<void> {
+    const taskspace = this.taskspaceWithUuid(uuid);
+    if (uuid === this.activeTaskSpaceUuid) {
+      throw new Error(`Not deleting ${taskspace.name}: it is the active taskspace; switch to another one first`);
+    }
+
+    const paths = new TaskSpacePaths(uuid);
+    const pidFile = serverPidFile(`${BASE_DIR}/${paths.serverDataDir}`);
+    try {
+      // Through the server supervisor if it runs the server, as it would restart a killed one
+      await execSSHCommand(this.hostname, `${serverdStopCommand(uuid)} || if [ -f ${pidFile} ]; then pkill -TERM -P "$(cat ${pidFile})"; kill "$(cat ${pidFile})"; fi; rm -f ${pidFile}; true`);
+    } catch (error) {
+      console.warn(`Could not stop the server of ${taskspace.name}: ${error.message}`);
+    }
+
+    this.tokenProxies.get(uuid)?.close();
+    this.tokenProxies.delete(uuid);
+    taskspace.vscodeView?.webContents.close();
+    taskspace.metaView?.webContents.close();
+    this.taskspaces = this.taskspaces.filter(t => t.uuid !== uuid);
+
+    this.notifyTaskSpacesChanged();
+    this.emitLifecycleEvent('deleted', taskspace);
+    if (purge) {
+      await execSSHCommand(this.hostname, `rm -rf ${BASE_DIR}/${paths.dir} ${BASE_DIR}/${path.posix.dirname(paths.serverDataDir)}`);
+      this.log(`✓ Deleted taskspace ${taskspace.name} and its files`);
+    } else {
+      this.log(`✓ Deleted taskspace ${taskspace.name} (its files remain in ${BASE_DIR}/${paths.dir})`);
+    }
+  }
+
+  /// Tell tools listening on the daemon that a taskspace was created, renamed,
+  /// annotated or deleted, so they can stay in sync without polling, and keep
+  /// the host's registry in step
+  emitLifecycleEvent(event: 'created' | 'renamed' | 'annotated' | 'deleted', taskspace: TaskSpace) {
+    this.updateRegistry(taskspace, event === 'deleted');
+    this.taskspaceManager.sendMessage(this.hostname, {
+      type: 'taskspace_lifecycle',
+      event,
+      uuid: taskspace.uuid,
+      name: taskspace.name,
+      notes: taskspace.notes,
+      labels: taskspace.labels,
+      timestamp: new Date().toISOString()
+    }).catch((error) => console.warn(`Could not send ${event} event: ${error.message}`));
+  }
+
+  /// Record the taskspace's current name, notes and labels in the host's registry,
+  /// or remove it from there when `deleted`
+  private updateRegistry(taskspace: TaskSpace, deleted = false) {
+    const update = deleted
+      ? this.taskspaceManager.deleteRegistryEntry(this.hostname, taskspace.uuid)
+      : this.taskspaceManager.putRegistryEntry(this.hostname, taskspace.uuid, {
+        name: taskspace.name,
+        notes: taskspace.notes,
+        labels: taskspace.labels
+      });
+    update.catch((error) => console.warn(`Could not update the registry entry of ${taskspace.name}: ${error.message}`));
+  }
+
+  /// A tool on the host changed the registry directly. Take over name, notes
+  /// and labels; deletions have to go through `delete-taskspace`, since the
+  /// server has to be stopped too, so a deleted entry is put back.
+  private handleRegistryChange(change: {
+    uuid: string;
+    revision: number;
+    fields?: Record<string, any>;
+    hostname: string;
+  }): void {
+    const taskspace = this.taskspaceWithUuid(change.uuid);
+    if (!taskspace) {
+      return;
+    }
+    if (!change.fields) {
+      this.log(`⚠️ ${taskspace.name} was removed from the registry; restoring it (use delete-taskspace to delete it)`);
+      this.updateRegistry(taskspace);
+      return;
+    }
+
+    const { name, notes, labels } = change.fields;
+    if (typeof name === 'string' && name) {
+      taskspace.name = name;
+    }
+    if (Array.isArray(notes)) {
+      taskspace.notes = notes.filter(note => typeof note === 'string');
+    }
+    if (labels && typeof labels === 'object' && !Array.isArray(labels)) {
+      taskspace.labels = Object.fromEntries(Object.entries(labels).filter(([, value]) => typeof value === 'string')) as Record<string, string>;
+    }
+    this.notifyTaskSpacesChanged();
+  }
+
   /// Handle progress log from CLI tools
   private handleProgressLog(log: {
     message: string;
@@ -566,13 +2293,34 @@ class SwitcherooApp {
     message: string;
     timestamp: string;
     hostname: string;
+    taskspaceUuid?: string;
   }): void {
     console.log(`User signal: ${signal.message}`);
-    
-    // For now, just log to console. In Phase 3, this will show GUI notification
     this.log(`🔔 Agent needs help: ${signal.message}`);
-    
-    // TODO: Show notification dialog or status bar alert
+
+    const taskspace = signal.taskspaceUuid ? this.taskspaceWithUuid(signal.taskspaceUuid) : undefined;
+    this.notifier.notify({
+      event: 'signal',
+      title: taskspace ? `${taskspace.name} needs help` : 'An agent needs help',
+      message: signal.message,
+      taskspace: taskspace ? { uuid: taskspace.uuid, name: taskspace.name } : undefined,
+      url: taskspace?.vscodeUrl
+    });
+  }
+
+  /// Handle a copy from the clipboard bridge's shims. The daemon takes the
+  /// message whatever the setting says, so it is checked here.
+  private handleClipboardCopy(copy: { text: string; hostname: string; taskspaceUuid?: string }): void {
+    const taskspace = copy.taskspaceUuid ? this.taskspaceWithUuid(copy.taskspaceUuid) : undefined;
+    const from = taskspace ? taskspace.name : copy.hostname;
+    if (!loadSettings().clipboardBridge) {
+      this.log(`Ignored a copy from ${from}: clipboardBridge is off`);
+      return;
+    }
+    clipboard.writeText(copy.text);
+    // What was copied stays out of the log; it may well be a secret
+    audit('clipboard-copy', { host: copy.hostname, taskspace: taskspace?.name ?? null, characters: copy.text.length });
+    this.log(`📋 Copied ${copy.text.length} characters from ${from}`);
   }
 
   /// Show error view with custom message
@@ -608,11 +2356,54 @@ class SwitcherooApp {
     this.taskspaces.push(taskspace);
 
     this.notifyTaskSpacesChanged();
+    this.emitLifecycleEvent('created', taskspace);
+
+    // Warm the new taskspace in the background, so it is responsive by the time it is opened
+    const prewarmGlobs = loadSettings().prewarmGlobs;
+    if (prewarmGlobs && prewarmGlobs.length > 0) {
+      this.prewarmTaskSpace(taskspace, prewarmGlobs)
+        .then((result) => this.log(`✓ Pre-warmed ${taskspace.name}: ${result.opened} files in ${result.durationMs}ms`))
+        .catch((error) => console.warn(`Could not pre-warm ${taskspace.name}: ${error.message}`));
+    }
     return taskspace;
   }
 
+  /// Have the taskspace's server index the workspace before anyone opens it:
+  /// start the server, load its UI in a hidden view (which starts the
+  /// extension host) and let the built-in extension open the files matching
+  /// `globs`, which starts their language servers. Resolves with the
+  /// extension's summary once it is done.
+  async prewarmTaskSpace(taskspace: TaskSpace, globs: string[]): Promise<any> {
+    const taskspaceDir = `${BASE_DIR}/${path.posix.dirname(new TaskSpacePaths(taskspace.uuid).cloneDir)}`;
+    const request = { globs, maxFiles: PREWARM_MAX_FILES, requestedAt: new Date().toISOString() };
+    this.log(`Pre-warming ${taskspace.name} (${globs.join(', ')})...`);
+
+    // The extension reads the request when it activates, so it must be in place before the view loads
+    await execSSHCommand(this.hostname, `rm -f ${taskspaceDir}/prewarm-result.json && printf '%s\n' ${shellQuote(JSON.stringify(request))} > ${taskspaceDir}/prewarm.json`);
+
+    if (taskspace.vscodeView) {
+      // The extension host is already running and has read its request (or there was none); reload it
+      taskspace.vscodeView.webContents.reload();
+    } else {
+      await taskspace.ensureVSCodeView(this);
+    }
+
+    const deadline = Date.now() + PREWARM_TIMEOUT_MS;
+    while (Date.now() < deadline) {
+      const output = await execSSHCommand(this.hostname, `cat ${taskspaceDir}/prewarm-result.json 2>/dev/null || true`);
+      if (output.trim()) {
+        return { taskspace: taskspace.name, ...JSON.parse(output) };
+      }
+      await new Promise(resolve => setTimeout(resolve, PREWARM_POLL_INTERVAL_MS));
+    }
+    throw new Error(`${taskspace.name} did not finish pre-warming within ${PREWARM_TIMEOUT_MS / 60000} minutes`);
+  }
+
   async restoreSavedTaskSpaces(savedTaskSpaceData: SavedTaskSpaceData) {
     this.log(`Restoring previous session with ${savedTaskSpaceData.taskspaces.length} existing taskspaces`);
+    if (savedTaskSpaceData.detachedAt) {
+      this.log(`Reattaching to the servers left running when the app detached at ${savedTaskSpaceData.detachedAt}`);
+    }
 
     // Check directory existence for each saved taskspace
     for (const savedTaskSpaceDatum of savedTaskSpaceData.taskspaces) {
@@ -627,6 +2418,17 @@ class SwitcherooApp {
 
         // Create taskspace object with saved port (server status unknown)
         const taskspace = new TaskSpace(savedTaskSpaceDatum.uuid, savedTaskSpaceDatum.name, this.hostname, savedTaskSpaceDatum.port, this, savedTaskSpaceDatum.extensions);
+        taskspace.webUiUrl = savedTaskSpaceDatum.webUiUrl;
+        taskspace.listenAddress = savedTaskSpaceDatum.listenAddress;
+        taskspace.tlsFingerprint = savedTaskSpaceDatum.tlsFingerprint;
+        taskspace.localPort = savedTaskSpaceDatum.localPort;
+        taskspace.notes = savedTaskSpaceDatum.notes || [];
+        taskspace.labels = savedTaskSpaceDatum.labels || {};
+        taskspace.applied = savedTaskSpaceDatum.applied;
+        taskspace.fingerprint = savedTaskSpaceDatum.fingerprint;
+        if (connectionTokensEnabled()) {
+          taskspace.proxyPort = savedTaskSpaceDatum.proxyPort;
+        }
         this.taskspaces.push(taskspace);
       } catch (error) {
         this.log(`✗ TaskSpace ${savedTaskSpaceDatum.name}: Directory missing, removing from list`);
@@ -651,7 +2453,8 @@ class SwitcherooApp {
     // Test basic SSH connection first
     if (loadingView) loadingView.updateMessage(`Testing SSH connection for ${name}...`);
     this.log('Testing SSH connection...');
-    await execSSHCommand(this.hostname, 'echo "SSH connection successful"');
+    await withRetry('Testing the SSH connection', () => execSSHCommand(this.hostname, 'echo "SSH connection successful"'),
+      (message) => loadingView?.updateMessage(message));
     this.log('✓ SSH connection test successful');
 
     // Setup remote directory
@@ -671,9 +2474,10 @@ class SwitcherooApp {
     const projectName = 'theoldswitcheroo';
     const projectDir = path.join(LOCAL_DATA_DIR, 'projects', projectName);
     const cloneScript = path.join(projectDir, 'fresh-clone.sh');
+    const cloneRepo = loadSettings().cloneRepo;
 
-    // Check if project definition exists
-    if (!fs.existsSync(cloneScript)) {
+    // Check if project definition exists; a configured repository needs no clone script
+    if (!cloneRepo && !fs.existsSync(cloneScript)) {
       throw new Error(`Project definition not found: ${cloneScript}`);
     }
 
@@ -690,6 +2494,17 @@ class SwitcherooApp {
     if (loadingView) loadingView.updateMessage(`Creating taskspace ${name} directory...`);
     await execSSHCommand(this.hostname, `mkdir -p ${BASE_DIR}/${taskspacePaths.dir}`);
 
+    if (cloneRepo) {
+      const branch = loadSettings().cloneBranch;
+      if (loadingView) loadingView.updateMessage(`Cloning ${cloneRepo}${branch ? ` (${branch})` : ''} for taskspace ${name}...`);
+      const output = await execSSHCommand(this.hostname, cloneRepoCommand(cloneRepo, branch, remoteTargetDir));
+      const kept = /^kept (.*)$/m.exec(output);
+      if (kept) {
+        this.log(`⚠️ TaskSpace ${name}: left the existing clone as it is, since it ${kept[1]}`);
+      }
+      return extensions;
+    }
+
     // Upload the clone script to taskspace directory
     if (loadingView) loadingView.updateMessage(`Uploading clone script for ${name}...`);
     const remoteScriptPath = `${BASE_DIR}/${taskspacePaths.freshClone}`;
@@ -725,12 +2540,22 @@ class SwitcherooApp {
       const data = {
         hostname: this.hostname,
         activeTaskSpaceUuid: this.activeTaskSpaceUuid,
+        detachedAt: this.detachedAt ?? undefined,
         taskspaces: this.taskspaces.map(s => ({
           uuid: s.uuid,
           name: s.name,
           port: s.port,
+          localPort: s.localPort,
+          proxyPort: s.proxyPort,
+          notes: s.notes,
+          labels: s.labels,
+          webUiUrl: s.webUiUrl,
+          listenAddress: s.listenAddress,
+          tlsFingerprint: s.tlsFingerprint,
           serverDataDir: `${BASE_DIR}/${new TaskSpacePaths(s.uuid).serverDataDir}`,
-          lastSeen: new Date().toISOString()
+          lastSeen: new Date().toISOString(),
+          applied: s.applied,
+          fingerprint: s.fingerprint
         }))
       };
 
@@ -741,91 +2566,296 @@ class SwitcherooApp {
     }
   }
 
+  /// Upload the taskspace's local extensions and install all of them into its
+  /// extensions directory. Returns the ids of the ones that failed to install.
+  async installTaskSpaceExtensions(hostname: string, taskspaceUuid: string, taskspaceName: string, extensions: Extensions): Promise<string[]> {
+    // Upload local extensions if any
+    if (extensions.local && extensions.local.length > 0) {
+      console.log(`Uploading custom extensions...`);
+      for (const localExt of extensions.local) {
+        const localPath = path.resolve(__dirname, localExt);
+        const remotePath = `${BASE_DIR}/${path.basename(localExt)}`;
+        console.log(`Uploading ${localPath} to ${remotePath}`);
+        await execSCP(hostname, localPath, remotePath);
+      }
+    }
+
+    const ids = extensionIds(extensions);
+    if (ids.length === 0) {
+      return [];
+    }
+    const summary = await installExtensions(hostname, `${BASE_DIR}/${new TaskSpacePaths(taskspaceUuid).extensionsDir}`, ids,
+      loadSettings().extensionInstallConcurrency || 3);
+    this.log(`Installed ${summary.installed.length}/${ids.length} extensions for ${taskspaceName}`);
+    for (const failure of summary.failed) {
+      console.warn(`✗ Extension ${failure.extension} failed to install: ${failure.reason}`);
+    }
+    return summary.failed.map(failure => failure.extension);
+  }
+
+  /// Bring a running server in line with the current settings without
+  /// restarting it: install the extensions added since it was set up and
+  /// rewrite its Machine settings if they changed. A changed environment can
+  /// only be reported, since it takes a restart.
+  async applyConfigChanges(taskspace: TaskSpace, loadingView: ILoadingView | null = null): Promise<void> {
+    const extensions = readProjectExtensions();
+    const desired = desiredConfig(extensions);
+    const delta = diffConfig(taskspace.applied, desired);
+    if (isEmptyDelta(delta)) {
+      return;
+    }
+
+    let failedExtensions: string[] = [];
+    if (delta.addedExtensions.length > 0) {
+      if (loadingView) loadingView.updateMessage(`Installing ${delta.addedExtensions.length} new extensions for ${taskspace.name}...`);
+      const added = {
+        marketplace: extensions.marketplace.filter(id => delta.addedExtensions.includes(id)),
+        local: extensions.local.filter(file => delta.addedExtensions.includes(path.basename(file)))
+      };
+      failedExtensions = await this.installTaskSpaceExtensions(this.hostname, taskspace.uuid, taskspace.name, added);
+    }
+
+    if (delta.changedSettings.length > 0) {
+      await writeMachineSettings(this.hostname, taskspace.uuid, desired.machineSettings);
+    }
+
+    taskspace.extensions = extensions;
+    taskspace.applied = {
+      extensions: desired.extensions.filter(id => !failedExtensions.includes(id)),
+      machineSettings: desired.machineSettings,
+      // Still what the server runs with until it restarts
+      environment: taskspace.applied ? taskspace.applied.environment : desired.environment
+    };
+    await this.recordFingerprint(taskspace);
+    this.saveTaskSpaceData();
+
+    this.log(`Applied configuration changes to ${taskspace.name}:`);
+    for (const line of formatDelta(delta, failedExtensions)) {
+      this.log(`  - ${line}`);
+    }
+  }
+
+  /// Remember how the host looks for `taskspace` now that the app has set it
+  /// up, for `--status` to report drift from. Without it there is no drift check.
+  async recordFingerprint(taskspace: TaskSpace): Promise<void> {
+    const serverDataDir = `${BASE_DIR}/${new TaskSpacePaths(taskspace.uuid).serverDataDir}`;
+    try {
+      const fingerprints = await collectFingerprints(this.hostname, [{ uuid: taskspace.uuid, serverDataDir }]);
+      taskspace.fingerprint = fingerprints.get(taskspace.uuid);
+    } catch (error) {
+      console.warn(`Could not fingerprint ${taskspace.name}: ${error.message}`);
+    }
+  }
+
+  /// Bring a taskspace that drifted from its fingerprint back to the declared
+  /// configuration: reinstall removed extensions, rewrite edited Machine
+  /// settings and upload the agent again. A changed server is reinstalled on
+  /// its next start, and extensions added by hand are left installed. Resolves
+  /// with what was found and what was done.
+  async reconcileTaskSpace(taskspace: TaskSpace): Promise<string[]> {
+    if (!taskspace.fingerprint) {
+      return ['no fingerprint recorded; open the taskspace to record one'];
+    }
+    const paths = new TaskSpacePaths(taskspace.uuid);
+    const serverDataDir = `${BASE_DIR}/${paths.serverDataDir}`;
+    const current = (await collectFingerprints(this.hostname, [{ uuid: taskspace.uuid, serverDataDir }])).get(taskspace.uuid);
+    const drift = diffFingerprint(taskspace.fingerprint, current);
+    if (!hasDrift(drift)) {
+      return [];
+    }
+
+    const done = formatDrift(drift);
+    if (drift.removedExtensions.length > 0 && taskspace.applied) {
+      // Forgetting them makes applyConfigChanges install them again; extension directories are `<id>-<version>`
+      const removed = (id: string) => drift.removedExtensions.some(name => name.toLowerCase().startsWith(`${id.split('@')[0].toLowerCase()}-`));
+      taskspace.applied.extensions = taskspace.applied.extensions.filter(id => !removed(id));
+      await this.applyConfigChanges(taskspace);
+      done.push('reinstalled the missing extensions');
+    }
+    if (drift.machineSettingsChanged) {
+      await writeMachineSettings(this.hostname, taskspace.uuid, taskspace.applied?.machineSettings || machineSettings());
+      done.push('rewrote the Machine settings');
+    }
+    if (drift.addedExtensions.length > 0) {
+      done.push('left the added extensions installed; uninstall them from the Extensions view if they are unwanted');
+    }
+    if (drift.agentChanged) {
+      await this.taskspaceManager.deployDaemonFiles(this.hostname);
+      done.push('uploaded the agent again; it runs from the next restart of the daemon');
+    }
+    if (drift.server) {
+      // Without its marker the install step reinstalls the declared release on the next start
+      const flavor = serverFlavor();
+      await execSSHCommand(this.hostname, `rm -f ${BASE_DIR}/${flavor.installDir}/.release`);
+      done.push('the declared server is reinstalled when the taskspace restarts');
+    }
+    const recorded = taskspace.fingerprint;
+    await this.recordFingerprint(taskspace);
+    if (drift.server) {
+      // The running server is still the changed one, so keep reporting it until the restart
+      taskspace.fingerprint = { ...taskspace.fingerprint, serverRelease: recorded.serverRelease, serverVersion: recorded.serverVersion };
+    }
+    this.saveTaskSpaceData();
+    return done;
+  }
+
   /// Start a vscode server process for the given taskspace, connected to the given uuid, with the given name.
   async startVSCodeServer(hostname: string, taskspaceUuid: string, taskspaceName: string, extensions: Extensions = { marketplace: [], local: [] }): Promise<ServerInfo> {
     this.log(`Starting SSH with port forwarding for session ${taskspaceName}...`);
 
-    // Upload local extensions if any
-    if (extensions.local && extensions.local.length > 0) {
-      console.log(`Uploading custom extensions...`);
-      for (const localExt of extensions.local) {
-        const localPath = path.resolve(__dirname, localExt);
-        const remotePath = `${BASE_DIR}/${path.basename(localExt)}`;
-        console.log(`Uploading ${localPath} to ${remotePath}`);
-        await execSCP(hostname, localPath, remotePath);
-      }
-    }
+    // Install extensions before starting the server; a failed extension is reported, not fatal
+    const dirs = new TaskSpacePaths(taskspaceUuid);
+    const failedExtensions = await this.installTaskSpaceExtensions(hostname, taskspaceUuid, taskspaceName, extensions);
 
     return new Promise((resolve, reject) => {
 
-      const dirs = new TaskSpacePaths(taskspaceUuid);
-
-      // Build extension install commands
-      const marketplaceCommands = extensions.marketplace?.length > 0
-        ? extensions.marketplace.map(ext => `./openvscode-server/bin/openvscode-server --extensions-dir ${BASE_DIR}/${dirs.extensionsDir} --install-extension ${ext}`).join(' && ')
-        : '';
-
-      const localCommands = extensions.local?.length > 0
-        ? extensions.local.map(ext => `./openvscode-server/bin/openvscode-server --extensions-dir ${BASE_DIR}/${dirs.extensionsDir} --install-extension ${BASE_DIR}/${path.basename(ext)}`).join(' && ')
-        : '';
+      const settings = loadSettings();
+      const flavor = serverFlavor();
+      const logFilter = logFilterEnabled(settings)
+        ? logFilterCommands(settings, `${BASE_DIR}/${flavor.installDir}/${flavor.nodeBinary}`, `${BASE_DIR}/${dirs.serverDataDir}/server.log`)
+        : null;
+      // A supervised server binds the port in SERVER_PORT (see pinnedPortCommand)
+      const supervised = serverdEnabled();
+      const portFlag = serverPortFlag(settings.remotePort);
+      const launchFlags = flavor.launchFlags({
+        port: supervised ? '$SERVER_PORT' : portFlag,
+        userDataDir: `${BASE_DIR}/vscode-user-data`,
+        serverDataDir: `${BASE_DIR}/${dirs.serverDataDir}`,
+        extensionsDir: `${BASE_DIR}/${dirs.extensionsDir}`,
+        folder: `${BASE_DIR}/${dirs.cloneDir}`,
+        tokenFile: connectionTokensEnabled() ? connectionTokenFile(dirs) : undefined,
+        tls: settings.tls ? { cert: `${TLS_DIR}/cert.pem`, key: `${TLS_DIR}/key.pem` } : undefined,
+        autoShutdown: !settings.persistServers,
+        bindAddress: serverBindAddress()
+      });
+      audit('server-start', {
+        host: hostname,
+        taskspace: taskspaceName,
+        flavor: flavor.name,
+        bindAddress: serverBindAddress() || null,
+        connectionToken: connectionTokensEnabled(),
+        tls: !!settings.tls,
+        autoShutdown: !settings.persistServers
+      });
 
-      const allExtensionCommands = [marketplaceCommands, localCommands].filter(cmd => cmd).join(' && ');
+      // What starts the server itself: its environment, process settings and command line
+      const launchScript = `
+        cd ${BASE_DIR}
+        ${localeExports()}
+        ${cudaExports()}
+        ${sessionExports()}
+        ${clipboardExports()}
+        ${supervised ? pinnedPortCommand(`${BASE_DIR}/${dirs.serverDataDir}`, flavor.name === 'code-server' ? portFlag.split('-')[0] : portFlag) : ''}
+        # Apply the configured working directory, umask and limits, and log what the server gets
+        ${serverProcessSetup()}
+        echo "Server process: cwd=$(pwd) umask=$(umask) open-files=$(ulimit -n) processes=$(ulimit -u)"
+
+        ${logFilter ? `# Keep the full log here and stream only what the log settings ask for${logFilter.setup}` : ''}
+
+        # Start VSCode with data directories and dynamic port, opening the cloned project.
+        # exec keeps the PID, so the recorded one is the server's (see --status)
+        echo $$ > ${serverPidFile(`${BASE_DIR}/${dirs.serverDataDir}`)}
+        exec ${BASE_DIR}/${flavor.installDir}/${flavor.binary} \\
+          ${launchFlags.join(' \\\n          ')} ${logFilter ? logFilter.redirect : ''}
+      `;
 
-      // Simple server script with auto-shutdown and data directories
+      // Simple server script with auto-shutdown and data directories; with
+      // serverSupervisor "serverd" the daemon runs the launch script, and this only follows it
       const serverScript = `
         cd ${BASE_DIR}
-        
+
         # Create session-specific directories
         mkdir -p ${dirs.serverDataDir}
         mkdir -p ${dirs.extensionsDir}
         mkdir -p vscode-user-data
-        
-        ${allExtensionCommands ? `# Install extensions\n        ${allExtensionCommands}\n        ` : ''}
-        # Start VSCode with data directories and dynamic port, opening the cloned project
-        ./openvscode-server/bin/openvscode-server \\
-          --host 0.0.0.0 \\
-          --port 0 \\
-          --user-data-dir ${BASE_DIR}/vscode-user-data \\
-          --server-data-dir ${BASE_DIR}/${dirs.serverDataDir} \\
-          --extensions-dir ${BASE_DIR}/${dirs.extensionsDir} \\
-          --without-connection-token \\
-          --enable-remote-auto-shutdown \\
-          --disable-workspace-trust \\
-          --default-folder ${BASE_DIR}/${dirs.cloneDir} 2>&1
+        ${machineSettingsCommand(dirs)}
+        ${connectionTokenCommand(dirs)}
+        ${tlsCertificateCommand()}
+        ${supervised ? supervisedServerCommand(taskspaceUuid, `${BASE_DIR}/${dirs.serverDataDir}`, launchScript) : launchScript}
       `;
 
       console.log(serverScript);
 
       // Get the SSH process for streaming
       sshManager.executeStreamingCommand(hostname, serverScript).then(ssh => {
+        const logFile = new SessionLogFile(taskspaceUuid);
+        logFile.write(`=== Starting VSCode server for ${taskspaceName} on ${hostname}`);
+        this.log(`Logging VSCode server ${taskspaceName} output to ${logFile.path}`);
+
         let actualPort = null;
+        const facts: ServerStartupFacts = {};
+        const stderrLines: string[] = [];
+        let graceTimer: NodeJS.Timeout | null = null;
 
-        ssh.stdout.on('data', (data) => {
-          const output = data.toString();
-          this.log(`[VSCode Server ${taskspaceName}] ${output.trim()}`);
+        const markReady = () => {
+          if (actualPort) {
+            return;
+          }
+          if (graceTimer) {
+            clearTimeout(graceTimer);
+          }
 
-          // Look for VSCode's port announcement in its output
-          // VSCode typically outputs: "Web UI available at http://localhost:XXXX"
-          const portMatch = output.match(/Web UI available at.*:(\d+)/i) ||
-            output.match(/localhost:(\d+)/) ||
-            output.match(/127\.0\.0\.1:(\d+)/) ||
-            output.match(/0\.0\.0\.0:(\d+)/);
+          // The port the server actually bound is authoritative; the Web UI
+          // line is only a hint and can disagree if the server picked another port.
+          actualPort = facts.listenPort ?? facts.webUiPort;
+          if (facts.listenPort && facts.webUiPort && facts.listenPort !== facts.webUiPort) {
+            console.warn(`VSCode server ${taskspaceName} advertised port ${facts.webUiPort} but is listening on ${facts.listenPort}; using ${facts.listenPort}`);
+          }
+          this.log(`✓ VSCode server ${taskspaceName} ready on port ${actualPort}` +
+            (facts.listenAddress ? ` (bound to ${facts.listenAddress})` : ''));
+
+          resolve({
+            serverProcess: ssh,
+            port: actualPort,
+            webUiUrl: facts.webUiUrl,
+            listenAddress: facts.listenAddress,
+            connectionToken: facts.connectionToken,
+            tlsHost: facts.tlsHost,
+            tlsFingerprint: facts.tlsFingerprint,
+            failedExtensions,
+          });
+        };
+
+        const handleLine = (source: OutputSource, line: string) => {
+          logFile.write(`[${source}] ${line}`);
+          if (source === 'stderr') {
+            stderrLines.push(line);
+            console.error(`${logTimestampPrefix()}[VSCode Server ${taskspaceName} ${source}] ${line}`);
+          } else {
+            this.log(`${logTimestampPrefix()}[VSCode Server ${taskspaceName} ${source}] ${line}`);
+          }
 
-          if (portMatch && !actualPort) {
-            actualPort = parseInt(portMatch[1]);
-            this.log(`✓ VSCode server ${taskspaceName} ready on port ${actualPort}`);
+          Object.assign(facts, parseServerOutputLine(line));
 
-            resolve({ serverProcess: ssh, port: actualPort });
+          // "Web UI available at ..." is the last line of the startup banner.
+          // If we only ever see the bind line, give the banner a moment and then go with what we have.
+          if (facts.webUiUrl) {
+            markReady();
+          } else if (facts.listenPort && !graceTimer && !actualPort) {
+            graceTimer = setTimeout(markReady, 2000);
           }
-        });
+        };
 
-        ssh.stderr.on('data', (data) => {
-          const output = data.toString().trim();
-          console.error(`[VSCode Server ${taskspaceName} Error] ${output}`);
-        });
+        forEachLine(ssh.stdout, (line) => handleLine('stdout', line));
+        forEachLine(ssh.stderr, (line) => handleLine('stderr', line));
 
         ssh.on('close', (code) => {
           this.log(`SSH process for session ${taskspaceName} exited with code ${code}`);
+          logFile.write(`=== SSH process exited with code ${code}`);
+          if (actualPort && !shuttingDown) {
+            // A native channel that closes with its connection has no exit code at all
+            if (code === SSH_CONNECTION_LOST_EXIT || (code === null && !ssh.killed)) {
+              this.handleLostServerSession(taskspaceUuid, taskspaceName, logFile)
+                .catch((error) => console.error(`Could not check on ${taskspaceName} after losing its SSH session:`, error));
+            } else {
+              this.reportServerExit(taskspaceUuid, taskspaceName, code, logFile);
+            }
+          }
+          if (!actualPort) {
+            const stderrTail = stderrLines.slice(-20).join('\n');
+            reject(new ServerStartupError(`VSCode server for session ${taskspaceName} exited with code ${code} before it was ready` +
+              (stderrTail ? `:\n${stderrTail}` : ''), code, stderrTail));
+          }
         });
 
         ssh.on('error', (err) => {
@@ -835,16 +2865,111 @@ class SwitcherooApp {
         // Timeout if server doesn't start
         setTimeout(() => {
           if (!actualPort) {
-            reject(new Error(`VSCode server startup timeout for session ${taskspaceName}`));
+            reject(new ServerStartupError(`VSCode server startup timeout for session ${taskspaceName}`, null, stderrLines.slice(-20).join('\n')));
           }
         }, 60000); // 60 second timeout
       }).catch(reject);
     });
   }
 
-  // Set up port forwarding for the actual port
-  forwardPort(port: number) {
-    return sshManager.createTunnel(this.hostname, port, port);
+  /// Put the taskspace behind a local proxy that adds its connection token.
+  ///
+  /// The proxy keeps its port for the life of the taskspace (it is saved in
+  /// taskspaces.json), so a restarted server with a new token and port is
+  /// still reached at the same URL.
+  async attachTokenProxy(taskspace: TaskSpace, token: string | undefined) {
+    if (!token) {
+      throw new Error(`TaskSpace ${taskspace.name}: server did not report a connection token`);
+    }
+
+    const existing = this.tokenProxies.get(taskspace.uuid);
+    if (existing) {
+      existing.retarget(taskspace.localPort || taskspace.port, token);
+      return;
+    }
+
+    const proxy = new TokenProxy(taskspace.localPort || taskspace.port, token);
+    try {
+      taskspace.proxyPort = await proxy.listen(taskspace.proxyPort || 0);
+    } catch (error) {
+      this.log(`Proxy port ${taskspace.proxyPort} for ${taskspace.name} is taken (${error.message}), picking a new one`);
+      taskspace.proxyPort = await proxy.listen(0);
+    }
+    this.tokenProxies.set(taskspace.uuid, proxy);
+    this.log(`✓ TaskSpace ${taskspace.name}: Proxy on localhost:${taskspace.proxyPort} adds the connection token`);
+  }
+
+  /// Forward a local port to the taskspace's server. The local port is the
+  /// one used before (saved in taskspaces.json), else the first free one from
+  /// the `localPort` setting, else the server's port.
+  /// If it is already taken, e.g. by the tunnel of a run that crashed, and it
+  /// already reaches this taskspace's server, attach to it. If something else
+  /// has it, forward the next free port instead and record it as the
+  /// taskspace's `localPort`.
+  async forwardPort(taskspace: TaskSpace, token?: string) {
+    const configured = loadSettings().localPort;
+    if (!taskspace.localPort && configured) {
+      // Skip ports other taskspaces hold even if their tunnels are not listening yet
+      const taken = new Set(this.taskspaces.filter(other => other !== taskspace).map(other => other.localPort));
+      let localPort = await findFreeLocalPort(configured);
+      while (taken.has(localPort)) {
+        localPort = await findFreeLocalPort(localPort + 1);
+      }
+      this.tunnels.open(localPort, taskspace.port, !!taskspace.tlsFingerprint);
+      taskspace.localPort = localPort;
+      return;
+    }
+
+    const wanted = taskspace.localPort || taskspace.port;
+    if (!(await isLocalPortInUse(wanted))) {
+      this.tunnels.open(wanted, taskspace.port, !!taskspace.tlsFingerprint);
+      taskspace.localPort = wanted;
+      return;
+    }
+
+    if (await localEndpointServes(wanted, taskspace.uuid, token)) {
+      this.log(`✓ localhost:${wanted} is already forwarded to this taskspace's server, reusing it`);
+      taskspace.localPort = wanted;
+      return;
+    }
+
+    const localPort = await findFreeLocalPort(wanted + 1);
+    this.log(`Local port ${wanted} is in use by something else; forwarding localhost:${localPort} to port ${taskspace.port} on ${this.hostname} instead`);
+    this.tunnels.open(localPort, taskspace.port, !!taskspace.tlsFingerprint);
+    taskspace.localPort = localPort;
+  }
+
+  /// The forwards to `localPorts` work again after a drop: reload the
+  /// taskspaces behind them, whose pages lost their connection meanwhile
+  handleTunnelsHealed(localPorts: number[]) {
+    for (const taskspace of this.taskspaces) {
+      if (taskspace.localPort && localPorts.includes(taskspace.localPort) && taskspace.vscodeView) {
+        this.log(`Reloading ${taskspace.name} after reconnecting`);
+        taskspace.vscodeView.webContents.reload();
+      }
+    }
+  }
+
+  /// The SSH session a server was started in dropped (ssh exits with 255 when
+  /// its connection does), which does not mean the server stopped. Wait for
+  /// the tunnels to reconnect, then re-attach if the server is still there,
+  /// and only report a crash if it is not.
+  async handleLostServerSession(uuid: string, name: string, logFile: SessionLogFile) {
+    this.log(`TaskSpace ${name}: lost the SSH session of its VSCode server; reconnecting to check on it`);
+    logFile.write('=== Lost the SSH session; the server may still be running');
+    await this.tunnels.heal(`the SSH session of ${name}'s server dropped`);
+
+    const taskspace = this.taskspaceWithUuid(uuid);
+    if (!taskspace || shuttingDown) {
+      return;
+    }
+    const token = connectionTokensEnabled() ? await readConnectionToken(this.hostname, uuid) : undefined;
+    if (await checkTaskSpaceHealth(this.hostname, taskspace.port, token)) {
+      this.log(`✓ TaskSpace ${name}: re-attached to its server on port ${taskspace.port}; its output is no longer streamed until it restarts`);
+      logFile.write('=== Re-attached to the running server');
+      return;
+    }
+    this.reportServerExit(uuid, name, 255, logFile);
   }
 
 
@@ -855,6 +2980,8 @@ class SwitcherooApp {
     const taskspaceData = this.taskspaces.map(taskspace => ({
       uuid: taskspace.uuid,
       name: taskspace.name,
+      notes: taskspace.notes,
+      labels: taskspace.labels,
       active: taskspace.uuid === this.activeTaskSpaceUuid
     }));
     console.log(`Posting message to sidecar`, taskspaceData);
@@ -883,11 +3010,22 @@ class TaskSpace {
   name: string;
   hostname: string;
   port: number;
+  proxyPort?: number; // Local port of the token proxy, if the server requires a connection token
+  localPort?: number; // Local end of the tunnel to `port`; differs from it when that port was taken locally
+  notes: string[] = []; // Free-form notes from `theoldswitcheroo annotate`, oldest first
+  labels: Record<string, string> = {}; // key=value labels from `theoldswitcheroo annotate`
+  applied?: AppliedConfig; // What the server was set up with, to apply only what changed on a later connect
+  fingerprint?: EnvironmentFingerprint; // How the host looked right after that, to notice changes made behind our back
+  webUiUrl?: string;
+  listenAddress?: string;
+  tlsFingerprint?: string; // SHA-256 fingerprint of the server's self-signed certificate, when it serves HTTPS (`tls`)
   viewName: string;
   createdAt: Date;
   vscodeView: WebContentsView | null = null;
   metaView: WebContentsView | null = null;
   extensions!: Extensions;
+  // Set while `vscodeView` is being created, so a pre-warm and a switch share one view
+  private pendingVSCodeView: Promise<WebContentsView> | null = null;
 
   /// Create TaskSpace with the given uuid/name running on the given host.
   ///
@@ -910,8 +3048,11 @@ class TaskSpace {
   }
 
   get vscodeUrl() {
-    // Always localhost due to port forwarding
-    return `http://localhost:${this.port}`;
+    // Always localhost due to port forwarding; through the token proxy if there is one
+    if (this.proxyPort) {
+      return `http://localhost:${this.proxyPort}`;
+    }
+    return `${this.tlsFingerprint ? 'https' : 'http'}://localhost:${this.localPort || this.port}`;
   }
 
   toggleView() {
@@ -922,6 +3063,50 @@ class TaskSpace {
     }
   }
 
+  /// Ensure the VSCode view exists and has loaded, starting the server if
+  /// needed. The view does not have to be on screen: a pre-warm loads it
+  /// hidden, which starts the workspace's extension host.
+  async ensureVSCodeView(switcheroo: SwitcherooApp, loadingView: ILoadingView | null = null): Promise<WebContentsView> {
+    if (this.vscodeView) {
+      return this.vscodeView;
+    }
+    if (!this.pendingVSCodeView) {
+      this.pendingVSCodeView = this.createVSCodeView(switcheroo, loadingView)
+        .finally(() => this.pendingVSCodeView = null);
+    }
+    return this.pendingVSCodeView;
+  }
+
+  private async createVSCodeView(switcheroo: SwitcherooApp, loadingView: ILoadingView | null): Promise<WebContentsView> {
+    // Ensure VSCode server is running
+    await switcheroo.ensureVSCodeServer(this, loadingView);
+
+    // Create WebContentsView for active session
+    const view = new WebContentsView({
+      webPreferences: {
+        nodeIntegration: false,
+        contextIsolation: true,
+        session: switcheroo.vscodeSession,
+        webSecurity: false, // Allow localhost connections
+        allowRunningInsecureContent: true
+      }
+    });
+    view.setBackgroundColor('#2d2d30');
+    view.webContents.setUserAgent(STANDARD_USER_AGENT);
+
+    // Wait for server to be ready before attempting to load the UI
+    if (loadingView) loadingView.updateMessage('Waiting for VSCode server...');
+    await waitForServer(this.vscodeUrl);
+
+    // Load VSCode in the view
+    if (loadingView) loadingView.updateMessage('Loading VSCode interface...');
+    await view.webContents.loadURL(this.vscodeUrl);
+
+    if (loadingView) loadingView.updateMessage('Ready!');
+    this.vscodeView = view;
+    return view;
+  }
+
   /// Ensure that the current view exists, either vscode or meta.
   ///
   /// Lazilly starts up the vscode server etc.
@@ -930,36 +3115,8 @@ class TaskSpace {
     console.log("ensureView", this.viewName, vscodeSession);
 
     if (this.viewName == 'vscode') {
-      if (!this.vscodeView) {
-        // Ensure VSCode server is running
-        await switcheroo.ensureVSCodeServer(this);
-
-        // Create WebContentsView for active session
-        this.vscodeView = new WebContentsView({
-          webPreferences: {
-            nodeIntegration: false,
-            contextIsolation: true,
-            session: vscodeSession,
-            webSecurity: false, // Allow localhost connections
-            allowRunningInsecureContent: true
-          }
-        });
-        this.vscodeView.setBackgroundColor('#2d2d30');
-        this.vscodeView.webContents.setUserAgent(STANDARD_USER_AGENT);
-
-        // Wait for server to be ready before attempting to load the UI
-        if (loadingView) loadingView.updateMessage('Waiting for VSCode server...');
-        await waitForServer(this.vscodeUrl);
-
-        // Load VSCode in the view
-        if (loadingView) loadingView.updateMessage('Loading VSCode interface...');
-        await this.vscodeView.webContents.loadURL(this.vscodeUrl);
-
-        if (loadingView) loadingView.updateMessage('Ready!');
-      }
-
       // Add views to the window
-      return this.vscodeView;
+      return this.ensureVSCodeView(switcheroo, loadingView);
     }
 
     if (!this.metaView) {
@@ -1048,12 +3205,56 @@ function getHostname() {
     return settings.hostname;
   }
 
-  console.error('No hostname configured in settings.');
-  console.error('Create ~/.socratic-shell/theoldswitcheroo/settings.json with {"hostname": "your-host"}');
+  console.error(t('no-hostname-configured'));
+  console.error(t('no-hostname-configured-hint', { file: SETTINGS_FILE }));
   app.quit();
   process.exit(1);
 }
 
+/// Pick the host to connect to. If `hostname` names a group in `hostGroups`,
+/// try each member in order and use the first one that passes preflight
+/// (reachable, SSH works and, if configured, remote user switching works).
+/// A plain hostname is only checked for reachability and gets its managed
+/// key, if any, so other errors surface during normal startup.
+async function selectHost(hostname: string): Promise<string> {
+  const group = loadSettings().hostGroups?.[hostname];
+  if (!group || group.length === 0) {
+    configureRemoteUser(hostname);
+    await ensureReachable(hostname);
+    await prepareManagedKey(hostname);
+    return hostname;
+  }
+
+  for (const [index, candidate] of group.entries()) {
+    configureRemoteUser(candidate);
+    try {
+      await ensureReachable(candidate);
+      await prepareManagedKey(candidate);
+      await execSSHCommand(candidate, 'echo "SSH connection successful"');
+      await sshManager.verifyRemoteUser(candidate);
+      console.log(`✓ Using ${candidate} from host group ${hostname}`);
+      return candidate;
+    } catch (error) {
+      const next = group[index + 1];
+      console.warn(`✗ Host ${candidate} failed preflight: ${error.message}`);
+      if (next) {
+        console.warn(`  Falling back to ${next}...`);
+      }
+    }
+  }
+
+  throw new Error(`No host in group ${hostname} passed preflight (tried ${group.join(', ')})`);
+}
+
+// Route all remote operations for `hostname` through the configured remote user, if any
+function configureRemoteUser(hostname: string) {
+  const settings = loadSettings();
+  if (settings.remoteUser) {
+    console.log(`Remote operations on ${hostname} will run as ${settings.remoteUser}`);
+    sshManager.setRemoteUser(hostname, settings.remoteUser);
+  }
+}
+
 // Execute SSH command using SSH manager with ControlMaster
 async function execSSHCommand(hostname: string, command: string): Promise<string> {
   return sshManager.executeCommand(hostname, command);
@@ -1065,76 +3266,643 @@ async function execSCP(hostname: string, localPath: string, remotePath: string):
   return sshManager.uploadFile(hostname, localPath, remotePath);
 }
 
-// Map architecture output to VSCode server architecture
-function mapArchitecture(arch: string): string {
+// Marketplace ids and uploaded .vsix file names (relative to BASE_DIR) to install
+function extensionIds(extensions: Extensions): string[] {
+  return [...(extensions.marketplace || []), ...(extensions.local || []).map(ext => path.basename(ext))];
+}
+
+// What a server started now would be set up with
+/// Replace a taskspace's Machine settings. The server watches its settings
+/// files, so the new values apply at once.
+async function writeMachineSettings(hostname: string, uuid: string, settings: Record<string, any>): Promise<void> {
+  const machineDir = `${BASE_DIR}/${new TaskSpacePaths(uuid).serverDataDir}/data/Machine`;
+  await execSSHCommand(hostname, `mkdir -p ${machineDir} && printf '%s\\n' ${shellQuote(JSON.stringify(settings, null, 2))} > ${machineDir}/settings.json`);
+}
+
+function desiredConfig(extensions: Extensions): AppliedConfig {
+  return {
+    extensions: extensionIds(extensions),
+    machineSettings: machineSettings(),
+    environment: [localeExports(), cudaExports(), sessionExports(), clipboardExports(), serverProcessSetup()].join('\n')
+  };
+}
+
+// Shell exports that carry the local timezone and locale to the remote server,
+// or an empty string unless `propagateLocale` is set
+function localeExports(): string {
+  if (!loadSettings().propagateLocale) {
+    return '';
+  }
+
+  const env: Record<string, string | undefined> = {
+    TZ: process.env.TZ || Intl.DateTimeFormat().resolvedOptions().timeZone,
+    LANG: process.env.LANG,
+    LC_ALL: process.env.LC_ALL,
+  };
+
+  return Object.entries(env)
+    .filter(([, value]) => value)
+    .map(([name, value]) => `export ${name}=${shellQuote(value)}`)
+    .join('; ');
+}
+
+// Points the CLI tool and MCP server in a named session's terminals at that
+// session's daemon; the default session's daemon is where they look anyway
+function sessionExports(): string {
+  if (SESSION_NAME === DEFAULT_SESSION) {
+    return '';
+  }
+  return `export THEOLDSWITCHEROO_SOCKET=${REMOTE_DAEMON_SOCKET.replace(/^~/, '$HOME')}`;
+}
+
+// The clipboard bridge's stand-ins for the copy commands, each passing what it
+// would copy to `theoldswitcheroo copy`. Pasting stays with the browser.
+const CLIPBOARD_SHIMS: Record<string, string> = {
+  'pbcopy': 'exec "$CLI" copy',
+  'wl-copy': 'exec "$CLI" copy',
+  // Copy unless asked to print the selection
+  'xclip': 'for a; do case "$a" in -o|-out) echo "xclip: paste in the browser instead" >&2; exit 1;; esac; done; exec "$CLI" copy',
+  'xsel': 'for a; do case "$a" in -o|--output) echo "xsel: paste in the browser instead" >&2; exit 1;; esac; done; exec "$CLI" copy',
+};
+
+// Shell commands that write the clipboard shims and put them first on the
+// server's PATH, or an empty string unless `clipboardBridge` is set
+function clipboardExports(): string {
+  if (!loadSettings().clipboardBridge) {
+    return '';
+  }
+  const dir = `${BASE_DIR}/clipboard-bin`.replace(/^~/, '$HOME');
+  const cli = `${BASE_DIR}/bin/theoldswitcheroo`.replace(/^~/, '$HOME');
+  const shims = Object.entries(CLIPBOARD_SHIMS).map(([name, body]) =>
+    `printf '%s\\n' '#!/bin/sh' ${shellQuote(`CLI=${cli}`)} ${shellQuote(body)} > ${dir}/${name} && chmod +x ${dir}/${name}`);
+  return [`mkdir -p ${dir}`, ...shims, `export PATH=${dir}:$PATH`].join('; ');
+}
+
+// Shell exports for the CUDA-related settings, or an empty string if none are set
+function cudaExports(): string {
+  const settings = loadSettings();
+  const exports: string[] = [];
+
+  if (settings.cudaEnv) {
+    exports.push('if [ -d /usr/local/cuda ]; then export PATH=/usr/local/cuda/bin:$PATH; export LD_LIBRARY_PATH=/usr/local/cuda/lib64${LD_LIBRARY_PATH:+:$LD_LIBRARY_PATH}; fi');
+  }
+  if (settings.cudaVisibleDevices) {
+    exports.push(`export CUDA_VISIBLE_DEVICES=${shellQuote(settings.cudaVisibleDevices)}`);
+  }
+
+  return exports.join('; ');
+}
+
+// The `serverBindAddress` setting, checked, as it ends up on the server's command line
+function serverBindAddress(): string | undefined {
+  const address = loadSettings().serverBindAddress;
+  if (address && !/^[0-9A-Za-z.:-]+$/.test(address)) {
+    throw new Error(`Invalid serverBindAddress "${address}"`);
+  }
+  return address || undefined;
+}
+
+// Shell commands applying `serverWorkingDir`, `serverUmask` and `serverUlimits`.
+// Invalid values are rejected here rather than producing a broken script.
+function serverProcessSetup(): string {
+  const settings = loadSettings();
+  const commands: string[] = [];
+
+  if (settings.serverUmask) {
+    if (!/^[0-7]{3,4}$/.test(settings.serverUmask)) {
+      throw new Error(`Invalid serverUmask "${settings.serverUmask}": expected an octal mode like 022`);
+    }
+    commands.push(`umask ${settings.serverUmask}`);
+  }
+
+  for (const [flag, limit] of Object.entries(settings.serverUlimits || {})) {
+    if (!/^[a-zA-Z]$/.test(flag) || !/^(\d+|unlimited)$/.test(String(limit))) {
+      throw new Error(`Invalid serverUlimits entry "${flag}": ${limit}`);
+    }
+    commands.push(`ulimit -${flag} ${limit}`);
+  }
+
+  if (settings.serverWorkingDir) {
+    const dir = shellQuotePath(settings.serverWorkingDir);
+    commands.push(`mkdir -p ${dir} && cd ${dir}`);
+  }
+
+  // A limit above the hard limit fails here; don't start the server with the wrong limits
+  return commands.length > 0 ? `{ ${commands.join(' && ')}; } || exit 1` : '';
+}
+
+// Server-side ("Machine") VSCode settings derived from our settings
+function machineSettings(): Record<string, any> {
+  const settings = loadSettings();
+  const machine: Record<string, any> = {};
+
+  if (settings.terminalShell) {
+    machine['terminal.integrated.profiles.linux'] = {
+      theoldswitcheroo: {
+        path: settings.terminalShell,
+        args: settings.terminalLoginShell === false ? [] : ['-l']
+      }
+    };
+    machine['terminal.integrated.defaultProfile.linux'] = 'theoldswitcheroo';
+  }
+
+  // Explicitly configured settings win over the ones derived above
+  Object.assign(machine, settings.remoteSettings || {});
+
+  return machine;
+}
+
+// Shell command that writes the taskspace's Machine settings file,
+// or an empty string if there is nothing to write
+function machineSettingsCommand(dirs: TaskSpacePaths): string {
+  const machine = machineSettings();
+  if (Object.keys(machine).length === 0) {
+    return '';
+  }
+
+  const machineDir = `${dirs.serverDataDir}/data/Machine`;
+  return `mkdir -p ${machineDir} && printf '%s\\n' ${shellQuote(JSON.stringify(machine, null, 2))} > ${machineDir}/settings.json`;
+}
+
+// Remote file holding the taskspace server's connection token, with connection tokens on
+function connectionTokenFile(dirs: TaskSpacePaths): string {
+  return `${BASE_DIR}/${dirs.serverDataDir}/connection-token`;
+}
+
+// Shell command that writes a fresh connection token for the server, so each
+// server start rotates it, or an empty string with connection tokens off
+function connectionTokenCommand(dirs: TaskSpacePaths): string {
+  if (!connectionTokensEnabled()) {
+    return '';
+  }
+  return `(umask 077 && head -c 32 /dev/urandom | od -An -tx1 | tr -d ' \\n' > ${connectionTokenFile(dirs)})`;
+}
+
+// Electron's form of a certificate fingerprint, `sha256/<base64>`, from openssl's `AB:CD:...`
+function electronFingerprint(fingerprint: string): string {
+  return `sha256/${Buffer.from(fingerprint.replace(/:/g, ''), 'hex').toString('base64')}`;
+}
+
+// Remote directory holding the host's self-signed certificate for `tls`,
+// shared by every taskspace so the host has one fingerprint to check
+const TLS_DIR = `${BASE_DIR}/tls`;
+
+// Shell command that generates the host's certificate and key on first use
+// and prints the fingerprint for the startup facts, or an empty string without `tls`
+function tlsCertificateCommand(): string {
+  if (!loadSettings().tls) {
+    return '';
+  }
+  return `
+    if [ ! -s ${TLS_DIR}/cert.pem ]; then
+      (umask 077 && mkdir -p ${TLS_DIR} &&
+        openssl req -x509 -newkey rsa:2048 -nodes -sha256 -days 825 -subj "/CN=$(hostname)" \\
+          -keyout ${TLS_DIR}/key.pem.$$ -out ${TLS_DIR}/cert.pem.$$ 2>&1 &&
+        mv ${TLS_DIR}/key.pem.$$ ${TLS_DIR}/key.pem && mv ${TLS_DIR}/cert.pem.$$ ${TLS_DIR}/cert.pem) >/dev/null ||
+        { echo "Could not generate a TLS certificate in ${TLS_DIR}; is openssl installed?" >&2; exit 1; }
+    fi
+    echo "TLS certificate for $(hostname): $(openssl x509 -noout -fingerprint -sha256 -in ${TLS_DIR}/cert.pem | cut -d= -f2)"
+  `;
+}
+
+// Ports the server may listen on tried in order from `remotePort`
+const REMOTE_PORT_RANGE = 100;
+
+// Value for the server's --port: a range from `remotePort`, from which the
+// server takes the first free port, or 0 for any free port
+function serverPortFlag(remotePort?: number): string {
+  if (!remotePort) {
+    return '0';
+  }
+  if (!Number.isInteger(remotePort) || remotePort < 1 || remotePort > 65535) {
+    throw new Error(`remotePort must be a port number, got ${remotePort}`);
+  }
+  return `${remotePort}-${Math.min(remotePort + REMOTE_PORT_RANGE - 1, 65535)}`;
+}
+
+// Read the connection token of a taskspace's running server, if it has one
+async function readConnectionToken(hostname: string, uuid: string): Promise<string | undefined> {
+  const token = await execSSHCommand(hostname, `cat ${connectionTokenFile(new TaskSpacePaths(uuid))} 2>/dev/null || true`);
+  return token.trim() || undefined;
+}
+
+/// Extract whatever startup facts a single line of server output carries.
+///
+/// openvscode-server prints a banner like:
+///
+///     Server bound to 0.0.0.0:43211 (IPv4)
+///     Extension host agent listening on 43211
+///     Web UI available at http://localhost:43211/?tkn=...
+///
+/// code-server prints only:
+///
+///     [2025-08-20T10:00:00.000Z] info  HTTP server listening on http://0.0.0.0:43211/
+///
+/// With `tls`, the start script prints the certificate's fingerprint first:
+///
+///     TLS certificate for devbox: AB:CD:...
+function parseServerOutputLine(line: string): ServerStartupFacts {
+  const facts: ServerStartupFacts = {};
+
+  const bound = line.match(/Server bound to (\S+):(\d+)/i);
+  if (bound) {
+    facts.listenAddress = bound[1];
+    facts.listenPort = parseInt(bound[2]);
+  }
+
+  const listening = line.match(/Extension host agent listening on (\d+)/i);
+  if (listening) {
+    facts.listenPort = parseInt(listening[1]);
+  }
+
+  const httpServer = line.match(/HTTP server listening on https?:\/\/(\S+):(\d+)/i);
+  if (httpServer) {
+    facts.listenAddress = httpServer[1];
+    facts.listenPort = parseInt(httpServer[2]);
+  }
+
+  const tls = line.match(/^TLS certificate for (\S+): ([0-9A-F:]+)$/i);
+  if (tls) {
+    facts.tlsHost = tls[1];
+    facts.tlsFingerprint = tls[2];
+  }
+
+  const webUi = line.match(/Web UI available at (\S+)/i);
+  if (webUi) {
+    facts.webUiUrl = webUi[1];
+    try {
+      const url = new URL(webUi[1]);
+      if (url.port) {
+        facts.webUiPort = parseInt(url.port);
+      }
+      const token = url.searchParams.get('tkn');
+      if (token) {
+        facts.connectionToken = token;
+      }
+    } catch (error) {
+      // Not a parseable URL; keep the raw text only
+    }
+  }
+
+  return facts;
+}
+
+/// Server architecture of `hostname`: the `arch` setting if there is one, otherwise detected
+async function remoteArch(hostname: string): Promise<string> {
+  const configured = loadSettings().arch;
+  if (configured) {
+    return configured;
+  }
+  const [os, machine] = String(await execSSHCommand(hostname, 'uname -sm')).toLowerCase().split(/\s+/);
+  return mapArchitecture(os, machine);
+}
+
+// Map `uname -s` and `uname -m` output to VSCode server architecture
+function mapArchitecture(os: string, arch: string): string {
+  const platform = os === 'darwin' ? 'darwin' : 'linux';
   switch (arch) {
     case 'x86_64':
-      return 'linux-x64';
+      return `${platform}-x64`;
     case 'aarch64':
     case 'arm64':
-      return 'linux-arm64';
+      return `${platform}-arm64`;
     default:
-      console.warn(`Unknown architecture '${arch}', defaulting to linux-x64`);
-      return 'linux-x64';
+      console.warn(`Unknown architecture '${arch}', defaulting to ${platform}-x64`);
+      return `${platform}-x64`;
   }
 }
 
+// How often (in files) extraction reports progress; the server has a few thousand files
+const EXTRACT_PROGRESS_EVERY = 500;
+
 // Install VSCode server
-async function installVSCodeServer(hostname: string, arch: string): Promise<void> {
-  console.log(`Installing openvscode-server for ${arch}...`);
+async function installVSCodeServer(hostname: string, arch: string, loadingView: ILoadingView | null = null): Promise<void> {
+  const flavor = serverFlavor();
+  const version = await resolveServerVersion();
+  const release = serverReleaseName(version, arch);
+  const transfer = serverTransfer();
+  const reportUpload = (line: string) => loadingView?.updateMessage(`Installing VSCode server: ${line}`);
+  console.log(`Installing ${flavor.displayName} ${version} for ${arch}...`);
+
+  // Hosts without outbound access get the tarball from this machine; the install then finds it cached
+  if (transfer === 'upload') {
+    await uploadServerTarball(hostname, version, arch, reportUpload);
+  }
 
+  // The installed release is recorded next to the server, so changing `serverVersion` reinstalls it.
+  // On NFS homes the install directory is a symlink to local scratch space; install through it.
+  // Extraction is silent and can take a minute on slow disks, so tar's file list is
+  // condensed into a progress line every EXTRACT_PROGRESS_EVERY files.
   const installScript = `
+    set -e
     cd ${BASE_DIR}
-    if [ ! -f openvscode-server.tar.gz ]; then
-      curl -L https://github.com/gitpod-io/openvscode-server/releases/download/openvscode-server-v1.103.1/openvscode-server-v1.103.1-${arch}.tar.gz -o openvscode-server.tar.gz
-    fi
-    if [ ! -d openvscode-server ]; then
-      tar -xzf openvscode-server.tar.gz
-      mv openvscode-server-v1.103.1-${arch} openvscode-server
-      chmod +x openvscode-server/bin/openvscode-server
+    ${fetchServerTarballCommand(version, arch)}
+    INSTALL_DIR=${flavor.installDir}
+    if [ -L ${flavor.installDir} ]; then INSTALL_DIR=$(readlink ${flavor.installDir}); fi
+    if [ "$(cat $INSTALL_DIR/.release 2>/dev/null)" != "${release}" ]; then
+      PARENT=$(dirname "$INSTALL_DIR")
+      mkdir -p "$PARENT"
+      rm -rf "$INSTALL_DIR" "$PARENT/${release}" "$PARENT/.extract-failed"
+      echo "Extracting ${release}..."
+      { tar -xzvf ${cachedServerTarball(version, arch)} -C "$PARENT" || touch "$PARENT/.extract-failed"; } |
+        awk '{ n++ } n % ${EXTRACT_PROGRESS_EVERY} == 0 { print "Extracted " n " files"; fflush() } END { print "Extracted " n " files" }'
+      if [ -f "$PARENT/.extract-failed" ]; then
+        rm -f "$PARENT/.extract-failed"
+        echo "Extracting ${cachedServerTarball(version, arch)} failed" >&2
+        exit 1
+      fi
+      mv "$PARENT/${release}" "$INSTALL_DIR"
+      echo "Setting permissions..."
+      chmod +x "$INSTALL_DIR/${flavor.binary}"
+      echo "${release}" > "$INSTALL_DIR/.release"
     fi
   `;
 
-  await execSSHCommand(hostname, installScript);
+  const install = () => sshManager.executeLineStreamingCommand(hostname, installScript, (source, line) => {
+    console.log(`${logTimestampPrefix()}[VSCode install ${source}] ${line}`);
+    if (loadingView && source === 'stdout' && /^(Extract|Setting permissions)/.test(line)) {
+      loadingView.updateMessage(`Installing VSCode server: ${line}`);
+    }
+  });
+  try {
+    try {
+      await install();
+    } catch (error) {
+      const download = asServerDownloadError(error);
+      if (transfer !== 'auto' || !(download instanceof ServerDownloadError && download.blocked)) {
+        throw error;
+      }
+      console.warn(`${hostname} cannot download ${release} (${download.message}); uploading it from this machine instead`);
+      await uploadServerTarball(hostname, version, arch, reportUpload);
+      await install();
+    }
+  } catch (error) {
+    const download = asServerDownloadError(error);
+    if (download instanceof ServerDownloadError || download instanceof ServerChecksumError) {
+      throw new Error(`${download.message} on ${hostname}.\n\n${download.guidance()}`);
+    }
+    throw error;
+  }
+  await applyExtensionGallery(hostname);
   console.log('✓ VSCode server installation complete');
 }
 
+/// Point the installed server at the gallery from the `extensionGallery` setting.
+///
+/// The shipped product.json is kept as product.json.orig and every run starts
+/// from it, so changing or removing the setting takes effect on the next start.
+async function applyExtensionGallery(hostname: string): Promise<void> {
+  const gallery = loadSettings().extensionGallery;
+  const flavor = serverFlavor();
+  const productJson = `${BASE_DIR}/${flavor.installDir}/${flavor.productJson}`;
+
+  if (!gallery) {
+    await sshManager.executeCommand(hostname, `if [ -f ${productJson}.orig ]; then cp ${productJson}.orig ${productJson}; fi`);
+    return;
+  }
+
+  const original = await sshManager.executeCommand(hostname,
+    `[ -f ${productJson}.orig ] || cp ${productJson} ${productJson}.orig; cat ${productJson}.orig`);
+  const product = JSON.parse(original);
+  product.extensionsGallery = { ...product.extensionsGallery, ...gallery };
+
+  const localCopy = path.join(os.tmpdir(), `theoldswitcheroo-product-${randomUUID()}.json`);
+  try {
+    fs.writeFileSync(localCopy, JSON.stringify(product, null, 2));
+    await sshManager.uploadFile(hostname, localCopy, productJson);
+  } finally {
+    fs.rmSync(localCopy, { force: true });
+  }
+  console.log(`✓ Using extension gallery ${gallery.serviceUrl}`);
+}
+
+/// Work out why a VSCode server failed to start and suggest a fix.
+///
+/// Inspects the failure itself (exit code, stderr) and the installed binary on
+/// the host (`file` for its architecture, `ldd` for missing shared libraries,
+/// and whether its C library is musl, which the server builds do not support).
+async function diagnoseServerStartupFailure(hostname: string, arch: string, failure: ServerStartupError): Promise<string> {
+  // The launcher script execs the bundled node binary, so that is what has to load
+  const installDir = `${BASE_DIR}/${serverFlavor().installDir}`;
+  const binary = `${installDir}/${serverFlavor().nodeBinary}`;
+  let report = '';
+  try {
+    report = await execSSHCommand(hostname, `
+      echo "uname: $(uname -m)"
+      ${LIBC_PROBE}
+      if [ -e ${binary} ]; then
+        echo "file: $(file -b ${binary} 2>&1)"
+        ldd ${binary} 2>&1 | grep 'not found' | sed 's/^/missing: /'
+      else
+        echo "binary: missing"
+      fi
+      true
+    `);
+  } catch (error) {
+    return `could not inspect ${hostname} (${error.message}); see the server output above`;
+  }
+
+  const field = (name: string) => report.split('\n')
+    .filter(line => line.startsWith(`${name}: `))
+    .map(line => line.slice(name.length + 2).trim());
+
+  if (field('binary').length > 0) {
+    return `the server binary ${binary} is missing, so the install is incomplete. ` +
+      `Remove ${installDir} and the tarballs in ${SERVER_CACHE_DIR} on ${hostname} and try again.`;
+  }
+
+  const missing = field('missing').map(line => line.split(/\s+/)[0]);
+  // On musl, a glibc binary fails in the loader ("not found", "Error relocating") before the server says anything useful
+  if (field('libc')[0] === 'musl') {
+    return muslGuidance(hostname) + (missing.length > 0 ? ` Missing libraries: ${missing.join(', ')}.` : '');
+  }
+  if (missing.length > 0) {
+    return `the server needs shared libraries that are not installed on ${hostname}: ${missing.join(', ')}. ` +
+      `Install them with the host's package manager (e.g. \`apt install\` / \`dnf install\`), ` +
+      `or run the app with \`--install-prereqs ${hostname}\`, and try again.`;
+  }
+
+  const [fileType] = field('file');
+  const [hostArch] = field('uname');
+  const expected = arch === 'linux-arm64' ? /aarch64|ARM aarch64/i : arch === 'linux-armhf' ? /ARM,/i
+    : arch === 'darwin-arm64' ? /arm64/i : arch === 'darwin-x64' ? /x86_64/i : /x86-64/i;
+  if (fileType && /ELF|Mach-O/.test(fileType) && !expected.test(fileType)) {
+    return `the installed server binary is "${fileType}", which does not match this ${hostArch} host. ` +
+      `Remove ${installDir} and the tarballs in ${SERVER_CACHE_DIR} on ${hostname} so the correct build is downloaded.`;
+  }
+
+  if (/GLIBC_[\d.]+' not found/.test(failure.stderr)) {
+    return `the C library on ${hostname} is too old for this server build (${failure.stderr.match(/GLIBC_[\d.]+/)![0]} required). ` +
+      `Use a newer host or an older server version.`;
+  }
+  if (/EADDRINUSE/.test(failure.stderr)) {
+    return `the server could not bind its port because it is already in use on ${hostname}.`;
+  }
+  if (failure.exitCode === 126 || /Permission denied/.test(failure.stderr)) {
+    return `the server could not be executed (permission denied). Check that ${BASE_DIR} is not on a noexec mount and that the binaries are executable.`;
+  }
+  if (failure.exitCode === null) {
+    return `the server did not report a listening port within the startup timeout; see its output in the log.`;
+  }
+
+  return `the server exited with code ${failure.exitCode} and no known cause was found; see its stderr above.`;
+}
+
+/// Look for host-level reasons a VSCode server disappeared: OOM-killer entries
+/// in the kernel log and a full filesystem under BASE_DIR.
+/// Returns a human-readable cause, or null if nothing conclusive was found.
+// Describe the host's NVIDIA GPUs, driver and CUDA version,
+// or return null if there is no usable nvidia-smi
+/// If BASE_DIR is on NFS, move the server install and tarball cache to local
+/// scratch space and leave symlinks behind.
+///
+/// chmod and file locking are unreliable on NFS (root_squash, stale `.nfs*`
+/// files while a server still has them open), and the server binaries don't
+/// need to be shared between hosts. Taskspace data stays in BASE_DIR.
+/// Shell command that clones `repo` into `dir`, checking out `branch` or else
+/// the default branch. Where a clone is already there, e.g. because the
+/// taskspace's server went missing, it fetches and resets it to the remote
+/// branch instead, unless that would lose work: then it prints `kept <why>`
+/// and leaves the clone alone.
+function cloneRepoCommand(repo: string, branch: string | undefined, dir: string): string {
+  const ref = branch ? shellQuote(branch) : '';
+  return `
+    set -e
+    if [ ! -d ${dir}/.git ]; then
+      git clone ${branch ? `--branch ${ref} ` : ''}${shellQuote(repo)} ${dir}
+      exit 0
+    fi
+    cd ${dir}
+    git fetch --quiet origin
+    BRANCH=${ref || '$(git symbolic-ref --short refs/remotes/origin/HEAD 2>/dev/null | sed "s|^origin/||")'}
+    BRANCH=\${BRANCH:-$(git rev-parse --abbrev-ref HEAD)}
+    if [ -n "$(git status --porcelain)" ]; then
+      echo "kept has uncommitted changes"
+    elif ! git merge-base --is-ancestor HEAD "origin/$BRANCH"; then
+      echo "kept has commits that are not on origin/$BRANCH"
+    else
+      git checkout --quiet -B "$BRANCH" --track "origin/$BRANCH"
+    fi
+  `;
+}
+
+async function prepareNfsHome(hostname: string): Promise<void> {
+  const scratch = loadSettings().nfsScratchDir || '/var/tmp/theoldswitcheroo-$(id -u)';
+  const report = await execSSHCommand(hostname, `
+    mkdir -p ${BASE_DIR}
+    echo "fstype: $(stat -f -c %T ${BASE_DIR})"
+  `);
+  const fsType = /^fstype: (\S+)/m.exec(report)?.[1] || '';
+  if (!fsType.startsWith('nfs')) {
+    return;
+  }
+
+  // Anything already installed on NFS is moved over once; later runs only recreate missing targets
+  const output = await execSSHCommand(hostname, `
+    set -e
+    SCRATCH=${scratch}
+    mkdir -p "$SCRATCH"
+    chmod 700 "$SCRATCH"
+    for dir in ${serverFlavor().installDir} cache; do
+      if [ ! -L ${BASE_DIR}/$dir ]; then
+        rm -rf "$SCRATCH/$dir"
+        if [ -e ${BASE_DIR}/$dir ]; then mv ${BASE_DIR}/$dir "$SCRATCH/$dir"; fi
+        ln -sfn "$SCRATCH/$dir" ${BASE_DIR}/$dir
+      fi
+      mkdir -p "$(readlink ${BASE_DIR}/$dir)"
+    done
+    echo "$SCRATCH"
+  `);
+
+  console.warn(`⚠ ${BASE_DIR} on ${hostname} is on ${fsType}; the server is installed in ${output.trim()} instead.`);
+  console.warn('  Taskspace data stays on NFS: file watching may miss changes made from other hosts,');
+  console.warn('  and a server that is killed can leave .nfs* files that block deleting its taskspace until it exits.');
+}
+
+async function detectGpus(hostname: string): Promise<string | null> {
+  try {
+    const output = await execSSHCommand(hostname, `
+      command -v nvidia-smi >/dev/null 2>&1 || exit 0
+      nvidia-smi --query-gpu=index,name,driver_version,memory.total --format=csv,noheader
+      nvidia-smi | grep -o 'CUDA Version: [0-9.]*' || true
+    `);
+    return output.trim() || null;
+  } catch (error) {
+    console.warn(`Could not query GPUs on ${hostname}: ${error.message}`);
+    return null;
+  }
+}
+
+async function diagnoseServerExit(hostname: string): Promise<string | null> {
+  let report: string;
+  try {
+    // dmesg is often restricted to root; fall back to the kernel journal, and
+    // on macOS to the unified log, where memory kills are jetsam's
+    report = await execSSHCommand(hostname, `
+      { dmesg 2>/dev/null || journalctl -k --since "-1h" --no-pager 2>/dev/null ||
+        log show --last 1h --style compact --predicate 'eventMessage CONTAINS[c] "jetsam"' 2>/dev/null; } |
+        grep -iE 'out of memory|oom-kill|killed process|jetsam' | grep -i node | tail -1 | sed 's/^/oom: /'
+      df -Pk ${BASE_DIR} 2>/dev/null | tail -1 | awk '{print "disk: " $4 " " $5}'
+      true
+    `);
+  } catch (error) {
+    return null;
+  }
+
+  const oom = report.split('\n').find(line => line.startsWith('oom: '));
+  if (oom) {
+    return `killed by the out-of-memory killer (${oom.slice('oom: '.length).trim()})`;
+  }
+
+  const disk = report.split('\n').find(line => line.startsWith('disk: '));
+  if (disk) {
+    const [availableKb, usePercent] = disk.slice('disk: '.length).trim().split(/\s+/);
+    if (parseInt(availableKb) < 10 * 1024 || usePercent === '100%') {
+      return `the filesystem holding ${BASE_DIR} is full (${availableKb} KB free)`;
+    }
+  }
+
+  return null;
+}
+
 /// Check if the port is bound on the localhost
-async function checkTaskSpaceHealth(hostname: string, port: number): Promise<boolean> {
-  let resultCode = await execSSHCommand(hostname, `/usr/bin/curl -sL -w %{http_code} http://localhost:${port} -o /dev/null || true`);
+async function checkTaskSpaceHealth(hostname: string, port: number, token?: string): Promise<boolean> {
+  const cookie = token ? `-b vscode-tkn=${token} ` : '';
+  // -k: a `tls` server's certificate is self-signed
+  const scheme = loadSettings().tls ? 'https' : 'http';
+  let resultCode = await execSSHCommand(hostname, `/usr/bin/curl -skL ${cookie}-w %{http_code} ${scheme}://localhost:${port} -o /dev/null || true`);
   return (resultCode == '200');
 }
 
-// Wait for a URL to be accessible for up to `maxRetries` attempts
+/// The readiness checks to run for the configured flavor, optionally narrowed
+/// to the names listed in the `healthChecks` setting.
+function selectedHealthChecks(): HealthCheck[] {
+  const checks = BACKEND_HEALTH_CHECKS[serverFlavor().name];
+  const names = loadSettings().healthChecks;
+  return names ? checks.filter(check => names.includes(check.name)) : checks;
+}
+
+// Wait for a URL to pass the backend's health checks for up to `maxRetries` attempts
 async function waitForServer(url: string, maxRetries: number = 10): Promise<boolean> {
+  const checks = selectedHealthChecks();
+  const timeoutMs = loadSettings().healthCheckTimeoutMs || 2000;
+  let lastFailure = 'no checks ran';
+
   for (let retries = 0; retries < maxRetries; retries++) {
     try {
-      const response = await new Promise((resolve, reject) => {
-        const req = http.get(url, (res) => {
-          resolve(res);
-        });
-
-        req.on('error', reject);
-        req.setTimeout(2000, () => {
-          req.destroy();
-          reject(new Error('Timeout'));
-        });
-      });
-
-      if ((response as any).statusCode === 200) {
-        console.log('✓ Server is ready');
-        return true;
-      }
+      await runHealthChecks(checks, url, timeoutMs);
+      console.log('✓ Server is ready');
+      return true;
     } catch (error) {
-      // Continue to retry
+      lastFailure = error.message;
     }
 
     if (retries < maxRetries - 1) {
       const delay = Math.min(1000 * Math.pow(2, retries), 5000);
-      console.log(`Server at ${url} not ready, retrying in ${delay}ms... (${retries + 1}/${maxRetries})`);
+      console.log(`Server at ${url} not ready (${lastFailure}), retrying in ${delay}ms... (${retries + 1}/${maxRetries})`);
       await new Promise(resolve => setTimeout(resolve, delay));
     }
   }
 
-  throw new Error(`Server not ready after ${maxRetries} attempts`);
+  throw new Error(`Server not ready after ${maxRetries} attempts: ${lastFailure}`);
 }
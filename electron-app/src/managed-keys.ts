@@ -0,0 +1,143 @@
+/// A keypair of the app's own for each host (`managedKeys`), so the hosts
+/// only ever see a key that opens that one host, instead of the user's
+/// personal key or agent. The first connect installs it in the host's
+/// authorized_keys with the user's usual SSH login (the bootstrap); after
+/// that the app logs in with it alone, and replaces it once it is older than
+/// `managedKeyRotationDays`, taking the old one out of authorized_keys.
+
+import { execFile } from 'child_process';
+import * as fs from 'fs';
+import * as path from 'path';
+import { LOCAL_DATA_DIR, loadSettings } from './settings.js';
+import { sshManager, shellQuote } from './ssh-manager.js';
+
+const KEYS_DIR = path.join(LOCAL_DATA_DIR, 'keys');
+const DEFAULT_ROTATION_DAYS = 30;
+
+/// What the app knows about a host's managed key, kept next to it as key.json
+export interface ManagedKeyState {
+  host: string;
+  createdAt: string;
+  // authorized_keys on the host has it
+  installed: boolean;
+}
+
+// The comment managed keys for `host` carry in authorized_keys, by which old ones are found again
+function keyTag(host: string): string {
+  return `theoldswitcheroo-managed:${host.replace(/[^A-Za-z0-9._-]/g, '_')}`;
+}
+
+/// Where the managed key for `host` lives: `keyFile` and `keyFile.pub`, and its state
+export function managedKeyPaths(host: string): { dir: string; keyFile: string; stateFile: string } {
+  const dir = path.join(KEYS_DIR, host.replace(/[^A-Za-z0-9._-]/g, '_'));
+  return { dir, keyFile: path.join(dir, 'id_ed25519'), stateFile: path.join(dir, 'key.json') };
+}
+
+function run(command: string, args: string[]): Promise<string> {
+  return new Promise((resolve, reject) => {
+    execFile(command, args, { timeout: 30000 }, (error, stdout, stderr) => {
+      if (error) {
+        reject(new Error(stderr.trim() || error.message));
+      } else {
+        resolve(stdout);
+      }
+    });
+  });
+}
+
+// A fresh keypair at `keyFile`, without a passphrase, as nothing could type it
+async function generateKey(host: string, keyFile: string): Promise<void> {
+  fs.rmSync(keyFile, { force: true });
+  fs.rmSync(`${keyFile}.pub`, { force: true });
+  await run('ssh-keygen', ['-q', '-t', 'ed25519', '-N', '', '-C', `${keyTag(host)}:${new Date().toISOString().slice(0, 10)}`, '-f', keyFile]);
+}
+
+// Run `command` on `host` over a connection of its own, authenticating with
+// `identity` alone, or the user's usual way without one
+function runWithLogin(host: string, identity: string | null, command: string): Promise<string> {
+  return run('ssh', [
+    '-o', 'BatchMode=yes',
+    '-o', 'ConnectTimeout=10',
+    '-o', 'ControlPath=none',
+    ...sshManager.connectionOptions(),
+    ...(identity ? ['-i', identity, '-o', 'IdentitiesOnly=yes'] : []),
+    host,
+    command
+  ]);
+}
+
+/// Shell command that puts `publicKey` in authorized_keys in place of any
+/// other managed key for `host`, leaving every other key alone
+export function authorizeKeyCommand(host: string, publicKey: string): string {
+  return `
+    set -e
+    umask 077
+    mkdir -p ~/.ssh
+    touch ~/.ssh/authorized_keys
+    grep -v -F ${shellQuote(` ${keyTag(host)}:`)} ~/.ssh/authorized_keys > ~/.ssh/authorized_keys.theoldswitcheroo || true
+    printf '%s\\n' ${shellQuote(publicKey.trim())} >> ~/.ssh/authorized_keys.theoldswitcheroo
+    mv ~/.ssh/authorized_keys.theoldswitcheroo ~/.ssh/authorized_keys
+  `;
+}
+
+function saveState(stateFile: string, state: ManagedKeyState): void {
+  fs.writeFileSync(stateFile, JSON.stringify(state, null, 2) + '\n', { mode: 0o600 });
+}
+
+/// Have every connection to `host` log in with its managed key, creating,
+/// installing or rotating the key first as needed. A managed key the host
+/// no longer accepts is installed again with the user's usual login.
+export async function prepareManagedKey(host: string, log: (message: string) => void = console.log): Promise<void> {
+  const settings = loadSettings();
+  if (!settings.managedKeys || sshManager.isReplaying()) {
+    return;
+  }
+  const { dir, keyFile, stateFile } = managedKeyPaths(host);
+  fs.mkdirSync(dir, { recursive: true, mode: 0o700 });
+  let state: ManagedKeyState | null = fs.existsSync(stateFile) && fs.existsSync(keyFile)
+    ? JSON.parse(fs.readFileSync(stateFile, 'utf8'))
+    : null;
+
+  if (state?.installed) {
+    try {
+      await runWithLogin(host, keyFile, 'true');
+    } catch (error) {
+      log(`⚠️ ${host} no longer accepts the managed key (${error.message}); installing it again`);
+      state.installed = false;
+    }
+  }
+
+  // Bootstrap: a new key, or one the host does not have, goes in with the user's usual login
+  if (!state || !state.installed) {
+    if (!state) {
+      await generateKey(host, keyFile);
+      state = { host, createdAt: new Date().toISOString(), installed: false };
+    }
+    await runWithLogin(host, null, authorizeKeyCommand(host, fs.readFileSync(`${keyFile}.pub`, 'utf8')));
+    state.installed = true;
+    saveState(stateFile, state);
+    log(`✓ Installed a managed SSH key for ${host}; later connections log in with it`);
+  }
+
+  // Rotation: the new key goes in over the old one's login, which it replaces
+  const rotationDays = settings.managedKeyRotationDays ?? DEFAULT_ROTATION_DAYS;
+  const ageDays = (Date.now() - Date.parse(state.createdAt)) / (24 * 60 * 60 * 1000);
+  if (rotationDays > 0 && ageDays >= rotationDays) {
+    const nextKeyFile = `${keyFile}.next`;
+    await generateKey(host, nextKeyFile);
+    try {
+      await runWithLogin(host, keyFile, authorizeKeyCommand(host, fs.readFileSync(`${nextKeyFile}.pub`, 'utf8')));
+      fs.renameSync(nextKeyFile, keyFile);
+      fs.renameSync(`${nextKeyFile}.pub`, `${keyFile}.pub`);
+      state = { host, createdAt: new Date().toISOString(), installed: true };
+      saveState(stateFile, state);
+      log(`✓ Rotated the managed SSH key for ${host} after ${Math.floor(ageDays)} days`);
+    } catch (error) {
+      fs.rmSync(nextKeyFile, { force: true });
+      fs.rmSync(`${nextKeyFile}.pub`, { force: true });
+      log(`⚠️ Could not rotate the managed SSH key for ${host}: ${error.message}; keeping the old one`);
+    }
+  }
+
+  sshManager.setIdentity(host, keyFile);
+}
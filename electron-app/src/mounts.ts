@@ -0,0 +1,135 @@
+import { spawn } from 'child_process';
+import * as fs from 'fs';
+import * as path from 'path';
+import { SESSION_DIR } from './settings.js';
+import { sshManager, shellQuote } from './ssh-manager.js';
+
+/// Local mounts of a taskspace's clone over SFTP (`--mount <dir>`), for tools
+/// that must run locally against the remote files. The local `sshfs` makes
+/// them, connecting like the app does (jump host, managed key, batch mode).
+/// They belong to the session: the app unmounts them when it shuts down, and
+/// `--stop` does after a crash, from the list kept in the session directory.
+
+const MOUNTS_FILE = path.join(SESSION_DIR, 'mounts.json');
+
+export interface SessionMount {
+  dir: string;
+  taskspace: string;
+  remoteDir: string;
+}
+
+export function loadMounts(): SessionMount[] {
+  try {
+    return JSON.parse(fs.readFileSync(MOUNTS_FILE, 'utf8'));
+  } catch {
+    return [];
+  }
+}
+
+function saveMounts(mounts: SessionMount[]): void {
+  if (mounts.length === 0) {
+    fs.rmSync(MOUNTS_FILE, { force: true });
+    return;
+  }
+  fs.mkdirSync(SESSION_DIR, { recursive: true });
+  fs.writeFileSync(MOUNTS_FILE, JSON.stringify(mounts, null, 2));
+}
+
+function run(command: string, args: string[]): Promise<{ code: number; stderr: string }> {
+  return new Promise((resolve) => {
+    const child = spawn(command, args, { stdio: ['ignore', 'ignore', 'pipe'] });
+    let stderr = '';
+    child.stderr!.on('data', (data) => stderr += data.toString());
+    // Most likely ENOENT: the command is not installed
+    child.on('error', (error) => resolve({ code: 127, stderr: error.message }));
+    child.on('close', (code) => resolve({ code: code ?? 1, stderr: stderr.trim() }));
+  });
+}
+
+// The app's ssh options in the `-o Key=value` form sshfs passes on to ssh
+function sshfsOptions(host: string): string[] {
+  const options = sshManager.connectionOptions(host);
+  const converted: string[] = [];
+  for (let i = 0; i < options.length; i += 2) {
+    converted.push('-o', options[i] === '-i' ? `IdentityFile=${options[i + 1]}` : options[i + 1]);
+  }
+  return converted;
+}
+
+// For a `remoteUser`, the SFTP server runs as that user, from their home,
+// wherever the distribution keeps it. sshfs splits options on commas, so there are none.
+function sftpServerAs(user: string): string {
+  const servers = '/usr/lib/openssh/sftp-server /usr/libexec/openssh/sftp-server /usr/lib/ssh/sftp-server /usr/libexec/sftp-server';
+  return `sudo -n -H -u ${shellQuote(user)} sh -c ${shellQuote(`cd; for s in ${servers}; do [ -x $s ] && exec $s; done; exit 127`)}`;
+}
+
+/// Mount `remoteDir` (under the login's home, or with a leading `~/`) of
+/// `host` at `dir`, creating it if need be, and record the mount for the session
+export async function mountTaskSpace(host: string, taskspace: string, remoteDir: string, dir: string): Promise<void> {
+  const local = path.resolve(dir);
+  if (loadMounts().some(mount => mount.dir === local)) {
+    throw new Error(`${local} is already mounted for this session`);
+  }
+  fs.mkdirSync(local, { recursive: true });
+  if (fs.readdirSync(local).length > 0) {
+    throw new Error(`${local} is not empty`);
+  }
+
+  const user = sshManager.remoteUserFor(host);
+  const result = await run('sshfs', [
+    `${host}:${remoteDir.replace(/^~\//, '')}`,
+    local,
+    '-o', 'reconnect,ServerAliveInterval=15,ServerAliveCountMax=3,follow_symlinks',
+    ...(user ? ['-o', `sftp_server=${sftpServerAs(user)}`] : []),
+    ...sshfsOptions(host)
+  ]);
+  if (result.stderr.includes('ENOENT')) {
+    throw new Error('sshfs is not installed; install it (macOS: macFUSE and sshfs) and try again');
+  }
+  if (result.code !== 0) {
+    throw new Error(`sshfs failed: ${result.stderr || `exit code ${result.code}`}`);
+  }
+  saveMounts([...loadMounts(), { dir: local, taskspace, remoteDir }]);
+}
+
+// fusermount3 and fusermount on Linux, umount on macOS
+async function unmountDir(dir: string): Promise<void> {
+  let last = '';
+  for (const [command, args] of [['fusermount3', ['-u', dir]], ['fusermount', ['-u', dir]], ['umount', [dir]]] as [string, string[]][]) {
+    const result = await run(command, args);
+    if (result.code === 0) {
+      return;
+    }
+    if (result.code !== 127) {
+      last = result.stderr;
+    }
+  }
+  throw new Error(last || 'no fusermount or umount found');
+}
+
+/// Unmount the session's mount at `dir`, or all of them. Resolves with one
+/// line per mount; one that is gone already is just forgotten.
+export async function unmountSession(dir?: string): Promise<string[]> {
+  const mounts = loadMounts();
+  const selected = dir ? mounts.filter(mount => mount.dir === path.resolve(dir)) : mounts;
+  if (dir && selected.length === 0) {
+    throw new Error(`Nothing of this session is mounted at ${path.resolve(dir)}`);
+  }
+  const report: string[] = [];
+  const kept: SessionMount[] = mounts.filter(mount => !selected.includes(mount));
+  for (const mount of selected) {
+    try {
+      await unmountDir(mount.dir);
+      report.push(`Unmounted ${mount.taskspace} from ${mount.dir}`);
+    } catch (error) {
+      if (/not mounted|not found in \/etc\/mtab|not currently mounted|Invalid argument/i.test(error.message)) {
+        report.push(`${mount.dir} was no longer mounted`);
+      } else {
+        report.push(`Could not unmount ${mount.dir}: ${error.message}`);
+        kept.push(mount);
+      }
+    }
+  }
+  saveMounts(kept);
+  return report;
+}
@@ -0,0 +1,29 @@
+import { format } from 'util';
+import { messageIdOf } from './i18n.js';
+import { loadSettings } from './settings.js';
+
+/// Runs with nobody at the terminal, e.g. CI provisioning a review
+/// environment (`--non-interactive`): SSH connects in batch mode, so a
+/// password, passphrase or unknown host key fails the connection instead of
+/// prompting; questions are not asked; nothing needs a terminal; and errors
+/// and warnings are printed as one JSON object per line on stderr.
+
+export function nonInteractive(): boolean {
+  return !!loadSettings().nonInteractive;
+}
+
+/// An error or warning line as a JSON object: `level`, the message `id` from
+/// locales/en.ftl where the line starts with a translated message, and the
+/// `message` as it would have been printed
+export function machineReadableLine(level: 'error' | 'warning', args: any[]): string {
+  const id = typeof args[0] === 'string' ? messageIdOf(args[0]) : undefined;
+  return JSON.stringify({ level, id, message: format(...args) });
+}
+
+/// Print everything that goes through console.warn and console.error as machine readable lines from now on
+export function enableMachineReadableErrors(): void {
+  for (const [method, level] of [['warn', 'warning'], ['error', 'error']] as const) {
+    const print = console[method].bind(console);
+    console[method] = (...args: any[]) => print(machineReadableLine(level, args));
+  }
+}
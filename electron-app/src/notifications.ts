@@ -0,0 +1,188 @@
+import * as http from 'http';
+import * as https from 'https';
+import { spawn } from 'child_process';
+import { Notification as DesktopNotification } from 'electron';
+
+/// Things the app tells the user about
+export type NotificationEvent = 'ready' | 'crash' | 'signal' | 'idle-shutdown';
+
+export const NOTIFICATION_EVENTS: NotificationEvent[] = ['ready', 'crash', 'signal', 'idle-shutdown'];
+
+export interface Notification {
+  event: NotificationEvent;
+  title: string;
+  message: string;
+  taskspace?: { uuid: string; name: string };
+  url?: string;
+  timestamp: string;
+}
+
+/// Somewhere a notification can go. `send` rejects with the reason it could
+/// not be delivered; the notifier logs it and carries on with the other sinks.
+export interface NotificationSink {
+  name: string;
+  send(notification: Notification): Promise<void>;
+}
+
+/// A sink as written in the `notifications` setting: a name, or an object
+/// with `type` for the sinks that need more
+export type NotificationSinkConfig =
+  | 'desktop'
+  | 'bell'
+  | { type: 'desktop' }
+  | { type: 'bell' }
+  | { type: 'webhook'; url: string; headers?: Record<string, string> }
+  | { type: 'command'; command: string };
+
+// How long a webhook or command may take before it counts as failed
+const SINK_TIMEOUT_MS = 10000;
+
+/// A desktop notification through the OS notification center.
+export const desktopSink: NotificationSink = {
+  name: 'desktop',
+  async send(notification: Notification): Promise<void> {
+    if (!DesktopNotification.isSupported()) {
+      throw new Error('desktop notifications are not supported here');
+    }
+    new DesktopNotification({ title: notification.title, body: notification.message }).show();
+  }
+};
+
+/// The terminal bell, for when the app runs in a terminal you are looking at.
+export const bellSink: NotificationSink = {
+  name: 'bell',
+  async send(): Promise<void> {
+    process.stdout.write('\x07');
+  }
+};
+
+/// POSTs the notification as JSON to `url`.
+export function webhookSink(url: string, headers: Record<string, string> = {}): NotificationSink {
+  return {
+    name: `webhook ${url}`,
+    send(notification: Notification): Promise<void> {
+      return new Promise((resolve, reject) => {
+        const body = JSON.stringify(notification);
+        const request = (url.startsWith('https:') ? https : http).request(url, {
+          method: 'POST',
+          headers: { ...headers, 'Content-Type': 'application/json', 'Content-Length': Buffer.byteLength(body) }
+        }, (res) => {
+          res.resume();
+          if (res.statusCode && res.statusCode >= 200 && res.statusCode < 300) {
+            resolve();
+          } else {
+            reject(new Error(`HTTP ${res.statusCode}`));
+          }
+        });
+
+        request.on('error', reject);
+        request.setTimeout(SINK_TIMEOUT_MS, () => {
+          request.destroy();
+          reject(new Error(`no response within ${SINK_TIMEOUT_MS}ms`));
+        });
+        request.end(body);
+      });
+    }
+  };
+}
+
+/// Runs `command` with `sh -c`. The notification is on stdin as JSON, and in
+/// THEOLDSWITCHEROO_EVENT, _TITLE, _MESSAGE, _TASKSPACE and _URL.
+export function commandSink(command: string): NotificationSink {
+  return {
+    name: `command ${command}`,
+    send(notification: Notification): Promise<void> {
+      return new Promise((resolve, reject) => {
+        const child = spawn('sh', ['-c', command], {
+          stdio: ['pipe', 'ignore', 'pipe'],
+          env: {
+            ...process.env,
+            THEOLDSWITCHEROO_EVENT: notification.event,
+            THEOLDSWITCHEROO_TITLE: notification.title,
+            THEOLDSWITCHEROO_MESSAGE: notification.message,
+            THEOLDSWITCHEROO_TASKSPACE: notification.taskspace?.name || '',
+            THEOLDSWITCHEROO_URL: notification.url || ''
+          }
+        });
+
+        let stderr = '';
+        child.stderr!.on('data', (chunk) => stderr += chunk);
+        const timer = setTimeout(() => child.kill(), SINK_TIMEOUT_MS);
+        child.on('error', (error) => {
+          clearTimeout(timer);
+          reject(error);
+        });
+        child.on('close', (code, signal) => {
+          clearTimeout(timer);
+          if (code === 0) {
+            resolve();
+          } else {
+            reject(new Error(`${signal ? `killed by ${signal}` : `exited with code ${code}`}${stderr.trim() ? `: ${stderr.trim()}` : ''}`));
+          }
+        });
+        // A command that does not read stdin closes it early
+        child.stdin!.on('error', () => {});
+        child.stdin!.end(JSON.stringify(notification));
+      });
+    }
+  };
+}
+
+/// Build the sink a `notifications` setting entry describes
+export function createSink(config: NotificationSinkConfig): NotificationSink {
+  const type = typeof config === 'string' ? config : config.type;
+  switch (type) {
+    case 'desktop':
+      return desktopSink;
+    case 'bell':
+      return bellSink;
+    case 'webhook': {
+      const { url, headers } = config as { url: string; headers?: Record<string, string> };
+      if (!url) {
+        throw new Error('a webhook notification sink needs a url');
+      }
+      return webhookSink(url, headers);
+    }
+    case 'command': {
+      const { command } = config as { command: string };
+      if (!command) {
+        throw new Error('a command notification sink needs a command');
+      }
+      return commandSink(command);
+    }
+    default:
+      throw new Error(`unknown notification sink ${JSON.stringify(type)} (expected desktop, bell, webhook or command)`);
+  }
+}
+
+/// Sinks used for events the `notifications` setting does not mention
+export const DEFAULT_NOTIFICATIONS: Partial<Record<NotificationEvent, NotificationSinkConfig[]>> = {
+  crash: ['desktop'],
+  signal: ['desktop']
+};
+
+/// Sends each notification to the sinks configured for its event.
+export class Notifier {
+  private sinks = new Map<NotificationEvent, NotificationSink[]>();
+
+  /// Throws on an unknown event or a malformed sink, so a typo in the
+  /// settings is reported at startup rather than when something goes wrong
+  constructor(config: Partial<Record<string, NotificationSinkConfig[]>> = {}) {
+    for (const event of Object.keys(config)) {
+      if (!NOTIFICATION_EVENTS.includes(event as NotificationEvent)) {
+        throw new Error(`unknown notification event ${JSON.stringify(event)} (expected ${NOTIFICATION_EVENTS.join(', ')})`);
+      }
+    }
+    for (const event of NOTIFICATION_EVENTS) {
+      const sinks = config[event] ?? DEFAULT_NOTIFICATIONS[event] ?? [];
+      this.sinks.set(event, sinks.map(createSink));
+    }
+  }
+
+  /// Deliver to every sink at once; a failing sink is logged, never thrown
+  async notify(notification: Omit<Notification, 'timestamp'>): Promise<void> {
+    const full = { ...notification, timestamp: new Date().toISOString() };
+    await Promise.all((this.sinks.get(notification.event) || []).map(sink =>
+      sink.send(full).catch((error) => console.warn(`Could not send ${notification.event} notification to ${sink.name}: ${error.message}`))));
+  }
+}
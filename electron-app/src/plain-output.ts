@@ -0,0 +1,45 @@
+import { format } from 'util';
+import { formatTimestamp } from './log-format.js';
+
+/// Output for screen readers and anything else that reads the terminal line
+/// by line (`--plain`): every line the app prints is timestamped, status
+/// symbols become words, and terminal control sequences from remote tools are
+/// dropped, with a line redrawn in place (`\r`, as progress bars do) reduced
+/// to what it showed last.
+
+// The symbols the app's messages start with, and what they mean
+const SYMBOLS: [RegExp, string][] = [
+  [/(✓|✅)️?\s*/g, 'OK: '],
+  [/(✗|❌)️?\s*/g, 'FAILED: '],
+  [/⚠️?\s*/g, 'WARNING: '],
+  [/ℹ️?\s*/g, 'INFO: '],
+  [/❓️?\s*/g, 'UNKNOWN: '],
+  [/🔔️?\s*/g, 'NOTICE: '],
+  [/🛠️?\s*/g, 'MAINTENANCE: ']
+];
+
+// Colors, cursor movement and erasing (CSI), and window titles (OSC)
+const CONTROL_SEQUENCE = /\x1b\[[0-9;?]*[ -\/]*[@-~]|\x1b\][^\x07\x1b]*(\x07|\x1b\\)/g;
+
+const TIMESTAMP_FORMAT = 'YYYY-MM-DD HH:mm:ss';
+
+/// One line as plain text: what a terminal would finally show of it, without
+/// control sequences, and with symbols spelled out
+export function plainLine(line: string): string {
+  const shown = line.split('\r').map(part => part.replace(CONTROL_SEQUENCE, '')).filter(part => part.trim()).pop() || '';
+  return SYMBOLS.reduce((text, [symbol, word]) => text.replace(symbol, word), shown).trimEnd();
+}
+
+/// `text` as timestamped plain lines, leaving out lines with nothing to read
+export function plainLines(text: string, now: Date = new Date()): string[] {
+  const stamp = `[${formatTimestamp(now, TIMESTAMP_FORMAT, false)}]`;
+  return text.split('\n').map(plainLine).filter(line => line).map(line => `${stamp} ${line}`);
+}
+
+/// Print everything that goes through console.log, info, warn and error as plain lines from now on
+export function enablePlainOutput(): void {
+  for (const method of ['log', 'info', 'warn', 'error'] as const) {
+    const print = console[method].bind(console);
+    console[method] = (...args: any[]) => plainLines(format(...args)).forEach(line => print(line));
+  }
+}
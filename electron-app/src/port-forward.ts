@@ -0,0 +1,92 @@
+import * as http from 'http';
+import * as https from 'https';
+import * as net from 'net';
+
+/// Whether something on this machine is already listening on `port` on localhost.
+export function isLocalPortInUse(port: number): Promise<boolean> {
+  return new Promise((resolve) => {
+    const probe = net.createServer();
+    probe.once('error', (error: NodeJS.ErrnoException) => {
+      resolve(error.code === 'EADDRINUSE');
+    });
+    probe.once('listening', () => {
+      probe.close(() => resolve(false));
+    });
+    probe.listen(port, '127.0.0.1');
+  });
+}
+
+/// The first port from `start` on (trying at most `attempts`) that nothing on localhost listens on
+export async function findFreeLocalPort(start: number, attempts: number = 100): Promise<number> {
+  for (let port = start; port < start + attempts && port <= 65535; port++) {
+    if (!(await isLocalPortInUse(port))) {
+      return port;
+    }
+  }
+  throw new Error(`No free local port between ${start} and ${start + attempts - 1}`);
+}
+
+/// Whether `http://localhost:<port>` serves a workbench page mentioning `marker`.
+/// `token` is sent as the connection token cookie for servers that require one.
+///
+/// The workbench page embeds the server's configuration, including the folder
+/// it was started with, so a marker unique to that folder (the taskspace uuid)
+/// tells our server apart from whatever else might be on the port.
+export function localEndpointServes(port: number, marker: string, token?: string, timeoutMs: number = 2000): Promise<boolean> {
+  return new Promise((resolve) => {
+    const headers = token ? { cookie: `vscode-tkn=${token}` } : {};
+    const req = http.get(`http://localhost:${port}/`, { headers }, (res) => {
+      if (res.statusCode !== 200) {
+        res.resume();
+        resolve(false);
+        return;
+      }
+      let body = '';
+      res.setEncoding('utf8');
+      res.on('data', (chunk: string) => body += chunk);
+      res.on('end', () => resolve(body.includes(marker)));
+    });
+
+    req.on('error', () => resolve(false));
+    req.setTimeout(timeoutMs, () => {
+      req.destroy();
+      resolve(false);
+    });
+  });
+}
+
+/// Whether a TCP connection to `localhost:<port>` is accepted within `timeoutMs`.
+export function isLocalPortAccepting(port: number, timeoutMs: number = 2000): Promise<boolean> {
+  return new Promise((resolve) => {
+    const socket = net.connect(port, '127.0.0.1', () => {
+      socket.destroy();
+      resolve(true);
+    });
+    socket.once('error', () => resolve(false));
+    socket.setTimeout(timeoutMs, () => {
+      socket.destroy();
+      resolve(false);
+    });
+  });
+}
+
+/// Whether anything answers HTTP on `http://localhost:<port>`, whatever the
+/// status. Through an SSH tunnel this means the tunnel and the server behind
+/// it both work; a dead tunnel still accepts the connection but never answers.
+/// With `tls`, the server speaks HTTPS with a self-signed certificate, which is not verified.
+export function localEndpointResponds(port: number, timeoutMs: number = 5000, tls: boolean = false): Promise<boolean> {
+  return new Promise((resolve) => {
+    const onResponse = (res: http.IncomingMessage) => {
+      res.resume();
+      resolve(true);
+    };
+    const req = tls
+      ? https.get(`https://localhost:${port}/`, { rejectUnauthorized: false }, onResponse)
+      : http.get(`http://localhost:${port}/`, onResponse);
+    req.on('error', () => resolve(false));
+    req.setTimeout(timeoutMs, () => {
+      req.destroy();
+      resolve(false);
+    });
+  });
+}
@@ -0,0 +1,118 @@
+import { sshManager } from './ssh-manager.js';
+import { serverFlavor } from './server-flavor.js';
+
+/// Packages openvscode-server and the install script need that minimal images
+/// may lack, per package manager: libatomic (loaded by the server's node),
+/// curl and tar (download and unpack the server), git (project clones) and
+/// CA certificates (HTTPS downloads). Alpine also gets gcompat and libstdc++,
+/// the glibc compatibility layer the glibc-only server builds need on musl.
+export const PREREQUISITE_PACKAGES: Record<string, string[]> = {
+  'apt-get': ['libatomic1', 'curl', 'tar', 'git', 'ca-certificates'],
+  'dnf': ['libatomic', 'curl', 'tar', 'git', 'ca-certificates'],
+  'yum': ['libatomic', 'curl', 'tar', 'git', 'ca-certificates'],
+  'zypper': ['libatomic1', 'curl', 'tar', 'git', 'ca-certificates'],
+  'apk': ['libatomic', 'curl', 'tar', 'git', 'ca-certificates', 'gcompat', 'libstdc++'],
+};
+
+// Non-interactive install command for each package manager
+const INSTALL_COMMANDS: Record<string, string> = {
+  'apt-get': 'apt-get update -q && DEBIAN_FRONTEND=noninteractive apt-get install -y -q',
+  'dnf': 'dnf install -y -q',
+  'yum': 'yum install -y -q',
+  'zypper': 'zypper --non-interactive install',
+  'apk': 'apk add --no-cache',
+};
+
+/// Install the server's prerequisites on `hostname` with its package manager.
+///
+/// Runs as the SSH login user, not `remoteUser`: installing packages needs
+/// root, so the login user must be root or have passwordless sudo. Without
+/// either the script refuses up front instead of hanging on a password prompt.
+export async function installPrerequisites(hostname: string): Promise<void> {
+  const cases = Object.entries(INSTALL_COMMANDS).map(([manager, install]) => `
+      ${manager})
+        $SUDO sh -c '${install} ${PREREQUISITE_PACKAGES[manager].join(' ')}'
+        ;;`).join('');
+
+  const script = `
+    set -e
+    MANAGER=
+    for candidate in ${Object.keys(INSTALL_COMMANDS).join(' ')}; do
+      if command -v $candidate >/dev/null 2>&1; then MANAGER=$candidate; break; fi
+    done
+    if [ -z "$MANAGER" ]; then
+      echo "No supported package manager found (tried ${Object.keys(INSTALL_COMMANDS).join(', ')})" >&2
+      exit 1
+    fi
+
+    if [ "$(id -u)" = 0 ]; then
+      SUDO=
+    elif command -v sudo >/dev/null 2>&1 && sudo -n true 2>/dev/null; then
+      SUDO="sudo -n"
+    else
+      echo "Refusing to install packages: $(id -un) is not root and has no passwordless sudo." >&2
+      echo "Ask an administrator to install the packages for $MANAGER listed in the docs." >&2
+      exit 1
+    fi
+
+    echo "Installing prerequisites with $MANAGER"
+    case $MANAGER in${cases}
+    esac
+  `;
+
+  await sshManager.executeLineStreamingCommand(hostname, script, (source, line) => {
+    console.log(`[prereqs ${source}] ${line}`);
+  });
+}
+
+/// Shell commands printing `libc: musl` or `libc: glibc`, and `glibc-loader: yes`
+/// when a glibc dynamic loader is installed anyway (gcompat provides one on musl)
+export const LIBC_PROBE = `
+  if ldd --version 2>&1 | grep -qi musl || grep -qs '^ID=alpine' /etc/os-release; then
+    echo "libc: musl"
+  else
+    echo "libc: glibc"
+  fi
+  if ls /lib/ld-linux-*.so.* /lib64/ld-linux-*.so.* >/dev/null 2>&1; then echo "glibc-loader: yes"; fi
+`;
+
+/// What to do about a musl host, where the glibc-linked server cannot start
+export function muslGuidance(hostname: string): string {
+  return `${hostname} uses musl libc (e.g. Alpine), and the server is only published linked against glibc, ` +
+    `so its node fails to load there. Use a glibc-based host or container (Debian, Ubuntu, Fedora, ...), ` +
+    `or install the glibc compatibility layer with \`apk add gcompat libstdc++\` ` +
+    `(or \`--install-prereqs ${hostname}\`), which usually lets it run but is not supported upstream.`;
+}
+
+/// What to do about a macOS host with a flavor that has no darwin builds
+export function macosFlavorGuidance(hostname: string): string {
+  return `${hostname} runs macOS, and openvscode-server publishes no macOS builds, so its download would fail. ` +
+    `Set flavor to code-server (\`--flavor code-server\`), which publishes macOS builds for every release.`;
+}
+
+/// Refuse to install the server where it cannot run: on hosts that are
+/// neither Linux nor macOS, on macOS unless the flavor is code-server, and on
+/// musl hosts without a glibc loader, where it would only fail with a loader
+/// error. Warns when a musl host has a loader through gcompat. Glibc Linux
+/// hosts and macOS with code-server pass silently.
+export async function checkPlatform(hostname: string): Promise<void> {
+  const report = await sshManager.executeCommand(hostname, `echo "os: $(uname -s)"
+${LIBC_PROBE}`);
+  const os = /^os: (.*)$/m.exec(report)?.[1].trim();
+  if (os === 'Darwin') {
+    if (serverFlavor().name !== 'code-server') {
+      throw new Error(macosFlavorGuidance(hostname));
+    }
+    return;
+  }
+  if (os && os !== 'Linux') {
+    throw new Error(`${hostname} runs ${os}; servers are only managed on Linux and macOS hosts.`);
+  }
+  if (!/^libc: musl$/m.test(report)) {
+    return;
+  }
+  if (!/^glibc-loader: yes$/m.test(report)) {
+    throw new Error(muslGuidance(hostname));
+  }
+  console.warn(`⚠ ${hostname} uses musl libc; running the glibc server build through gcompat, which is best-effort`);
+}
@@ -0,0 +1,138 @@
+import { sshManager } from './ssh-manager.js';
+import { serverPidFile } from './session-status.js';
+
+/// The processes of a session's servers (`--ps`): each server with what it
+/// started, such as extension hosts, terminals and the tasks run in them, and
+/// `--kill` for a runaway one among them. A server's process is the one in
+/// its PID file, so only processes descended from one are shown or killed.
+
+export interface ServerProcess {
+  pid: number;
+  ppid: number;
+  cpu: number;
+  // Resident memory in KiB
+  rss: number;
+  elapsed: string;
+  command: string;
+  // How far below the server process, which is at 0
+  depth: number;
+}
+
+export interface ServerProcessTree {
+  taskspace: string;
+  // The server's PID, or null if its PID file is missing or the process gone
+  pid: number | null;
+  processes: ServerProcess[];
+}
+
+interface SavedTaskSpace {
+  name: string;
+  serverDataDir: string;
+}
+
+// One round trip: every server's PID file, then all processes of the host
+async function snapshot(hostname: string, taskspaces: SavedTaskSpace[]): Promise<{ roots: (number | null)[]; processes: ServerProcess[] }> {
+  const script = [
+    ...taskspaces.map(t => `echo "root $(cat ${serverPidFile(t.serverDataDir)} 2>/dev/null || echo -)"`),
+    'ps -e -o pid=,ppid=,pcpu=,rss=,etime=,args='
+  ].join('\n');
+  const roots: (number | null)[] = [];
+  const processes: ServerProcess[] = [];
+  for (const line of (await sshManager.executeCommand(hostname, script)).split('\n')) {
+    const root = /^root (\S+)$/.exec(line);
+    if (root) {
+      roots.push(/^\d+$/.test(root[1]) ? Number(root[1]) : null);
+      continue;
+    }
+    const match = /^\s*(\d+)\s+(\d+)\s+([\d.]+)\s+(\d+)\s+(\S+)\s+(.*)$/.exec(line);
+    if (match) {
+      processes.push({
+        pid: Number(match[1]),
+        ppid: Number(match[2]),
+        cpu: Number(match[3]),
+        rss: Number(match[4]),
+        elapsed: match[5],
+        command: match[6],
+        depth: 0
+      });
+    }
+  }
+  return { roots, processes };
+}
+
+// The process `root` and its descendants, each after its parent
+function descendants(root: number, processes: ServerProcess[]): ServerProcess[] {
+  const children = new Map<number, ServerProcess[]>();
+  for (const proc of processes) {
+    children.set(proc.ppid, [...(children.get(proc.ppid) || []), proc]);
+  }
+  const top = processes.find(proc => proc.pid === root);
+  if (!top) {
+    return [];
+  }
+  const tree: ServerProcess[] = [];
+  const visit = (proc: ServerProcess, depth: number) => {
+    tree.push({ ...proc, depth });
+    for (const child of children.get(proc.pid) || []) {
+      visit(child, depth + 1);
+    }
+  };
+  visit(top, 0);
+  return tree;
+}
+
+/// The process tree of each taskspace's server on `hostname`
+export async function serverProcessTrees(hostname: string, taskspaces: SavedTaskSpace[]): Promise<ServerProcessTree[]> {
+  const { roots, processes } = await snapshot(hostname, taskspaces);
+  return taskspaces.map((taskspace, i) => {
+    const tree = roots[i] !== null ? descendants(roots[i]!, processes) : [];
+    return { taskspace: taskspace.name, pid: tree.length > 0 ? roots[i] : null, processes: tree };
+  });
+}
+
+function formatMemory(kib: number): string {
+  return kib >= 1024 * 1024 ? `${(kib / 1024 / 1024).toFixed(1)} GiB` : `${Math.round(kib / 1024)} MiB`;
+}
+
+/// `--ps` output: a table per server, commands indented under their parents
+/// and cut to `width` columns
+export function formatProcessTrees(trees: ServerProcessTree[], width: number): string[] {
+  const lines: string[] = [];
+  for (const tree of trees) {
+    if (tree.pid === null) {
+      lines.push(`${tree.taskspace}: server not running`);
+      continue;
+    }
+    const cpu = tree.processes.reduce((sum, proc) => sum + proc.cpu, 0);
+    const rss = tree.processes.reduce((sum, proc) => sum + proc.rss, 0);
+    lines.push(`${tree.taskspace}: ${tree.processes.length} processes, ${cpu.toFixed(1)}% CPU, ${formatMemory(rss)}`);
+    lines.push(`  ${'PID'.padEnd(8)}${'CPU%'.padStart(6)}${'MEM'.padStart(10)}  ${'ELAPSED'.padEnd(12)}COMMAND`);
+    for (const proc of tree.processes) {
+      const row = `  ${String(proc.pid).padEnd(8)}${proc.cpu.toFixed(1).padStart(6)}${formatMemory(proc.rss).padStart(10)}  ${proc.elapsed.padEnd(12)}${'  '.repeat(proc.depth)}${proc.command}`;
+      lines.push(row.length > width ? `${row.slice(0, width - 1)}…` : row);
+    }
+  }
+  return lines;
+}
+
+/// Send `signal` to process `pid` on `hostname` if it is one a taskspace's
+/// server started, and resolve with what it was. The server itself is left
+/// to `--stop` and `--taskspace delete`, which also clean up after it.
+export async function killServerProcess(hostname: string, taskspaces: SavedTaskSpace[], pid: number, signal: string): Promise<string> {
+  const trees = await serverProcessTrees(hostname, taskspaces);
+  const tree = trees.find(candidate => candidate.processes.some(proc => proc.pid === pid));
+  if (!tree) {
+    throw new Error(`${pid} is not a process of this session's servers; see --ps`);
+  }
+  const proc = tree.processes.find(candidate => candidate.pid === pid)!;
+  if (proc.depth === 0) {
+    throw new Error(`${pid} is ${tree.taskspace}'s server; stop it with --taskspace delete or --stop`);
+  }
+  if (!/^[A-Z0-9]+$/.test(signal)) {
+    throw new Error(`Invalid signal '${signal}', e.g. TERM or KILL`);
+  }
+  // The parent is checked again right before the kill, in case the PID was reused since the snapshot
+  await sshManager.executeCommand(hostname,
+    `[ "$(ps -o ppid= -p ${pid} | tr -d ' ')" = ${proc.ppid} ] || { echo "process ${pid} is gone" >&2; exit 1; }; kill -s ${signal} ${pid}`);
+  return `${tree.taskspace}: ${proc.command}`;
+}
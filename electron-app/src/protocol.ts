@@ -0,0 +1,159 @@
+// Message schema for the single-line JSON protocol between CLI tools, the
+// remote daemon, and the Electron app. Shared by all three: the daemon and CLI
+// bundle this file, the app compiles it.
+
+/// Protocol versions this build understands, newest last
+export const PROTOCOL_VERSIONS = [1];
+export const PROTOCOL_VERSION = PROTOCOL_VERSIONS[PROTOCOL_VERSIONS.length - 1];
+
+export interface TaskSpaceMessage {
+  type: string;
+  // Protocol version; messages without one are treated as version 1
+  v?: number;
+  timestamp?: string;
+  [key: string]: any;
+}
+
+/// Why a message was rejected, sent back to the client as an `error` message
+export type ProtocolErrorCode = 'invalid_json' | 'invalid_message' | 'unknown_type' | 'unsupported_version' | 'conflict' | 'internal_error';
+
+export interface ProtocolError {
+  code: ProtocolErrorCode;
+  message: string;
+}
+
+type FieldKind = 'string' | 'number' | 'object' | 'array' | readonly string[];
+
+interface MessageSchema {
+  required: Record<string, FieldKind>;
+  optional: Record<string, FieldKind>;
+}
+
+const PROGRESS_CATEGORIES = ['info', 'warn', 'error', 'milestone', 'question'] as const;
+
+/// Messages clients send to the app through the daemon. Fields not listed are
+/// allowed and ignored, so a newer client can add optional fields without a version bump.
+export const CLIENT_MESSAGE_SCHEMAS: Record<string, MessageSchema> = {
+  hello: { required: { versions: 'array' }, optional: {} },
+  subscribe: { required: { topics: 'array' }, optional: {} },
+  unsubscribe: { required: { topics: 'array' }, optional: {} },
+  new_taskspace_request: { required: { name: 'string' }, optional: { description: 'string', cwd: 'string', initial_prompt: 'string' } },
+  update_taskspace: { required: { uuid: 'string' }, optional: { name: 'string', description: 'string' } },
+  annotate_taskspace: { required: { uuid: 'string' }, optional: { note: 'string', labels: 'object' } },
+  delete_taskspace: { required: { uuid: 'string' }, optional: {} },
+  registry_get: { required: {}, optional: { uuid: 'string', request_id: 'string' } },
+  registry_put: { required: { uuid: 'string', fields: 'object' }, optional: { expected_revision: 'number', request_id: 'string' } },
+  registry_delete: { required: { uuid: 'string' }, optional: { expected_revision: 'number', request_id: 'string' } },
+  status_request: { required: {}, optional: { request_id: 'string' } },
+  logs_request: { required: { uuid: 'string' }, optional: { lines: 'number', request_id: 'string' } },
+  progress_log: { required: { message: 'string', category: PROGRESS_CATEGORIES }, optional: { taskspace_uuid: 'string' } },
+  user_signal: { required: { message: 'string' }, optional: { taskspace_uuid: 'string' } },
+  clipboard_copy: { required: { text: 'string' }, optional: { taskspace_uuid: 'string' } },
+};
+
+/// Messages the daemon itself sends to the app, in addition to the client messages it forwards
+export const DAEMON_MESSAGE_SCHEMAS: Record<string, MessageSchema> = {
+  registry_changed: { required: { uuid: 'string', revision: 'number', writer: 'string' }, optional: { fields: 'object' } },
+  error: { required: { code: 'string', message: 'string' }, optional: { request_id: 'string' } },
+  maintenance_result: { required: { seq: 'number', job: 'string', output: 'string' }, optional: { task: 'string', started_at: 'string', duration_ms: 'number' } },
+};
+
+function checkField(name: string, kind: FieldKind, value: any): string | null {
+  if (Array.isArray(kind)) {
+    return kind.includes(value) ? null : `'${name}' must be one of ${kind.join(', ')}`;
+  }
+  switch (kind) {
+    case 'array':
+      return Array.isArray(value) ? null : `'${name}' must be an array`;
+    case 'object':
+      return value !== null && typeof value === 'object' && !Array.isArray(value) ? null : `'${name}' must be an object`;
+    default:
+      return typeof value === kind ? null : `'${name}' must be a ${kind}`;
+  }
+}
+
+/// Parse and validate one line sent by a client.
+export function parseClientMessage(line: string): { message: TaskSpaceMessage } | { error: ProtocolError } {
+  return parseMessage(line, CLIENT_MESSAGE_SCHEMAS);
+}
+
+/// Parse and validate one line the daemon wrote to the app: a forwarded client message or one of its own.
+export function parseDaemonMessage(line: string): { message: TaskSpaceMessage } | { error: ProtocolError } {
+  return parseMessage(line, { ...CLIENT_MESSAGE_SCHEMAS, ...DAEMON_MESSAGE_SCHEMAS });
+}
+
+function parseMessage(line: string, schemas: Record<string, MessageSchema>): { message: TaskSpaceMessage } | { error: ProtocolError } {
+  let message: any;
+  try {
+    message = JSON.parse(line);
+  } catch (error) {
+    return { error: { code: 'invalid_json', message: `not valid JSON: ${(error as Error).message}` } };
+  }
+
+  if (message === null || typeof message !== 'object' || Array.isArray(message) || typeof message.type !== 'string') {
+    return { error: { code: 'invalid_message', message: "message must be a JSON object with a string 'type'" } };
+  }
+
+  const version = message.v ?? 1;
+  if (!PROTOCOL_VERSIONS.includes(version)) {
+    return {
+      error: {
+        code: 'unsupported_version',
+        message: `protocol version ${JSON.stringify(version)} is not supported (supported: ${PROTOCOL_VERSIONS.join(', ')})`
+      }
+    };
+  }
+
+  // Only the schemas' own keys are types; 'toString' or '__proto__' are unknown like any other name
+  const schema = Object.prototype.hasOwnProperty.call(schemas, message.type) ? schemas[message.type] : undefined;
+  if (!schema) {
+    return { error: { code: 'unknown_type', message: `unknown message type '${message.type}'` } };
+  }
+
+  for (const [name, kind] of Object.entries(schema.required)) {
+    if (message[name] === undefined) {
+      return { error: { code: 'invalid_message', message: `${message.type}: missing '${name}'` } };
+    }
+    const problem = checkField(name, kind, message[name]);
+    if (problem) {
+      return { error: { code: 'invalid_message', message: `${message.type}: ${problem}` } };
+    }
+  }
+  for (const [name, kind] of Object.entries(schema.optional)) {
+    const problem = message[name] === undefined ? null : checkField(name, kind, message[name]);
+    if (problem) {
+      return { error: { code: 'invalid_message', message: `${message.type}: ${problem}` } };
+    }
+  }
+
+  return { message };
+}
+
+/// Subscribing to this topic receives every message, which is what clients get until they subscribe
+export const ALL_TOPICS = '*';
+
+/// The topic a message is delivered under: `taskspace:<uuid>` when it concerns
+/// one taskspace, `global` otherwise (status, new taskspaces, user signals
+/// without a taskspace).
+export function messageTopic(message: TaskSpaceMessage): string {
+  const uuid = message.taskspace_uuid || message.uuid;
+  return typeof uuid === 'string' ? `taskspace:${uuid}` : 'global';
+}
+
+/// The highest version both sides support, or null if there is none
+export function negotiateVersion(clientVersions: number[]): number | null {
+  const common = PROTOCOL_VERSIONS.filter(version => clientVersions.includes(version));
+  return common.length > 0 ? common[common.length - 1] : null;
+}
+
+/// The `error` reply for `error`; `requestId` is echoed so the reply reaches the client that sent the request
+export function errorMessage(error: ProtocolError, requestId?: string): TaskSpaceMessage {
+  return {
+    type: 'error',
+    v: PROTOCOL_VERSION,
+    code: error.code,
+    message: error.message,
+    ...(requestId && { request_id: requestId }),
+    timestamp: new Date().toISOString()
+  };
+}
@@ -0,0 +1,139 @@
+/// A quick check, before any SSH, that the host can be reached at all: that
+/// its name resolves and its SSH port answers. Most "cannot connect" failures
+/// are a VPN that is not up yet, which this reports in seconds, with what
+/// failed and the user's own hint for the host, instead of after SSH's
+/// connect timeout and retries.
+
+import * as dns from 'dns';
+import * as net from 'net';
+import { ResolvedHost, resolveHost } from './native-ssh.js';
+import { sshManager } from './ssh-manager.js';
+import { loadSettings } from './settings.js';
+
+const DEFAULT_TIMEOUT_MS = 3000;
+
+/// What the check found for one host
+export interface Reachability {
+  // Where the check connected: the host, or the first jump host in front of it
+  target: string;
+  hostname: string;
+  port: number;
+  // Addresses the name resolved to; empty when it did not resolve
+  addresses: string[];
+  // null when the port answered
+  problem: string | null;
+}
+
+/// The check failed: the message says what failed, followed by the host's hint
+export class HostUnreachableError extends Error {
+  constructor(public host: string, public result: Reachability, hint: string | undefined) {
+    super([
+      `Cannot reach ${host}${result.target !== host ? ` (through ${result.target})` : ''}: ${result.problem}`,
+      ...(hint ? [`Hint: ${hint}`] : [])
+    ].join('\n'));
+  }
+}
+
+/// The hint configured in `reachabilityHints` for `host`: by its exact name,
+/// else by the first pattern that matches, where `*` stands for any characters
+export function reachabilityHint(host: string, hints: Record<string, string> = loadSettings().reachabilityHints || {}): string | undefined {
+  if (hints[host]) {
+    return hints[host];
+  }
+  const pattern = Object.keys(hints).find(pattern => {
+    const regex = new RegExp(`^${pattern.split('*').map(part => part.replace(/[.+?^${}()|[\]\\]/g, '\\$&')).join('.*')}$`, 'i');
+    return regex.test(host);
+  });
+  return pattern ? hints[pattern] : undefined;
+}
+
+// The first hop of a ProxyJump value, e.g. "admin@gate:2222,bastion" -> gate, 2222
+function firstJump(proxyJump: string): { host: string; port?: number } {
+  const hop = proxyJump.split(',')[0].replace(/^[^@]*@/, '');
+  const match = /^\[?([^\]]+?)\]?(?::(\d+))?$/.exec(hop);
+  return { host: match ? match[1] : hop, port: match?.[2] ? Number(match[2]) : undefined };
+}
+
+// Open a TCP connection to `address`:`port` and close it again; resolves with what went wrong, or null
+function probePort(address: string, port: number, timeoutMs: number): Promise<string | null> {
+  return new Promise(resolve => {
+    const socket = net.connect({ host: address, port });
+    const finish = (problem: string | null) => {
+      socket.destroy();
+      resolve(problem);
+    };
+    socket.setTimeout(timeoutMs, () => finish(`port ${port} did not answer within ${timeoutMs / 1000}s, so it is filtered by a firewall or the host is down (not on the VPN?)`));
+    socket.on('connect', () => finish(null));
+    socket.on('error', (error: NodeJS.ErrnoException) => {
+      switch (error.code) {
+        case 'ECONNREFUSED':
+          finish(`the host is up but refused port ${port}; is sshd running, and on that port?`);
+          break;
+        case 'EHOSTUNREACH':
+        case 'ENETUNREACH':
+          finish(`there is no route to ${address} (${error.code}); the network it is on is not connected (not on the VPN?)`);
+          break;
+        default:
+          finish(`connecting to port ${port} failed: ${error.message}`);
+      }
+    });
+  });
+}
+
+/// Check that `host`, as ssh would connect to it (~/.ssh/config and `jumpHost`
+/// included), resolves and answers on its SSH port. With a jump host, the
+/// first jump host is checked instead; with a ProxyCommand there is nothing
+/// to check.
+export async function checkReachability(host: string, timeoutMs: number = DEFAULT_TIMEOUT_MS): Promise<Reachability | null> {
+  let resolved: ResolvedHost;
+  let target = host;
+  try {
+    resolved = resolveHost(host, sshManager.connectionOptions(host));
+    if (resolved.proxyJump) {
+      const jump = firstJump(resolved.proxyJump);
+      target = jump.host;
+      resolved = resolveHost(jump.host, []);
+      resolved.port = jump.port || resolved.port;
+    }
+  } catch (error) {
+    // No ssh to ask; SSH itself will fail with the details
+    return null;
+  }
+  if (resolved.proxy) {
+    return null;
+  }
+
+  const result: Reachability = { target, hostname: resolved.hostname, port: resolved.port, addresses: [], problem: null };
+  try {
+    let timer: NodeJS.Timeout;
+    const timeout = new Promise<never>((_, reject) => {
+      timer = setTimeout(() => reject(Object.assign(new Error('timed out'), { code: 'ETIMEOUT' })), timeoutMs);
+    });
+    const lookup = dns.promises.lookup(resolved.hostname, { all: true }).finally(() => clearTimeout(timer));
+    result.addresses = (await Promise.race([lookup, timeout])).map(entry => entry.address);
+  } catch (error) {
+    result.problem = error.code === 'ETIMEOUT'
+      ? `looking up ${resolved.hostname} did not finish within ${timeoutMs / 1000}s; the DNS server is unreachable (not on the VPN?)`
+      : `${resolved.hostname} does not resolve (${error.code}); it is likely only known to the VPN's or the office's DNS`;
+    return result;
+  }
+  result.problem = await probePort(result.addresses[0], resolved.port, timeoutMs);
+  if (result.problem) {
+    result.problem = `${resolved.hostname} resolves to ${result.addresses.join(', ')}, but ${result.problem}`;
+  }
+  return result;
+}
+
+/// Throw a HostUnreachableError, with the host's hint, if `host` can't be
+/// reached. Off with `reachabilityCheck` set to false, and during a replay.
+export async function ensureReachable(host: string): Promise<void> {
+  const settings = loadSettings();
+  if (settings.reachabilityCheck === false || sshManager.isReplaying()) {
+    return;
+  }
+  const result = await checkReachability(host, settings.reachabilityTimeoutMs || DEFAULT_TIMEOUT_MS);
+  if (result?.problem) {
+    const hint = [host, result.target, result.hostname].map(name => reachabilityHint(name)).find(hint => hint);
+    throw new HostUnreachableError(host, result, hint);
+  }
+}
@@ -0,0 +1,74 @@
+import { loadSettings } from './settings.js';
+
+// Failures that say nothing about the host or the command, only that the network was not
+// there for a moment: from ssh, the native client (Node's errno codes) and the server download
+const TRANSIENT_FAILURE = new RegExp([
+  'Could not resolve (the )?host(name)?',
+  'Temporary failure in name resolution',
+  'Name or service not known',
+  'Network is unreachable',
+  'No route to host',
+  'Connection (timed out|refused|reset|closed)',
+  'Operation timed out',
+  'kex_exchange_identification',
+  'Failed to connect to',
+  'ETIMEDOUT|ECONNRESET|ECONNREFUSED|EAI_AGAIN|ENOTFOUND|EHOSTUNREACH|ENETUNREACH'
+].join('|'), 'i');
+
+// However many retries, no wait between them is longer than this
+const MAX_DELAY_MS = 60 * 1000;
+
+export interface RetryPolicy {
+  // Attempts after the first one
+  retries: number;
+  // Wait before the first retry; each later one waits twice as long as the one before
+  delayMs: number;
+}
+
+/// The policy from the `retries` and `retryDelay` settings
+export function retryPolicy(): RetryPolicy {
+  const settings = loadSettings();
+  return {
+    retries: Math.max(0, settings.retries ?? 3),
+    delayMs: Math.max(0, (settings.retryDelay ?? 2) * 1000)
+  };
+}
+
+/// Whether `error` looks like a network blip worth trying again after
+export function isTransientFailure(error: Error): boolean {
+  return TRANSIENT_FAILURE.test(error.message);
+}
+
+/// Run `step`, described by `what` (e.g. "Connecting to build2"), and run it
+/// again with exponential backoff while it fails transiently. Other failures,
+/// e.g. a refused login, and the last transient one are thrown as they are.
+/// Each failed attempt is logged and passed to `report`, e.g. to show it on
+/// the loading view.
+export async function withRetry<T>(
+  what: string,
+  step: () => Promise<T>,
+  report: (message: string) => void = () => {},
+  policy: RetryPolicy = retryPolicy()
+): Promise<T> {
+  const attempts = policy.retries + 1;
+  for (let attempt = 1; ; attempt++) {
+    try {
+      return await step();
+    } catch (error) {
+      if (attempt >= attempts || !isTransientFailure(error)) {
+        throw error;
+      }
+      const delay = Math.min(policy.delayMs * Math.pow(2, attempt - 1), MAX_DELAY_MS);
+      const message = `${what} failed (attempt ${attempt}/${attempts}): ${lastLine(error.message)}; retrying in ${Math.round(delay / 100) / 10}s...`;
+      console.warn(message);
+      report(message);
+      await new Promise(resolve => setTimeout(resolve, delay));
+    }
+  }
+}
+
+// The useful part of an SSH failure is the last line of stderr
+function lastLine(message: string): string {
+  const lines = message.split('\n').map(line => line.trim()).filter(line => line);
+  return lines[lines.length - 1] || message;
+}
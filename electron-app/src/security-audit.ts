@@ -0,0 +1,208 @@
+import { sshManager } from './ssh-manager.js';
+import { BASE_DIR, loadSettings } from './settings.js';
+import { DEFAULT_SERVER_VERSION } from './server-cache.js';
+import { listSessions } from './sessions.js';
+
+export type Severity = 'critical' | 'high' | 'medium' | 'low';
+
+const SEVERITY_ORDER: Severity[] = ['critical', 'high', 'medium', 'low'];
+
+/// One problem found on one host, with what to do about it
+export interface AuditFinding {
+  host: string;
+  severity: Severity;
+  check: 'exposed-server' | 'no-token' | 'token-file' | 'server-version' | 'advisory' | 'data-permissions';
+  detail: string;
+  fix: string;
+}
+
+/// The audit of a whole fleet: findings most severe first, and the hosts
+/// that could not be audited
+export interface AuditReport {
+  hosts: string[];
+  findings: AuditFinding[];
+  unreachable: { host: string; error: string }[];
+}
+
+/// A published vulnerability in openvscode-server, from the `securityAdvisories` setting
+export interface SecurityAdvisory {
+  id: string;
+  fixedIn: string;
+  severity?: Severity;
+}
+
+// One `key: value` line per fact, like the --inspect script. Servers are found
+// by their node process (server-main.js), whose arguments are the server's flags.
+const AUDIT_SCRIPT = `
+  cd ${BASE_DIR} 2>/dev/null || exit 0
+  echo "release: $(cat openvscode-server/.release 2>/dev/null)"
+  ps -u "$(id -u)" -o pid=,args= 2>/dev/null | grep '[s]erver-main.js' | sed 's/^ */server: /'
+  for file in taskspaces/taskspace-*/server-data/connection-token; do
+    [ -f "$file" ] && echo "token-file: $(stat -c %a "$file" 2>/dev/null || stat -f %Lp "$file") $file"
+  done
+  for dir in . taskspaces taskspaces/* taskspaces/taskspace-*/server-data vscode-user-data; do
+    [ -d "$dir" ] && echo "dir: $(stat -c %a "$dir" 2>/dev/null || stat -f %Lp "$dir") $dir"
+  done
+  true
+`;
+
+/// Every host the app knows about: the `hostname` setting, the members of
+/// every host group, and the host of every saved session
+export async function fleetHosts(): Promise<string[]> {
+  const settings = loadSettings();
+  const hosts = new Set<string>();
+  const groups = settings.hostGroups || {};
+  if (settings.hostname && !groups[settings.hostname]) {
+    hosts.add(settings.hostname);
+  }
+  Object.values(groups).forEach(members => members.forEach(host => hosts.add(host)));
+  for (const session of await listSessions()) {
+    if (session.hostname) {
+      hosts.add(session.hostname);
+    }
+  }
+  return [...hosts].sort();
+}
+
+/// Audit `hosts` for insecure deployments, all at once, one SSH round trip each
+export async function auditFleet(hosts: string[]): Promise<AuditReport> {
+  const settings = loadSettings();
+  const report: AuditReport = { hosts, findings: [], unreachable: [] };
+
+  await Promise.all(hosts.map(async (host) => {
+    if (settings.remoteUser) {
+      sshManager.setRemoteUser(host, settings.remoteUser);
+    }
+    try {
+      const output = await sshManager.executeCommand(host, AUDIT_SCRIPT);
+      report.findings.push(...auditOutput(host, output, settings.securityAdvisories || []));
+    } catch (error) {
+      report.unreachable.push({ host, error: error.message.split('\n').filter((line: string) => line.trim()).pop() || error.message });
+    }
+  }));
+
+  report.findings.sort((a, b) =>
+    SEVERITY_ORDER.indexOf(a.severity) - SEVERITY_ORDER.indexOf(b.severity) || a.host.localeCompare(b.host));
+  report.unreachable.sort((a, b) => a.host.localeCompare(b.host));
+  return report;
+}
+
+function auditOutput(host: string, output: string, advisories: SecurityAdvisory[]): AuditFinding[] {
+  const findings: AuditFinding[] = [];
+  const values = (key: string) => output.split('\n')
+    .filter(line => line.startsWith(`${key}: `))
+    .map(line => line.slice(key.length + 2).trim());
+
+  for (const server of values('server')) {
+    const [pid, ...args] = server.split(/\s+/);
+    const bind = flagValue(args, '--host') || 'localhost';
+    const exposed = bind === '0.0.0.0' || bind === '::' || bind === '[::]';
+    const tokenless = args.includes('--without-connection-token');
+    const folder = flagValue(args, '--default-folder') || `PID ${pid}`;
+    if (exposed && tokenless) {
+      findings.push({
+        host, severity: 'critical', check: 'no-token',
+        detail: `server ${folder} (PID ${pid}) listens on ${bind} without a connection token: anyone who can reach the port gets a shell`,
+        fix: 'restart it without --insecure and with tokenProxy not turned off, or stop it with --stop'
+      });
+    } else if (exposed) {
+      findings.push({
+        host, severity: 'medium', check: 'exposed-server',
+        detail: `server ${folder} (PID ${pid}) listens on ${bind}, not only on localhost`,
+        fix: 'firewall its port; the app only needs it through the SSH tunnel'
+      });
+    } else if (tokenless) {
+      findings.push({
+        host, severity: 'low', check: 'no-token',
+        detail: `server ${folder} (PID ${pid}) runs without a connection token; other users on the host can connect to it`,
+        fix: 'restart it without --insecure'
+      });
+    }
+  }
+
+  for (const entry of values('token-file')) {
+    const [mode, file] = splitMode(entry);
+    if (mode & 0o077) {
+      findings.push({
+        host, severity: 'high', check: 'token-file',
+        detail: `${file} is readable by others (mode ${mode.toString(8)})`,
+        fix: `chmod 600 ${BASE_DIR}/${file}`
+      });
+    }
+  }
+
+  for (const entry of values('dir')) {
+    const [mode, dir] = splitMode(entry);
+    if (mode & 0o004) {
+      findings.push({
+        host, severity: dir.includes('server-data') || dir === 'vscode-user-data' ? 'high' : 'medium', check: 'data-permissions',
+        detail: `${dir === '.' ? BASE_DIR : `${BASE_DIR}/${dir}`} is world-readable (mode ${mode.toString(8)})`,
+        fix: `chmod o-rwx ${dir === '.' ? BASE_DIR : `${BASE_DIR}/${dir}`}`
+      });
+    }
+  }
+
+  const version = /openvscode-server-v([\d.]+)-/.exec(values('release')[0] || '')?.[1];
+  if (version) {
+    for (const advisory of advisories) {
+      if (compareVersions(version, advisory.fixedIn) < 0) {
+        findings.push({
+          host, severity: advisory.severity || 'high', check: 'advisory',
+          detail: `openvscode-server ${version} is affected by ${advisory.id}, fixed in ${advisory.fixedIn}`,
+          fix: `set serverVersion to ${advisory.fixedIn} or later and restart the taskspaces`
+        });
+      }
+    }
+    if (compareVersions(version, DEFAULT_SERVER_VERSION) < 0) {
+      findings.push({
+        host, severity: 'low', check: 'server-version',
+        detail: `openvscode-server ${version} is older than ${DEFAULT_SERVER_VERSION}, the release this app installs by default`,
+        fix: 'remove the serverVersion setting, or raise it, and restart the taskspaces'
+      });
+    }
+  }
+
+  return findings;
+}
+
+function flagValue(args: string[], flag: string): string | undefined {
+  const index = args.indexOf(flag);
+  return index !== -1 ? args[index + 1] : undefined;
+}
+
+// `stat -c %a` (or BSD `stat -f %Lp`) output: an octal mode, then the path
+function splitMode(entry: string): [number, string] {
+  const [mode, ...rest] = entry.split(' ');
+  return [parseInt(mode, 8), rest.join(' ')];
+}
+
+function compareVersions(a: string, b: string): number {
+  const left = a.split('.').map(Number);
+  const right = b.split('.').map(Number);
+  for (let i = 0; i < Math.max(left.length, right.length); i++) {
+    const difference = (left[i] || 0) - (right[i] || 0);
+    if (difference !== 0) {
+      return difference;
+    }
+  }
+  return 0;
+}
+
+/// Human-readable form of the report, most severe findings first
+export function formatAuditReport(report: AuditReport): string {
+  const lines = [`Audited ${report.hosts.length - report.unreachable.length} of ${report.hosts.length} hosts: ${report.findings.length} findings`];
+  for (const severity of SEVERITY_ORDER) {
+    const findings = report.findings.filter(finding => finding.severity === severity);
+    if (findings.length > 0) {
+      lines.push('', `${severity.toUpperCase()} (${findings.length}):`);
+      for (const finding of findings) {
+        lines.push(`  ${finding.host}: ${finding.detail}`, `    fix: ${finding.fix}`);
+      }
+    }
+  }
+  if (report.unreachable.length > 0) {
+    lines.push('', `Not audited (${report.unreachable.length}):`);
+    report.unreachable.forEach(({ host, error }) => lines.push(`  ${host}: ${error}`));
+  }
+  return lines.join('\n');
+}
@@ -0,0 +1,424 @@
+import { spawn } from 'child_process';
+import { createHash } from 'crypto';
+import * as fs from 'fs';
+import * as path from 'path';
+import { sshManager, shellQuote, forEachLine } from './ssh-manager.js';
+import { BASE_DIR, LOCAL_DATA_DIR, loadSettings } from './settings.js';
+import { logTimestampPrefix } from './log-format.js';
+import { retryPolicy } from './retry.js';
+import { CURL_HTTP_ERROR, fetcherFor, urlScheme } from './artifact-fetch.js';
+import { CHAOS_DOWNLOAD_DELAY_SECS, chaos } from './chaos.js';
+import { SERVER_FLAVORS, serverFlavor } from './server-flavor.js';
+
+/// The openvscode-server release this app was tested with
+export const DEFAULT_SERVER_VERSION = SERVER_FLAVORS['openvscode'].defaultVersion;
+
+export const SERVER_ARCHITECTURES = ['linux-x64', 'linux-arm64'];
+
+/// Remote directory holding downloaded server tarballs, one per flavor, version and architecture
+export const SERVER_CACHE_DIR = `${BASE_DIR}/cache`;
+
+// The GitHub API, for the newest release (which `serverVersion = "latest"`
+// installs) and the published SHA-256 of every release asset, its `digest`
+const GITHUB_API_URL = 'https://api.github.com/repos';
+
+// How long to wait for the GitHub API before giving up on resolving "latest"
+const RELEASE_LOOKUP_TIMEOUT_MS = 15000;
+
+// Resolved once per run, so every taskspace and the last-good snapshot get the same release
+let latestServerVersion: Promise<string> | null = null;
+
+/// The server version `requested` stands for, by default the `serverVersion`
+/// setting or the flavor's default: a version number as it is (with or
+/// without a leading `v`), or the newest release for `latest`, looked up in
+/// the GitHub releases API from this machine. `GITHUB_TOKEN`, if set, raises
+/// the API's rate limit.
+export async function resolveServerVersion(requested: string = loadSettings().serverVersion || serverFlavor().defaultVersion): Promise<string> {
+  const version = requested.trim().replace(/^v/, '');
+  if (version === 'latest') {
+    if (!latestServerVersion) {
+      latestServerVersion = latestRelease().catch((error) => {
+        latestServerVersion = null;
+        throw error;
+      });
+    }
+    return latestServerVersion;
+  }
+  if (!/^\d+\.\d+\.\d+$/.test(version)) {
+    throw new Error(`'${requested}' is not a ${serverFlavor().displayName} version: use a release number (x.y.z) or latest`);
+  }
+  return version;
+}
+
+async function latestRelease(): Promise<string> {
+  const flavor = serverFlavor();
+  const headers: Record<string, string> = { 'Accept': 'application/vnd.github+json', 'User-Agent': 'theoldswitcheroo' };
+  if (process.env.GITHUB_TOKEN) {
+    headers['Authorization'] = `Bearer ${process.env.GITHUB_TOKEN}`;
+  }
+  let response: Response;
+  try {
+    response = await fetch(`${GITHUB_API_URL}/${flavor.githubRepo}/releases/latest`, { headers, signal: AbortSignal.timeout(RELEASE_LOOKUP_TIMEOUT_MS) });
+  } catch (error) {
+    // Node reports network failures as "fetch failed" with the errno code as the cause
+    throw new Error(`Looking up the latest ${flavor.displayName} release failed: ${error.cause?.code || error.message}`);
+  }
+  if (!response.ok) {
+    const limited = response.status === 403 || response.status === 429;
+    throw new Error(`Looking up the latest ${flavor.displayName} release failed: HTTP ${response.status}` +
+      (limited ? ' (rate-limited; set GITHUB_TOKEN, or set serverVersion to a release number)' : ''));
+  }
+  const tag = String((await response.json()).tag_name || '');
+  const version = flavor.versionFromTag(tag);
+  if (!version) {
+    throw new Error(`The latest ${flavor.displayName} release has an unexpected tag '${tag}'; set serverVersion to a release number`);
+  }
+  console.log(`Latest ${flavor.displayName} release: ${version}`);
+  return version;
+}
+
+/// Release name of `version` of the configured flavor for `arch`, e.g. `openvscode-server-v1.103.1-linux-x64`
+export function serverReleaseName(version: string, arch: string): string {
+  return serverFlavor().releaseName(version, arch);
+}
+
+/// Remote path of the cached tarball for `version` and `arch`
+export function cachedServerTarball(version: string, arch: string): string {
+  return `${SERVER_CACHE_DIR}/${serverReleaseName(version, arch)}.tar.gz`;
+}
+
+/// Local path of the tarball for `version` and `arch` when it is downloaded
+/// here and uploaded; the local data directory has the same layout as the remote one
+export function localServerTarball(version: string, arch: string): string {
+  return path.join(LOCAL_DATA_DIR, 'cache', `${serverReleaseName(version, arch)}.tar.gz`);
+}
+
+// Where the flavor's releases are downloaded from without a `serverDownloadUrl` setting
+function githubReleasesUrl(): string {
+  return `https://github.com/${serverFlavor().githubRepo}/releases/download`;
+}
+
+/// Exit code of the download step when the tarball could not be fetched
+const DOWNLOAD_FAILED_EXIT = 90;
+
+// curl's exit code for a transfer that ended short
+const CURL_PARTIAL_FILE = 18;
+
+/// Exit code of the download step when the tarball could not be verified
+const CHECKSUM_FAILED_EXIT = 91;
+
+/// Exit code of `--prime-cache` when a download was refused or blocked
+export const DOWNLOAD_BLOCKED_EXIT_CODE = 3;
+
+/// The server tarball download was rejected (e.g. HTTP error, access denied) or never reached the source
+export class ServerDownloadError extends Error {
+  httpStatus: number | null;
+  exitCode: number;
+  // URL scheme of the source, which decides how the failure is explained
+  scheme: string;
+
+  constructor(release: string, scheme: string, httpStatus: number | null, exitCode: number) {
+    const fetcher = fetcherFor(`${scheme}://`);
+    super(`Downloading ${release} failed: ${fetcher.describeFailure(httpStatus, exitCode)}`);
+    this.name = 'ServerDownloadError';
+    this.scheme = scheme;
+    this.httpStatus = httpStatus;
+    this.exitCode = exitCode;
+  }
+
+  /// Whether the host was rate-limited, refused, or could not reach the download server at all
+  get blocked(): boolean {
+    return fetcherFor(`${this.scheme}://`).blocked(this.httpStatus, this.exitCode);
+  }
+
+  /// What the user can do about it, depending on how the download failed
+  guidance(): string {
+    return fetcherFor(`${this.scheme}://`).guidance(this.httpStatus, this.exitCode);
+  }
+}
+
+/// The downloaded tarball did not have the SHA-256 it should have, every time
+/// it was downloaded, or there was no checksum to verify it against and
+/// `requireServerChecksum` is on
+export class ServerChecksumError extends Error {
+  expected: string | null;
+  actual: string | null;
+
+  constructor(release: string, expected: string | null, actual: string | null) {
+    super(expected
+      ? `Downloading ${release} failed: its SHA-256 is ${actual}, expected ${expected}`
+      : `Downloading ${release} failed: no SHA-256 checksum was found to verify it against`);
+    this.name = 'ServerChecksumError';
+    this.expected = expected;
+    this.actual = actual;
+  }
+
+  guidance(): string {
+    if (!this.expected) {
+      return 'Add the release\'s checksum to the `serverChecksums` setting, or turn `requireServerChecksum` off.';
+    }
+    return 'The download was truncated or altered on the way, e.g. by a proxy or a mirror serving a different file. ' +
+      'Check `serverDownloadUrl` and `serverDownloadProxy`, and `serverChecksums` if you pinned this release.';
+  }
+}
+
+/// Turn a failed download step into a ServerDownloadError or ServerChecksumError,
+/// or return `error` unchanged if it failed for some other reason
+export function asServerDownloadError(error: Error): Error {
+  const checksum = /server-checksum-failed: release=(\S+) expected=(\S*) actual=(\S*)/.exec(error.message);
+  if (checksum) {
+    return new ServerChecksumError(checksum[1], checksum[2] || null, checksum[3] || null);
+  }
+  const match = /server-download-failed: release=(\S+) scheme=(\S+) http=(\d*) rc=(\d+)/.exec(error.message);
+  if (!match) {
+    return error;
+  }
+  const httpStatus = Number(match[3]) || null;
+  return new ServerDownloadError(match[1], match[2], httpStatus, Number(match[4]));
+}
+
+// Shell command printing the expected SHA-256 of `release`, or nothing: the
+// digest from the GitHub releases API, or a `.sha256` file next to the
+// tarball on a mirror or in a bucket
+function expectedChecksumCommand(baseUrl: string, proxy: string | undefined, version: string, release: string): string {
+  const flavor = serverFlavor();
+  if (baseUrl !== githubReleasesUrl()) {
+    return fetcherFor(baseUrl, proxy).print(`${baseUrl}/${flavor.releaseTag(version)}/${release}.tar.gz.sha256`);
+  }
+  // One field per line, so the asset's name and the digest after it are found however the JSON is formatted
+  const asset = `"name": *"${release.replace(/\./g, '\\.')}\\.tar\\.gz"`;
+  const releaseApiUrl = `${GITHUB_API_URL}/${flavor.githubRepo}/releases/tags/${flavor.releaseTag(version)}`;
+  return `${fetcherFor(releaseApiUrl, proxy).print(releaseApiUrl)} | tr ',{}' '\\n\\n\\n' |
+        awk '/${asset}/ { found = 1 } found && /"digest"/ { print; exit }'`;
+}
+
+/// Shell commands that download the tarball for `version` and `arch` into the
+/// cache unless it is already there. The download goes to a temporary name and
+/// is moved into place only once it has the size the source reports and its
+/// SHA-256 matches the pinned one from the `serverChecksums` setting or the
+/// published one. An interrupted download is resumed where it stopped, in the
+/// same run or the next; a wrong size or checksum is downloaded again; both up
+/// to the `retries` setting. An interrupted or altered download is therefore
+/// never mistaken for a cached one. A failed download exits with a marker line
+/// that `asServerDownloadError` recognises.
+export function fetchServerTarballCommand(version: string, arch: string): string {
+  const settings = loadSettings();
+  const baseUrl = (settings.serverDownloadUrl || githubReleasesUrl()).replace(/\/+$/, '');
+  const fetcher = fetcherFor(baseUrl, settings.serverDownloadProxy);
+  const release = serverReleaseName(version, arch);
+  const tarball = cachedServerTarball(version, arch);
+  const pinned = (settings.serverChecksums || {})[release] || '';
+  const attempts = retryPolicy().retries + 1;
+  const url = `${baseUrl}/${serverFlavor().releaseTag(version)}/${release}.tar.gz`;
+  const slow = chaos('slow-download', `the download of ${release}`) ? `sleep ${CHAOS_DOWNLOAD_DELAY_SECS}` : '';
+  // Only the first attempt, so the retry on a checksum mismatch gets a good copy
+  const corrupt = chaos('corrupt-download', `the download of ${release}`)
+    ? `if [ "$attempt" = 1 ]; then truncate -s 1000000 ${tarball}.partial; fi` : '';
+  return `
+    mkdir -p ${SERVER_CACHE_DIR}
+    if [ ! -f ${tarball} ]; then
+      expected=${shellQuote(pinned.toLowerCase())}
+      if [ -z "$expected" ]; then
+        expected=$( { ${expectedChecksumCommand(baseUrl, settings.serverDownloadProxy, version, release)}; } 2>/dev/null | grep -o '[0-9a-f]\\{64\\}' | head -n 1) || true
+      fi
+      if [ -z "$expected" ]; then
+        if [ ${settings.requireServerChecksum ? 1 : 0} = 1 ]; then
+          echo "server-checksum-failed: release=${release} expected= actual=" >&2
+          exit ${CHECKSUM_FAILED_EXIT}
+        fi
+        echo "No SHA-256 checksum found for ${release}; installing it unverified" >&2
+      fi
+      size=$( { ${fetcher.size(url)}; } 2>/dev/null) || true
+      attempt=1
+      while :; do
+        have=0
+        if [ -f ${tarball}.partial ]; then have=$(wc -c < ${tarball}.partial | tr -d ' '); fi
+        if [ -n "$size" ] && [ "$have" -gt "$size" ]; then
+          rm -f ${tarball}.partial
+          have=0
+        fi
+        if [ -n "$size" ] && [ "$have" = "$size" ]; then
+          echo "${release} is already downloaded"
+        else
+          if [ "$have" -gt 0 ]; then echo "Resuming ${release} at $have of \${size:-?} bytes"; fi
+          ${slow}
+          ${fetcher.download(url, `${tarball}.partial`).trim()}
+          if [ "$rc" != 0 ]; then
+            # Keep what arrived for the next attempt or run to resume, unless the source refused outright
+            if [ "$rc" = ${CURL_HTTP_ERROR} ] || [ "$attempt" -ge ${attempts} ]; then
+              if [ "$rc" = ${CURL_HTTP_ERROR} ]; then rm -f ${tarball}.partial; fi
+              echo "server-download-failed: release=${release} scheme=${urlScheme(baseUrl)} http=$status rc=$rc" >&2
+              exit ${DOWNLOAD_FAILED_EXIT}
+            fi
+            echo "Download of ${release} was interrupted ($rc) at $(wc -c < ${tarball}.partial 2>/dev/null || echo 0) bytes; resuming (attempt $attempt/${attempts})" >&2
+            attempt=$((attempt + 1))
+            sleep $attempt
+            continue
+          fi
+        fi
+        ${corrupt}
+        # A short file can pass as complete when the connection closed cleanly; start it over
+        if [ -n "$size" ] && [ "$(wc -c < ${tarball}.partial | tr -d ' ')" != "$size" ]; then
+          rm -f ${tarball}.partial
+          if [ "$attempt" -ge ${attempts} ]; then
+            echo "server-download-failed: release=${release} scheme=${urlScheme(baseUrl)} http=$status rc=${CURL_PARTIAL_FILE}" >&2
+            exit ${DOWNLOAD_FAILED_EXIT}
+          fi
+          echo "${release} is not $size bytes long; downloading it again (attempt $attempt/${attempts})" >&2
+          attempt=$((attempt + 1))
+          continue
+        fi
+        [ -z "$expected" ] && break
+        actual=$({ sha256sum ${tarball}.partial 2>/dev/null || shasum -a 256 ${tarball}.partial; } | cut -d ' ' -f 1)
+        [ "$actual" = "$expected" ] && break
+        rm -f ${tarball}.partial
+        if [ "$attempt" -ge ${attempts} ]; then
+          echo "server-checksum-failed: release=${release} expected=$expected actual=$actual" >&2
+          exit ${CHECKSUM_FAILED_EXIT}
+        fi
+        echo "SHA-256 of ${release} is $actual, expected $expected; downloading it again (attempt $attempt/${attempts})" >&2
+        attempt=$((attempt + 1))
+      done
+      [ -n "$expected" ] && echo "Verified SHA-256 of ${release}"
+      mv ${tarball}.partial ${tarball}
+    fi
+  `;
+}
+
+/// How the server tarball gets onto a host, from the `serverTransfer` setting:
+/// the host downloads it, this machine downloads it and uploads it, or the
+/// host tries first and this machine takes over when the host cannot reach
+/// the download server
+export type ServerTransfer = 'download' | 'upload' | 'auto';
+
+export function serverTransfer(): ServerTransfer {
+  const transfer = loadSettings().serverTransfer || 'download';
+  if (transfer !== 'download' && transfer !== 'upload' && transfer !== 'auto') {
+    throw new Error(`Unknown serverTransfer '${transfer}': use download, upload or auto`);
+  }
+  return transfer;
+}
+
+/// Put the tarball for `version` and `arch` into the remote cache on
+/// `hostname` without the host touching the network: download it into the
+/// local cache with the same commands (mirror, proxy and checksum included)
+/// the host would run, then upload it over the SSH connection. The upload goes
+/// to a temporary name and is moved into place once the host has the same
+/// SHA-256 as the local copy.
+export async function uploadServerTarball(hostname: string, version: string, arch: string, report: (line: string) => void = () => {}): Promise<void> {
+  const release = serverReleaseName(version, arch);
+  const tarball = cachedServerTarball(version, arch);
+  const cached = await sshManager.executeCommand(hostname, `test -f ${tarball} && echo cached || true`);
+  if (cached.trim() === 'cached') {
+    return;
+  }
+
+  const localPath = localServerTarball(version, arch);
+  if (!fs.existsSync(localPath)) {
+    report(`Downloading ${release} on this machine...`);
+    await runLocally(`set -e\n${fetchServerTarballCommand(version, arch)}`, release);
+  }
+
+  const checksum = await fileChecksum(localPath);
+  report(`Uploading ${release} to ${hostname}...`);
+  await sshManager.executeCommand(hostname, `mkdir -p ${SERVER_CACHE_DIR}`);
+  await sshManager.uploadFile(hostname, localPath, `${tarball}.partial`);
+  const verify = await sshManager.executeCommand(hostname, `
+    actual=$({ sha256sum ${tarball}.partial 2>/dev/null || shasum -a 256 ${tarball}.partial; } | cut -d ' ' -f 1)
+    if [ "$actual" = ${checksum} ]; then mv ${tarball}.partial ${tarball}; echo ok; else rm -f ${tarball}.partial; echo "$actual"; fi
+  `);
+  if (verify.trim() !== 'ok') {
+    throw new Error(`Uploading ${release} to ${hostname} failed: its SHA-256 there is ${verify.trim() || 'unknown'}, expected ${checksum}`);
+  }
+  console.log(`✓ Uploaded ${release} to ${hostname}`);
+}
+
+// Run a download script on this machine, with output like the remote one's and the same failure markers
+function runLocally(script: string, release: string): Promise<void> {
+  return new Promise((resolve, reject) => {
+    const shell = spawn('sh', ['-c', script], { stdio: ['ignore', 'pipe', 'pipe'] });
+    let stderr = '';
+    forEachLine(shell.stdout, line => console.log(`${logTimestampPrefix()}[${release} local stdout] ${line}`));
+    forEachLine(shell.stderr, (line) => {
+      stderr += `${line}\n`;
+      console.log(`${logTimestampPrefix()}[${release} local stderr] ${line}`);
+    });
+    shell.on('error', reject);
+    shell.on('close', (code) => {
+      if (code === 0) {
+        resolve();
+      } else {
+        reject(asServerDownloadError(new Error(`Downloading ${release} on this machine failed (${code}): ${stderr}`)));
+      }
+    });
+  });
+}
+
+function fileChecksum(file: string): Promise<string> {
+  return new Promise((resolve, reject) => {
+    const hash = createHash('sha256');
+    fs.createReadStream(file)
+      .on('data', chunk => hash.update(chunk))
+      .on('error', reject)
+      .on('end', () => resolve(hash.digest('hex')));
+  });
+}
+
+/// Some downloads of a `--prime-cache` run failed
+export class PrimeCacheError extends Error {
+  failures: Error[];
+
+  constructor(failures: Error[], total: number) {
+    super(`${failures.length} of ${total} downloads failed:\n  ${failures.map(failure => failure.message).join('\n  ')}`);
+    this.name = 'PrimeCacheError';
+    this.failures = failures;
+  }
+
+  /// The first download that was refused or blocked, if any
+  get blocked(): ServerDownloadError | undefined {
+    return this.failures.find(failure => failure instanceof ServerDownloadError && failure.blocked) as ServerDownloadError | undefined;
+  }
+}
+
+/// Download every combination of `versions` and `arches` into the remote cache
+/// on `hostname`, in parallel, so later installs need no network access.
+/// The `serverTransfer` setting applies, so hosts that cannot download at all
+/// can be primed through this machine.
+///
+/// Every download is attempted; the error lists all combinations that failed,
+/// and carries guidance if any of them were refused or blocked.
+export async function primeServerCache(hostname: string, arches: string[], versions: string[]): Promise<void> {
+  const jobs = versions.flatMap(version => arches.map(arch => ({ version, arch })));
+  const transfer = serverTransfer();
+
+  const results = await Promise.allSettled(jobs.map(async ({ version, arch }) => {
+    const release = serverReleaseName(version, arch);
+    const upload = () => uploadServerTarball(hostname, version, arch, line => console.log(`${logTimestampPrefix()}[${release}] ${line}`));
+    if (transfer === 'upload') {
+      await upload();
+      console.log(`✓ Cached ${release}`);
+      return;
+    }
+    try {
+      await sshManager.executeLineStreamingCommand(hostname, `set -e\n${fetchServerTarballCommand(version, arch)}`, (source, line) => {
+        console.log(`${logTimestampPrefix()}[${release} ${source}] ${line}`);
+      });
+    } catch (error) {
+      const download = asServerDownloadError(error);
+      if (transfer !== 'auto' || !(download instanceof ServerDownloadError && download.blocked)) {
+        throw download;
+      }
+      console.warn(`${hostname} cannot download ${release} (${download.message}); uploading it from this machine instead`);
+      await upload();
+    }
+    console.log(`✓ Cached ${release}`);
+  }));
+
+  const failed = results
+    .map((result, index) => ({ result, release: serverReleaseName(jobs[index].version, jobs[index].arch) }))
+    .filter(({ result }) => result.status === 'rejected')
+    .map(({ result }) => (result as PromiseRejectedResult).reason as Error);
+  if (failed.length > 0) {
+    throw new PrimeCacheError(failed, jobs.length);
+  }
+}
@@ -0,0 +1,172 @@
+import { loadSettings } from './settings.js';
+
+/// Where a server's state lives and what it opens, for building its command line
+export interface ServerLaunch {
+  // The `--port` value: a port, or a range to pick a free one from
+  port: string;
+  // Shared user data (settings, keybindings), where the flavor has such a thing
+  userDataDir: string;
+  // The taskspace's own server state
+  serverDataDir: string;
+  extensionsDir: string;
+  folder: string;
+  // The connection token file, unless tokens are off (see connectionTokensEnabled)
+  tokenFile?: string;
+  // The certificate and key to serve HTTPS with, with the `tls` setting on
+  tls?: { cert: string; key: string };
+  // Whether the server may shut itself down once nothing is connected to it
+  autoShutdown: boolean;
+  // The `serverBindAddress` setting, if set; otherwise each flavor has its default
+  bindAddress?: string;
+}
+
+/// Everything that differs between the VS Code servers the app can deploy:
+/// where releases come from, how the archive is laid out, and how the server
+/// is started. The install, cache, checksum and startup flow is the same for all.
+export interface ServerFlavor {
+  name: 'openvscode' | 'code-server';
+  // For messages, e.g. "openvscode-server"
+  displayName: string;
+  // Release installed without a `serverVersion` setting
+  defaultVersion: string;
+  // GitHub repository publishing the releases
+  githubRepo: string;
+  /// The release's tag, which is also its directory under the download URL
+  releaseTag(version: string): string;
+  /// The version a tag names, or null for tags that are not releases
+  versionFromTag(tag: string): string | null;
+  /// The tarball's base name, which is also the directory it unpacks to, e.g. `openvscode-server-v1.103.1-linux-x64`
+  releaseName(version: string, arch: string): string;
+  // Where it is installed, relative to BASE_DIR
+  installDir: string;
+  // Whether the server takes a connection token, and so runs with one by default
+  connectionTokens: boolean;
+  // Paths inside the install: the launcher, the bundled node binary, and product.json
+  binary: string;
+  nodeBinary: string;
+  productJson: string;
+  /// Command line flags for `launch`, after the binary
+  launchFlags(launch: ServerLaunch): string[];
+  /// The User directory (settings.json, keybindings.json, snippets) the server started with `launch` reads
+  userSettingsDir(launch: Pick<ServerLaunch, 'userDataDir' | 'serverDataDir'>): string;
+}
+
+const openvscode: ServerFlavor = {
+  name: 'openvscode',
+  displayName: 'openvscode-server',
+  defaultVersion: '1.103.1',
+  githubRepo: 'gitpod-io/openvscode-server',
+  releaseTag: version => `openvscode-server-v${version}`,
+  versionFromTag: tag => /^openvscode-server-v(\d+\.\d+\.\d+)$/.exec(tag)?.[1] || null,
+  releaseName: (version, arch) => `openvscode-server-v${version}-${arch}`,
+  installDir: 'openvscode-server',
+  connectionTokens: true,
+  binary: 'bin/openvscode-server',
+  nodeBinary: 'node',
+  productJson: 'product.json',
+  launchFlags: (launch) => {
+    if (launch.tls) {
+      throw new Error('The tls setting needs code-server; openvscode-server cannot serve HTTPS. Turn tls off or set flavor to code-server.');
+    }
+    return [
+      `--host ${launch.bindAddress || '0.0.0.0'}`,
+      `--port ${launch.port}`,
+      `--user-data-dir ${launch.userDataDir}`,
+      `--server-data-dir ${launch.serverDataDir}`,
+      `--extensions-dir ${launch.extensionsDir}`,
+      launch.tokenFile ? `--connection-token-file ${launch.tokenFile}` : '--without-connection-token',
+      ...(launch.autoShutdown ? ['--enable-remote-auto-shutdown'] : []),
+      '--disable-workspace-trust',
+      `--default-folder ${launch.folder}`
+    ];
+  },
+  userSettingsDir: launch => `${launch.userDataDir}/User`
+};
+
+// code-server keeps everything, Machine settings included, in its user data
+// directory, so each taskspace gets its own (`<server data>/data`, where
+// openvscode-server keeps Machine settings too) and none is shared. It has no
+// connection tokens, so it listens on the host's loopback only; its own
+// password auth is off, as the server is only reached through the SSH tunnel.
+// With `tls` it serves HTTPS on all interfaces instead, for sharing.
+const codeServer: ServerFlavor = {
+  name: 'code-server',
+  displayName: 'code-server',
+  defaultVersion: 'latest',
+  githubRepo: 'coder/code-server',
+  releaseTag: version => `v${version}`,
+  versionFromTag: tag => /^v(\d+\.\d+\.\d+)$/.exec(tag)?.[1] || null,
+  releaseName: (version, arch) => `code-server-${version}-${CODE_SERVER_ARCHES[arch] || arch}`,
+  installDir: 'code-server',
+  connectionTokens: false,
+  binary: 'bin/code-server',
+  nodeBinary: 'lib/node',
+  productJson: 'lib/vscode/product.json',
+  launchFlags: (launch) => {
+    if (launch.tokenFile) {
+      throw new Error('The tokenProxy setting needs openvscode-server; code-server has no connection tokens. Unset tokenProxy or set flavor to openvscode.');
+    }
+    // code-server takes a single port, not a range: the range's first
+    const port = launch.port.split('-')[0];
+    const flags = [
+      '--auth none',
+      `--user-data-dir ${launch.serverDataDir}/data`,
+      `--extensions-dir ${launch.extensionsDir}`,
+      '--disable-telemetry',
+      '--disable-update-check',
+      '--disable-workspace-trust',
+      launch.folder
+    ];
+    if (launch.tls) {
+      return [
+        `--bind-addr ${bindAddr(launch.bindAddress || '0.0.0.0', port)}`,
+        `--cert ${launch.tls.cert}`,
+        `--cert-key ${launch.tls.key}`,
+        ...flags
+      ];
+    }
+    return [`--bind-addr ${bindAddr(launch.bindAddress || '127.0.0.1', port)}`, ...flags];
+  },
+  userSettingsDir: launch => `${launch.serverDataDir}/data/User`
+};
+
+// code-server's `--bind-addr`, with an IPv6 address in brackets
+function bindAddr(address: string, port: string): string {
+  return address.includes(':') ? `[${address}]:${port}` : `${address}:${port}`;
+}
+
+// code-server names architectures the Debian way, and macOS "macos"
+const CODE_SERVER_ARCHES: Record<string, string> = {
+  'linux-x64': 'linux-amd64',
+  'linux-arm64': 'linux-arm64',
+  'linux-armhf': 'linux-armv7l',
+  'darwin-x64': 'macos-amd64',
+  'darwin-arm64': 'macos-arm64'
+};
+
+/// Flavors by the name the `flavor` setting takes
+export const SERVER_FLAVORS: Record<string, ServerFlavor> = {
+  'openvscode': openvscode,
+  'code-server': codeServer
+};
+
+/// The flavor from the `flavor` setting (default: openvscode)
+export function serverFlavor(): ServerFlavor {
+  const name = loadSettings().flavor || 'openvscode';
+  const flavor = SERVER_FLAVORS[name];
+  if (!flavor) {
+    throw new Error(`Unknown flavor '${name}': use ${Object.keys(SERVER_FLAVORS).join(' or ')}`);
+  }
+  return flavor;
+}
+
+/// Whether servers start with a connection token: by default where the
+/// flavor has them, unless the `insecure` setting (`--insecure`) is on or
+/// `tokenProxy` is turned off
+export function connectionTokensEnabled(): boolean {
+  const settings = loadSettings();
+  if (settings.insecure || settings.tokenProxy === false) {
+    return false;
+  }
+  return settings.tokenProxy || serverFlavor().connectionTokens;
+}
@@ -0,0 +1,128 @@
+/// Running taskspace servers under theoldswitcheroo-serverd (`serverSupervisor`
+/// set to "serverd"), the small Rust daemon in server-daemon/, instead of
+/// directly in the SSH session's shell. The daemon starts each server in a
+/// process group of its own, restarts it when it fails, and keeps running
+/// when the SSH session that started it drops. The session only watches:
+/// `serverd follow` streams the server's log and exits with its status, so
+/// startup and exit are handled the same as for a server the shell runs.
+
+import { createHash } from 'crypto';
+import * as fs from 'fs';
+import * as path from 'path';
+import { fileURLToPath } from 'url';
+import { BASE_DIR, loadSettings } from './settings.js';
+import { sshManager, shellQuote } from './ssh-manager.js';
+
+const __dirname = path.dirname(fileURLToPath(import.meta.url));
+
+/// The uploaded binary, and the directory with its socket, PID files and serverd.log
+export const SERVERD_BINARY = `${BASE_DIR}/bin/theoldswitcheroo-serverd`;
+export const SERVERD_DIR = `${BASE_DIR}/serverd`;
+
+const SERVERD = `${SERVERD_BINARY} --socket ${SERVERD_DIR}/serverd.sock --state-dir ${SERVERD_DIR}`;
+
+// Rust targets for the server architectures; static musl builds run on any distribution
+const RUST_TARGETS: Record<string, string> = {
+  'linux-x64': 'x86_64-unknown-linux-musl',
+  'linux-arm64': 'aarch64-unknown-linux-musl',
+};
+
+// The port a supervised server bound first, next to its data, so a restart binds it again
+const PINNED_PORT_FILE = 'supervised.port';
+
+export function serverdEnabled(): boolean {
+  return loadSettings().serverSupervisor === 'serverd';
+}
+
+/// The local binary to upload for `arch`: `serverdBinary`, else the release
+/// build in the checkout's server-daemon/target
+export function localServerdBinary(arch: string): string {
+  const configured = loadSettings().serverdBinary;
+  if (configured) {
+    return configured;
+  }
+  const target = RUST_TARGETS[arch];
+  if (!target) {
+    throw new Error(`serverSupervisor "serverd" has no build for ${arch}; set serverSupervisor to "shell"`);
+  }
+  return path.resolve(__dirname, '..', '..', 'server-daemon', 'target', target, 'release', 'theoldswitcheroo-serverd');
+}
+
+function fileChecksum(file: string): string {
+  return createHash('sha256').update(fs.readFileSync(file)).digest('hex');
+}
+
+/// Upload the daemon for `arch` unless the host already has this build. A
+/// running daemon keeps its old binary until it is shut down; the new one
+/// goes in next to it and is renamed over it, which leaves that one alone.
+export async function installServerd(hostname: string, arch: string): Promise<void> {
+  const local = localServerdBinary(arch);
+  if (!fs.existsSync(local)) {
+    throw new Error(`theoldswitcheroo-serverd not found at ${local}. Build it first: ` +
+      `cd server-daemon && cargo build --release --target ${RUST_TARGETS[arch] || '<target>'}`);
+  }
+  const checksum = fileChecksum(local);
+  const remote = await sshManager.executeCommand(hostname, `sha256sum ${SERVERD_BINARY} 2>/dev/null | cut -d' ' -f1`);
+  if (remote.trim() === checksum) {
+    return;
+  }
+  await sshManager.executeCommand(hostname, `mkdir -p ${path.posix.dirname(SERVERD_BINARY)}`);
+  await sshManager.uploadFile(hostname, local, `${SERVERD_BINARY}.new`);
+  await sshManager.executeCommand(hostname, `chmod +x ${SERVERD_BINARY}.new && mv ${SERVERD_BINARY}.new ${SERVERD_BINARY}`);
+  console.log(`✓ Uploaded theoldswitcheroo-serverd to ${hostname}`);
+}
+
+/// Shell line setting SERVER_PORT, which the supervised server's `--port`
+/// uses: the port it bound before if the daemon is restarting it, so its
+/// forwards and proxy still reach it, else `portFlag`
+export function pinnedPortCommand(serverDataDir: string, portFlag: string): string {
+  return `SERVER_PORT=$(cat ${serverDataDir}/${PINNED_PORT_FILE} 2>/dev/null || echo ${portFlag})`;
+}
+
+/// Record the port a supervised server bound, for pinnedPortCommand
+export async function pinServerPort(hostname: string, serverDataDir: string, port: number): Promise<void> {
+  await sshManager.executeCommand(hostname, `echo ${port} > ${serverDataDir}/${PINNED_PORT_FILE}`);
+}
+
+/// Shell commands that have the daemon run `launchScript` as the server of
+/// taskspace `uuid`, starting the daemon first if need be, and then follow
+/// the server's output until it is done. The port is chosen afresh, as a
+/// start from the app means the old server is gone or unhealthy.
+export function supervisedServerCommand(uuid: string, serverDataDir: string, launchScript: string): string {
+  const launch = `${serverDataDir}/launch.sh`;
+  const log = `${serverDataDir}/supervised.log`;
+  return `
+    printf '%s\\n' ${shellQuote(launchScript)} > ${launch}
+    rm -f ${serverDataDir}/${PINNED_PORT_FILE}
+    : > ${log}
+    ${SERVERD} ensure || exit 1
+    ${SERVERD} start ${uuid} ${log} on-failure sh ${launch} > /dev/null || exit 1
+    exec ${SERVERD} follow ${uuid} ${log}
+  `;
+}
+
+// Succeeds when the daemon answers and has the server of `uuid` running or about to restart
+function supervisesCommand(uuid: string): string {
+  return `[ -S ${SERVERD_DIR}/serverd.sock ] && ${SERVERD} status ${uuid} 2>/dev/null | awk -F '\\t' '$3 == "running" || $3 == "backoff" { found = 1 } END { exit !found }'`;
+}
+
+/// Shell command that stops the server of taskspace `uuid` through the
+/// daemon, which would restart a server killed behind its back. It succeeds
+/// only if the daemon was running that server, so the caller can fall back
+/// to killing an unsupervised one.
+export function serverdStopCommand(uuid: string): string {
+  return `{ ${supervisesCommand(uuid)} && ${SERVERD} stop ${uuid} > /dev/null; }`;
+}
+
+/// Shell commands that shut the daemon down, with every server it runs, and
+/// print a line saying so. With `whenIdle`, only if it runs no server any
+/// more, as other sessions on the host may still use it. Nothing happens on
+/// a host without a running daemon, whatever `serverSupervisor` says now.
+export function serverdShutdownCommand(whenIdle: boolean): string {
+  const idle = `[ -z "$(${SERVERD} status 2>/dev/null | awk -F '\\t' '$3 == "running" || $3 == "backoff"')" ]`;
+  return `
+    if [ -S ${SERVERD_DIR}/serverd.sock ] && [ -x ${SERVERD_BINARY} ]${whenIdle ? ` && ${idle}` : ''}; then
+      if ${SERVERD} shutdown > /dev/null 2>&1; then echo "Shut down the server supervisor"; fi
+    fi
+  `;
+}
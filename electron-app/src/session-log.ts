@@ -0,0 +1,64 @@
+import * as fs from 'fs';
+import * as path from 'path';
+import { LOG_DIR } from './settings.js';
+
+// Rotate once a log grows past this size, keeping a few old generations
+const MAX_LOG_BYTES = 5 * 1024 * 1024;
+const MAX_ROTATED_LOGS = 3;
+
+/// Append-only log file for one taskspace's VSCode server output,
+/// stored at `LOG_DIR/<uuid>.log` so it survives crashes and lost scrollback.
+///
+/// When the file exceeds `MAX_LOG_BYTES` it is renamed to `<uuid>.log.1`
+/// (shifting older generations up to `.log.3`) and a fresh file is started.
+export class SessionLogFile {
+  readonly path: string;
+  private size: number;
+
+  constructor(uuid: string) {
+    this.path = path.join(LOG_DIR, `${uuid}.log`);
+    try {
+      fs.mkdirSync(LOG_DIR, { recursive: true });
+      this.size = fs.existsSync(this.path) ? fs.statSync(this.path).size : 0;
+    } catch (error) {
+      console.log(`Warning: Could not prepare log directory ${LOG_DIR}: ${error.message}`);
+      this.size = 0;
+    }
+  }
+
+  write(line: string): void {
+    const entry = `${new Date().toISOString()} ${line}\n`;
+    try {
+      if (this.size + entry.length > MAX_LOG_BYTES) {
+        this.rotate();
+      }
+      fs.appendFileSync(this.path, entry);
+      this.size += Buffer.byteLength(entry);
+    } catch (error) {
+      // Logging to disk is best effort; never let it take down the server stream
+    }
+  }
+
+  /// The last `count` lines of the current log generation, oldest first
+  tail(count: number): string[] {
+    try {
+      const lines = fs.readFileSync(this.path, 'utf8').split('\n').filter(line => line);
+      return lines.slice(-count);
+    } catch (error) {
+      return [];
+    }
+  }
+
+  private rotate(): void {
+    for (let generation = MAX_ROTATED_LOGS - 1; generation >= 1; generation--) {
+      const from = `${this.path}.${generation}`;
+      if (fs.existsSync(from)) {
+        fs.renameSync(from, `${this.path}.${generation + 1}`);
+      }
+    }
+    if (fs.existsSync(this.path)) {
+      fs.renameSync(this.path, `${this.path}.1`);
+    }
+    this.size = 0;
+  }
+}
@@ -0,0 +1,132 @@
+import * as fs from 'fs';
+import { sshManager } from './ssh-manager.js';
+import { t } from './i18n.js';
+import { TASKSPACES_FILE, loadSettings } from './settings.js';
+import { isLocalPortAccepting } from './port-forward.js';
+import { EnvironmentFingerprint, collectFingerprints, diffFingerprint, formatDrift, hasDrift } from './drift.js';
+
+/// One check of `--status`, with a line saying what was found
+export interface StatusCheck {
+  name: string;
+  ok: boolean;
+  detail: string;
+}
+
+/// Remote file the server's shell writes its PID to before exec'ing the server
+export function serverPidFile(serverDataDir: string): string {
+  return `${serverDataDir}/server.pid`;
+}
+
+/// Check the session saved in taskspaces.json: that the host answers over SSH,
+/// that each taskspace's server process is still running, and that its local
+/// port forward accepts connections, and whether the host drifted from the
+/// fingerprint recorded when the server was set up. Checks that depend on a
+/// failed one are reported as skipped rather than run.
+export async function checkSessionStatus(): Promise<{ hostname: string | null; checks: StatusCheck[] }> {
+  if (!fs.existsSync(TASKSPACES_FILE)) {
+    return { hostname: null, checks: [{ name: t('status-session'), ok: false, detail: t('status-session-missing', { file: TASKSPACES_FILE }) }] };
+  }
+
+  let data: any;
+  try {
+    data = JSON.parse(fs.readFileSync(TASKSPACES_FILE, 'utf8'));
+  } catch (error) {
+    return { hostname: null, checks: [{ name: t('status-session'), ok: false, detail: t('status-session-unreadable', { file: TASKSPACES_FILE, reason: error.message }) }] };
+  }
+
+  const hostname: string | null = data.hostname;
+  const taskspaces: any[] = data.taskspaces || [];
+  const checks: StatusCheck[] = [{
+    name: t('status-session'),
+    ok: !!hostname,
+    detail: hostname ? t('status-session-taskspaces', { count: taskspaces.length, host: hostname }) : t('status-session-no-host', { file: TASKSPACES_FILE })
+  }];
+  if (!hostname) {
+    return { hostname, checks };
+  }
+
+  const remoteUser = loadSettings().remoteUser;
+  if (remoteUser) {
+    sshManager.setRemoteUser(hostname, remoteUser);
+  }
+
+  let sshOk = false;
+  try {
+    await sshManager.executeCommand(hostname, 'true');
+    sshOk = true;
+    checks.push({ name: t('status-ssh'), ok: true, detail: t('status-ssh-connected', { host: hostname }) });
+  } catch (error) {
+    checks.push({ name: t('status-ssh'), ok: false, detail: error.message });
+  }
+
+  // One round trip for every taskspace: `<uuid> <pid or -> <running|stopped|no-pid>`
+  const serverStates = new Map<string, string>();
+  if (sshOk && taskspaces.length > 0) {
+    const script = taskspaces.map(taskspace => `
+      pidfile=${serverPidFile(taskspace.serverDataDir)}
+      if [ ! -f $pidfile ]; then echo "${taskspace.uuid} - no-pid"
+      elif kill -0 "$(cat $pidfile)" 2>/dev/null; then echo "${taskspace.uuid} $(cat $pidfile) running"
+      else echo "${taskspace.uuid} $(cat $pidfile) stopped"; fi`).join('');
+    try {
+      for (const line of (await sshManager.executeCommand(hostname, script)).split('\n')) {
+        const [uuid, pid, state] = line.trim().split(' ');
+        if (uuid) {
+          serverStates.set(uuid, `${state} ${pid}`);
+        }
+      }
+    } catch (error) {
+      checks.push({ name: t('status-servers'), ok: false, detail: error.message });
+    }
+  }
+
+  // One more round trip for the current fingerprints, to compare with the recorded ones
+  let fingerprints = new Map<string, EnvironmentFingerprint>();
+  const fingerprinted = taskspaces.filter(taskspace => taskspace.fingerprint);
+  if (sshOk && fingerprinted.length > 0) {
+    try {
+      fingerprints = await collectFingerprints(hostname, fingerprinted.map(taskspace => ({ uuid: taskspace.uuid, serverDataDir: taskspace.serverDataDir })));
+    } catch (error) {
+      checks.push({ name: t('status-drift-check'), ok: false, detail: error.message });
+    }
+  }
+
+  for (const taskspace of taskspaces) {
+    const [state, pid] = (serverStates.get(taskspace.uuid) || '').split(' ');
+    checks.push({
+      name: t('status-server', { taskspace: taskspace.name }),
+      ok: state === 'running',
+      detail: !sshOk ? t('status-skipped')
+        : state === 'running' ? t('status-server-running', { pid })
+        : state === 'stopped' ? t('status-server-stopped', { pid })
+        : state === 'no-pid' ? t('status-server-no-pid')
+        : t('status-unknown')
+    });
+
+    const localPort = taskspace.proxyPort || taskspace.localPort || taskspace.port;
+    const accepting = await isLocalPortAccepting(localPort);
+    checks.push({
+      name: t('status-forward', { taskspace: taskspace.name }),
+      ok: accepting,
+      detail: t(accepting ? 'status-forward-accepting' : 'status-forward-refused', { port: localPort })
+    });
+
+    const current = fingerprints.get(taskspace.uuid);
+    const drift = current && diffFingerprint(taskspace.fingerprint, current);
+    checks.push({
+      name: t('status-drift', { taskspace: taskspace.name }),
+      ok: !drift || !hasDrift(drift),
+      detail: !taskspace.fingerprint ? t('status-drift-no-fingerprint')
+        : !sshOk ? t('status-skipped')
+        : !drift ? t('status-unknown')
+        : hasDrift(drift) ? t('status-drift-changed', { changes: formatDrift(drift).join('; '), time: taskspace.fingerprint.recordedAt })
+        : t('status-drift-none', { time: taskspace.fingerprint.recordedAt })
+    });
+  }
+
+  return { hostname, checks };
+}
+
+/// Human-readable form of the checks, one per line
+export function formatStatus(checks: StatusCheck[]): string {
+  return checks.map(check => `${check.ok ? '✓' : '✗'} ${check.name}: ${check.detail}`).join('\n');
+}
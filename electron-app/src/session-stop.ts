@@ -0,0 +1,99 @@
+import * as fs from 'fs';
+import * as path from 'path';
+import { sshManager, shellQuote } from './ssh-manager.js';
+import { DEFAULT_SESSION, REMOTE_DAEMON_SOCKET, SESSION_NAME, TASKSPACES_FILE, loadSettings } from './settings.js';
+import { serverPidFile } from './session-status.js';
+import { cancelDeadManCommand } from './dead-man.js';
+import { serverdShutdownCommand, serverdStopCommand } from './serverd.js';
+
+// Kills a process and all its descendants, and prints how many there were.
+// sh has no local variables, so each child is printed before recursing into it.
+const KILL_TREE = `
+  descendants() {
+    for child in $(ps -o pid= --ppid "$1" 2>/dev/null); do
+      echo "$child"
+      descendants "$child"
+    done
+  }
+  kill_tree() {
+    pids="$(descendants "$1") $1"
+    kill -TERM $pids 2>/dev/null
+    sleep 1
+    kill -KILL $pids 2>/dev/null
+    echo $pids | wc -w
+  }
+`;
+
+/// Shell script that kills each of `taskspaces`' servers with its children
+/// and the session's daemon, printing a line for each thing it did. Servers
+/// that theoldswitcheroo-serverd runs are stopped through it, and it is shut
+/// down once it runs none any more.
+/// `taskspaces` are as saved in taskspaces.json (a uuid, name and `serverDataDir`).
+export function stopSessionScript(taskspaces: { uuid?: string; name: string; serverDataDir: string }[]): string {
+  return KILL_TREE + taskspaces.map(t => `
+    pidfile=${serverPidFile(t.serverDataDir)}
+    name=${shellQuote(t.name)}
+    if ${t.uuid ? serverdStopCommand(t.uuid) : 'false'}; then
+      echo "Stopped the supervised server of $name"
+    elif [ -f $pidfile ] && kill -0 "$(cat $pidfile)" 2>/dev/null; then
+      echo "Stopped the server of $name (PID $(cat $pidfile), $(( $(kill_tree "$(cat $pidfile)") - 1 )) child processes)"
+    else
+      echo "The server of $name was not running"
+    fi
+    rm -f $pidfile`).join('') + `
+    daemons=$(pgrep -u "$(id -u)" -f "daemon-bundled.cjs --socket-path ${REMOTE_DAEMON_SOCKET.replace(/^~/, '$HOME')}\$")
+    if [ -n "$daemons" ]; then
+      for pid in $daemons; do kill_tree $pid >/dev/null; done
+      echo "Stopped the daemon (PID $(echo $daemons))"
+    fi
+    rm -f ${REMOTE_DAEMON_SOCKET}
+    ${serverdShutdownCommand(true)}
+  `;
+}
+
+/// stopSessionScript, then everything else a session leaves on the host:
+/// its taskspaces' files (`dirs`), its own directory under sessions/ (the
+/// default session has none of its own) and its dead-man timer. For
+/// ephemeral sessions, which must not outlive their time to live.
+export function teardownSessionScript(taskspaces: { uuid?: string; name: string; serverDataDir: string }[], dirs: string[]): string {
+  const sessionDir = SESSION_NAME === DEFAULT_SESSION ? '' : path.posix.dirname(REMOTE_DAEMON_SOCKET);
+  return stopSessionScript(taskspaces) + `
+    ${cancelDeadManCommand()}
+    rm -rf ${dirs.join(' ')} ${sessionDir}
+    echo "Deleted the files of ${taskspaces.length} taskspaces"
+  `;
+}
+
+/// Tear down the session saved in taskspaces.json after the app died without
+/// shutting down: kill each taskspace's server with its children and the
+/// session's daemon, cancel its dead-man timer, then remove taskspaces.json. Other sessions on the same
+/// host are left alone. Resolves with one line per thing done.
+export async function stopSession(): Promise<string[]> {
+  if (!fs.existsSync(TASKSPACES_FILE)) {
+    throw new Error(`No saved session at ${TASKSPACES_FILE}`);
+  }
+  const data = JSON.parse(fs.readFileSync(TASKSPACES_FILE, 'utf8'));
+  const hostname: string | null = data.hostname;
+  if (!hostname) {
+    throw new Error(`${TASKSPACES_FILE} names no host`);
+  }
+
+  const remoteUser = loadSettings().remoteUser;
+  if (remoteUser) {
+    sshManager.setRemoteUser(hostname, remoteUser);
+  }
+
+  // One round trip; each line of output is already a report line
+  const script = stopSessionScript(data.taskspaces || []) + `
+    ${cancelDeadManCommand()}
+  `;
+
+  const report = (await sshManager.executeCommand(hostname, script))
+    .split('\n')
+    .map(line => line.trim())
+    .filter(line => line);
+
+  fs.unlinkSync(TASKSPACES_FILE);
+  report.push(`Removed ${TASKSPACES_FILE}`);
+  return report.map(line => `${hostname}: ${line}`);
+}
@@ -0,0 +1,67 @@
+import * as fs from 'fs';
+import * as path from 'path';
+import { DEFAULT_SESSION, SESSIONS_DIR, isValidSessionName, sessionDir } from './settings.js';
+import { controlSocketPath, sendControlCommand } from './control-socket.js';
+import { t } from './i18n.js';
+
+/// One session as shown by `--sessions`
+export interface SessionSummary {
+  name: string;
+  hostname: string | null;
+  taskspaces: number;
+  // PID of the app running the session, or null if none is
+  pid: number | null;
+  detached: boolean;
+}
+
+/// Every session with saved state: the default one and each directory under
+/// `SESSIONS_DIR`, with whether an app is running it right now.
+export async function listSessions(): Promise<SessionSummary[]> {
+  const names = [DEFAULT_SESSION];
+  if (fs.existsSync(SESSIONS_DIR)) {
+    names.push(...fs.readdirSync(SESSIONS_DIR).filter(name => isValidSessionName(name)).sort());
+  }
+
+  const sessions: SessionSummary[] = [];
+  for (const name of names) {
+    const dir = sessionDir(name);
+    let data: any = {};
+    try {
+      data = JSON.parse(fs.readFileSync(path.join(dir, 'taskspaces.json'), 'utf8'));
+    } catch {
+      // Not started yet, or stopped with --stop
+    }
+
+    let running: any = null;
+    try {
+      running = await sendControlCommand('status', {}, controlSocketPath(dir));
+    } catch {
+      // Something answered, but not a running app; treat it as not running
+    }
+
+    // The default session is listed only if it has ever been used
+    if (name === DEFAULT_SESSION && !data.hostname && !running) {
+      continue;
+    }
+    sessions.push({
+      name,
+      hostname: data.hostname || running?.hostname || null,
+      taskspaces: (data.taskspaces || []).length,
+      pid: running?.pid ?? null,
+      detached: !!running?.detached
+    });
+  }
+  return sessions;
+}
+
+/// Human-readable form of the sessions, one per line
+export function formatSessions(sessions: SessionSummary[]): string {
+  if (sessions.length === 0) {
+    return t('sessions-none');
+  }
+  return sessions.map(session => {
+    const state = session.pid === null ? t('sessions-stopped')
+      : t(session.detached ? 'sessions-running-detached' : 'sessions-running', { pid: session.pid });
+    return t('sessions-row', { session: session.name, host: session.hostname || t('sessions-no-host'), count: session.taskspaces, state });
+  }).join('\n');
+}
@@ -0,0 +1,140 @@
+/// Bringing this machine's VS Code user settings, keybindings and snippets
+/// to a server's User directory (`--sync-settings`), merged with what the
+/// server already has rather than replacing it.
+
+import * as fs from 'fs';
+import * as os from 'os';
+import * as path from 'path';
+import { sshManager, shellQuote } from './ssh-manager.js';
+import { loadSettings } from './settings.js';
+
+/// The local VS Code User directory: the `localVscodeUserDir` setting, else
+/// where VS Code keeps it on this platform
+export function localVSCodeUserDir(): string {
+  const configured = loadSettings().localVscodeUserDir;
+  if (configured) {
+    return path.resolve(configured.replace(/^~(?=$|\/)/, os.homedir()));
+  }
+  switch (process.platform) {
+    case 'darwin':
+      return path.join(os.homedir(), 'Library', 'Application Support', 'Code', 'User');
+    case 'win32':
+      return path.join(process.env.APPDATA || path.join(os.homedir(), 'AppData', 'Roaming'), 'Code', 'User');
+    default:
+      return path.join(process.env.XDG_CONFIG_HOME || path.join(os.homedir(), '.config'), 'Code', 'User');
+  }
+}
+
+/// Parse VS Code's JSON with comments and trailing commas. An empty file is `fallback`.
+export function parseJsonc(text: string, fallback: any): any {
+  let json = '';
+  for (let i = 0; i < text.length; i++) {
+    const c = text[i];
+    if (c === '"') {
+      const start = i;
+      for (i++; i < text.length && text[i] !== '"'; i++) {
+        if (text[i] === '\\') {
+          i++;
+        }
+      }
+      json += text.slice(start, i + 1);
+    } else if (c === '/' && text[i + 1] === '/') {
+      while (i < text.length && text[i] !== '\n') {
+        i++;
+      }
+      json += '\n';
+    } else if (c === '/' && text[i + 1] === '*') {
+      const end = text.indexOf('*/', i + 2);
+      i = end === -1 ? text.length : end + 1;
+    } else {
+      json += c;
+    }
+  }
+  // Drop trailing commas, but not a `,}` inside a string
+  json = json.replace(/,(\s*[}\]])/g, (match, closing, offset) => insideString(json, offset) ? match : closing);
+  return json.trim() ? JSON.parse(json) : fallback;
+}
+
+// Whether `offset` in `json` falls inside a string literal
+function insideString(json: string, offset: number): boolean {
+  let inside = false;
+  for (let i = 0; i < offset; i++) {
+    if (json[i] === '\\' && inside) {
+      i++;
+    } else if (json[i] === '"') {
+      inside = !inside;
+    }
+  }
+  return inside;
+}
+
+/// The settings the server ends up with: every local setting, except those
+/// VS Code's own sync is told to leave alone (`settingsSync.ignoredSettings`),
+/// plus every setting only the server has
+export function mergeUserSettings(local: Record<string, any>, remote: Record<string, any>): Record<string, any> {
+  const ignored: string[] = local['settingsSync.ignoredSettings'] || [];
+  const merged = { ...remote };
+  for (const [key, value] of Object.entries(local)) {
+    if (!ignored.includes(key)) {
+      merged[key] = value;
+    }
+  }
+  return merged;
+}
+
+/// The keybindings the server ends up with: the local ones, then those only
+/// the server has. A binding is the same if key, command and when are.
+export function mergeKeybindings(local: any[], remote: any[]): any[] {
+  const identity = (binding: any) => JSON.stringify([binding.key, binding.command, binding.when || '']);
+  const known = new Set(local.map(identity));
+  return [...local, ...remote.filter(binding => !known.has(identity(binding)))];
+}
+
+async function readRemote(hostname: string, file: string): Promise<string> {
+  return sshManager.executeCommand(hostname, `cat ${file} 2>/dev/null || true`);
+}
+
+async function writeRemote(hostname: string, file: string, value: any): Promise<void> {
+  await sshManager.executeCommand(hostname, `printf '%s\\n' ${shellQuote(JSON.stringify(value, null, 2))} > ${file}`);
+}
+
+/// Bring the local VS Code settings, keybindings and snippets into the
+/// server's User directory `remoteUserDir` on `hostname`, merging with what
+/// is already there (see mergeUserSettings and mergeKeybindings). Snippet
+/// files replace those of the same name; the server's others stay, and
+/// snippet files with names that would need shell quoting are left out. Resolves
+/// with what was synced, e.g. ["settings", "3 snippet files"].
+export async function syncUserSettings(hostname: string, remoteUserDir: string): Promise<string[]> {
+  const localDir = localVSCodeUserDir();
+  if (!fs.existsSync(localDir)) {
+    throw new Error(`No VS Code user directory at ${localDir}; set localVscodeUserDir`);
+  }
+  await sshManager.executeCommand(hostname, `mkdir -p ${remoteUserDir}/snippets`);
+  const synced: string[] = [];
+
+  const localSettings = path.join(localDir, 'settings.json');
+  if (fs.existsSync(localSettings)) {
+    const remote = parseJsonc(await readRemote(hostname, `${remoteUserDir}/settings.json`), {});
+    await writeRemote(hostname, `${remoteUserDir}/settings.json`, mergeUserSettings(parseJsonc(fs.readFileSync(localSettings, 'utf8'), {}), remote));
+    synced.push('settings');
+  }
+
+  const localKeybindings = path.join(localDir, 'keybindings.json');
+  if (fs.existsSync(localKeybindings)) {
+    const remote = parseJsonc(await readRemote(hostname, `${remoteUserDir}/keybindings.json`), []);
+    await writeRemote(hostname, `${remoteUserDir}/keybindings.json`, mergeKeybindings(parseJsonc(fs.readFileSync(localKeybindings, 'utf8'), []), remote));
+    synced.push('keybindings');
+  }
+
+  const snippetsDir = path.join(localDir, 'snippets');
+  const snippets = fs.existsSync(snippetsDir)
+    ? fs.readdirSync(snippetsDir).filter(name => /^[\w.@-]+\.(json|code-snippets)$/.test(name))
+    : [];
+  for (const name of snippets) {
+    await sshManager.uploadFile(hostname, path.join(snippetsDir, name), `${remoteUserDir}/snippets/${name}`);
+  }
+  if (snippets.length > 0) {
+    synced.push(`${snippets.length} snippet file${snippets.length === 1 ? '' : 's'}`);
+  }
+  return synced;
+}
@@ -1,27 +1,652 @@
 import * as path from 'path';
 import * as os from 'os';
 import * as fs from 'fs';
+import { parseToml } from './toml.js';
+import type { NotificationEvent, NotificationSinkConfig } from './notifications.js';
+import type { SecurityAdvisory } from './security-audit.js';
 
 // Common constants
 export const LOCAL_DATA_DIR = path.join(os.homedir(), '.socratic-shell', 'theoldswitcheroo');
-export const TASKSPACES_FILE = path.join(LOCAL_DATA_DIR, 'taskspaces.json');
-export const SETTINGS_FILE = path.join(LOCAL_DATA_DIR, 'settings.json');
+export const SETTINGS_FILE = process.env.THEOLDSWITCHEROO_SETTINGS || path.join(LOCAL_DATA_DIR, 'settings.json');
+export const CONFIG_FILE = process.env.THEOLDSWITCHEROO_CONFIG
+  || path.join(process.env.XDG_CONFIG_HOME || path.join(os.homedir(), '.config'), 'theoldswitcheroo', 'config.toml');
 export const BASE_DIR = "~/.socratic-shell/theoldswitcheroo";
 
+/// The session used when no `--name` is given; it keeps the paths from before sessions had names
+export const DEFAULT_SESSION = 'default';
+export const SESSIONS_DIR = path.join(LOCAL_DATA_DIR, 'sessions');
+
+/// Session names end up in local and remote paths, so they are kept to a safe set of characters
+export function isValidSessionName(name: string): boolean {
+  return /^[A-Za-z0-9][A-Za-z0-9._-]*$/.test(name);
+}
+
+// The session this run belongs to, from `--name <session>` or THEOLDSWITCHEROO_SESSION.
+// Read at load time because every per-session path below depends on it.
+// A replay (`--replay`) gets a session of its own, so it leaves real ones alone.
+function sessionNameFromArgs(): string {
+  const index = process.argv.indexOf('--name');
+  const name = index !== -1 ? process.argv[index + 1] : process.env.THEOLDSWITCHEROO_SESSION;
+  if ((name === undefined || name === '') && process.argv.includes('--replay')) {
+    return 'replay';
+  }
+  if (name === undefined || name === '') {
+    return DEFAULT_SESSION;
+  }
+  if (!isValidSessionName(name)) {
+    console.error(`Invalid session name '${name}': use letters, digits, '.', '_' and '-'`);
+    process.exit(1);
+  }
+  return name;
+}
+
+export const SESSION_NAME = sessionNameFromArgs();
+
+/// Local directory holding a session's state: taskspaces.json, the control socket and the detached log
+export function sessionDir(name: string): string {
+  return name === DEFAULT_SESSION ? LOCAL_DATA_DIR : path.join(SESSIONS_DIR, name);
+}
+
+/// Remote socket of a session's daemon; its progress history and registry live next to it
+export function remoteDaemonSocket(name: string): string {
+  return name === DEFAULT_SESSION ? `${BASE_DIR}/daemon.sock` : `${BASE_DIR}/sessions/${name}/daemon.sock`;
+}
+
+export const SESSION_DIR = sessionDir(SESSION_NAME);
+export const TASKSPACES_FILE = path.join(SESSION_DIR, 'taskspaces.json');
+export const REMOTE_DAEMON_SOCKET = remoteDaemonSocket(SESSION_NAME);
+export const LAST_GOOD_DIR = path.join(LOCAL_DATA_DIR, 'last-good');
+export const LOG_DIR = path.join(process.env.XDG_STATE_HOME || path.join(os.homedir(), '.local', 'state'), 'theoldswitcheroo', 'logs');
+
 export interface Settings {
   hostname?: string;
+  // Server architecture, e.g. "linux-arm64", instead of detecting it with `uname -m`
+  arch?: string;
+  // Git repository each new taskspace clones, instead of running the project's fresh-clone.sh
+  cloneRepo?: string;
+  // Branch for `cloneRepo` to check out, e.g. "develop" (default: the repository's default branch)
+  cloneBranch?: string;
+  // Intermediate hosts to reach the host through, as for `ssh -J`, e.g. "bastion" or "admin@gate:2222,bastion"
+  jumpHost?: string;
+  // Run installs, servers, and the daemon as this user (via `sudo -n -u`)
+  // instead of the SSH login user.
+  remoteUser?: string;
+  // Log in to each host with a keypair of the app's own, installed with the usual login on first connect (default: false)
+  managedKeys?: boolean;
+  // Replace a host's managed key once it is this many days old; 0 never does (default: 30)
+  managedKeyRotationDays?: number;
+  // Check that the host's name resolves and its SSH port answers before connecting (default: true)
+  reachabilityCheck?: boolean;
+  // How long the reachability check waits for DNS and for the port, in milliseconds (default: 3000)
+  reachabilityTimeoutMs?: number;
+  // What to tell the user when a host can't be reached, by host name or pattern with `*`, e.g. {"*.corp.example.com": "Connect to the corp VPN first"}
+  reachabilityHints?: Record<string, string>;
+  // Named groups of equivalent hosts. If `hostname` names a group, the
+  // first member that passes preflight is used.
+  hostGroups?: Record<string, string[]>;
+  // Timestamp prefix for streamed remote log lines (default: none)
+  logTimestamps?: 'utc' | 'local' | 'relative' | 'none';
+  // Format for `utc`/`local` timestamps, using YYYY MM DD HH mm ss SSS
+  logTimestampFormat?: string;
+  // Only stream server log lines at or above this level; the host keeps the full log
+  logLevel?: 'trace' | 'debug' | 'info' | 'warn' | 'error';
+  // Only stream server log lines matching this regular expression, e.g. "extension-host"
+  logFilter?: string;
+  // Stream only every Nth trace/debug/info line that passes the other filters (default: 1)
+  logSample?: number;
+  // Names of the readiness checks to run (default: all checks for the backend)
+  healthChecks?: string[];
+  // Per-check timeout for readiness checks, in milliseconds (default: 2000)
+  healthCheckTimeoutMs?: number;
+  // Export the local timezone and locale (TZ, LANG, LC_ALL) to the remote
+  // VSCode server so its terminals match this machine (default: false)
+  propagateLocale?: boolean;
+  // Language of the CLI's messages, e.g. "de" (default: from LC_ALL, LC_MESSAGES or LANG; English where there is no translation)
+  locale?: string;
+  // Print only timestamped plain lines, with words instead of status symbols and no terminal control sequences, e.g. for screen readers (default: false)
+  plain?: boolean;
+  // Never wait for a person, for CI: SSH in batch mode, no questions, and errors and warnings as JSON lines on stderr (default: false)
+  nonInteractive?: boolean;
+  // Chromium-family browser that `--open-app` opens taskspaces with, e.g. /usr/bin/chromium (default: the first of Chrome, Chromium, Edge and Brave installed)
+  appBrowser?: string;
+  // Default shell for the remote server's integrated terminals, e.g. /usr/bin/zsh
+  terminalShell?: string;
+  // Start `terminalShell` as a login shell so it reads the profile (default: true)
+  terminalLoginShell?: boolean;
+  // Put the remote CUDA toolkit on PATH/LD_LIBRARY_PATH for the server (default: false)
+  cudaEnv?: boolean;
+  // Value for CUDA_VISIBLE_DEVICES in the server and its terminals, e.g. "0,1"
+  cudaVisibleDevices?: string;
+  // Let `pbcopy`, `xclip`, `xsel` and `wl-copy` in the server's terminals copy to this machine's clipboard (default: false)
+  clipboardBridge?: boolean;
+  // Working directory for the VSCode server process (default: BASE_DIR)
+  serverWorkingDir?: string;
+  // umask for the VSCode server process, e.g. "027" (default: inherited)
+  serverUmask?: string;
+  // `ulimit` limits for the VSCode server process, keyed by ulimit flag, e.g. {"n": 65536}
+  serverUlimits?: Record<string, number | 'unlimited'>;
+  // What runs the taskspace servers on the host: "shell" (the SSH session's shell) or "serverd" (the
+  // theoldswitcheroo-serverd daemon built from server-daemon/, which restarts servers that fail) (default: shell)
+  serverSupervisor?: 'shell' | 'serverd';
+  // Local theoldswitcheroo-serverd binary to upload for `serverSupervisor` "serverd" (default: the release build in server-daemon/target for the host's architecture)
+  serverdBinary?: string;
+  // Start openvscode-server without its idle shutdown, so a server nothing is connected to, e.g. after detaching, keeps running (default: false)
+  persistServers?: boolean;
+  // How many extensions to install at once (default: 3)
+  extensionInstallConcurrency?: number;
+  // Marketplace extensions to install in every taskspace, e.g. ["rust-lang.rust-analyzer"], besides vscode-extensions.json's; `--extension <id>` may be repeated
+  extensions?: string[];
+  // Commands `--run <alias>` runs in a taskspace's clone, by alias, e.g. {"test": "cargo test", "lint": "cargo clippy"}
+  aliases?: Record<string, string>;
+  // Dotfiles repository to clone on the host and install before servers start, e.g. "octocat/dotfiles" (GitHub) or a git URL
+  dotfilesRepo?: string;
+  // Where on the host to clone `dotfilesRepo` (default: ~/dotfiles)
+  dotfilesTargetPath?: string;
+  // Command to install the dotfiles with, run in the clone (default: the repository's install.sh, bootstrap.sh, setup.sh or the like, else linking its dotfiles into ~)
+  dotfilesInstallCommand?: string;
+  // Copy this machine's VS Code settings.json, keybindings.json and snippets into each server's User directory when it starts, keeping settings and keybindings only the server has (default: false)
+  syncSettings?: boolean;
+  // This machine's VS Code User directory for `syncSettings` (default: where VS Code keeps it, e.g. ~/.config/Code/User)
+  localVscodeUserDir?: string;
+  // Local .vsix files (or directories of them) to upload and install in every taskspace
+  extensionFiles?: string[];
+  // Extension gallery written into the server's product.json, e.g. an internal marketplace mirror
+  extensionGallery?: {
+    serviceUrl: string;
+    itemUrl?: string;
+    resourceUrlTemplate?: string;
+  };
+  // VS Code server to deploy: "openvscode" (openvscode-server) or "code-server" (default: openvscode)
+  flavor?: 'openvscode' | 'code-server';
+  // Server release to install, e.g. "1.103.1", or "latest" for the newest one (default: the openvscode-server version this app was tested with; latest for code-server)
+  serverVersion?: string;
+  // Base URL to download server releases from instead of GitHub, laid out like the GitHub releases; may be s3:// or gs://
+  serverDownloadUrl?: string;
+  // Proxy for the remote host's server download, e.g. "http://proxy.corp:3128"
+  serverDownloadProxy?: string;
+  // Where to download Node.js for the taskspace agent instead of https://nodejs.org/dist, laid out the same way; may be s3:// or gs://
+  nodeDownloadUrl?: string;
+  // How the server tarball reaches the host: "download" (the host fetches it), "upload" (this machine fetches it and uploads it over SSH), or "auto" (upload when the host cannot download) (default: download)
+  serverTransfer?: 'download' | 'upload' | 'auto';
+  // Known SHA-256 of server tarballs by release name, e.g. {"openvscode-server-v1.103.1-linux-x64": "<hex>"}, checked instead of the published one
+  serverChecksums?: Record<string, string>;
+  // Refuse to install a server tarball that has no checksum to verify it against (default: false, install it with a warning)
+  requireServerChecksum?: boolean;
+  // Local directory for the server install when the remote home is on NFS (default: /var/tmp/theoldswitcheroo-<uid>)
+  nfsScratchDir?: string;
+  // First local port to forward taskspace servers from; each takes the next free one (default: the server's port)
+  localPort?: number;
+  // First port on the host for taskspace servers; each takes the next free one of the 100 from here (default: any free port)
+  remotePort?: number;
+  // Require a connection token on every server and reach it through a local proxy that adds it (default: true where the flavor has tokens)
+  tokenProxy?: boolean;
+  // Start servers without a connection token, so anyone who can reach the host's port gets the editor (default: false)
+  insecure?: boolean;
+  // Serve HTTPS on all interfaces with a self-signed certificate generated on the host, e.g. to share a taskspace on the LAN; code-server only (default: false)
+  tls?: boolean;
+  // Address the servers listen on, e.g. "127.0.0.1" for the host's loopback only (default: "0.0.0.0" for openvscode-server;
+  // for code-server "127.0.0.1", or "0.0.0.0" with `tls`)
+  serverBindAddress?: string;
+  // Bundle of defaults for the security settings: "personal", "shared-host" or "paranoid" (see SECURITY_PRESETS);
+  // settings given explicitly override the preset's (default: none)
+  securityPreset?: SecurityPresetName;
+  // Record security-relevant actions (server starts, shells, --run, --kill, mounts, clipboard copies) as JSON lines in audit.log in the session directory (default: false)
+  auditLog?: boolean;
+  // "native" to connect in-process with the ssh2 package where possible, once it is installed (`npm install ssh2`),
+  // instead of running the ssh binary (default: "subprocess")
+  sshTransport?: 'native' | 'subprocess';
+  // Seconds a remote step (command or upload) may take before it is stopped (default: 300)
+  remoteCommandTimeoutSecs?: number;
+  // How many times connecting, detecting the architecture and installing the server are
+  // retried after a network failure (default: 3)
+  retries?: number;
+  // Seconds before the first retry, doubling for each later one (default: 2)
+  retryDelay?: number;
+  // VSCode settings seeded into every taskspace's server-side (Machine) settings,
+  // e.g. {"security.workspace.trust.enabled": false, "telemetry.telemetryLevel": "off"}
+  remoteSettings?: Record<string, any>;
+  // Where each kind of event is announced, e.g.
+  // {"crash": ["desktop", {"type": "webhook", "url": "https://hooks.example/x"}], "ready": ["bell"]}
+  // (default: desktop notifications for crashes and signals)
+  notifications?: Partial<Record<NotificationEvent, NotificationSinkConfig[]>>;
+  // Hours without the app checking in after which the host stops the session's servers and daemon (default: never)
+  deadManHours?: number;
+  // Globs whose files a new taskspace opens in the background right after it is created, so its
+  // language servers have indexed the workspace by the first open, e.g. ["src/**/*.ts"] (default: none)
+  prewarmGlobs?: string[];
+  // Known vulnerabilities `--audit` checks installed servers against, e.g.
+  // [{"id": "CVE-2099-0001", "fixedIn": "1.99.0", "severity": "critical"}]
+  securityAdvisories?: SecurityAdvisory[];
+  // Jobs the host's maintenance agent runs on a cron schedule, even while the app is disconnected, e.g.
+  // [{"name": "logs", "schedule": "@daily", "task": "rotate-logs", "keepDays": 7}]
+  maintenanceJobs?: Array<{
+    name: string;
+    schedule: string;
+    task: 'rotate-logs' | 'prune-cache' | 'idle-check' | 'snapshot' | 'command';
+    keepDays?: number;
+    keep?: number;
+    idleMinutes?: number;
+    command?: string;
+    timeoutSecs?: number;
+  }>;
+}
+
+type SettingKind = 'string' | 'number' | 'boolean' | 'json';
+
+export type SecurityPresetName = 'personal' | 'shared-host' | 'paranoid';
+
+/// The defaults each `securityPreset` brings, beneath every other source of
+/// settings. "personal" is for a host only you use, "shared-host" for one
+/// other users log in to, and "paranoid" gives up convenience for exposure.
+/// None sets `tokenProxy`, as its default already turns connection tokens on
+/// wherever the flavor has them; all of them turn `insecure` off.
+export const SECURITY_PRESETS: Record<SecurityPresetName, Settings> = {
+  'personal': {
+    insecure: false,
+    // Servers outlive a detach; only the app or --stop ends them
+    persistServers: true,
+    auditLog: false,
+  },
+  'shared-host': {
+    insecure: false,
+    serverBindAddress: '127.0.0.1',
+    serverUmask: '027',
+    persistServers: false,
+    deadManHours: 24,
+    auditLog: true,
+    clipboardBridge: false,
+  },
+  'paranoid': {
+    insecure: false,
+    tls: false,
+    serverBindAddress: '127.0.0.1',
+    serverUmask: '077',
+    serverUlimits: { c: 0 },
+    persistServers: false,
+    deadManHours: 8,
+    auditLog: true,
+    clipboardBridge: false,
+    managedKeys: true,
+    requireServerChecksum: true,
+  },
+};
+
+// Presets already warned about, so a typo is reported once rather than on every loadSettings()
+const warnedPresets = new Set<string>();
+
+function securityPresetDefaults(name: string | undefined): Settings {
+  if (!name) {
+    return {};
+  }
+  if (!Object.prototype.hasOwnProperty.call(SECURITY_PRESETS, name)) {
+    if (!warnedPresets.has(name)) {
+      warnedPresets.add(name);
+      console.log(`Warning: Ignoring unknown securityPreset '${name}' (presets: ${Object.keys(SECURITY_PRESETS).join(', ')})`);
+    }
+    return {};
+  }
+  return SECURITY_PRESETS[name];
+}
+
+// How each setting is parsed from its THEOLDSWITCHEROO_* environment variable.
+// Keyed by every field of Settings, so a new setting can't be added without one.
+const SETTING_KINDS: { [K in keyof Settings]-?: SettingKind } = {
+  hostname: 'string',
+  arch: 'string',
+  cloneRepo: 'string',
+  cloneBranch: 'string',
+  jumpHost: 'string',
+  managedKeys: 'boolean',
+  managedKeyRotationDays: 'number',
+  reachabilityCheck: 'boolean',
+  reachabilityTimeoutMs: 'number',
+  reachabilityHints: 'json',
+  remoteUser: 'string',
+  hostGroups: 'json',
+  logTimestamps: 'string',
+  logTimestampFormat: 'string',
+  logLevel: 'string',
+  logFilter: 'string',
+  logSample: 'number',
+  healthChecks: 'json',
+  healthCheckTimeoutMs: 'number',
+  propagateLocale: 'boolean',
+  locale: 'string',
+  plain: 'boolean',
+  nonInteractive: 'boolean',
+  appBrowser: 'string',
+  terminalShell: 'string',
+  terminalLoginShell: 'boolean',
+  cudaEnv: 'boolean',
+  cudaVisibleDevices: 'string',
+  clipboardBridge: 'boolean',
+  serverWorkingDir: 'string',
+  serverUmask: 'string',
+  serverUlimits: 'json',
+  serverSupervisor: 'string',
+  serverdBinary: 'string',
+  persistServers: 'boolean',
+  extensionInstallConcurrency: 'number',
+  extensions: 'json',
+  aliases: 'json',
+  dotfilesRepo: 'string',
+  dotfilesTargetPath: 'string',
+  dotfilesInstallCommand: 'string',
+  syncSettings: 'boolean',
+  localVscodeUserDir: 'string',
+  extensionFiles: 'json',
+  extensionGallery: 'json',
+  flavor: 'string',
+  serverVersion: 'string',
+  serverDownloadUrl: 'string',
+  serverDownloadProxy: 'string',
+  nodeDownloadUrl: 'string',
+  serverTransfer: 'string',
+  serverChecksums: 'json',
+  requireServerChecksum: 'boolean',
+  nfsScratchDir: 'string',
+  localPort: 'number',
+  remotePort: 'number',
+  tokenProxy: 'boolean',
+  insecure: 'boolean',
+  tls: 'boolean',
+  serverBindAddress: 'string',
+  securityPreset: 'string',
+  auditLog: 'boolean',
+  sshTransport: 'string',
+  remoteCommandTimeoutSecs: 'number',
+  retries: 'number',
+  retryDelay: 'number',
+  remoteSettings: 'json',
+  notifications: 'json',
+  maintenanceJobs: 'json',
+  deadManHours: 'number',
+  prewarmGlobs: 'json',
+  securityAdvisories: 'json',
+};
+
+/// Environment variable that overrides `key`, e.g. `remoteUser` -> `THEOLDSWITCHEROO_REMOTE_USER`
+export function settingEnvVar(key: string): string {
+  return `THEOLDSWITCHEROO_${key.replace(/([A-Z])/g, '_$1').toUpperCase()}`;
+}
+
+function parseSettingValue(kind: SettingKind, raw: string): any {
+  switch (kind) {
+    case 'number': {
+      const value = Number(raw);
+      if (raw.trim() === '' || Number.isNaN(value)) {
+        throw new Error(`${JSON.stringify(raw)} is not a number`);
+      }
+      return value;
+    }
+    case 'boolean':
+      return ['1', 'true', 'yes', 'on'].includes(raw.toLowerCase());
+    case 'json':
+      return JSON.parse(raw);
+    default:
+      return raw;
+  }
+}
+
+// Settings from a file as `parse` read it, read again only once the file
+// changes, so a broken file is warned about once rather than on every loadSettings()
+const parsedFiles = new Map<string, { stamp: string; contents: any }>();
+
+function readSettingsFile<T>(file: string, parse: () => T): T {
+  let stamp = 'missing';
+  try {
+    const stat = fs.statSync(file);
+    stamp = `${stat.mtimeMs} ${stat.size}`;
+  } catch {
+    // Not there (yet); parsed again once it is
+  }
+  const cached = parsedFiles.get(file);
+  if (cached && cached.stamp === stamp) {
+    return cached.contents;
+  }
+  const contents = parse();
+  parsedFiles.set(file, { stamp, contents });
+  return contents;
+}
+
+// Collect settings given through THEOLDSWITCHEROO_* environment variables
+function loadSettingsFromEnv(): Settings {
+  const settings: Settings = {};
+  for (const [key, kind] of Object.entries(SETTING_KINDS)) {
+    const envVar = settingEnvVar(key);
+    const raw = process.env[envVar];
+    if (raw === undefined || raw === '') {
+      continue;
+    }
+    try {
+      settings[key] = parseSettingValue(kind, raw);
+    } catch (error) {
+      console.log(`Warning: Ignoring ${envVar}: ${error.message}`);
+    }
+  }
+  return settings;
+}
+
+/// Command line flag that overrides `key`, e.g. `serverVersion` -> `--server-version`
+export function settingFlag(key: string): string {
+  return `--${key.replace(/([A-Z])/g, '-$1').toLowerCase()}`;
+}
+
+// Collect settings given as command line flags, e.g. `--hostname build2 --token-proxy`.
+// A boolean flag without a value means true.
+function loadSettingsFromArgs(): Settings {
+  const settings: Settings = {};
+  const args = process.argv;
+  for (const [key, kind] of Object.entries(SETTING_KINDS)) {
+    const index = args.indexOf(settingFlag(key));
+    if (index === -1) {
+      continue;
+    }
+    const raw = args[index + 1];
+    if (raw === undefined || raw.startsWith('--')) {
+      if (kind === 'boolean') {
+        settings[key] = true;
+      }
+      continue;
+    }
+    try {
+      settings[key] = parseSettingValue(kind, raw);
+    } catch (error) {
+      console.log(`Warning: Ignoring ${settingFlag(key)}: ${error.message}`);
+    }
+  }
+  // `--repo <url>` and `--branch <name>` are short for `--clone-repo` and `--clone-branch`
+  for (const [flag, key] of [['--repo', 'cloneRepo'], ['--branch', 'cloneBranch']]) {
+    const raw = args[args.indexOf(flag) + 1];
+    if (args.includes(flag) && raw && !raw.startsWith('--') && settings[key] === undefined) {
+      settings[key] = raw;
+    }
+  }
+  // `--extension <id>`, once per extension, adds to any `--extensions` list
+  const extensions = args.filter((arg, index) => index > 0 && args[index - 1] === '--extension' && !arg.startsWith('--'));
+  if (extensions.length > 0) {
+    settings.extensions = [...(settings.extensions || []), ...extensions];
+  }
+  return settings;
+}
+
+// The profile picked with `--profile <name>` or THEOLDSWITCHEROO_PROFILE, if any
+function profileNameFromArgs(): string | null {
+  const index = process.argv.indexOf('--profile');
+  const name = index !== -1 ? process.argv[index + 1] : process.env.THEOLDSWITCHEROO_PROFILE;
+  return name || null;
+}
+
+export const PROFILE_NAME = profileNameFromArgs();
+
+// config.toml keys may be written in snake case, as is usual in TOML
+function settingKey(key: string): string {
+  return key.replace(/[_-]([a-z])/g, (_, letter) => letter.toUpperCase());
+}
+
+// Only the settings' own keys, not what every object inherits, such as `toString`
+function isSettingKey(key: string): boolean {
+  return Object.prototype.hasOwnProperty.call(SETTING_KINDS, key);
+}
+
+function tomlSettings(table: Record<string, any>): Settings {
+  const settings: Settings = {};
+  for (const [key, value] of Object.entries(table)) {
+    if (isSettingKey(settingKey(key))) {
+      settings[settingKey(key)] = value;
+    }
+  }
+  return settings;
+}
+
+function readConfigFile(): Record<string, any> | null {
+  return readSettingsFile(CONFIG_FILE, () => {
+    if (!fs.existsSync(CONFIG_FILE)) {
+      return null;
+    }
+    try {
+      return parseToml(fs.readFileSync(CONFIG_FILE, 'utf8'));
+    } catch (error) {
+      console.log(`Warning: Could not load ${CONFIG_FILE}: ${error.message}`);
+      return null;
+    }
+  });
+}
+
+// Settings from config.toml: its top-level keys, overlaid with the selected profile's
+function loadConfigFile(): Settings {
+  const { profiles = {}, ...common } = readConfigFile() || {};
+  const profile = PROFILE_NAME && Object.prototype.hasOwnProperty.call(profiles, PROFILE_NAME) ? profiles[PROFILE_NAME] : {};
+  return { ...tomlSettings(common), ...tomlSettings(profile) };
+}
+
+// Check config.toml once at startup rather than on every loadSettings().
+// A profile that does not exist is a typo; starting with the wrong host would be worse than stopping.
+function checkConfigFile(): void {
+  const config = readConfigFile();
+  if (!config) {
+    if (PROFILE_NAME) {
+      console.error(`No profile '${PROFILE_NAME}': ${CONFIG_FILE} does not exist or cannot be read`);
+      process.exit(1);
+    }
+    return;
+  }
+
+  const { profiles = {}, ...common } = config;
+  if (PROFILE_NAME && !Object.prototype.hasOwnProperty.call(profiles, PROFILE_NAME)) {
+    const known = Object.keys(profiles);
+    console.error(`No profile '${PROFILE_NAME}' in ${CONFIG_FILE}${known.length ? ` (profiles: ${known.join(', ')})` : ''}`);
+    process.exit(1);
+  }
+  const tables: [string, Record<string, any>][] = [['', common], ...Object.entries(profiles).map(([name, profile]) => [`profiles.${name}.`, profile] as [string, Record<string, any>])];
+  for (const [prefix, table] of tables) {
+    for (const key of Object.keys(table)) {
+      if (!isSettingKey(settingKey(key))) {
+        console.log(`Warning: Ignoring unknown setting '${prefix}${key}' in ${CONFIG_FILE}`);
+      }
+    }
+  }
 }
 
+checkConfigFile();
+
 // Load settings from file
+function loadSettingsFile(): Settings {
+  return readSettingsFile(SETTINGS_FILE, () => {
+    try {
+      if (fs.existsSync(SETTINGS_FILE)) {
+        return JSON.parse(fs.readFileSync(SETTINGS_FILE, 'utf8'));
+      }
+    } catch (error) {
+      console.log(`Warning: Could not load settings: ${error.message}`);
+    }
+    return {};
+  });
+}
+
+// Settings replayed from a last-known-good snapshot, which replace the file and environment
+let pinnedSettings: Settings | null = null;
+
+/// Use `settings` for the rest of the run instead of the settings file and environment
+export function pinSettings(settings: Settings): void {
+  pinnedSettings = settings;
+}
+
+// Settings of a named session, e.g. its own `hostname`, layered over the settings file
+function loadSessionSettingsFile(): Settings {
+  const file = path.join(SESSION_DIR, 'settings.json');
+  if (SESSION_NAME === DEFAULT_SESSION) {
+    return {};
+  }
+  return readSettingsFile(file, () => {
+    if (!fs.existsSync(file)) {
+      return {};
+    }
+    try {
+      return JSON.parse(fs.readFileSync(file, 'utf8'));
+    } catch (error) {
+      console.log(`Warning: Could not load session settings: ${error.message}`);
+      return {};
+    }
+  });
+}
+
+// The environment and command line don't change while the app runs, so they are parsed
+// once, and a bad value is warned about once
+let processSettings: Settings | null = null;
+
+// Load settings. From lowest to highest precedence: the selected `securityPreset`'s
+// defaults, the settings file, config.toml and its selected profile, the
+// session's settings, environment variables, and command line flags.
 export function loadSettings(): Settings {
+  if (pinnedSettings) {
+    return { ...pinnedSettings };
+  }
+  if (!processSettings) {
+    processSettings = { ...loadSettingsFromEnv(), ...loadSettingsFromArgs() };
+  }
+  const settings: Settings = {
+    ...loadSettingsFile(),
+    ...loadConfigFile(),
+    ...loadSessionSettingsFile(),
+    ...processSettings
+  };
+  return { ...securityPresetDefaults(settings.securityPreset), ...settings };
+}
+
+/// The configuration a host last started successfully with
+export interface LastGoodSnapshot {
+  // The host as configured (possibly a host group) and the host that was used
+  configuredHostname: string;
+  hostname: string;
+  savedAt: string;
+  arch: string;
+  // Resolved settings, with defaults that change between releases filled in
+  settings: Settings;
+  // Server ports at the time, for reference; servers still pick their port on replay
+  taskspacePorts: Record<string, number>;
+}
+
+function lastGoodFile(configuredHostname: string): string {
+  return path.join(LAST_GOOD_DIR, `${configuredHostname.replace(/[^A-Za-z0-9._-]/g, '_')}.json`);
+}
+
+// Save the snapshot for its configured host, replacing the previous one
+export function saveLastGood(snapshot: LastGoodSnapshot): void {
   try {
-    if (fs.existsSync(SETTINGS_FILE)) {
-      return JSON.parse(fs.readFileSync(SETTINGS_FILE, 'utf8'));
-    }
+    fs.mkdirSync(LAST_GOOD_DIR, { recursive: true });
+    fs.writeFileSync(lastGoodFile(snapshot.configuredHostname), JSON.stringify(snapshot, null, 2));
   } catch (error) {
-    console.log(`Warning: Could not load settings: ${error.message}`);
+    console.log(`Warning: Could not save last known good configuration: ${error.message}`);
+  }
+}
+
+export function loadLastGood(configuredHostname: string): LastGoodSnapshot | null {
+  const file = lastGoodFile(configuredHostname);
+  if (!fs.existsSync(file)) {
+    return null;
   }
-  return {};
+  return JSON.parse(fs.readFileSync(file, 'utf8'));
 }
 
 // Save settings to file
@@ -29,6 +654,7 @@ export function saveSettings(settings: Settings): void {
   try {
     fs.mkdirSync(path.dirname(SETTINGS_FILE), { recursive: true });
     fs.writeFileSync(SETTINGS_FILE, JSON.stringify(settings, null, 2));
+    parsedFiles.delete(SETTINGS_FILE);
   } catch (error) {
     console.log(`Warning: Could not save settings: ${error.message}`);
   }
@@ -1,36 +1,327 @@
 import { spawn, ChildProcess } from 'child_process';
+import { randomUUID } from 'crypto';
+import { Readable } from 'stream';
+import * as fs from 'fs';
 import * as path from 'path';
 import * as os from 'os';
+import * as net from 'net';
+import { NativeTransport, RemoteProcess, loadSsh2 } from './native-ssh.js';
+import { ForwardMultiplexer, ForwardStats } from './forward-mux.js';
+import { chaos, chaosError } from './chaos.js';
+import { TapeRecorder, TapeReplay } from './tape.js';
+
+export type { RemoteProcess } from './native-ssh.js';
+
+/// `native` connects in-process with the `ssh2` package when it is installed
+/// and the host allows it; `subprocess` always runs the `ssh` binary
+export type SshTransport = 'native' | 'subprocess';
 
 interface MasterConnection {
-  process: ChildProcess;
+  // Absent when the master belongs to another instance of the app
+  process?: ChildProcess;
   socketPath: string;
   host: string;
 }
 
+export type OutputSource = 'stdout' | 'stderr';
+
+/**
+ * Invoke `onLine` for each complete line written to `stream`.
+ *
+ * Chunks from a pipe can end mid-line; partial lines are buffered until their
+ * newline arrives so that stdout and stderr output never get spliced together.
+ */
+export function forEachLine(stream: Readable, onLine: (line: string) => void): void {
+  let buffer = '';
+  stream.setEncoding('utf8');
+  stream.on('data', (chunk: string) => {
+    buffer += chunk;
+    const lines = buffer.split('\n');
+    buffer = lines.pop()!;
+    for (const line of lines) {
+      onLine(line.replace(/\r$/, ''));
+    }
+  });
+  stream.on('end', () => {
+    if (buffer) {
+      onLine(buffer);
+      buffer = '';
+    }
+  });
+}
+
+/**
+ * Quote a string for safe use as a single POSIX shell word.
+ */
+export function shellQuote(value: string): string {
+  return `'${value.replace(/'/g, `'\\''`)}'`;
+}
+
+/**
+ * Quote a remote path for the shell like shellQuote, except that a leading
+ * `~` still means the home directory, e.g. `~/my dir` → `"$HOME"'/my dir'`.
+ */
+export function shellQuotePath(value: string): string {
+  if (/^~(?=$|\/)/.test(value)) {
+    return `"$HOME"${value.length > 1 ? shellQuote(value.slice(1)) : ''}`;
+  }
+  return shellQuote(value);
+}
+
+/**
+ * Wrap a remote command so that it runs as `user` via non-interactive sudo.
+ * If no user is given, the command is returned unchanged.
+ *
+ * `-H` makes `~` resolve to the target user's home, so paths like BASE_DIR
+ * land in the target user's directory rather than the login user's.
+ */
+export function asRemoteUser(user: string | undefined, command: string): string {
+  if (!user) {
+    return command;
+  }
+  return `sudo -n -H -u ${shellQuote(user)} bash -c ${shellQuote(command)}`;
+}
+
+/**
+ * A remote command ran longer than the command timeout and was stopped.
+ */
+export class RemoteCommandTimeoutError extends Error {
+  step: string;
+  timeoutSecs: number;
+
+  constructor(host: string, step: string, timeoutSecs: number) {
+    super(`Remote step on ${host} timed out after ${timeoutSecs}s: ${step}`);
+    this.name = 'RemoteCommandTimeoutError';
+    this.step = step;
+    this.timeoutSecs = timeoutSecs;
+  }
+}
+
+/**
+ * Short description of a (possibly multi-line) remote command for error messages:
+ * its first non-empty line, truncated.
+ */
+export function describeStep(command: string): string {
+  const first = command.split('\n').map(line => line.trim()).find(line => line) || command.trim();
+  return first.length > 80 ? `${first.slice(0, 77)}...` : first;
+}
+
+// Exit code of coreutils `timeout` when it had to stop the command
+const REMOTE_TIMEOUT_EXIT = 124;
+
+// Extra time the remote `timeout` gets to stop a command before we kill ssh locally
+const LOCAL_TIMEOUT_GRACE_SECS = 10;
+
+// How often to check whether a new master connection is ready
+const MASTER_POLL_INTERVAL_MS = 100;
+
+// How long a new master connection may take to connect and authenticate
+const MASTER_READY_TIMEOUT_MS = 30000;
+
+// How long a command through an existing master may take before the master is presumed dead
+const MASTER_PROBE_TIMEOUT_MS = 10000;
+
+/**
+ * Run `command` under the remote `timeout` utility when the host has it, so a
+ * stuck step (e.g. on a hung NFS mount) is killed on the host and not just
+ * abandoned. Hosts without `timeout` run the command as before.
+ */
+export function withRemoteTimeout(timeoutSecs: number, command: string): string {
+  const quoted = shellQuote(command);
+  return `if command -v timeout >/dev/null 2>&1; then exec timeout -k 5 ${timeoutSecs} "\${SHELL:-sh}" -c ${quoted}; ` +
+    `else exec "\${SHELL:-sh}" -c ${quoted}; fi`;
+}
+
 /**
  * SSH Connection Manager using ControlMaster for efficient connection multiplexing.
  * 
  * Maintains master SSH connections that stay alive for the application lifecycle,
  * allowing all SSH/SCP operations to use fast multiplexed connections.
+ *
+ * With the native transport, a host gets one in-process `ssh2` connection
+ * instead, and falls back to the `ssh` binary if that can't be used for it.
+ *
+ * With a tape recorder, every remote step is also written to a tape; with a
+ * tape replay, no host is contacted and the steps are played back from it.
  */
 export class SSHConnectionManager {
   private masters = new Map<string, MasterConnection>();
+  private pendingMasters = new Map<string, Promise<string>>();
+  private transport: SshTransport = 'subprocess';
+  private nativeLoading: Promise<NativeTransport | null> | null = null;
+  private native: NativeTransport | null = null;
+  // Hosts connected natively, and hosts that use the ssh binary despite the native transport
+  private nativeHosts = new Set<string>();
+  private subprocessHosts = new Set<string>();
+  // The native forwards to each host, multiplexed over its connection
+  private forwards = new Map<string, ForwardMultiplexer>();
+  private jumpHosts: string | undefined;
+  private identities = new Map<string, string>();
+  private batchMode = false;
+  private remoteUsers = new Map<string, string>();
+  private commandTimeoutSecs = 300;
+  private recorder: TapeRecorder | null = null;
+  private replay: TapeReplay | null = null;
+
+  /**
+   * Choose how to connect to hosts from now on.
+   */
+  setTransport(transport: SshTransport): void {
+    this.transport = transport;
+  }
+
+  /**
+   * Write every remote step from now on to `recorder`'s tape (`--record`).
+   */
+  setRecorder(recorder: TapeRecorder | null): void {
+    this.recorder = recorder;
+  }
+
+  /**
+   * Play every remote step back from `replay`'s tape instead of connecting (`--replay`).
+   */
+  setReplay(replay: TapeReplay | null): void {
+    this.replay = replay;
+  }
+
+  /**
+   * Whether remote steps are played back from a tape rather than run on a host.
+   */
+  isReplaying(): boolean {
+    return this.replay !== null;
+  }
+
+  /**
+   * Reach every host through these intermediate hosts, in the form `ssh -J`
+   * takes, e.g. "bastion" or "admin@gate:2222,bastion".
+   */
+  setJumpHost(jumpHosts: string | undefined): void {
+    if (jumpHosts && !/^[\w.@:\[\]-]+(,[\w.@:\[\]-]+)*$/.test(jumpHosts)) {
+      throw new Error(`Invalid jump host '${jumpHosts}'; expected [user@]host[:port], comma-separated`);
+    }
+    this.jumpHosts = jumpHosts || undefined;
+  }
+
+  /**
+   * Authenticate to `host` with this private key only, rather than the
+   * user's own keys and agent (the managed keys of `managedKeys`).
+   */
+  setIdentity(host: string, keyFile: string): void {
+    this.identities.set(host, keyFile);
+  }
+
+  /**
+   * Fail to connect rather than ask for a password, a passphrase or whether
+   * to trust an unknown host key, for runs with nobody to answer.
+   */
+  setBatchMode(on: boolean): void {
+    this.batchMode = on;
+  }
+
+  /**
+   * Options every ssh and scp process of the app connects with, to `host`
+   * where there is one.
+   */
+  connectionOptions(host?: string): string[] {
+    const options = [
+      ...(this.jumpHosts ? ['-o', `ProxyJump=${this.jumpHosts}`] : []),
+      ...(this.batchMode ? ['-o', 'BatchMode=yes'] : [])
+    ];
+    const identity = host && this.identities.get(host);
+    return identity ? [...options, '-i', identity, '-o', 'IdentitiesOnly=yes'] : options;
+  }
+
+  /**
+   * Stop remote commands (other than long-running servers) after `secs` seconds.
+   */
+  setCommandTimeout(secs: number): void {
+    this.commandTimeoutSecs = secs;
+  }
+
+  /**
+   * Kill `child` if it is still running after the command timeout plus a grace
+   * period, in case the remote `timeout` is missing or the connection is hung.
+   * `onTimeout` is called once if that happens. Returns a function that cancels the timer.
+   */
+  private watchTimeout(child: RemoteProcess, onTimeout: () => void): () => void {
+    const timer = setTimeout(() => {
+      child.kill('SIGKILL');
+      onTimeout();
+    }, (this.commandTimeoutSecs + LOCAL_TIMEOUT_GRACE_SECS) * 1000);
+    return () => clearTimeout(timer);
+  }
+
+  /**
+   * Run all commands and uploads for `host` as `user` (via `sudo -n -u`)
+   * instead of the SSH login user.
+   */
+  setRemoteUser(host: string, user: string): void {
+    this.remoteUsers.set(host, user);
+  }
+
+  /**
+   * The user that remote commands for `host` run as, if it differs from the login user.
+   */
+  remoteUserFor(host: string): string | undefined {
+    return this.remoteUsers.get(host);
+  }
+
+  /**
+   * Check that the login user can switch to the configured remote user without
+   * a password prompt. We can never answer a sudo prompt, so fail early with
+   * an actionable message instead of hanging later.
+   */
+  async verifyRemoteUser(host: string): Promise<void> {
+    const user = this.remoteUsers.get(host);
+    if (!user) {
+      return;
+    }
+
+    try {
+      await this.executeRawCommand(host, `sudo -n -u ${shellQuote(user)} true`);
+    } catch (error) {
+      throw new Error(`Cannot switch to remote user '${user}' on ${host} without a password. ` +
+        `Configure passwordless sudo (e.g. '<login-user> ALL=(${user}) NOPASSWD: ALL') or remove 'remoteUser' from settings.`);
+    }
+  }
 
   /**
    * Ensure a master connection exists for the given host.
-   * If a master already exists, this is a no-op.
-   * If no master exists, establishes a new one.
+   * If a master already exists, this is a no-op, and concurrent callers share
+   * one attempt. A live master on the socket left by another instance of the
+   * app (another session, or `--status`) is reused rather than replaced.
+   * Resolves once the master accepts sessions, so no command races it into
+   * a handshake of its own.
    */
   async ensureMaster(host: string): Promise<string> {
+    if (this.replay || await this.usesNative(host)) {
+      return '';
+    }
     if (this.masters.has(host)) {
       return this.masters.get(host)!.socketPath;
     }
+    if (!this.pendingMasters.has(host)) {
+      const pending = this.startMaster(host).finally(() => this.pendingMasters.delete(host));
+      this.pendingMasters.set(host, pending);
+    }
+    return this.pendingMasters.get(host)!;
+  }
 
+  private async startMaster(host: string): Promise<string> {
     const socketPath = this.generateSocketPath(host);
-    
+
+    if (await this.checkMaster(host, socketPath)) {
+      console.log(`Reusing the SSH master connection to ${host} at ${socketPath}`);
+      this.masters.set(host, { socketPath, host });
+      return socketPath;
+    }
+
+    // A socket left by an app that was killed would make ssh skip multiplexing
+    fs.rmSync(socketPath, { force: true });
+
     console.log(`Establishing SSH master connection to ${host}`);
-    
+    const started = Date.now();
+
     return new Promise((resolve, reject) => {
       const masterProcess = spawn('ssh', [
         '-M',           // Master mode
@@ -40,68 +331,243 @@ export class SSHConnectionManager {
         '-o', 'ConnectTimeout=10',
         '-o', 'ServerAliveInterval=60',
         '-o', 'ServerAliveCountMax=3',
+        ...this.connectionOptions(host),
         host
       ], {
-        stdio: 'ignore'  // Run silently in background
-      });
-
-      // Give the master a moment to establish
-      setTimeout(() => {
-        if (!masterProcess.killed) {
-          this.masters.set(host, {
-            process: masterProcess,
-            socketPath,
-            host
-          });
-          
-          console.log(`SSH master connection established for ${host}`);
-          resolve(socketPath);
+        stdio: ['ignore', 'ignore', 'pipe']  // Run silently in background, keeping errors for the report
+      });
+
+      let stderr = '';
+      let settled = false;
+      masterProcess.stderr!.on('data', (data) => {
+        stderr += data.toString();
+      });
+
+      // Poll until the master answers on its socket, which is when the
+      // handshake and authentication are done
+      const poll = async () => {
+        while (!settled) {
+          if (Date.now() - started > MASTER_READY_TIMEOUT_MS) {
+            settled = true;
+            masterProcess.kill();
+            reject(new Error(`SSH master connection to ${host} was not ready within ${MASTER_READY_TIMEOUT_MS / 1000}s`));
+            return;
+          }
+          if (await this.checkMaster(host, socketPath)) {
+            if (!settled) {
+              settled = true;
+              this.masters.set(host, { process: masterProcess, socketPath, host });
+              console.log(`SSH master connection established for ${host} in ${Date.now() - started}ms`);
+              resolve(socketPath);
+            }
+            return;
+          }
+          await new Promise(resolve => setTimeout(resolve, MASTER_POLL_INTERVAL_MS));
         }
-      }, 1000);
+      };
+      poll();
 
       masterProcess.on('error', (err) => {
         console.error(`Failed to establish SSH master for ${host}:`, err);
+        settled = true;
         reject(err);
       });
 
       masterProcess.on('close', (code) => {
         console.log(`SSH master for ${host} closed with code ${code}`);
         this.masters.delete(host);
+        if (!settled) {
+          settled = true;
+          reject(new Error(`Could not connect to ${host} over SSH (${code})${stderr.trim() ? `: ${stderr.trim()}` : ''}`));
+        }
       });
     });
   }
 
+  // Whether `host` is (now) connected through the native transport. The first
+  // time the native transport can't be used for a host, say why and stay
+  // with the ssh binary for it.
+  private async usesNative(host: string): Promise<boolean> {
+    if (this.transport !== 'native' || this.subprocessHosts.has(host)) {
+      return false;
+    }
+    if (!this.nativeLoading) {
+      this.nativeLoading = loadSsh2().then((ssh2) => {
+        if (!ssh2) {
+          console.log('The ssh2 package is not installed; using the ssh binary');
+          return null;
+        }
+        this.native = new NativeTransport(ssh2, (host) => this.connectionOptions(host));
+        return this.native;
+      });
+    }
+    const native = await this.nativeLoading;
+    if (!native) {
+      return false;
+    }
+
+    try {
+      await native.connect(host);
+      this.nativeHosts.add(host);
+      return true;
+    } catch (error) {
+      console.log(`Using the ssh binary for ${host}: ${(error as Error).message}`);
+      this.nativeHosts.delete(host);
+      this.subprocessHosts.add(host);
+      return false;
+    }
+  }
+
+  // Start `command` on `host` over whichever transport the host uses, or from the tape
+  private spawnRemote(host: string, socketPath: string, command: string): RemoteProcess {
+    if (this.replay) {
+      return this.replay.spawn(host, command);
+    }
+    const proc = this.nativeHosts.has(host) ? this.native!.spawn(host, command) : spawn('ssh', [
+      '-o', `ControlPath=${socketPath}`,
+      ...this.connectionOptions(host),
+      host,
+      command
+    ], {
+      stdio: ['pipe', 'pipe', 'pipe']
+    });
+    return this.recorder ? this.recorder.command(host, command, proc) : proc;
+  }
+
+  /**
+   * Run `command` on `host` in a terminal attached to this process's, e.g. a
+   * shell to poke around in. Only the ssh binary can drive a terminal, so this
+   * uses it whatever the transport. It shares the master connection at the
+   * usual socket if one is up (a running app, or an earlier command) and
+   * otherwise connects itself. Resolves with the command's exit code.
+   * Without `tty`, e.g. when the output goes to a pipe, no terminal is
+   * allocated and the command's output streams through as it is.
+   */
+  interactive(host: string, command: string, tty = true): Promise<number> {
+    if (this.replay) {
+      return Promise.reject(new Error('A terminal cannot be replayed from a tape'));
+    }
+    return new Promise((resolve, reject) => {
+      const ssh = spawn('ssh', [
+        tty ? '-t' : '-T',
+        '-o', `ControlPath=${this.generateSocketPath(host)}`,
+        '-o', 'ControlMaster=auto',
+        ...this.connectionOptions(host),
+        host,
+        asRemoteUser(this.remoteUsers.get(host), command)
+      ], {
+        stdio: 'inherit'
+      });
+      ssh.on('error', reject);
+      ssh.on('close', (code) => resolve(code ?? 1));
+    });
+  }
+
+  /**
+   * Make sure the connection to `host` still carries traffic, e.g. after the
+   * laptop slept or the network dropped, and replace it if it does not.
+   * A master that answers `ssh -O check` may still sit on a dead TCP
+   * connection until its keepalives give up, so it has to run a command.
+   * Resolves with whether the connection had to be replaced.
+   */
+  async reconnect(host: string): Promise<boolean> {
+    if (this.replay) {
+      return false;
+    }
+    if (this.nativeHosts.has(host)) {
+      // A connection that closed is forgotten already; one that hangs has to be dropped
+      if (this.native!.isConnected(host)) {
+        if (await this.native!.responds(host, MASTER_PROBE_TIMEOUT_MS)) {
+          return false;
+        }
+        console.log(`Native SSH connection to ${host} no longer responds; replacing it`);
+        this.native!.drop(host);
+      }
+      await this.native!.connect(host);
+      return true;
+    }
+    const master = this.masters.get(host);
+    if (master && await this.masterResponds(host, master.socketPath)) {
+      return false;
+    }
+    if (master) {
+      console.log(`SSH master connection to ${host} no longer responds; replacing it`);
+      master.process?.kill();
+      spawn('ssh', ['-O', 'exit', '-o', `ControlPath=${master.socketPath}`, host], { stdio: 'ignore' }).on('error', () => {});
+      this.masters.delete(host);
+    }
+    await this.ensureMaster(host);
+    return true;
+  }
+
+  // Whether a command gets through the master on `socketPath` within MASTER_PROBE_TIMEOUT_MS
+  private masterResponds(host: string, socketPath: string): Promise<boolean> {
+    return new Promise((resolve) => {
+      const probe = spawn('ssh', ['-o', `ControlPath=${socketPath}`, '-o', 'BatchMode=yes', host, 'true'], { stdio: 'ignore' });
+      const timer = setTimeout(() => {
+        probe.kill();
+        resolve(false);
+      }, MASTER_PROBE_TIMEOUT_MS);
+      probe.on('error', () => {
+        clearTimeout(timer);
+        resolve(false);
+      });
+      probe.on('close', (code) => {
+        clearTimeout(timer);
+        resolve(code === 0);
+      });
+    });
+  }
+
+  // Whether a master is accepting sessions on `socketPath`
+  private checkMaster(host: string, socketPath: string): Promise<boolean> {
+    return new Promise((resolve) => {
+      const check = spawn('ssh', ['-O', 'check', '-o', `ControlPath=${socketPath}`, host], { stdio: 'ignore' });
+      check.on('error', () => resolve(false));
+      check.on('close', (code) => resolve(code === 0));
+    });
+  }
+
   /**
    * Execute a command on the remote host using the master connection.
    */
   async executeCommand(host: string, command: string): Promise<string> {
+    return this.executeRawCommand(host, asRemoteUser(this.remoteUsers.get(host), command));
+  }
+
+  /**
+   * Execute a command as the SSH login user, ignoring any configured remote user.
+   */
+  private async executeRawCommand(host: string, command: string): Promise<string> {
     const socketPath = await this.ensureMaster(host);
+    if (chaos('command', `'${describeStep(command)}' on ${host}`)) {
+      throw chaosError(`SSH command '${command}' on ${host}`);
+    }
     
     return new Promise((resolve, reject) => {
       console.log(`Executing SSH command on ${host}: ${command}`);
       
-      const ssh = spawn('ssh', [
-        '-o', `ControlPath=${socketPath}`,
-        host,
-        command
-      ], {
-        stdio: ['pipe', 'pipe', 'pipe']
-      });
+      const ssh = this.spawnRemote(host, socketPath, withRemoteTimeout(this.commandTimeoutSecs, command));
 
       let stdout = '';
       let stderr = '';
+      const timeoutError = new RemoteCommandTimeoutError(host, describeStep(command), this.commandTimeoutSecs);
+      const cancelTimeout = this.watchTimeout(ssh, () => reject(timeoutError));
 
-      ssh.stdout.on('data', (data) => {
+      ssh.stdout!.on('data', (data) => {
         stdout += data.toString();
       });
 
-      ssh.stderr.on('data', (data) => {
+      ssh.stderr!.on('data', (data) => {
         stderr += data.toString();
       });
 
       ssh.on('close', (code) => {
+        cancelTimeout();
         if (code === 0) {
           resolve(stdout.trim());
+        } else if (code === REMOTE_TIMEOUT_EXIT) {
+          reject(timeoutError);
         } else {
           reject(new Error(`SSH command '${command}' on ${host} failed (${code}): ${stderr}`));
         }
@@ -113,50 +579,158 @@ export class SSHConnectionManager {
 
   /**
    * Execute a streaming command on the remote host using the master connection.
-   * Returns the SSH process for custom handling of stdout/stderr.
+   * Returns the SSH process (or native channel) for custom handling of stdout/stderr.
    */
-  async executeStreamingCommand(host: string, command: string): Promise<ChildProcess> {
+  async executeStreamingCommand(host: string, command: string): Promise<RemoteProcess> {
     const socketPath = await this.ensureMaster(host);
     
     console.log(`Executing streaming SSH command on ${host}: ${command}`);
     
-    return spawn('ssh', [
-      '-o', `ControlPath=${socketPath}`,
-      host,
-      command
-    ], {
-      stdio: ['pipe', 'pipe', 'pipe']
+    return this.spawnRemote(host, socketPath, asRemoteUser(this.remoteUsers.get(host), command));
+  }
+
+  /**
+   * Execute a command on the remote host, reporting each line of stdout and
+   * stderr as it arrives. Resolves with the collected stdout on success;
+   * on failure the error includes everything the command wrote to stderr.
+   */
+  async executeLineStreamingCommand(
+    host: string,
+    command: string,
+    onLine: (source: OutputSource, line: string) => void
+  ): Promise<string> {
+    if (chaos('command', `'${describeStep(command)}' on ${host}`)) {
+      throw chaosError(`SSH command on ${host}`);
+    }
+    const ssh = await this.executeStreamingCommand(host, withRemoteTimeout(this.commandTimeoutSecs, command));
+
+    return new Promise((resolve, reject) => {
+      const stdout: string[] = [];
+      const stderr: string[] = [];
+      const timeoutError = new RemoteCommandTimeoutError(host, describeStep(command), this.commandTimeoutSecs);
+      const cancelTimeout = this.watchTimeout(ssh, () => reject(timeoutError));
+
+      forEachLine(ssh.stdout!, (line) => {
+        stdout.push(line);
+        onLine('stdout', line);
+      });
+
+      forEachLine(ssh.stderr!, (line) => {
+        stderr.push(line);
+        onLine('stderr', line);
+      });
+
+      ssh.on('close', (code) => {
+        cancelTimeout();
+        if (code === 0) {
+          resolve(stdout.join('\n').trim());
+        } else if (code === REMOTE_TIMEOUT_EXIT) {
+          reject(timeoutError);
+        } else {
+          reject(new Error(`SSH command on ${host} failed (${code}): ${stderr.join('\n')}`));
+        }
+      });
+
+      ssh.on('error', reject);
     });
   }
 
   /**
    * Upload a file to the remote host using SCP with the master connection.
+   *
+   * When a remote user is configured, SCP can only write as the login user,
+   * so the file is staged in /tmp and then copied into place as the remote user.
    */
   async uploadFile(host: string, localPath: string, remotePath: string): Promise<void> {
+    const user = this.remoteUsers.get(host);
+    if (user) {
+      const stagingPath = `/tmp/theoldswitcheroo-upload-${randomUUID()}`;
+      await this.scpFile(host, localPath, stagingPath);
+      try {
+        await this.executeRawCommand(host, `chmod a+r ${stagingPath}`);
+        await this.executeCommand(host, `cp ${stagingPath} ${remotePath}`);
+      } finally {
+        await this.executeRawCommand(host, `rm -f ${stagingPath}`).catch(() => {});
+      }
+      return;
+    }
+
+    await this.scpFile(host, localPath, remotePath);
+  }
+
+  /**
+   * Download a file from the remote host using SCP with the master connection.
+   *
+   * When a remote user is configured, SCP can only read as the login user,
+   * so the file is first copied to /tmp as the remote user and made readable.
+   */
+  async downloadFile(host: string, remotePath: string, localPath: string): Promise<void> {
+    const user = this.remoteUsers.get(host);
+    if (user) {
+      const stagingPath = `/tmp/theoldswitcheroo-download-${randomUUID()}`;
+      await this.executeCommand(host, `cp ${remotePath} ${stagingPath} && chmod a+r ${stagingPath}`);
+      try {
+        await this.scpFile(host, localPath, stagingPath, 'download');
+      } finally {
+        await this.executeCommand(host, `rm -f ${stagingPath}`).catch(() => {});
+      }
+      return;
+    }
+
+    await this.scpFile(host, localPath, remotePath, 'download');
+  }
+
+  private async scpFile(host: string, localPath: string, remotePath: string, direction: 'upload' | 'download' = 'upload'): Promise<void> {
+    const [from, to] = direction === 'upload' ? [localPath, remotePath] : [remotePath, localPath];
+    if (this.replay) {
+      return this.replay.transfer(direction, host, from, to);
+    }
+    if (this.recorder) {
+      return this.recorder.transfer(direction, host, from, to, () => this.copyFile(host, localPath, remotePath, direction));
+    }
+    return this.copyFile(host, localPath, remotePath, direction);
+  }
+
+  private async copyFile(host: string, localPath: string, remotePath: string, direction: 'upload' | 'download'): Promise<void> {
     const socketPath = await this.ensureMaster(host);
+    const step = direction === 'upload' ? `upload ${localPath} -> ${remotePath}` : `download ${remotePath} -> ${localPath}`;
+    const description = direction === 'upload'
+      ? `Uploading file to ${host}: ${localPath} -> ${remotePath}`
+      : `Downloading file from ${host}: ${remotePath} -> ${localPath}`;
+    if (this.nativeHosts.has(host)) {
+      console.log(description);
+      return direction === 'upload'
+        ? this.native!.upload(host, localPath, remotePath)
+        : this.native!.download(host, remotePath, localPath);
+    }
     
     return new Promise((resolve, reject) => {
-      console.log(`Uploading file to ${host}: ${localPath} -> ${remotePath}`);
+      console.log(description);
       
+      const remote = `${host}:${remotePath}`;
       const scp = spawn('scp', [
         '-o', `ControlPath=${socketPath}`,
-        localPath,
-        `${host}:${remotePath}`
+        ...this.connectionOptions(host),
+        ...(direction === 'upload' ? [localPath, remote] : [remote, localPath])
       ], {
         stdio: ['pipe', 'pipe', 'pipe']
       });
 
       let stderr = '';
+      const cancelTimeout = this.watchTimeout(scp, () => {
+        reject(new RemoteCommandTimeoutError(host, step, this.commandTimeoutSecs));
+      });
 
       scp.stderr.on('data', (data) => {
         stderr += data.toString();
       });
 
       scp.on('close', (code) => {
+        cancelTimeout();
         if (code === 0) {
           resolve();
         } else {
-          reject(new Error(`SCP upload to ${host} failed (${code}): ${stderr}`));
+          reject(new Error(`SCP ${direction} ${direction === 'upload' ? 'to' : 'from'} ${host} failed (${code}): ${stderr}`));
         }
       });
 
@@ -166,9 +740,18 @@ export class SSHConnectionManager {
 
   /**
    * Create an SSH tunnel using the master connection.
-   * Returns the SSH process for the tunnel.
+   * Returns the SSH process for the tunnel, or the local listener of a native one.
    */
-  createTunnel(host: string, localPort: number, remotePort: number): ChildProcess {
+  createTunnel(host: string, localPort: number, remotePort: number): ChildProcess | net.Server {
+    if (this.replay) {
+      console.log(`Replay: standing in for the tunnel localhost:${localPort} -> ${host}:${remotePort}`);
+      return this.replay.tunnel(localPort);
+    }
+    if (this.nativeHosts.has(host)) {
+      console.log(`Creating native SSH tunnel: localhost:${localPort} -> ${host}:${remotePort}`);
+      return this.forwardsTo(host).open(localPort, remotePort);
+    }
+
     const socketPath = this.masters.get(host)?.socketPath;
     if (!socketPath) {
       throw new Error(`No master connection exists for ${host}. Call ensureMaster() first.`);
@@ -180,12 +763,31 @@ export class SSHConnectionManager {
       '-o', `ControlPath=${socketPath}`,
       '-L', `${localPort}:localhost:${remotePort}`,
       '-N',  // No command
+      ...this.connectionOptions(host),
       host
     ], {
       stdio: 'ignore'
     });
   }
 
+  // The multiplexer of `host`'s native forwards, whose streams are direct-tcpip channels
+  private forwardsTo(host: string): ForwardMultiplexer {
+    let forwards = this.forwards.get(host);
+    if (!forwards) {
+      forwards = new ForwardMultiplexer(host, (remotePort, origin) => this.native!.openStream(host, remotePort, origin));
+      this.forwards.set(host, forwards);
+    }
+    return forwards;
+  }
+
+  /**
+   * Traffic through each native port forward to `host`. Forwards through the
+   * ssh binary are separate processes that keep no count, so they are not listed.
+   */
+  forwardStats(host: string): ForwardStats[] {
+    return this.forwards.get(host)?.stats() || [];
+  }
+
   /**
    * Generate a unique socket path for the host.
    */
@@ -202,13 +804,16 @@ export class SSHConnectionManager {
     console.log('Cleaning up SSH master connections...');
     
     for (const [host, { process }] of this.masters) {
-      if (!process.killed) {
+      if (process && !process.killed) {
         console.log(`Terminating SSH master for ${host}`);
         process.kill();
       }
     }
     
     this.masters.clear();
+    this.forwards.forEach(forwards => forwards.closeAll());
+    this.forwards.clear();
+    this.native?.close();
   }
 
   /**
@@ -222,7 +827,6 @@ export class SSHConnectionManager {
 // Global instance
 export const sshManager = new SSHConnectionManager();
 
-// Set up cleanup handlers
-process.on('SIGINT', () => sshManager.cleanup());
-process.on('SIGTERM', () => sshManager.cleanup());
+// Set up cleanup handlers. Termination signals are handled by the app, which
+// needs the master connections alive while it shuts down gracefully.
 process.on('exit', () => sshManager.cleanup());
@@ -0,0 +1,226 @@
+/// Recording a run's remote steps to a tape, and replaying a tape in place of
+/// the host, to reproduce a user's setup failure offline: `--record <file>`
+/// writes every command, upload and download with its output and timing, and
+/// `--replay <file>` runs the app against the tape instead of SSH (see
+/// md/development.md). Off unless asked for.
+
+import * as fs from 'fs';
+import * as net from 'net';
+import { EventEmitter } from 'events';
+import { PassThrough } from 'stream';
+import { RemoteProcess } from './native-ssh.js';
+import { OutputSource } from './ssh-manager.js';
+import { Settings } from './settings.js';
+
+const TAPE_VERSION = 1;
+
+/// The first line of a tape: the settings the run used, which a replay uses too
+export interface TapeHeader {
+  tape: number;
+  recordedAt: string;
+  settings: Settings;
+}
+
+/// Output of a step, `at` milliseconds after it started
+export interface TapeChunk {
+  at: number;
+  source: OutputSource;
+  data: string;
+}
+
+/// One remote step of the recorded run
+export interface TapeStep {
+  // Order the steps started in
+  seq: number;
+  kind: 'command' | 'upload' | 'download';
+  host: string;
+  // The command as sent, or `<from> -> <to>` for a transfer
+  command: string;
+  // Milliseconds after the recording started
+  startedAt: number;
+  output: TapeChunk[];
+  // null when it was killed, or still running when the run ended (`unfinished`)
+  exitCode: number | null;
+  durationMs: number;
+  // Why it could not be started, or why a transfer failed
+  error?: string;
+  unfinished?: boolean;
+}
+
+/// Writes the remote steps of this run to a tape file, one JSON line per step
+/// as it ends. Steps still running when the app exits, like the servers, are
+/// written then, marked unfinished.
+export class TapeRecorder {
+  private started = Date.now();
+  private seq = 0;
+  private running = new Set<TapeStep>();
+
+  constructor(private file: string, settings: Settings) {
+    const header: TapeHeader = { tape: TAPE_VERSION, recordedAt: new Date().toISOString(), settings };
+    fs.writeFileSync(file, JSON.stringify(header) + '\n', { mode: 0o600 });
+    process.on('exit', () => this.flush());
+  }
+
+  /// Record the output and exit of `proc`, which runs `command` on `host`, and hand it back
+  command(host: string, command: string, proc: RemoteProcess): RemoteProcess {
+    const step = this.begin('command', host, command);
+    const began = Date.now();
+    const capture = (source: OutputSource) => (chunk: Buffer | string) => {
+      step.output.push({ at: Date.now() - began, source, data: chunk.toString() });
+    };
+    proc.stdout?.on('data', capture('stdout'));
+    proc.stderr?.on('data', capture('stderr'));
+    proc.on('error', (error: Error) => {
+      step.error = error.message;
+      this.end(step, null);
+    });
+    proc.on('close', (code: number | null) => this.end(step, code));
+    return proc;
+  }
+
+  /// Record how `transfer`, an upload or download between `from` and `to`, turns out
+  async transfer(kind: 'upload' | 'download', host: string, from: string, to: string, transfer: () => Promise<void>): Promise<void> {
+    const step = this.begin(kind, host, `${from} -> ${to}`);
+    try {
+      await transfer();
+      this.end(step, 0);
+    } catch (error) {
+      step.error = error.message;
+      this.end(step, 1);
+      throw error;
+    }
+  }
+
+  private begin(kind: TapeStep['kind'], host: string, command: string): TapeStep {
+    const step: TapeStep = { seq: this.seq++, kind, host, command, startedAt: Date.now() - this.started, output: [], exitCode: null, durationMs: 0 };
+    this.running.add(step);
+    return step;
+  }
+
+  private end(step: TapeStep, exitCode: number | null): void {
+    if (!this.running.delete(step)) {
+      return;
+    }
+    step.exitCode = exitCode;
+    step.durationMs = Date.now() - this.started - step.startedAt;
+    fs.appendFileSync(this.file, JSON.stringify(step) + '\n');
+  }
+
+  // Write the steps that are still running, on exit
+  private flush(): void {
+    for (const step of this.running) {
+      step.unfinished = true;
+      this.end(step, null);
+    }
+  }
+}
+
+/// Stands in for the host during a replay: each remote step gets the
+/// recorded step with the same command, or else the next unused one of its
+/// kind (commands with random parts, like staging paths, differ between
+/// runs), and plays back its output with the recorded timing.
+export class TapeReplay {
+  private steps: TapeStep[];
+  private used = new Set<TapeStep>();
+
+  constructor(private file: string, public header: TapeHeader, steps: TapeStep[]) {
+    this.steps = steps.sort((a, b) => a.seq - b.seq);
+  }
+
+  /// Read a tape written by TapeRecorder
+  static load(file: string): TapeReplay {
+    const lines = fs.readFileSync(file, 'utf8').split('\n').filter(line => line.trim());
+    const header = lines.length > 0 ? JSON.parse(lines[0]) as TapeHeader : null;
+    if (!header || header.tape !== TAPE_VERSION) {
+      throw new Error(`${file} is not a tape from --record (version ${TAPE_VERSION})`);
+    }
+    return new TapeReplay(file, header, lines.slice(1).map(line => JSON.parse(line)));
+  }
+
+  /// A process playing back the recorded step for `command`. With no step
+  /// left for it, it fails with exit code 255, like a lost connection.
+  spawn(host: string, command: string): RemoteProcess {
+    const proc = new EventEmitter() as RemoteProcess;
+    const stdout = new PassThrough();
+    const stderr = new PassThrough();
+    proc.stdin = new PassThrough();
+    proc.stdout = stdout;
+    proc.stderr = stderr;
+    proc.killed = false;
+
+    const timers: NodeJS.Timeout[] = [];
+    const finish = (code: number | null) => {
+      timers.forEach(timer => clearTimeout(timer));
+      stdout.end();
+      stderr.end();
+      setImmediate(() => {
+        proc.emit('exit', code);
+        proc.emit('close', code);
+      });
+    };
+    proc.kill = () => {
+      if (!proc.killed) {
+        proc.killed = true;
+        finish(null);
+      }
+      return true;
+    };
+
+    const step = this.take('command', host, command);
+    if (!step) {
+      setImmediate(() => {
+        stderr.write(`Not on the tape ${this.file}: ${command}\n`);
+        finish(255);
+      });
+      return proc;
+    }
+    if (step.error && step.exitCode === null && !step.unfinished) {
+      setImmediate(() => proc.emit('error', new Error(step.error)));
+      return proc;
+    }
+    for (const chunk of step.output) {
+      timers.push(setTimeout(() => (chunk.source === 'stdout' ? stdout : stderr).write(chunk.data), chunk.at));
+    }
+    // A step that was still running when the recording ended runs until killed
+    if (!step.unfinished) {
+      timers.push(setTimeout(() => finish(step.exitCode), step.durationMs));
+    }
+    return proc;
+  }
+
+  /// Play back a recorded upload or download: nothing is copied, but it
+  /// fails if the recorded one did
+  async transfer(kind: 'upload' | 'download', host: string, from: string, to: string): Promise<void> {
+    const step = this.take(kind, host, `${from} -> ${to}`);
+    if (!step) {
+      throw new Error(`Not on the tape ${this.file}: ${kind} ${from} -> ${to}`);
+    }
+    await new Promise(resolve => setTimeout(resolve, step.durationMs));
+    if (step.exitCode !== 0) {
+      throw new Error(step.error || `${kind} failed on the tape`);
+    }
+  }
+
+  /// A stand-in for a port forward: a local listener that hangs up on every
+  /// connection, since there is no server behind a tape
+  tunnel(localPort: number): net.Server {
+    const server = net.createServer(socket => socket.destroy());
+    server.on('error', (error) => console.error(`Replay: could not listen on localhost:${localPort}: ${error.message}`));
+    server.listen(localPort, '127.0.0.1');
+    return server;
+  }
+
+  // The step with exactly this command, else the first unused one of its kind
+  private take(kind: TapeStep['kind'], host: string, command: string): TapeStep | undefined {
+    const unused = this.steps.filter(step => !this.used.has(step) && step.kind === kind);
+    let step = unused.find(step => step.host === host && step.command === command);
+    if (!step && unused.length > 0) {
+      step = unused[0];
+      console.warn(`Replay: no recorded ${kind} matches '${command}' exactly; using step ${step.seq}, '${step.command}'`);
+    }
+    if (step) {
+      this.used.add(step);
+    }
+    return step;
+  }
+}
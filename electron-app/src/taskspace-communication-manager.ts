@@ -2,18 +2,16 @@ import { ChildProcess, spawn } from 'child_process';
 import * as fs from 'fs';
 import * as path from 'path';
 import { fileURLToPath } from 'url';
-import { SSHConnectionManager } from './ssh-manager.js';
+import { SSHConnectionManager, asRemoteUser, forEachLine } from './ssh-manager.js';
+import { logTimestampPrefix } from './log-format.js';
+import { PROTOCOL_VERSION, TaskSpaceMessage, parseDaemonMessage } from './protocol.js';
+import { REMOTE_DAEMON_SOCKET, loadSettings } from './settings.js';
+import { fetcherFor } from './artifact-fetch.js';
 
 // ES6 module equivalent of __dirname
 const __filename = fileURLToPath(import.meta.url);
 const __dirname = path.dirname(__filename);
 
-interface TaskSpaceMessage {
-  type: string;
-  timestamp: string;
-  [key: string]: any;
-}
-
 interface TaskSpaceRequest {
   type: 'new_taskspace_request';
   name: string;
@@ -22,9 +20,22 @@ interface TaskSpaceRequest {
   timestamp: string;
 }
 
+/// Longest wait between attempts to restart a daemon whose SSH connection dropped
+const MAX_RESTART_DELAY_MS = 30000;
+
+/// The user chose not to take over a daemon another instance is running
+class HandoffDeclinedError extends Error {
+  constructor() {
+    super('User declined to take over existing instance');
+    this.name = 'HandoffDeclinedError';
+  }
+}
+
 export class TaskSpaceCommunicationManager {
   private sshManager: SSHConnectionManager;
   private daemonProcesses = new Map<string, ChildProcess>();
+  private restartTimers = new Map<string, NodeJS.Timeout>();
+  private restartAttempts = new Map<string, number>();
   private messageHandlers = new Map<string, (message: TaskSpaceMessage) => void>();
 
   constructor(sshManager: SSHConnectionManager) {
@@ -35,9 +46,16 @@ export class TaskSpaceCommunicationManager {
   private setupMessageHandlers(): void {
     this.messageHandlers.set('new_taskspace_request', this.handleNewTaskSpaceRequest.bind(this));
     this.messageHandlers.set('update_taskspace', this.handleUpdateTaskSpace.bind(this));
+    this.messageHandlers.set('annotate_taskspace', this.handleAnnotateTaskSpace.bind(this));
+    this.messageHandlers.set('delete_taskspace', this.handleDeleteTaskSpace.bind(this));
     this.messageHandlers.set('status_request', this.handleStatusRequest.bind(this));
+    this.messageHandlers.set('logs_request', this.handleLogsRequest.bind(this));
     this.messageHandlers.set('progress_log', this.handleProgressLog.bind(this));
     this.messageHandlers.set('user_signal', this.handleUserSignal.bind(this));
+    this.messageHandlers.set('clipboard_copy', this.handleClipboardCopy.bind(this));
+    this.messageHandlers.set('registry_changed', this.handleRegistryChanged.bind(this));
+    this.messageHandlers.set('error', this.handleDaemonError.bind(this));
+    this.messageHandlers.set('maintenance_result', this.handleMaintenanceResult.bind(this));
   }
 
   async startDaemon(hostname: string): Promise<void> {
@@ -49,7 +67,8 @@ export class TaskSpaceCommunicationManager {
       }
 
       const baseDir = `~/.socratic-shell/theoldswitcheroo`;
-      const socketPath = `${baseDir}/daemon.sock`;
+      // Each session has its own daemon, so sessions on the same host don't take over each other's
+      const socketPath = REMOTE_DAEMON_SOCKET;
       const daemonPath = `${baseDir}/daemon-bundled.cjs`;
 
       // Check if daemon files exist on remote host
@@ -65,7 +84,7 @@ export class TaskSpaceCommunicationManager {
       if (instanceCheck.exists) {
         const shouldTakeOver = await this.showHandoffDialog(hostname, instanceCheck.pid);
         if (!shouldTakeOver) {
-          throw new Error('User declined to take over existing instance');
+          throw new HandoffDeclinedError();
         }
 
         // Kill existing daemon
@@ -74,40 +93,53 @@ export class TaskSpaceCommunicationManager {
       }
 
       // Start daemon via SSH
-      const daemonCommand = `cd ${baseDir} && ./nodejs/bin/node daemon-bundled.cjs --socket-path ${socketPath}`;
+      const daemonCommand = `cd ${baseDir} && mkdir -p $(dirname ${socketPath}) && ./nodejs/bin/node daemon-bundled.cjs --socket-path ${socketPath} --maintenance-results $PWD/maintenance/results.jsonl`;
       const daemonProcess = spawn('ssh', [
         '-o', 'ControlMaster=no',
         '-o', 'ControlPath=none',
+        ...this.sshManager.connectionOptions(hostname),
         hostname,
-        daemonCommand
+        asRemoteUser(this.sshManager.remoteUserFor(hostname), daemonCommand)
       ], {
         stdio: ['pipe', 'pipe', 'pipe']
       });
 
       // Handle daemon output
-      daemonProcess.stdout?.on('data', (data) => {
-        const lines = data.toString().split('\n').filter(line => line.trim());
-        for (const line of lines) {
-          try {
-            const message = JSON.parse(line) as TaskSpaceMessage;
-            this.handleMessage(hostname, message);
-          } catch (error) {
-            // Not JSON, probably daemon log output
-            console.log(`[${hostname}] Daemon:`, line);
-          }
+      forEachLine(daemonProcess.stdout, (line) => {
+        if (!line.trim()) {
+          return;
+        }
+        const parsed = parseDaemonMessage(line);
+        if ('message' in parsed) {
+          this.handleMessage(hostname, parsed.message);
+        } else if (parsed.error.code === 'invalid_json') {
+          // Not JSON, probably daemon log output
+          console.log(`[${hostname}] Daemon:`, line);
+        } else {
+          // The daemon validates client messages, so this means it is out of date
+          console.warn(`[${hostname}] Rejected message from daemon (${parsed.error.code}): ${parsed.error.message}`);
         }
       });
 
-      daemonProcess.stderr?.on('data', (data) => {
-        console.error(`[${hostname}] Daemon error:`, data.toString());
+      forEachLine(daemonProcess.stderr, (line) => {
+        console.error(`[${hostname}] Daemon error:`, line);
       });
 
       daemonProcess.on('exit', (code) => {
         console.log(`[${hostname}] Daemon exited with code ${code}`);
+        if (this.daemonProcesses.get(hostname) !== daemonProcess) {
+          // Stopped on purpose
+          return;
+        }
         this.daemonProcesses.delete(hostname);
+        // 255 is ssh's own exit code: the connection dropped, not the daemon
+        if (code === 255) {
+          this.scheduleRestart(hostname);
+        }
       });
 
       this.daemonProcesses.set(hostname, daemonProcess);
+      this.restartAttempts.delete(hostname);
       console.log(`Started daemon for ${hostname}`);
 
     } catch (error) {
@@ -116,7 +148,29 @@ export class TaskSpaceCommunicationManager {
     }
   }
 
+  /// Start the daemon again after its SSH connection dropped, backing off
+  /// exponentially while the host stays unreachable.
+  private scheduleRestart(hostname: string): void {
+    const attempt = (this.restartAttempts.get(hostname) || 0) + 1;
+    this.restartAttempts.set(hostname, attempt);
+    const delay = Math.min(1000 * 2 ** (attempt - 1), MAX_RESTART_DELAY_MS);
+    console.log(`[${hostname}] Lost connection to daemon; reconnecting in ${delay / 1000}s (attempt ${attempt})`);
+
+    this.restartTimers.set(hostname, setTimeout(() => {
+      this.restartTimers.delete(hostname);
+      this.startDaemon(hostname).catch((error) => {
+        if (!(error instanceof HandoffDeclinedError)) {
+          this.scheduleRestart(hostname);
+        }
+      });
+    }, delay));
+  }
+
   async stopDaemon(hostname: string): Promise<void> {
+    clearTimeout(this.restartTimers.get(hostname));
+    this.restartTimers.delete(hostname);
+    this.restartAttempts.delete(hostname);
+
     const daemonProcess = this.daemonProcesses.get(hostname);
     if (daemonProcess) {
       daemonProcess.kill('SIGTERM');
@@ -145,6 +199,11 @@ export class TaskSpaceCommunicationManager {
   }
 
   private async showHandoffDialog(hostname: string, pid?: string): Promise<boolean> {
+    // Nobody to ask: take the dialog's default
+    if (loadSettings().nonInteractive) {
+      console.log(`Taking over from the instance already running on ${hostname}${pid ? ` (PID ${pid})` : ''}`);
+      return true;
+    }
     try {
       const { dialog, BrowserWindow } = await import('electron');
 
@@ -217,6 +276,34 @@ export class TaskSpaceCommunicationManager {
     }
   }
 
+  private handleAnnotateTaskSpace(message: TaskSpaceMessage): void {
+    console.log('Annotating taskspace:', message);
+
+    // Emit event for main app to handle
+    if (this.onTaskSpaceRequest) {
+      this.onTaskSpaceRequest({
+        type: 'annotate_taskspace',
+        uuid: (message as any).uuid,
+        note: (message as any).note,
+        labels: (message as any).labels,
+        hostname: this.getCurrentHostname(message)
+      });
+    }
+  }
+
+  private handleDeleteTaskSpace(message: TaskSpaceMessage): void {
+    console.log('Deleting taskspace:', message);
+
+    // Emit event for main app to handle
+    if (this.onTaskSpaceRequest) {
+      this.onTaskSpaceRequest({
+        type: 'delete_taskspace',
+        uuid: (message as any).uuid,
+        hostname: this.getCurrentHostname(message)
+      });
+    }
+  }
+
   private handleStatusRequest(message: TaskSpaceMessage): void {
     console.log('Status request received');
 
@@ -227,11 +314,29 @@ export class TaskSpaceCommunicationManager {
       this.sendMessage(hostname, {
         type: 'status_response',
         timestamp: new Date().toISOString(),
+        ...(message.request_id && { request_id: message.request_id }),
         ...status
       }).catch(console.error);
     }
   }
 
+  private handleLogsRequest(message: TaskSpaceMessage): void {
+    console.log('Logs request received');
+
+    // Send back the tail of the taskspace's server log via daemon
+    const hostname = this.getCurrentHostname(message);
+    if (this.onLogsRequest) {
+      const lines = this.onLogsRequest(message.uuid, Math.min(Number(message.lines) || 100, 1000));
+      this.sendMessage(hostname, {
+        type: 'logs_response',
+        timestamp: new Date().toISOString(),
+        ...(message.request_id && { request_id: message.request_id }),
+        uuid: message.uuid,
+        lines
+      }).catch(console.error);
+    }
+  }
+
   private handleProgressLog(message: TaskSpaceMessage): void {
     console.log('Progress log received:', message);
 
@@ -254,11 +359,53 @@ export class TaskSpaceCommunicationManager {
       this.onUserSignal({
         message: message.message,
         timestamp: message.timestamp,
+        hostname: this.getCurrentHostname(message),
+        taskspaceUuid: message.taskspace_uuid
+      });
+    }
+  }
+
+  private handleClipboardCopy(message: TaskSpaceMessage): void {
+    // Forward to main app, which decides whether the bridge is on
+    if (this.onClipboardCopy) {
+      this.onClipboardCopy({
+        text: message.text,
+        hostname: this.getCurrentHostname(message),
+        taskspaceUuid: message.taskspace_uuid
+      });
+    }
+  }
+
+  private handleRegistryChanged(message: TaskSpaceMessage): void {
+    // Changes made by the app itself are not echoed back, so this is a client's write
+    if (this.onRegistryChange) {
+      this.onRegistryChange({
+        uuid: message.uuid,
+        revision: message.revision,
+        fields: message.fields,
+        hostname: this.getCurrentHostname(message)
+      });
+    }
+  }
+
+  private handleMaintenanceResult(message: TaskSpaceMessage): void {
+    // Forward to main app for display
+    if (this.onMaintenanceResult) {
+      this.onMaintenanceResult({
+        job: message.job,
+        ok: message.ok === true,
+        output: message.output,
+        startedAt: message.started_at,
         hostname: this.getCurrentHostname(message)
       });
     }
   }
 
+  private handleDaemonError(message: TaskSpaceMessage): void {
+    // Only sent in reply to the app's own requests, such as registry updates
+    console.error(`[${this.getCurrentHostname(message)}] Daemon rejected a request (${message.code}): ${message.message}`);
+  }
+
   private getCurrentHostname(message: TaskSpaceMessage): string {
     // Find which hostname this message came from
     for (const [hostname, process] of this.daemonProcesses) {
@@ -271,19 +418,23 @@ export class TaskSpaceCommunicationManager {
 
   // Event handlers for main app integration
   private onTaskSpaceRequest?: (request: {
-    type: 'new_taskspace' | 'update_taskspace';
+    type: 'new_taskspace' | 'update_taskspace' | 'annotate_taskspace' | 'delete_taskspace';
     name?: string;
     description?: string;
     cwd?: string;
     uuid?: string;
+    note?: string;
+    labels?: Record<string, string>;
     hostname: string;
   }) => void;
 
   private onStatusRequest?: (hostname: string) => {
-    taskspaces: Array<{ name: string; status: string; uuid: string }>;
+    taskspaces: Array<{ name: string; status: string; uuid: string; port: number; notes: string[]; labels: Record<string, string> }>;
     activeTaskSpace?: string;
   };
 
+  private onLogsRequest?: (uuid: string, lines: number) => string[];
+
   private onProgressLog?: (log: {
     message: string;
     category: 'info' | 'warn' | 'error' | 'milestone' | 'question';
@@ -295,6 +446,29 @@ export class TaskSpaceCommunicationManager {
     message: string;
     timestamp: string;
     hostname: string;
+    taskspaceUuid?: string;
+  }) => void;
+
+  private onClipboardCopy?: (copy: {
+    text: string;
+    hostname: string;
+    taskspaceUuid?: string;
+  }) => void;
+
+  // `fields` is absent when the taskspace was deleted
+  private onRegistryChange?: (change: {
+    uuid: string;
+    revision: number;
+    fields?: Record<string, any>;
+    hostname: string;
+  }) => void;
+
+  private onMaintenanceResult?: (result: {
+    job: string;
+    ok: boolean;
+    output: string;
+    startedAt?: string;
+    hostname: string;
   }) => void;
 
   setTaskSpaceRequestHandler(handler: typeof this.onTaskSpaceRequest): void {
@@ -305,6 +479,10 @@ export class TaskSpaceCommunicationManager {
     this.onStatusRequest = handler;
   }
 
+  setLogsRequestHandler(handler: typeof this.onLogsRequest): void {
+    this.onLogsRequest = handler;
+  }
+
   setProgressLogHandler(handler: typeof this.onProgressLog): void {
     this.onProgressLog = handler;
   }
@@ -313,13 +491,57 @@ export class TaskSpaceCommunicationManager {
     this.onUserSignal = handler;
   }
 
+  setClipboardCopyHandler(handler: typeof this.onClipboardCopy): void {
+    this.onClipboardCopy = handler;
+  }
+
+  setRegistryChangeHandler(handler: typeof this.onRegistryChange): void {
+    this.onRegistryChange = handler;
+  }
+
+  setMaintenanceResultHandler(handler: typeof this.onMaintenanceResult): void {
+    this.onMaintenanceResult = handler;
+  }
+
+  /// Hand `jobs` to the host's maintenance agent and (re)start it, detached
+  /// from this SSH session so the jobs keep running after the app disconnects.
+  /// With no jobs, the agent is stopped. The agent is shared by every session
+  /// on the host, so the last app to start decides its jobs.
+  async configureMaintenance(hostname: string, jobs: any[]): Promise<void> {
+    const dir = `~/.socratic-shell/theoldswitcheroo/maintenance`;
+    const stopAgent = `if [ -f ${dir}/agent.pid ]; then kill "$(cat ${dir}/agent.pid)" 2>/dev/null; rm -f ${dir}/agent.pid; fi`;
+
+    if (jobs.length === 0) {
+      await this.sshManager.executeCommand(hostname, `rm -f ${dir}/jobs.json; ${stopAgent}; true`);
+      return;
+    }
+
+    await this.sshManager.executeCommand(hostname, `mkdir -p ${dir} && cat > ${dir}/jobs.json << 'EOF'
+${JSON.stringify({ jobs }, null, 2)}
+EOF`);
+    // Restarting makes sure the agent runs the version just deployed
+    await this.sshManager.executeCommand(hostname, `${stopAgent}
+      cd ~/.socratic-shell/theoldswitcheroo && nohup ./nodejs/bin/node maintenance-bundled.cjs --dir "$PWD/maintenance" >> maintenance/agent.log 2>&1 < /dev/null &`);
+    console.log(`Started the maintenance agent on ${hostname} with ${jobs.length} jobs`);
+  }
+
+  /// Record a taskspace's fields in the host's registry
+  async putRegistryEntry(hostname: string, uuid: string, fields: Record<string, any>): Promise<void> {
+    await this.sendMessage(hostname, { type: 'registry_put', uuid, fields });
+  }
+
+  /// Remove a taskspace from the host's registry
+  async deleteRegistryEntry(hostname: string, uuid: string): Promise<void> {
+    await this.sendMessage(hostname, { type: 'registry_delete', uuid });
+  }
+
   async sendMessage(hostname: string, message: TaskSpaceMessage): Promise<void> {
     const daemonProcess = this.daemonProcesses.get(hostname);
     if (!daemonProcess || !daemonProcess.stdin) {
       throw new Error(`No active daemon for ${hostname}`);
     }
 
-    const messageStr = JSON.stringify(message);
+    const messageStr = JSON.stringify({ v: PROTOCOL_VERSION, ...message });
     daemonProcess.stdin.write(messageStr + '\n');
   }
 
@@ -337,6 +559,7 @@ export class TaskSpaceCommunicationManager {
     // Upload bundled daemon and CLI files
     const daemonSource = path.join(distDir, 'daemon-bundled.cjs');
     const cliSource = path.join(distDir, 'theoldswitcheroo-bundled.cjs');
+    const maintenanceSource = path.join(distDir, 'maintenance-bundled.cjs');
 
     if (!fs.existsSync(daemonSource)) {
       throw new Error('Daemon bundle not found. Run npm run build first.');
@@ -346,9 +569,16 @@ export class TaskSpaceCommunicationManager {
       throw new Error('CLI bundle not found. Run npm run build first.');
     }
 
+    if (!fs.existsSync(maintenanceSource)) {
+      throw new Error('Maintenance agent bundle not found. Run npm run build first.');
+    }
+
     // Upload daemon to base directory
     await this.sshManager.uploadFile(hostname, daemonSource, `${baseDir}/daemon-bundled.cjs`);
 
+    // Upload maintenance agent next to the daemon
+    await this.sshManager.uploadFile(hostname, maintenanceSource, `${baseDir}/maintenance-bundled.cjs`);
+
     // Upload CLI tool to bin directory (with .cjs extension)
     await this.sshManager.uploadFile(hostname, cliSource, `${binDir}/theoldswitcheroo-bundled.cjs`);
     
@@ -362,7 +592,7 @@ exec "$HOME/.socratic-shell/theoldswitcheroo/nodejs/bin/node" "$HOME/.socratic-s
 ${wrapperScript}EOF`);
     
     // Make files executable
-    await this.sshManager.executeCommand(hostname, `chmod +x ${baseDir}/daemon-bundled.cjs ${binDir}/theoldswitcheroo-bundled.cjs ${binDir}/theoldswitcheroo`);
+    await this.sshManager.executeCommand(hostname, `chmod +x ${baseDir}/daemon-bundled.cjs ${baseDir}/maintenance-bundled.cjs ${binDir}/theoldswitcheroo-bundled.cjs ${binDir}/theoldswitcheroo`);
 
     console.log(`Deployed daemon files to ${hostname}`);
     console.log(`CLI tool available at: ${binDir}/theoldswitcheroo`);
@@ -385,9 +615,9 @@ ${wrapperScript}EOF`);
     
     console.log('Installing Node.js...');
     
-    // Detect architecture
-    const archResult = await this.sshManager.executeCommand(hostname, 'uname -m');
-    const arch = archResult.trim();
+    // Detect platform and architecture
+    const [os, arch] = (await this.sshManager.executeCommand(hostname, 'uname -sm')).trim().split(/\s+/);
+    const platform = os === 'Darwin' ? 'darwin' : 'linux';
     
     // Map architecture to Node.js download names
     let nodeArch: string;
@@ -401,20 +631,32 @@ ${wrapperScript}EOF`);
       throw new Error(`Unsupported architecture: ${arch}`);
     }
     
-    // Download and extract Node.js
+    // Download and extract Node.js, from nodejs.org or the `nodeDownloadUrl` mirror or bucket
     const nodeVersion = 'v20.11.0'; // LTS version
-    const nodeUrl = `https://nodejs.org/dist/${nodeVersion}/node-${nodeVersion}-linux-${nodeArch}.tar.xz`;
+    const settings = loadSettings();
+    const baseUrl = (settings.nodeDownloadUrl || 'https://nodejs.org/dist').replace(/\/+$/, '');
+    const nodeUrl = `${baseUrl}/${nodeVersion}/node-${nodeVersion}-${platform}-${nodeArch}.tar.xz`;
+    const fetcher = fetcherFor(nodeUrl, settings.serverDownloadProxy);
     
     const installScript = `
       cd ${baseDir}
-      curl -L ${nodeUrl} | tar -xJ
-      mv node-${nodeVersion}-linux-${nodeArch} nodejs
+      ${fetcher.download(nodeUrl, 'node.tar.xz').trim()}
+      if [ "$rc" != 0 ]; then
+        rm -f node.tar.xz
+        echo "Downloading ${nodeUrl} failed (${fetcher.tool} exited with $rc, HTTP status $status)" >&2
+        exit 1
+      fi
+      tar -xJf node.tar.xz
+      rm -f node.tar.xz
+      mv node-${nodeVersion}-${platform}-${nodeArch} nodejs
       chmod +x nodejs/bin/node
       echo "Node.js installed successfully"
     `;
     
-    await this.sshManager.executeCommand(hostname, installScript);
-    console.log(`✓ Node.js ${nodeVersion} installed for ${nodeArch}`);
+    await this.sshManager.executeLineStreamingCommand(hostname, installScript, (source, line) => {
+      console.log(`${logTimestampPrefix()}[Node.js install ${source}] ${line}`);
+    });
+    console.log(`✓ Node.js ${nodeVersion} installed for ${platform}-${nodeArch}`);
   }
 
   async deployAdditionalTools(hostname: string, tools: Array<{ localPath: string; remoteName: string }>): Promise<void> {
@@ -438,7 +680,8 @@ ${wrapperScript}EOF`);
     return this.daemonProcesses.has(hostname);
   }
 
+  /// Hosts with a running daemon or one waiting to be restarted
   getActiveHosts(): string[] {
-    return Array.from(this.daemonProcesses.keys());
+    return Array.from(new Set([...this.daemonProcesses.keys(), ...this.restartTimers.keys()]));
   }
 }
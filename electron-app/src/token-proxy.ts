@@ -0,0 +1,88 @@
+import * as http from 'http';
+import * as net from 'net';
+
+/// Cookie openvscode-server accepts in place of the `?tkn=` query parameter
+const TOKEN_COOKIE = 'vscode-tkn';
+
+/// A local HTTP/websocket proxy in front of a server that requires a
+/// connection token. It adds the token to every request, so the URL the
+/// webview loads stays the same when the server restarts with a new token.
+export class TokenProxy {
+  private server: http.Server;
+  private targetPort: number;
+  private token: string;
+
+  constructor(targetPort: number, token: string) {
+    this.targetPort = targetPort;
+    this.token = token;
+    this.server = http.createServer((req, res) => this.forwardRequest(req, res));
+    this.server.on('upgrade', (req, socket, head) => this.forwardUpgrade(req, socket as net.Socket, head));
+  }
+
+  /// Listen on `port` (0 picks a free one) and resolve with the port in use
+  listen(port: number): Promise<number> {
+    return new Promise((resolve, reject) => {
+      this.server.once('error', reject);
+      this.server.listen(port, '127.0.0.1', () => {
+        this.server.off('error', reject);
+        resolve((this.server.address() as net.AddressInfo).port);
+      });
+    });
+  }
+
+  /// Point the proxy at a restarted server
+  retarget(targetPort: number, token: string): void {
+    this.targetPort = targetPort;
+    this.token = token;
+  }
+
+  close(): void {
+    this.server.close();
+  }
+
+  private headersWithToken(headers: http.IncomingHttpHeaders): http.OutgoingHttpHeaders {
+    const cookie = [headers.cookie, `${TOKEN_COOKIE}=${this.token}`].filter(c => c).join('; ');
+    return { ...headers, host: `localhost:${this.targetPort}`, cookie };
+  }
+
+  private forwardRequest(req: http.IncomingMessage, res: http.ServerResponse): void {
+    const upstream = http.request({
+      host: 'localhost',
+      port: this.targetPort,
+      method: req.method,
+      path: req.url,
+      headers: this.headersWithToken(req.headers),
+    }, (upstreamRes) => {
+      res.writeHead(upstreamRes.statusCode || 502, upstreamRes.headers);
+      upstreamRes.pipe(res);
+    });
+
+    upstream.on('error', (error) => {
+      if (!res.headersSent) {
+        res.writeHead(502, { 'Content-Type': 'text/plain' });
+      }
+      res.end(`theoldswitcheroo proxy: ${error.message}`);
+    });
+    req.pipe(upstream);
+  }
+
+  /// Websocket upgrades are relayed as raw bytes once the rewritten request is sent
+  private forwardUpgrade(req: http.IncomingMessage, socket: net.Socket, head: Buffer): void {
+    const upstream = net.connect(this.targetPort, 'localhost', () => {
+      const headers = this.headersWithToken(req.headers);
+      const lines = [`${req.method} ${req.url} HTTP/${req.httpVersion}`];
+      for (const [name, value] of Object.entries(headers)) {
+        for (const v of Array.isArray(value) ? value : [value]) {
+          lines.push(`${name}: ${v}`);
+        }
+      }
+      upstream.write(lines.join('\r\n') + '\r\n\r\n');
+      upstream.write(head);
+      upstream.pipe(socket);
+      socket.pipe(upstream);
+    });
+
+    upstream.on('error', () => socket.destroy());
+    socket.on('error', () => upstream.destroy());
+  }
+}
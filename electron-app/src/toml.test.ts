@@ -0,0 +1,106 @@
+import { describe, test } from 'node:test';
+import * as assert from 'node:assert/strict';
+import { parseToml } from './toml.js';
+
+// Parsing `text` fails with an error whose message contains `message`
+function assertRejects(text: string, message: string): void {
+  assert.throws(() => parseToml(text), (error: Error) => error.message.includes(message));
+}
+
+describe('config.toml parser', () => {
+  test('parses tables, dotted keys and values', () => {
+    const config = parseToml([
+      '# Top-level keys apply always',
+      'hostname = "build1"',
+      'remote_port = 8_000',
+      'managed-keys = true',
+      "log_filter = 'warn'",
+      'extensions = [',
+      '  "rust-lang.rust-analyzer",',
+      '  "ms-python.python",',
+      ']',
+      '',
+      '[profiles.gpu]',
+      'hostname = "gpu-lab-3"',
+      'cuda.visible = "0,1"',
+      'server_ulimits = { n = 65536, c = 0 }',
+      '',
+      '[profiles."with dots.in.name".aliases]',
+      'test = "cargo test"  # the usual',
+    ].join('\n'));
+
+    assert.deepEqual(config, {
+      hostname: 'build1',
+      remote_port: 8000,
+      'managed-keys': true,
+      log_filter: 'warn',
+      extensions: ['rust-lang.rust-analyzer', 'ms-python.python'],
+      profiles: {
+        gpu: {
+          hostname: 'gpu-lab-3',
+          cuda: { visible: '0,1' },
+          server_ulimits: { n: 65536, c: 0 }
+        },
+        'with dots.in.name': { aliases: { test: 'cargo test' } }
+      }
+    });
+  });
+
+  test('parses numbers and escapes', () => {
+    assert.deepEqual(parseToml('a = -1.5e3\nb = 0x1F\nc = +inf\nd = "tab\\there \\u00e9"\ne = 0\nf = -0.5\ng = 1e05'), {
+      a: -1500,
+      b: 31,
+      c: Infinity,
+      d: 'tab\there é',
+      e: 0,
+      f: -0.5,
+      g: 100000
+    });
+  });
+
+  test('rejects what it does not support, naming the line', () => {
+    assertRejects('a = 1\n[[servers]]', 'line 2: arrays of tables');
+    assertRejects('a = """x"""', 'multi-line strings');
+    assertRejects('a = 1979-05-27', "unsupported value '1979-05-27'");
+    assertRejects('a = "open', 'unterminated string');
+    assertRejects('a = 1 b = 2', "unexpected 'b'");
+  });
+
+  test('rejects integers with leading zeros', () => {
+    assertRejects('a = 007', "line 1: leading zeros are not allowed in '007'");
+    assertRejects('a = -01', "leading zeros are not allowed in '-01'");
+    assertRejects('a = 0_7', "leading zeros are not allowed in '0_7'");
+    assertRejects('a = 01.5', "leading zeros are not allowed in '01.5'");
+  });
+
+  test('rejects keys and tables defined twice, and values used as tables', () => {
+    assertRejects('a = 1\na = 2', "line 2: 'a' is defined twice");
+    assertRejects('a = 1\n[a]', "'a' is not a table");
+    assertRejects('a.b = 1\na.b.c = 2', "'a.b' is not a table");
+    assertRejects('[a]\nx = 1\n[b]\n[a]\ny = 2', 'line 4: table [a] is defined twice');
+    assertRejects('[profiles.gpu]\n[profiles."gpu"]', 'table [profiles.gpu] is defined twice');
+    // A table created on the way to another may still get its own header
+    assert.deepEqual(parseToml('[a.b]\nx = 1\n[a]\ny = 2'), { a: { b: { x: 1 }, y: 2 } });
+  });
+
+  test('treats keys named like Object properties as plain keys', () => {
+    const config = parseToml([
+      'toString = "x"',
+      'constructor = 1',
+      '[__proto__]',
+      'polluted = true',
+      '[profiles.hasOwnProperty]',
+      'hostname = "h"',
+    ].join('\n'));
+
+    assert.deepEqual(Object.keys(config), ['toString', 'constructor', '__proto__', 'profiles']);
+    assert.equal(config.toString, 'x');
+    assert.equal(Object.getPrototypeOf(config), Object.prototype);
+    assert.deepEqual(config.profiles.hasOwnProperty, { hostname: 'h' });
+    assert.equal(({} as any).polluted, undefined);
+
+    assertRejects('toString = 1\ntoString = 2', "'toString' is defined twice");
+    parseToml('x = { __proto__ = { polluted = true } }');
+    assert.equal(({} as any).polluted, undefined);
+  });
+});
@@ -0,0 +1,258 @@
+// A parser for the part of TOML that config.toml needs: tables (`[a.b]`),
+// bare, quoted and dotted keys, basic and literal strings, integers, floats,
+// booleans, arrays (which may span lines) and inline tables. Multi-line
+// strings, dates and arrays of tables (`[[a]]`) are rejected.
+
+/// Parse `text`, throwing an error that names the line on anything malformed
+export function parseToml(text: string): Record<string, any> {
+  return new TomlParser(text).parse();
+}
+
+// Keys are the file's, so only own properties count, and `__proto__` or
+// `toString` is a key like any other rather than something inherited
+function hasOwn(table: Record<string, any>, key: string): boolean {
+  return Object.prototype.hasOwnProperty.call(table, key);
+}
+
+function setOwn(table: Record<string, any>, key: string, value: any): void {
+  Object.defineProperty(table, key, { value, writable: true, enumerable: true, configurable: true });
+}
+
+class TomlParser {
+  private pos = 0;
+  // Tables given a `[header]` so far; each may only have one
+  private headed = new Set<Record<string, any>>();
+
+  constructor(private text: string) {}
+
+  parse(): Record<string, any> {
+    const root: Record<string, any> = {};
+    let table = root;
+
+    while (true) {
+      this.skipBlank(true);
+      if (this.pos >= this.text.length) {
+        return root;
+      }
+
+      if (this.peek() === '[') {
+        if (this.text.startsWith('[[', this.pos)) {
+          this.fail('arrays of tables ([[...]]) are not supported');
+        }
+        this.pos++;
+        this.skipBlank(false);
+        const keys = this.parseKey();
+        this.skipBlank(false);
+        this.expect(']');
+        table = this.descend(root, keys);
+        if (this.headed.has(table)) {
+          this.fail(`table [${keys.join('.')}] is defined twice`);
+        }
+        this.headed.add(table);
+      } else {
+        const keys = this.parseKey();
+        this.skipBlank(false);
+        this.expect('=');
+        this.skipBlank(false);
+        this.assign(table, keys, this.parseValue());
+      }
+
+      // Only a comment may follow on the same line
+      this.skipBlank(false);
+      if (this.pos < this.text.length && this.peek() !== '\n') {
+        this.fail(`unexpected '${this.peek()}'`);
+      }
+    }
+  }
+
+  // A key: bare or quoted parts joined by dots
+  private parseKey(): string[] {
+    const keys: string[] = [];
+    while (true) {
+      const c = this.peek();
+      if (c === '"' || c === "'") {
+        keys.push(this.parseString());
+      } else {
+        const match = /^[A-Za-z0-9_-]+/.exec(this.text.slice(this.pos));
+        if (!match) {
+          this.fail('expected a key');
+        }
+        keys.push(match![0]);
+        this.pos += match![0].length;
+      }
+      this.skipBlank(false);
+      if (this.peek() !== '.') {
+        return keys;
+      }
+      this.pos++;
+      this.skipBlank(false);
+    }
+  }
+
+  private parseValue(): any {
+    const c = this.peek();
+    if (c === '"' || c === "'") {
+      if (this.text.startsWith(c.repeat(3), this.pos)) {
+        this.fail('multi-line strings are not supported');
+      }
+      return this.parseString();
+    }
+    if (c === '[') {
+      return this.parseArray();
+    }
+    if (c === '{') {
+      return this.parseInlineTable();
+    }
+
+    const match = /^[^\s,\]}#]+/.exec(this.text.slice(this.pos));
+    const word = match ? match[0] : '';
+    this.pos += word.length;
+    if (word === 'true' || word === 'false') {
+      return word === 'true';
+    }
+    const number = word.replace(/_/g, '');
+    if (/^[+-]?0\d/.test(number)) {
+      this.pos -= word.length;
+      this.fail(`leading zeros are not allowed in '${word}'`);
+    }
+    if (/^[+-]?(\d+(\.\d+)?([eE][+-]?\d+)?|inf|nan)$/.test(number) || /^0x[0-9a-fA-F]+$/.test(number)) {
+      return Number(number.replace(/^([+-]?)inf$/, '$1Infinity').replace(/^[+-]?nan$/, 'NaN'));
+    }
+    this.pos -= word.length;
+    return this.fail(word ? `unsupported value '${word}'` : 'expected a value');
+  }
+
+  private parseString(): string {
+    const quote = this.text[this.pos++];
+    let value = '';
+    while (true) {
+      const c = this.text[this.pos++];
+      if (c === undefined || c === '\n') {
+        this.fail('unterminated string');
+      }
+      if (c === quote) {
+        return value;
+      }
+      if (c === '\\' && quote === '"') {
+        value += this.parseEscape();
+      } else {
+        value += c;
+      }
+    }
+  }
+
+  private parseEscape(): string {
+    const c = this.text[this.pos++];
+    const simple: Record<string, string> = { b: '\b', t: '\t', n: '\n', f: '\f', r: '\r', '"': '"', '\\': '\\' };
+    if (hasOwn(simple, c)) {
+      return simple[c];
+    }
+    if (c === 'u' || c === 'U') {
+      const length = c === 'u' ? 4 : 8;
+      const hex = this.text.slice(this.pos, this.pos + length);
+      if (!/^[0-9a-fA-F]+$/.test(hex) || hex.length !== length) {
+        this.fail(`invalid escape \\${c}${hex}`);
+      }
+      this.pos += length;
+      return String.fromCodePoint(parseInt(hex, 16));
+    }
+    return this.fail(`invalid escape \\${c}`);
+  }
+
+  private parseArray(): any[] {
+    this.pos++;
+    const values: any[] = [];
+    while (true) {
+      this.skipBlank(true);
+      if (this.peek() === ']') {
+        this.pos++;
+        return values;
+      }
+      values.push(this.parseValue());
+      this.skipBlank(true);
+      if (this.peek() === ',') {
+        this.pos++;
+      } else if (this.peek() !== ']') {
+        this.fail("expected ',' or ']'");
+      }
+    }
+  }
+
+  private parseInlineTable(): Record<string, any> {
+    this.pos++;
+    const table: Record<string, any> = {};
+    this.skipBlank(false);
+    if (this.peek() === '}') {
+      this.pos++;
+      return table;
+    }
+    while (true) {
+      this.skipBlank(false);
+      const keys = this.parseKey();
+      this.expect('=');
+      this.skipBlank(false);
+      this.assign(table, keys, this.parseValue());
+      this.skipBlank(false);
+      if (this.peek() === '}') {
+        this.pos++;
+        return table;
+      }
+      this.expect(',');
+    }
+  }
+
+  // Walk to (creating as needed) the table at `keys`
+  private descend(table: Record<string, any>, keys: string[]): Record<string, any> {
+    for (const key of keys) {
+      if (!hasOwn(table, key)) {
+        setOwn(table, key, {});
+      } else if (typeof table[key] !== 'object' || Array.isArray(table[key])) {
+        this.fail(`'${keys.join('.')}' is not a table`);
+      }
+      table = table[key];
+    }
+    return table;
+  }
+
+  private assign(table: Record<string, any>, keys: string[], value: any): void {
+    const parent = this.descend(table, keys.slice(0, -1));
+    const key = keys[keys.length - 1];
+    if (hasOwn(parent, key)) {
+      this.fail(`'${keys.join('.')}' is defined twice`);
+    }
+    setOwn(parent, key, value);
+  }
+
+  // Skip spaces, tabs and comments, and newlines too if `newlines`
+  private skipBlank(newlines: boolean): void {
+    while (this.pos < this.text.length) {
+      const c = this.peek();
+      if (c === ' ' || c === '\t' || c === '\r' || (newlines && c === '\n')) {
+        this.pos++;
+      } else if (c === '#') {
+        while (this.pos < this.text.length && this.peek() !== '\n') {
+          this.pos++;
+        }
+      } else {
+        return;
+      }
+    }
+  }
+
+  private peek(): string {
+    return this.text[this.pos];
+  }
+
+  private expect(c: string): void {
+    this.skipBlank(false);
+    if (this.peek() !== c) {
+      this.fail(`expected '${c}'`);
+    }
+    this.pos++;
+  }
+
+  private fail(message: string): never {
+    const line = this.text.slice(0, this.pos).split('\n').length;
+    throw new Error(`line ${line}: ${message}`);
+  }
+}
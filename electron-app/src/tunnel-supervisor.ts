@@ -0,0 +1,180 @@
+import { ChildProcess } from 'child_process';
+import * as net from 'net';
+import { sshManager } from './ssh-manager.js';
+import { localEndpointResponds } from './port-forward.js';
+import { chaos } from './chaos.js';
+import { ForwardStats } from './forward-mux.js';
+
+// How often every tunnel is probed
+const PROBE_INTERVAL_MS = 15000;
+
+// A tunnel is broken after this many probes in a row got no answer, so one slow page load is not a drop
+const FAILED_PROBES = 2;
+
+// Waits between reconnect attempts double from the first up to the last
+const FIRST_RECONNECT_DELAY_MS = 1000;
+const MAX_RECONNECT_DELAY_MS = 30000;
+
+interface SupervisedTunnel {
+  localPort: number;
+  remotePort: number;
+  handle: ChildProcess | net.Server;
+  failedProbes: number;
+  // The server behind it speaks HTTPS (`tls`), so probes must too
+  tls: boolean;
+}
+
+/// Keeps the local port forwards to one host working. With the native
+/// transport they are listeners multiplexed over the host's one connection
+/// (see ForwardMultiplexer); otherwise each is an `ssh -L` process on the
+/// master connection. A forward is suspect when its `ssh -L` process exits, when it stops answering probes, or when
+/// the machine wakes from sleep and a probe fails. Then the SSH connection is
+/// checked and replaced if it is dead, and the forwards that went down with
+/// it are opened again on the same local ports, retrying with backoff until
+/// the network is back. The servers on the host are left alone: the forwards
+/// lead back to them as they are, and `onHealed` is told which local ports
+/// work again. A forward over a working connection whose server does not
+/// answer is the server's problem, not the tunnel's, and is left as it is.
+export class TunnelSupervisor {
+  private tunnels = new Map<number, SupervisedTunnel>();
+  private interval: NodeJS.Timeout | null = null;
+  private healing: Promise<void> | null = null;
+  private stopped = false;
+
+  constructor(
+    private hostname: string,
+    private onHealed: (localPorts: number[]) => void = () => {}
+  ) {}
+
+  /// Forward `localPort` to `remotePort` on the host, replacing any forward
+  /// of `localPort`, and keep it working. `tls` if the server there serves HTTPS.
+  open(localPort: number, remotePort: number, tls: boolean = false): void {
+    this.close(localPort);
+    const tunnel: SupervisedTunnel = { localPort, remotePort, handle: this.createTunnel(localPort, remotePort), failedProbes: 0, tls };
+    this.tunnels.set(localPort, tunnel);
+    this.watch(tunnel);
+  }
+
+  /// Stop forwarding `localPort`
+  close(localPort: number): void {
+    const tunnel = this.tunnels.get(localPort);
+    if (tunnel) {
+      this.tunnels.delete(localPort);
+      closeHandle(tunnel.handle);
+    }
+  }
+
+  /// Traffic through each forward, where the transport counts it (native forwards only)
+  stats(): ForwardStats[] {
+    return sshManager.forwardStats(this.hostname);
+  }
+
+  /// Probe every tunnel periodically until `stop`
+  start(): void {
+    this.interval = setInterval(() => this.check(), PROBE_INTERVAL_MS);
+  }
+
+  /// Stop supervising. The forwards stay up until the SSH connections are closed.
+  stop(): void {
+    this.stopped = true;
+    if (this.interval) {
+      clearInterval(this.interval);
+      this.interval = null;
+    }
+  }
+
+  /// Probe every tunnel now, and heal them if one is broken. `reason`, e.g.
+  /// waking from sleep, makes a single failed probe enough.
+  async check(reason?: string): Promise<void> {
+    if (this.stopped || this.healing) {
+      return;
+    }
+    const tunnels = [...this.tunnels.values()];
+    if (tunnels.length > 0 && chaos('tunnel', `the tunnels to ${this.hostname}`)) {
+      closeHandle(tunnels[Math.floor(Math.random() * tunnels.length)].handle);
+    }
+    for (const tunnel of tunnels) {
+      if (await localEndpointResponds(tunnel.localPort, undefined, tunnel.tls)) {
+        tunnel.failedProbes = 0;
+        continue;
+      }
+      tunnel.failedProbes++;
+      // Once per outage: a server that stays down is reported elsewhere, not reconnected to every probe
+      if (reason || tunnel.failedProbes === FAILED_PROBES) {
+        await this.heal(`localhost:${tunnel.localPort} stopped answering${reason ? ` after ${reason}` : ''}`);
+        return;
+      }
+    }
+  }
+
+  /// Reconnect if needed and reopen the forwards that are down; concurrent callers share one attempt
+  heal(reason: string): Promise<void> {
+    if (!this.healing) {
+      this.healing = this.reconnect(reason).finally(() => this.healing = null);
+    }
+    return this.healing;
+  }
+
+  private async reconnect(reason: string): Promise<void> {
+    console.warn(`Checking the tunnels to ${this.hostname} (${reason})...`);
+    const started = Date.now();
+    let delay = FIRST_RECONNECT_DELAY_MS;
+
+    for (let attempt = 1; !this.stopped; attempt++) {
+      try {
+        const replaced = await sshManager.reconnect(this.hostname);
+        // A new connection needs every forward again; otherwise only the ones whose process died
+        const reopen = [...this.tunnels.values()].filter(tunnel => replaced || isDead(tunnel.handle));
+        if (reopen.length === 0) {
+          console.log(`The connection to ${this.hostname} and its tunnels are up; the server behind them is not answering`);
+          return;
+        }
+        for (const tunnel of reopen) {
+          closeHandle(tunnel.handle);
+          tunnel.handle = this.createTunnel(tunnel.localPort, tunnel.remotePort);
+          tunnel.failedProbes = 0;
+          this.watch(tunnel);
+        }
+        const ports = reopen.map(tunnel => tunnel.localPort);
+        console.log(`✓ Reconnected to ${this.hostname} after ${Math.round((Date.now() - started) / 1000)}s; forwarding ${ports.map(port => `localhost:${port}`).join(', ')} again`);
+        this.onHealed(ports);
+        return;
+      } catch (error) {
+        console.warn(`Reconnecting to ${this.hostname} failed (attempt ${attempt}): ${error.message}; retrying in ${delay / 1000}s...`);
+        await new Promise(resolve => setTimeout(resolve, delay));
+        delay = Math.min(delay * 2, MAX_RECONNECT_DELAY_MS);
+      }
+    }
+  }
+
+  private createTunnel(localPort: number, remotePort: number): ChildProcess | net.Server {
+    return sshManager.createTunnel(this.hostname, localPort, remotePort);
+  }
+
+  // An `ssh -L` process exits when its connection drops; native forwards reconnect per connection and are only probed
+  private watch(tunnel: SupervisedTunnel): void {
+    const handle = tunnel.handle;
+    if (handle instanceof net.Server) {
+      return;
+    }
+    handle.on('exit', (code) => {
+      // Replaced or closed on purpose
+      if (this.stopped || tunnel.handle !== handle || this.tunnels.get(tunnel.localPort) !== tunnel) {
+        return;
+      }
+      this.heal(`the tunnel for localhost:${tunnel.localPort} exited with code ${code}`);
+    });
+  }
+}
+
+function isDead(handle: ChildProcess | net.Server): boolean {
+  return handle instanceof net.Server ? !handle.listening : handle.exitCode !== null || handle.signalCode !== null;
+}
+
+function closeHandle(handle: ChildProcess | net.Server): void {
+  if (handle instanceof net.Server) {
+    handle.close();
+  } else if (!isDead(handle)) {
+    handle.kill();
+  }
+}
@@ -0,0 +1,239 @@
+import { execFileSync } from 'child_process';
+import * as fs from 'fs';
+import * as os from 'os';
+import * as path from 'path';
+import { CONFIG_FILE } from './settings.js';
+import { parseToml } from './toml.js';
+import { sshManager, shellQuote } from './ssh-manager.js';
+
+/// A Remote-SSH target found in the user's VS Code, with the folders they
+/// recently opened on it
+export interface RemoteTarget {
+  host: string;
+  // From `remote.SSH.remotePlatform`, if VS Code recorded one
+  platform?: string;
+  folders: string[];
+}
+
+/// A config.toml profile to create for a target or one of its folders
+export interface ProfileSuggestion {
+  name: string;
+  host: string;
+  folder?: string;
+  cloneRepo?: string;
+  // Why the profile is less than it could be, e.g. the host was unreachable
+  note?: string;
+}
+
+// Where VS Code and its forks keep their user data on each platform
+function vscodeDataDirs(): string[] {
+  const home = os.homedir();
+  const products = ['Code', 'Code - Insiders', 'VSCodium', 'Cursor'];
+  const roots = process.platform === 'darwin' ? [path.join(home, 'Library', 'Application Support')]
+    : process.platform === 'win32' ? [process.env.APPDATA || path.join(home, 'AppData', 'Roaming')]
+    : [process.env.XDG_CONFIG_HOME || path.join(home, '.config')];
+  return roots.flatMap(root => products.map(product => path.join(root, product)))
+    .filter(dir => fs.existsSync(path.join(dir, 'User')));
+}
+
+// VS Code's settings.json allows comments and trailing commas
+function readJsonc(file: string): any {
+  try {
+    const text = fs.readFileSync(file, 'utf8')
+      .replace(/("(?:[^"\\]|\\.)*")|\/\/[^\n]*|\/\*[\s\S]*?\*\//g, (match, string) => string || '')
+      .replace(/,(\s*[}\]])/g, '$1');
+    return JSON.parse(text);
+  } catch {
+    return {};
+  }
+}
+
+// Concrete `Host` names of an ssh config file and the files it includes, skipping patterns
+function sshConfigHosts(file: string, seen = new Set<string>()): string[] {
+  if (seen.has(file) || !fs.existsSync(file)) {
+    return [];
+  }
+  seen.add(file);
+  const hosts: string[] = [];
+  for (const line of fs.readFileSync(file, 'utf8').split('\n')) {
+    const [keyword, ...values] = line.trim().split(/\s+/);
+    if (/^host$/i.test(keyword)) {
+      hosts.push(...values.filter(value => !/[*?!]/.test(value)));
+    } else if (/^include$/i.test(keyword)) {
+      for (const pattern of values) {
+        const resolved = pattern.replace(/^~/, os.homedir());
+        const absolute = path.isAbsolute(resolved) ? resolved : path.join(os.homedir(), '.ssh', resolved);
+        // Only `*` wildcards in the last component, which is what people write
+        const dir = path.dirname(absolute);
+        const matcher = new RegExp(`^${path.basename(absolute).replace(/[.+^${}()|[\]\\]/g, '\\$&').replace(/\*/g, '.*')}$`);
+        const files = fs.existsSync(dir) ? fs.readdirSync(dir).filter(name => matcher.test(name)).sort() : [];
+        files.forEach(name => hosts.push(...sshConfigHosts(path.join(dir, name), seen)));
+      }
+    }
+  }
+  return hosts;
+}
+
+// `vscode-remote://ssh-remote+<authority><path>` URIs anywhere in `text`. The
+// authority is the host alias, or hex-encoded JSON (`{"hostName": ...}`) for
+// hosts whose names do not fit in a URI.
+function remoteFolders(text: string): { host: string; folder: string }[] {
+  const found: { host: string; folder: string }[] = [];
+  const uri = /vscode-remote:\/\/ssh-remote(?:%2B|\+)([^/"\\\s]+)(\/[^"\\\s]*)?/gi;
+  for (let match = uri.exec(text); match; match = uri.exec(text)) {
+    let host = decodeURIComponent(match[1]);
+    if (/^[0-9a-f]+$/i.test(host) && host.length % 2 === 0 && host.startsWith('7b')) {
+      try {
+        host = JSON.parse(Buffer.from(host, 'hex').toString('utf8')).hostName || host;
+      } catch {
+        // Not JSON after all; keep it as it is
+      }
+    }
+    const folder = decodeURIComponent(match[2] || '');
+    if (folder && folder !== '/') {
+      found.push({ host, folder: folder.replace(/\/$/, '') });
+    }
+  }
+  return found;
+}
+
+// The recently opened list is in an SQLite database; read it with the sqlite3 tool if there is one
+function recentlyOpened(dataDir: string): string {
+  const texts: string[] = [];
+  const storage = path.join(dataDir, 'User', 'globalStorage', 'storage.json');
+  if (fs.existsSync(storage)) {
+    texts.push(fs.readFileSync(storage, 'utf8'));
+  }
+  const database = path.join(dataDir, 'User', 'globalStorage', 'state.vscdb');
+  if (fs.existsSync(database)) {
+    try {
+      texts.push(execFileSync('sqlite3', [database, "SELECT value FROM ItemTable WHERE key = 'history.recentlyOpenedPathsList'"],
+        { encoding: 'utf8', timeout: 10000, stdio: ['ignore', 'pipe', 'ignore'] }));
+    } catch {
+      console.warn(`Could not read the recently opened folders from ${database} (is sqlite3 installed?); using storage.json only`);
+    }
+  }
+  return texts.join('\n');
+}
+
+/// Every Remote-SSH target of the user's VS Code installations: the hosts of
+/// the SSH config file Remote-SSH uses, the hosts it has a platform recorded
+/// for, and the hosts of recently opened remote folders
+export function findRemoteTargets(): RemoteTarget[] {
+  const targets = new Map<string, RemoteTarget>();
+  const target = (host: string) => {
+    if (!targets.has(host)) {
+      targets.set(host, { host, folders: [] });
+    }
+    return targets.get(host)!;
+  };
+
+  const dataDirs = vscodeDataDirs();
+  const configFiles = new Set<string>([path.join(os.homedir(), '.ssh', 'config')]);
+  for (const dataDir of dataDirs) {
+    const settings = readJsonc(path.join(dataDir, 'User', 'settings.json'));
+    if (settings['remote.SSH.configFile']) {
+      configFiles.add(String(settings['remote.SSH.configFile']).replace(/^~/, os.homedir()));
+    }
+    for (const [host, platform] of Object.entries(settings['remote.SSH.remotePlatform'] || {})) {
+      target(host).platform = String(platform);
+    }
+    for (const { host, folder } of remoteFolders(recentlyOpened(dataDir))) {
+      const folders = target(host).folders;
+      if (!folders.includes(folder)) {
+        folders.push(folder);
+      }
+    }
+  }
+  for (const file of configFiles) {
+    sshConfigHosts(file).forEach(host => target(host));
+  }
+
+  return [...targets.values()].sort((a, b) => a.host.localeCompare(b.host));
+}
+
+// Profile names become TOML keys; keep them bare
+function profileName(...parts: string[]): string {
+  return parts.join('-').toLowerCase().replace(/[^a-z0-9_-]+/g, '-').replace(/^-+|-+$/g, '') || 'host';
+}
+
+/// Profiles for `targets`: one per host, and one per recent folder that is a
+/// git checkout, which clones the same repository into each taskspace. With
+/// `probe`, the hosts are asked over SSH, all at once, for their folders'
+/// `origin` remotes; without it, folders are only listed.
+export async function suggestProfiles(targets: RemoteTarget[], probe: boolean): Promise<ProfileSuggestion[]> {
+  const perTarget = await Promise.all(targets.map(target => suggestTargetProfiles(target, probe)));
+  return perTarget.flat();
+}
+
+async function suggestTargetProfiles(target: RemoteTarget, probe: boolean): Promise<ProfileSuggestion[]> {
+  if (target.platform && target.platform !== 'linux') {
+    return [{ name: profileName(target.host), host: target.host, note: `skipped: VS Code records it as ${target.platform}, and servers are only managed on Linux` }];
+  }
+  const suggestions: ProfileSuggestion[] = [{ name: profileName(target.host), host: target.host }];
+
+  let origins: Record<string, string> = {};
+  let note: string | undefined;
+  if (probe && target.folders.length > 0) {
+    try {
+      origins = await folderOrigins(target.host, target.folders);
+    } catch (error) {
+      note = `could not ask ${target.host} for its git remotes: ${error.message.split('\n')[0]}`;
+    }
+  }
+  for (const folder of target.folders) {
+    suggestions.push({
+      name: profileName(target.host, path.posix.basename(folder)),
+      host: target.host,
+      folder,
+      cloneRepo: origins[folder],
+      note: origins[folder] ? undefined : note || (probe ? 'not a git checkout with an origin remote' : 'not probed')
+    });
+  }
+  return suggestions;
+}
+
+async function folderOrigins(host: string, folders: string[]): Promise<Record<string, string>> {
+  const script = folders.map(folder =>
+    `printf '%s\\t%s\\n' ${shellQuote(folder)} "$(git -C ${shellQuote(folder)} remote get-url origin 2>/dev/null)"`).join('\n');
+  const output = await sshManager.executeCommand(host, script);
+  const origins: Record<string, string> = {};
+  for (const line of output.split('\n')) {
+    const [folder, origin] = line.split('\t');
+    if (folder && origin) {
+      origins[folder] = origin.trim();
+    }
+  }
+  return origins;
+}
+
+/// Profile names config.toml already has
+export function existingProfiles(): Set<string> {
+  try {
+    return new Set(Object.keys(parseToml(fs.readFileSync(CONFIG_FILE, 'utf8')).profiles || {}));
+  } catch {
+    return new Set();
+  }
+}
+
+/// The TOML for the usable suggestions, one `[profiles.<name>]` table each
+export function profilesToml(suggestions: ProfileSuggestion[]): string {
+  const tables = suggestions
+    .filter(suggestion => !suggestion.note?.startsWith('skipped'))
+    .filter(suggestion => !suggestion.folder || suggestion.cloneRepo)
+    .map(suggestion => [
+      `[profiles.${suggestion.name}]`,
+      ...(suggestion.folder ? [`# From ${suggestion.folder} on ${suggestion.host}, opened with Remote-SSH`] : []),
+      `hostname = ${JSON.stringify(suggestion.host)}`,
+      ...(suggestion.cloneRepo ? [`clone_repo = ${JSON.stringify(suggestion.cloneRepo)}`] : [])
+    ].join('\n'));
+  return tables.join('\n\n');
+}
+
+/// Append `toml` to config.toml, creating it if needed
+export function appendProfiles(toml: string): void {
+  fs.mkdirSync(path.dirname(CONFIG_FILE), { recursive: true });
+  const existing = fs.existsSync(CONFIG_FILE) ? fs.readFileSync(CONFIG_FILE, 'utf8') : '';
+  const separator = existing && !existing.endsWith('\n\n') ? (existing.endsWith('\n') ? '\n' : '\n\n') : '';
+  fs.appendFileSync(CONFIG_FILE, `${separator}# Imported from VS Code Remote-SSH on ${new Date().toISOString().slice(0, 10)}\n${toml}\n`);
+}
@@ -0,0 +1,122 @@
+import * as fs from 'fs';
+import * as path from 'path';
+import { sshManager } from './ssh-manager.js';
+import { BASE_DIR, LOCAL_DATA_DIR } from './settings.js';
+
+/// Local directory holding the archives of work saved before destructive operations
+export const BACKUPS_DIR = path.join(LOCAL_DATA_DIR, 'backups');
+
+/// What was saved of a host's taskspaces before a destructive operation
+export interface WorkBackup {
+  hostname: string;
+  // Local path of the archive, or null when no taskspace had unsaved work
+  archive: string | null;
+  // One line per taskspace with unsaved work, e.g. "<uuid>: 2 changed files, 1 unpushed commit"
+  taskspaces: string[];
+}
+
+/// Saving a host's unsaved work failed, so the operation that would destroy
+/// it should not go ahead without `--force`
+export class BackupFailedError extends Error {
+  constructor(public hostname: string, reason: string) {
+    super(`Could not back up the unsaved work on ${hostname}: ${reason}`);
+    this.name = 'BackupFailedError';
+  }
+}
+
+// For every clone with uncommitted changes, stashes or commits no remote has,
+// stage a directory with a bundle of all its refs (stashes included), the
+// changed and untracked files, a patch against HEAD and its status, then
+// pack them into one archive and print its path. A clone without commits
+// has no bundle; its files are still saved.
+function backupScript(): string {
+  return `
+    set -e
+    [ -d ${BASE_DIR}/taskspaces ] || exit 0
+    cd ${BASE_DIR}/taskspaces
+    STAGE=$(mktemp -d /tmp/theoldswitcheroo-backup-XXXXXX)
+    trap 'rm -rf "$STAGE"' EXIT
+    for clone in */clone; do
+      uuid=\${clone%/clone}
+      git -C "$clone" rev-parse --git-dir >/dev/null 2>&1 || continue
+      changed=$(git -C "$clone" status --porcelain | wc -l | tr -d ' ')
+      stashes=$(git -C "$clone" stash list | wc -l | tr -d ' ')
+      unpushed=$(git -C "$clone" log --oneline --branches --not --remotes 2>/dev/null | wc -l | tr -d ' ')
+      [ "$changed" = 0 ] && [ "$stashes" = 0 ] && [ "$unpushed" = 0 ] && continue
+      echo "unsaved: $uuid $changed $stashes $unpushed"
+      mkdir -p "$STAGE/$uuid"
+      if git -C "$clone" rev-parse --verify -q HEAD >/dev/null; then
+        git -C "$clone" bundle create "$STAGE/$uuid/repo.bundle" --all
+        git -C "$clone" diff --binary HEAD > "$STAGE/$uuid/changes.patch"
+      fi
+      git -C "$clone" ls-files -z --modified --others --exclude-standard |
+        tar -C "$clone" --null --ignore-failed-read -T - -czf "$STAGE/$uuid/worktree.tar.gz"
+      git -C "$clone" status --branch --short > "$STAGE/$uuid/status.txt"
+    done
+    if [ -n "$(ls "$STAGE")" ]; then
+      (umask 077 && tar -C "$STAGE" -czf "$STAGE.tar.gz" .)
+      echo "archive: $STAGE.tar.gz"
+    fi
+  `;
+}
+
+function plural(count: number, what: string): string {
+  return `${count} ${what}${count === 1 ? '' : 's'}`;
+}
+
+/// Save the work that exists only on `hostname` before something deletes it:
+/// every taskspace clone with uncommitted changes, stashes or unpushed commits
+/// is archived on the host, and the archive is downloaded to BACKUPS_DIR.
+/// Throws a BackupFailedError if anything on the way fails.
+export async function backupUnsavedWork(hostname: string): Promise<WorkBackup> {
+  let output: string;
+  try {
+    output = await sshManager.executeCommand(hostname, backupScript());
+  } catch (error) {
+    throw new BackupFailedError(hostname, error.message.split('\n').filter((line: string) => line.trim()).pop() || error.message);
+  }
+
+  const taskspaces = output.split('\n').filter(line => line.startsWith('unsaved: ')).map((line) => {
+    const [uuid, changed, stashes, unpushed] = line.slice('unsaved: '.length).trim().split(' ');
+    const facts = [[changed, 'changed file'], [stashes, 'stash'], [unpushed, 'unpushed commit']]
+      .filter(([count]) => Number(count) > 0)
+      .map(([count, what]) => plural(Number(count), what));
+    return `${uuid}: ${facts.join(', ')}`;
+  });
+  const remoteArchive = /^archive: (\S+)$/m.exec(output)?.[1];
+  if (!remoteArchive) {
+    return { hostname, archive: null, taskspaces };
+  }
+
+  const archive = path.join(BACKUPS_DIR, `${hostname}-${new Date().toISOString().replace(/[:.]/g, '-')}.tar.gz`);
+  try {
+    fs.mkdirSync(BACKUPS_DIR, { recursive: true });
+    await sshManager.downloadFile(hostname, remoteArchive, archive);
+  } catch (error) {
+    fs.rmSync(archive, { force: true });
+    throw new BackupFailedError(hostname, `downloading ${remoteArchive} failed: ${error.message}`);
+  } finally {
+    await sshManager.executeCommand(hostname, `rm -f ${remoteArchive}`).catch(() => {});
+  }
+  return { hostname, archive, taskspaces };
+}
+
+/// Back up `hostname`'s unsaved work before a destructive operation, and
+/// report what was saved. If the backup fails, throw unless `force`, in
+/// which case warn and let the operation go ahead.
+export async function guardUnsavedWork(hostname: string, force: boolean): Promise<void> {
+  try {
+    const backup = await backupUnsavedWork(hostname);
+    if (!backup.archive) {
+      console.log(`No unsaved work in the taskspaces on ${hostname}`);
+      return;
+    }
+    console.log(`Saved the unsaved work of ${plural(backup.taskspaces.length, 'taskspace')} on ${hostname} to ${backup.archive}:`);
+    backup.taskspaces.forEach(line => console.log(`  ${line}`));
+  } catch (error) {
+    if (!force) {
+      throw new Error(`${error.message}\nNothing was deleted. Fix the problem, or run again with --force to go ahead without a backup.`);
+    }
+    console.warn(`⚠ ${error.message}; going ahead without a backup because of --force`);
+  }
+}
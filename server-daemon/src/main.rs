@@ -0,0 +1,435 @@
+//! theoldswitcheroo-serverd: runs the VS Code servers of theoldswitcheroo's
+//! taskspaces on the remote host, so they outlive the SSH sessions that
+//! started them.
+//!
+//! `ensure` starts the daemon (`serve`) in the background unless it already
+//! answers on its socket. Every other command sends one request to it; see
+//! `protocol` for the wire format. `follow` streams a service's log until the
+//! service is done and exits with its status, which lets an SSH session
+//! watch a server the way it would watch one it ran itself.
+
+mod protocol;
+mod supervisor;
+
+use std::env;
+use std::fs::{self, DirBuilder, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::DirBuilderExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use protocol::{Request, StatusLine};
+use supervisor::Supervisor;
+
+const USAGE: &str = "\
+usage: theoldswitcheroo-serverd --socket PATH [--state-dir DIR] COMMAND
+
+commands:
+  serve                                  run the daemon in the foreground
+  ensure                                 start the daemon in the background unless it is running
+  start NAME LOG never|on-failure CMD... run CMD with sh -c as service NAME, output appended to LOG
+  stop NAME                              stop a service and everything it started
+  restart NAME                           start a service again with its last command
+  status [NAME]                          print NAME, PID, state and start count per service
+  shutdown                               stop every service and the daemon
+  follow NAME LOG                        print LOG as it grows until NAME is done, exiting with its status
+
+The state directory (default: the socket's directory) holds the services'
+PID files and the daemon's own log, serverd.log.";
+
+/// How often the supervisor looks for exited services
+const TICK: Duration = Duration::from_millis(250);
+/// How long `ensure` waits for a daemon it started to answer
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often `follow` reads the log, and asks about the service
+const FOLLOW_POLL: Duration = Duration::from_millis(200);
+const FOLLOW_STATUS_EVERY: u32 = 5;
+
+struct Options {
+    socket: PathBuf,
+    state_dir: PathBuf,
+    command: Vec<String>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut socket = None;
+    let mut state_dir = None;
+    let mut rest = args;
+    loop {
+        match rest {
+            [flag, value, tail @ ..] if flag == "--socket" => {
+                socket = Some(PathBuf::from(value));
+                rest = tail;
+            }
+            [flag, value, tail @ ..] if flag == "--state-dir" => {
+                state_dir = Some(PathBuf::from(value));
+                rest = tail;
+            }
+            _ => break,
+        }
+    }
+    let socket = socket.ok_or("--socket is required")?;
+    let state_dir = state_dir
+        .or_else(|| socket.parent().map(Path::to_path_buf))
+        .unwrap_or_else(|| PathBuf::from("."));
+    if rest.is_empty() {
+        return Err("no command given".to_string());
+    }
+    Ok(Options {
+        socket,
+        state_dir,
+        command: rest.to_vec(),
+    })
+}
+
+/// The request a client command stands for; a command of several words is
+/// joined with spaces, as ssh does
+fn client_request(command: &[String]) -> Result<Request, String> {
+    let fields: Vec<String> = match command {
+        [verb, name, log, restart, words @ ..] if verb == "start" => {
+            vec![
+                verb.clone(),
+                name.clone(),
+                log.clone(),
+                restart.clone(),
+                words.join(" "),
+            ]
+        }
+        _ => command.to_vec(),
+    };
+    if fields.iter().any(|field| field.contains(['\t', '\n'])) {
+        return Err("arguments can't contain tabs or newlines".to_string());
+    }
+    Request::parse(&fields.join("\t"))
+}
+
+/// Send `request` and read the reply: its data lines, or the daemon's error
+fn send(socket: &Path, request: &Request) -> io::Result<Result<Vec<String>, String>> {
+    let mut stream = UnixStream::connect(socket)?;
+    writeln!(stream, "{request}")?;
+    stream.shutdown(std::net::Shutdown::Write)?;
+    let mut lines = BufReader::new(stream).lines();
+    let first = lines.next().transpose()?.unwrap_or_default();
+    let data = lines.collect::<io::Result<Vec<String>>>()?;
+    Ok(match first.split_once('\t') {
+        None if first == "ok" => Ok(data),
+        Some(("error", message)) => Err(message.to_string()),
+        _ => Err(format!("unexpected reply {first:?}")),
+    })
+}
+
+fn lock(supervisor: &Mutex<Supervisor>) -> MutexGuard<'_, Supervisor> {
+    supervisor
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// Stop service `name` if there is one, waiting for it with the supervisor unlocked
+fn stop_and_wait(supervisor: &Mutex<Supervisor>, name: &str) -> Result<(), String> {
+    let stopping = lock(supervisor).stop(name)?;
+    if let Some(stopping) = stopping {
+        stopping.wait();
+    }
+    Ok(())
+}
+
+// Answer one connection's request. Waiting for services to stop takes up to
+// their grace period each, so it happens without holding the supervisor;
+// the supervisor keeps noticing crashes and answering `status` meanwhile.
+fn handle(stream: UnixStream, supervisor: &Mutex<Supervisor>, socket: &Path) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let reply: Result<Vec<String>, String> = match Request::parse(&line) {
+        Err(error) => Err(error),
+        Ok(Request::Start {
+            name,
+            log,
+            restart,
+            command,
+        }) => {
+            // In place of a running one of that name
+            let _ = stop_and_wait(supervisor, &name);
+            lock(supervisor)
+                .start(&name, log, restart, command)
+                .map(|pid| vec![pid.to_string()])
+        }
+        Ok(Request::Stop { name }) => stop_and_wait(supervisor, &name).map(|()| vec![]),
+        Ok(Request::Restart { name }) => stop_and_wait(supervisor, &name)
+            .and_then(|()| lock(supervisor).restart(&name))
+            .map(|pid| vec![pid.to_string()]),
+        Ok(Request::Status { name }) => Ok(lock(supervisor)
+            .status(name.as_deref())
+            .iter()
+            .map(StatusLine::to_string)
+            .collect()),
+        Ok(Request::Shutdown) => {
+            let stopping = lock(supervisor).stop_all();
+            thread::scope(|scope| {
+                for service in stopping {
+                    scope.spawn(move || service.wait());
+                }
+            });
+            let _ = fs::remove_file(socket);
+            let _ = writeln!(&stream, "ok");
+            eprintln!("serverd: shut down");
+            process::exit(0);
+        }
+    };
+    let mut stream = &stream;
+    match reply {
+        Ok(data) => {
+            writeln!(stream, "ok")?;
+            for line in data {
+                writeln!(stream, "{line}")?;
+            }
+        }
+        Err(error) => writeln!(stream, "error\t{}", error.replace(['\t', '\n'], " "))?,
+    }
+    Ok(())
+}
+
+fn create_state_dir(state_dir: &Path) -> io::Result<()> {
+    DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(state_dir)
+}
+
+fn serve(options: &Options) -> io::Result<()> {
+    create_state_dir(&options.state_dir)?;
+    if UnixStream::connect(&options.socket).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            "a daemon is already running on this socket",
+        ));
+    }
+    // Left behind by a daemon that did not get to shut down
+    let _ = fs::remove_file(&options.socket);
+    let listener = UnixListener::bind(&options.socket)?;
+    eprintln!(
+        "serverd: listening on {} (PID {})",
+        options.socket.display(),
+        process::id()
+    );
+
+    let supervisor = Arc::new(Mutex::new(Supervisor::new(&options.state_dir)));
+    let ticking = Arc::clone(&supervisor);
+    thread::spawn(move || loop {
+        thread::sleep(TICK);
+        lock(&ticking).tick();
+    });
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(error) => {
+                eprintln!("serverd: accept failed: {error}");
+                continue;
+            }
+        };
+        let supervisor = Arc::clone(&supervisor);
+        let socket = options.socket.clone();
+        thread::spawn(move || {
+            if let Err(error) = handle(stream, &supervisor, &socket) {
+                eprintln!("serverd: request failed: {error}");
+            }
+        });
+    }
+    Ok(())
+}
+
+fn ensure(options: &Options) -> Result<(), String> {
+    let alive = || {
+        matches!(
+            send(&options.socket, &Request::Status { name: None }),
+            Ok(Ok(_))
+        )
+    };
+    if alive() {
+        return Ok(());
+    }
+    create_state_dir(&options.state_dir)
+        .map_err(|error| format!("could not create {}: {error}", options.state_dir.display()))?;
+    let log_path = options.state_dir.join("serverd.log");
+    let log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_path)
+        .map_err(|error| format!("could not open {}: {error}", log_path.display()))?;
+    let exe = env::current_exe().map_err(|error| error.to_string())?;
+    // Detached: its own process group, and nothing tying it to this SSH session
+    Command::new(exe)
+        .arg("--socket")
+        .arg(&options.socket)
+        .arg("--state-dir")
+        .arg(&options.state_dir)
+        .arg("serve")
+        .stdin(Stdio::null())
+        .stdout(log.try_clone().map_err(|error| error.to_string())?)
+        .stderr(log)
+        .process_group(0)
+        .spawn()
+        .map_err(|error| format!("could not start the daemon: {error}"))?;
+
+    let deadline = Instant::now() + STARTUP_TIMEOUT;
+    while Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(50));
+        if alive() {
+            return Ok(());
+        }
+    }
+    Err(format!(
+        "the daemon did not come up within {}s; see {}",
+        STARTUP_TIMEOUT.as_secs(),
+        log_path.display()
+    ))
+}
+
+// Copy what was added to `log` since `offset` to stdout; false once stdout is gone
+fn copy_new(log: &mut File, offset: &mut u64) -> bool {
+    // Truncated, e.g. for a fresh start: read it from the top again
+    if log
+        .metadata()
+        .is_ok_and(|metadata| metadata.len() < *offset)
+    {
+        *offset = 0;
+    }
+    let mut buffer = Vec::new();
+    if log.seek(SeekFrom::Start(*offset)).is_err() || log.read_to_end(&mut buffer).is_err() {
+        return true;
+    }
+    *offset += buffer.len() as u64;
+    let mut stdout = io::stdout().lock();
+    buffer.is_empty() || (stdout.write_all(&buffer).is_ok() && stdout.flush().is_ok())
+}
+
+fn follow(socket: &Path, name: &str, log_path: &Path) -> i32 {
+    let mut log = None;
+    let mut offset = 0;
+    let mut polls = 0u32;
+    loop {
+        if log.is_none() {
+            log = File::open(log_path).ok();
+        }
+        if let Some(log) = log.as_mut() {
+            if !copy_new(log, &mut offset) {
+                return 0;
+            }
+        }
+
+        if polls.is_multiple_of(FOLLOW_STATUS_EVERY) {
+            let status = match send(
+                socket,
+                &Request::Status {
+                    name: Some(name.to_string()),
+                },
+            ) {
+                Ok(Ok(lines)) => lines
+                    .first()
+                    .and_then(|line| line.parse::<StatusLine>().ok()),
+                Ok(Err(error)) => {
+                    eprintln!("serverd: {error}");
+                    return 1;
+                }
+                Err(error) => {
+                    // Like ssh when its connection drops: the service may well still be running
+                    eprintln!("serverd: lost the daemon: {error}");
+                    return 255;
+                }
+            };
+            let Some(status) = status else {
+                eprintln!("serverd: no service {name:?}");
+                return 1;
+            };
+            if let Some(code) = status.state.exit_code() {
+                if let Some(log) = log.as_mut() {
+                    copy_new(log, &mut offset);
+                }
+                return code;
+            }
+        }
+        polls = polls.wrapping_add(1);
+        thread::sleep(FOLLOW_POLL);
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{USAGE}");
+        return;
+    }
+    let options = parse_args(&args).unwrap_or_else(|error| {
+        eprintln!("theoldswitcheroo-serverd: {error}\n\n{USAGE}");
+        process::exit(2);
+    });
+
+    let result = match options.command.as_slice() {
+        [command] if command == "serve" => serve(&options).map_err(|error| error.to_string()),
+        [command] if command == "ensure" => ensure(&options),
+        [command, name, log] if command == "follow" => {
+            process::exit(follow(&options.socket, name, Path::new(log)))
+        }
+        command => {
+            client_request(command).and_then(|request| match send(&options.socket, &request) {
+                Ok(Ok(lines)) => {
+                    for line in lines {
+                        println!("{line}");
+                    }
+                    Ok(())
+                }
+                Ok(Err(error)) => Err(error),
+                Err(error) => Err(format!(
+                    "could not reach the daemon at {}: {error}",
+                    options.socket.display()
+                )),
+            })
+        }
+    };
+    if let Err(error) = result {
+        eprintln!("theoldswitcheroo-serverd: {error}");
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn state_dir_defaults_to_the_sockets_directory() {
+        let options = parse_args(&strings(&["--socket", "/run/s/serverd.sock", "status"])).unwrap();
+        assert_eq!(options.state_dir, PathBuf::from("/run/s"));
+        assert_eq!(options.command, ["status"]);
+        assert!(parse_args(&strings(&["status"])).is_err());
+        assert!(parse_args(&strings(&["--socket", "s"])).is_err());
+    }
+
+    #[test]
+    fn start_joins_the_command_words() {
+        let request = client_request(&strings(&[
+            "start",
+            "a",
+            "/tmp/a.log",
+            "on-failure",
+            "sh",
+            "launch.sh",
+        ]))
+        .unwrap();
+        assert_eq!(
+            request.to_string(),
+            "start\ta\t/tmp/a.log\ton-failure\tsh launch.sh"
+        );
+        assert!(client_request(&strings(&["stop", "a\tb"])).is_err());
+    }
+}
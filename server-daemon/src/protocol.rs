@@ -0,0 +1,288 @@
+//! The control protocol spoken over the daemon's Unix socket.
+//!
+//! A client sends one request line and reads until the daemon closes the
+//! connection. The reply's first line is `ok` or `error\t<message>`; any
+//! data lines follow it. Fields are separated by tabs, so no field may
+//! contain a tab or a newline.
+
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// What the daemon does when a service's process exits on its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartPolicy {
+    Never,
+    /// Restart after any exit but a clean one (code 0), with backoff: a
+    /// nonzero code, or a signal the daemon did not send, e.g. the OOM
+    /// killer's. A service stopped through the daemon stays stopped.
+    OnFailure,
+}
+
+impl FromStr for RestartPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "never" => Ok(RestartPolicy::Never),
+            "on-failure" => Ok(RestartPolicy::OnFailure),
+            _ => Err(format!(
+                "unknown restart policy {s:?}, expected never or on-failure"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for RestartPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RestartPolicy::Never => "never",
+            RestartPolicy::OnFailure => "on-failure",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    /// Run `command` with `sh -c` as service `name`, appending its output to
+    /// `log`. A service of that name that is still running is stopped first.
+    Start {
+        name: String,
+        log: PathBuf,
+        restart: RestartPolicy,
+        command: String,
+    },
+    Stop {
+        name: String,
+    },
+    /// Start a service again with the command it was last started with
+    Restart {
+        name: String,
+    },
+    /// One status line per service, or only for `name`
+    Status {
+        name: Option<String>,
+    },
+    /// Stop every service and exit
+    Shutdown,
+}
+
+// Service names end up in file names, so keep them to a safe alphabet
+fn check_name(name: &str) -> Result<String, String> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c));
+    if valid {
+        Ok(name.to_string())
+    } else {
+        Err(format!("invalid service name {name:?}"))
+    }
+}
+
+impl Request {
+    pub fn parse(line: &str) -> Result<Request, String> {
+        let fields: Vec<&str> = line.trim_end_matches(['\r', '\n']).split('\t').collect();
+        match fields.as_slice() {
+            ["start", name, log, restart, command] if !command.is_empty() => Ok(Request::Start {
+                name: check_name(name)?,
+                log: PathBuf::from(log),
+                restart: restart.parse()?,
+                command: command.to_string(),
+            }),
+            ["stop", name] => Ok(Request::Stop {
+                name: check_name(name)?,
+            }),
+            ["restart", name] => Ok(Request::Restart {
+                name: check_name(name)?,
+            }),
+            ["status"] => Ok(Request::Status { name: None }),
+            ["status", name] => Ok(Request::Status {
+                name: Some(check_name(name)?),
+            }),
+            ["shutdown"] => Ok(Request::Shutdown),
+            [verb, ..] => Err(format!("malformed request {verb:?}")),
+            [] => Err("empty request".to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Request {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Request::Start {
+                name,
+                log,
+                restart,
+                command,
+            } => {
+                write!(f, "start\t{name}\t{}\t{restart}\t{command}", log.display())
+            }
+            Request::Stop { name } => write!(f, "stop\t{name}"),
+            Request::Restart { name } => write!(f, "restart\t{name}"),
+            Request::Status { name: None } => f.write_str("status"),
+            Request::Status { name: Some(name) } => write!(f, "status\t{name}"),
+            Request::Shutdown => f.write_str("shutdown"),
+        }
+    }
+}
+
+/// Where a service is in its life
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Running,
+    /// Exited with a failure and waiting to be restarted
+    Backoff,
+    /// Stopped with `stop` or `shutdown`
+    Stopped,
+    Exited(i32),
+    Killed(i32),
+}
+
+impl State {
+    /// The exit status a shell would report for a service that is done, or
+    /// None while it is running or about to be restarted
+    pub fn exit_code(self) -> Option<i32> {
+        match self {
+            State::Running | State::Backoff => None,
+            State::Stopped => Some(0),
+            State::Exited(code) => Some(code),
+            State::Killed(signal) => Some(128 + signal),
+        }
+    }
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            State::Running => f.write_str("running"),
+            State::Backoff => f.write_str("backoff"),
+            State::Stopped => f.write_str("stopped"),
+            State::Exited(code) => write!(f, "exited:{code}"),
+            State::Killed(signal) => write!(f, "killed:{signal}"),
+        }
+    }
+}
+
+impl FromStr for State {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let number = |n: &str| n.parse::<i32>().map_err(|_| format!("invalid state {s:?}"));
+        match s.split_once(':') {
+            None if s == "running" => Ok(State::Running),
+            None if s == "backoff" => Ok(State::Backoff),
+            None if s == "stopped" => Ok(State::Stopped),
+            Some(("exited", code)) => Ok(State::Exited(number(code)?)),
+            Some(("killed", signal)) => Ok(State::Killed(number(signal)?)),
+            _ => Err(format!("invalid state {s:?}")),
+        }
+    }
+}
+
+/// A data line of the `status` reply: `NAME\tPID\tSTATE\tSTARTS`, with `-`
+/// for the PID of a service that is not running
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusLine {
+    pub name: String,
+    pub pid: Option<u32>,
+    pub state: State,
+    /// How many times the service was started, restarts included
+    pub starts: u32,
+}
+
+impl fmt::Display for StatusLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pid = self
+            .pid
+            .map_or_else(|| "-".to_string(), |pid| pid.to_string());
+        write!(f, "{}\t{pid}\t{}\t{}", self.name, self.state, self.starts)
+    }
+}
+
+impl FromStr for StatusLine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid status line {s:?}");
+        let [name, pid, state, starts] = s.split('\t').collect::<Vec<_>>()[..] else {
+            return Err(invalid());
+        };
+        Ok(StatusLine {
+            name: name.to_string(),
+            pid: if pid == "-" {
+                None
+            } else {
+                Some(pid.parse().map_err(|_| invalid())?)
+            },
+            state: state.parse()?,
+            starts: starts.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_round_trip() {
+        let requests = [
+            Request::Start {
+                name: "3f2a-uuid".to_string(),
+                log: PathBuf::from("/home/u/server-data/supervised.log"),
+                restart: RestartPolicy::OnFailure,
+                command: "sh /home/u/server-data/launch.sh --verbose".to_string(),
+            },
+            Request::Stop {
+                name: "a".to_string(),
+            },
+            Request::Restart {
+                name: "a".to_string(),
+            },
+            Request::Status { name: None },
+            Request::Status {
+                name: Some("a".to_string()),
+            },
+            Request::Shutdown,
+        ];
+        for request in requests {
+            assert_eq!(Request::parse(&format!("{request}\n")), Ok(request));
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_requests() {
+        assert!(Request::parse("").is_err());
+        assert!(Request::parse("launch\ta").is_err());
+        assert!(Request::parse("stop").is_err());
+        assert!(Request::parse("stop\t../etc").is_err());
+        assert!(Request::parse("start\ta\tlog\talways\tsh x").is_err());
+        assert!(Request::parse("start\ta\tlog\tnever\t").is_err());
+    }
+
+    #[test]
+    fn status_lines_round_trip() {
+        for line in [
+            "a\t4242\trunning\t1",
+            "a\t-\tbackoff\t3",
+            "a\t-\texited:1\t2",
+            "a\t-\tkilled:15\t1",
+            "a\t-\tstopped\t1",
+        ] {
+            assert_eq!(line.parse::<StatusLine>().unwrap().to_string(), line);
+        }
+        assert!("a\t-\tgone\t1".parse::<StatusLine>().is_err());
+        assert!("a\tx\trunning\t1".parse::<StatusLine>().is_err());
+    }
+
+    #[test]
+    fn exit_codes_follow_the_shell() {
+        assert_eq!(State::Running.exit_code(), None);
+        assert_eq!(State::Backoff.exit_code(), None);
+        assert_eq!(State::Stopped.exit_code(), Some(0));
+        assert_eq!(State::Exited(3).exit_code(), Some(3));
+        assert_eq!(State::Killed(15).exit_code(), Some(143));
+    }
+}
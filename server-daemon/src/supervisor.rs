@@ -0,0 +1,387 @@
+//! The services the daemon runs: starting them in process groups of their
+//! own, stopping them with everything they started, and restarting the ones
+//! that fail.
+
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::protocol::{RestartPolicy, State, StatusLine};
+
+/// How long a stopped service gets between SIGTERM and SIGKILL
+const STOP_GRACE: Duration = Duration::from_secs(10);
+/// Restart delays start here and double for each quick failure in a row
+const FIRST_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// A service that ran at least this long before failing starts over at FIRST_BACKOFF
+const HEALTHY_RUN: Duration = Duration::from_secs(60);
+
+struct Service {
+    log: PathBuf,
+    restart: RestartPolicy,
+    command: String,
+    child: Option<Child>,
+    state: State,
+    starts: u32,
+    started_at: Instant,
+    // Failures in a row that came before HEALTHY_RUN, for the backoff
+    quick_failures: u32,
+    restart_at: Option<Instant>,
+}
+
+pub struct Supervisor {
+    state_dir: PathBuf,
+    services: BTreeMap<String, Service>,
+    shutting_down: bool,
+}
+
+// Send `signal` to the process group led by `pid`. std has no kill(2), so ask kill(1).
+fn signal_group(pid: u32, signal: &str) {
+    let _ = Command::new("kill")
+        .args([format!("-{signal}"), "--".to_string(), format!("-{pid}")])
+        .stderr(Stdio::null())
+        .status();
+}
+
+// Append a line of the daemon's own to a service's log, next to its output
+fn note(log: &Path, message: &str) {
+    if let Ok(mut log) = OpenOptions::new().create(true).append(true).open(log) {
+        let _ = writeln!(log, "[serverd] {message}");
+    }
+}
+
+fn backoff(quick_failures: u32) -> Duration {
+    FIRST_BACKOFF
+        .saturating_mul(1 << quick_failures.min(6))
+        .min(MAX_BACKOFF)
+}
+
+impl Service {
+    fn pid(&self) -> Option<u32> {
+        self.child.as_ref().map(Child::id)
+    }
+
+    fn note(&self, message: &str) {
+        note(&self.log, message);
+    }
+
+    fn spawn(&mut self, name: &str, pid_file: &Path) -> io::Result<u32> {
+        let log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.log)?;
+        // A group of its own, so a stop reaches everything it started, and no
+        // terminal's or SSH session's signals reach it
+        let child = Command::new("/bin/sh")
+            .arg("-c")
+            .arg(&self.command)
+            .stdin(Stdio::null())
+            .stdout(log.try_clone()?)
+            .stderr(log)
+            .process_group(0)
+            .spawn()?;
+        let pid = child.id();
+        self.child = Some(child);
+        self.state = State::Running;
+        self.starts += 1;
+        self.started_at = Instant::now();
+        self.restart_at = None;
+        fs::write(pid_file, format!("{pid}\n"))?;
+        eprintln!("serverd: started {name} (PID {pid})");
+        Ok(pid)
+    }
+
+    // Signal the process group to stop and hand its process over to be waited
+    // for, which can take STOP_GRACE and so happens without the supervisor
+    fn begin_stop(&mut self, name: &str) -> Option<Stopping> {
+        self.restart_at = None;
+        let Some(child) = self.child.take() else {
+            if self.state == State::Backoff {
+                self.state = State::Stopped;
+            }
+            return None;
+        };
+        signal_group(child.id(), "TERM");
+        self.state = State::Stopped;
+        Some(Stopping {
+            name: name.to_string(),
+            log: self.log.clone(),
+            child,
+        })
+    }
+}
+
+/// A service's process that was sent SIGTERM, on its way out
+pub struct Stopping {
+    name: String,
+    log: PathBuf,
+    child: Child,
+}
+
+impl Stopping {
+    /// Wait for the process to go, killing its group after STOP_GRACE
+    pub fn wait(mut self) {
+        let pid = self.child.id();
+        let deadline = Instant::now() + STOP_GRACE;
+        while matches!(self.child.try_wait(), Ok(None)) {
+            if Instant::now() >= deadline {
+                self.note(&format!(
+                    "{} did not stop within {}s of SIGTERM; killing it",
+                    self.name,
+                    STOP_GRACE.as_secs()
+                ));
+                signal_group(pid, "KILL");
+                let _ = self.child.wait();
+                break;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        // Whatever the group left behind goes too
+        signal_group(pid, "KILL");
+        self.note(&format!("stopped {}", self.name));
+        eprintln!("serverd: stopped {} (PID {pid})", self.name);
+    }
+
+    fn note(&self, message: &str) {
+        note(&self.log, message);
+    }
+}
+
+impl Supervisor {
+    pub fn new(state_dir: &Path) -> Supervisor {
+        Supervisor {
+            state_dir: state_dir.to_path_buf(),
+            services: BTreeMap::new(),
+            shutting_down: false,
+        }
+    }
+
+    fn pid_file(&self, name: &str) -> PathBuf {
+        self.state_dir.join(format!("{name}.pid"))
+    }
+
+    /// Start `command` as service `name`. One of that name that is still
+    /// running has to be stopped first.
+    pub fn start(
+        &mut self,
+        name: &str,
+        log: PathBuf,
+        restart: RestartPolicy,
+        command: String,
+    ) -> Result<u32, String> {
+        if self.shutting_down {
+            return Err("the daemon is shutting down".to_string());
+        }
+        let pid_file = self.pid_file(name);
+        let service = self.services.entry(name.to_string()).or_insert(Service {
+            log: log.clone(),
+            restart,
+            command: command.clone(),
+            child: None,
+            state: State::Stopped,
+            starts: 0,
+            started_at: Instant::now(),
+            quick_failures: 0,
+            restart_at: None,
+        });
+        if let Some(pid) = service.pid() {
+            return Err(format!("{name} is running (PID {pid})"));
+        }
+        service.log = log;
+        service.restart = restart;
+        service.command = command;
+        service.quick_failures = 0;
+        service
+            .spawn(name, &pid_file)
+            .map_err(|error| format!("could not start {name}: {error}"))
+    }
+
+    /// Stop service `name`: it is sent SIGTERM now, and the returned process,
+    /// if it was running, is to be waited for with the supervisor unlocked
+    pub fn stop(&mut self, name: &str) -> Result<Option<Stopping>, String> {
+        let service = self
+            .services
+            .get_mut(name)
+            .ok_or_else(|| format!("no service {name:?}"))?;
+        let stopping = service.begin_stop(name);
+        let _ = fs::remove_file(self.pid_file(name));
+        Ok(stopping)
+    }
+
+    /// Start a stopped service again with the command it was last started with
+    pub fn restart(&mut self, name: &str) -> Result<u32, String> {
+        let service = self
+            .services
+            .get(name)
+            .ok_or_else(|| format!("no service {name:?}"))?;
+        let (log, restart, command) = (
+            service.log.clone(),
+            service.restart,
+            service.command.clone(),
+        );
+        self.start(name, log, restart, command)
+    }
+
+    pub fn status(&self, name: Option<&str>) -> Vec<StatusLine> {
+        self.services
+            .iter()
+            .filter(|(service_name, _)| name.is_none_or(|name| name == service_name.as_str()))
+            .map(|(name, service)| StatusLine {
+                name: name.clone(),
+                pid: service.pid(),
+                state: service.state,
+                starts: service.starts,
+            })
+            .collect()
+    }
+
+    /// Stop every service, as for `stop`, and refuse to start any more
+    pub fn stop_all(&mut self) -> Vec<Stopping> {
+        self.shutting_down = true;
+        let names: Vec<String> = self.services.keys().cloned().collect();
+        names
+            .iter()
+            .filter_map(|name| self.stop(name).ok().flatten())
+            .collect()
+    }
+
+    /// Notice services that exited and restart the failed ones that are due
+    pub fn tick(&mut self) {
+        let now = Instant::now();
+        for (name, service) in &mut self.services {
+            let pid_file = self.state_dir.join(format!("{name}.pid"));
+            let exited = match service.child.as_mut().map(Child::try_wait) {
+                Some(Ok(Some(status))) => status,
+                _ => {
+                    if service.restart_at.is_some_and(|at| at <= now) {
+                        if let Err(error) = service.spawn(name, &pid_file) {
+                            service.note(&format!("could not restart {name}: {error}"));
+                            service.quick_failures += 1;
+                            service.restart_at = Some(now + backoff(service.quick_failures));
+                        }
+                    }
+                    continue;
+                }
+            };
+
+            service.child = None;
+            let _ = fs::remove_file(&pid_file);
+            service.state = match (exited.code(), exited.signal()) {
+                (Some(code), _) => State::Exited(code),
+                (None, signal) => State::Killed(signal.unwrap_or(0)),
+            };
+            service.note(&format!("{name} {}", describe(service.state)));
+            eprintln!("serverd: {name} {}", describe(service.state));
+
+            // Stops the daemon was asked for took the child before it got here,
+            // so anything but a clean exit is a failure, signals included
+            let failed = service.state != State::Exited(0);
+            if failed && service.restart == RestartPolicy::OnFailure && !self.shutting_down {
+                if service.started_at.elapsed() >= HEALTHY_RUN {
+                    service.quick_failures = 0;
+                }
+                let delay = backoff(service.quick_failures);
+                service.quick_failures += 1;
+                service.state = State::Backoff;
+                service.restart_at = Some(now + delay);
+                service.note(&format!("restarting {name} in {}s", delay.as_secs()));
+            }
+        }
+    }
+}
+
+fn describe(state: State) -> String {
+    match state {
+        State::Exited(code) => format!("exited with code {code}"),
+        State::Killed(signal) => format!("was killed by signal {signal}"),
+        state => state.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_a_minute() {
+        let delays: Vec<u64> = (0..9).map(|n| backoff(n).as_secs()).collect();
+        assert_eq!(delays, [1, 2, 4, 8, 16, 32, 60, 60, 60]);
+    }
+
+    // Tick until `done` holds for the service, or fail after `timeout`
+    fn tick_until(
+        supervisor: &mut Supervisor,
+        timeout: Duration,
+        done: impl Fn(&StatusLine) -> bool,
+    ) -> StatusLine {
+        let deadline = Instant::now() + timeout;
+        loop {
+            supervisor.tick();
+            let status = supervisor.status(Some("crashy")).remove(0);
+            if done(&status) {
+                return status;
+            }
+            assert!(Instant::now() < deadline, "gave up waiting, at {status}");
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn restarts_a_crashed_service_after_backoff_until_it_is_stopped() {
+        let dir = std::env::temp_dir().join(format!("serverd-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("crashy.log");
+        let mut supervisor = Supervisor::new(&dir);
+
+        // Dies by a signal nobody asked the daemon for, like the OOM killer's
+        let command = "echo up; sleep 0.2; kill -KILL $$".to_string();
+        supervisor
+            .start("crashy", log.clone(), RestartPolicy::OnFailure, command)
+            .unwrap();
+        assert!(dir.join("crashy.pid").exists());
+
+        let crashed = tick_until(&mut supervisor, Duration::from_secs(5), |status| {
+            status.state == State::Backoff
+        });
+        assert_eq!((crashed.pid, crashed.starts), (None, 1));
+        let restarted = tick_until(&mut supervisor, Duration::from_secs(5), |status| {
+            status.starts == 2
+        });
+        assert_eq!(restarted.state, State::Running);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while fs::read_to_string(&log).unwrap().matches("up\n").count() < 2 {
+            assert!(Instant::now() < deadline, "the restarted service never ran");
+            thread::sleep(Duration::from_millis(20));
+        }
+
+        supervisor
+            .stop("crashy")
+            .unwrap()
+            .expect("it was running")
+            .wait();
+        let stopped = supervisor.status(Some("crashy")).remove(0);
+        assert_eq!((stopped.state, stopped.pid), (State::Stopped, None));
+        // A stop is not a crash, so nothing brings it back
+        thread::sleep(FIRST_BACKOFF + Duration::from_millis(200));
+        supervisor.tick();
+        assert_eq!(supervisor.status(Some("crashy"))[0].starts, 2);
+        assert!(!dir.join("crashy.pid").exists());
+
+        let output = fs::read_to_string(&log).unwrap();
+        assert!(
+            output.contains("[serverd] crashy was killed by signal 9"),
+            "{output}"
+        );
+        assert!(
+            output.contains("[serverd] restarting crashy in 1s"),
+            "{output}"
+        );
+        assert!(output.contains("[serverd] stopped crashy"), "{output}");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}